@@ -0,0 +1,218 @@
+//! A diagnostic tool for inspecting the terminal using this crate's APIs.
+//!
+//! ```text
+//! cargo run --example diagnose -- <info|size|query|raw-test|keys|attrs> [args]
+//! ```
+
+use std::io::{self, Read as _, Write as _};
+use std::process::ExitCode;
+use std::time::Duration;
+use terminal_trx::{terminal, Provenance, Terminal, TerminalKind};
+
+const USAGE: &str = "usage: diagnose <command>
+
+commands:
+  info              how the terminal was found
+  size              the size of the terminal in columns and rows
+  query [request]   sends a query (default: primary device attributes) and prints the response,
+                    escapes such as \\e are supported
+  raw-test          checks that raw mode can be enabled and disabled
+  keys              prints the bytes received for each key press until Ctrl+C is pressed
+  attrs             the terminal's attributes and mode flags (termios flags on Unix,
+                    console modes on Windows)";
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+fn main() -> ExitCode {
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    let command: fn(&Terminal, &[&str]) -> io::Result<()> = match args.first().map(String::as_str) {
+        Some("info") => info,
+        Some("size") => size,
+        Some("query") => query,
+        Some("raw-test") => raw_test,
+        Some("keys") => keys,
+        Some("attrs") => attrs,
+        _ => {
+            eprintln!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let args: Vec<_> = args[1..].iter().map(String::as_str).collect();
+    let result = terminal()
+        .map_err(io::Error::from)
+        .and_then(|terminal| command(&terminal, &args));
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn info(terminal: &Terminal, _args: &[&str]) -> io::Result<()> {
+    let kind = match terminal.kind() {
+        TerminalKind::Tty => "tty",
+        TerminalKind::Console => "console",
+        TerminalKind::Msys => "msys",
+        _ => "unknown",
+    };
+    let provenance = match terminal.provenance() {
+        Provenance::Stdio => "standard I/O",
+        Provenance::Reopened => "reopened from standard I/O",
+        Provenance::ControllingTerminal => "controlling terminal",
+        Provenance::Console => "console",
+        Provenance::Pty => "pseudo-terminal",
+        _ => "unknown",
+    };
+    println!("kind:        {kind}");
+    println!("provenance:  {provenance}");
+    println!("path:        {}", terminal.path()?.display());
+    println!("stdin:       {}", terminal.is_stdin());
+    println!("stdout:      {}", terminal.is_stdout());
+    println!("stderr:      {}", terminal.is_stderr());
+    println!("foreground:  {}", terminal.is_foreground()?);
+    Ok(())
+}
+
+fn size(terminal: &Terminal, _args: &[&str]) -> io::Result<()> {
+    // XTWINOPS: Report the size of the text area in characters (CSI 8 ; rows ; columns t).
+    let response = terminal
+        .lock()
+        .query(b"\x1b[18t", |r| r.ends_with(b"t"), TIMEOUT)?;
+    let size = std::str::from_utf8(&response)
+        .ok()
+        .and_then(|r| r.strip_prefix("\x1b[8;")?.strip_suffix('t'))
+        .and_then(|r| r.split_once(';'))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "unexpected response"))?;
+    println!("columns: {}", size.1);
+    println!("rows:    {}", size.0);
+    Ok(())
+}
+
+fn query(terminal: &Terminal, args: &[&str]) -> io::Result<()> {
+    let request = args.first().copied().unwrap_or("\\e[c");
+    // The request is followed by a primary device attributes query, which virtually all terminals answer.
+    // This way we know when the response is complete without having to understand it.
+    let mut request = unescape(request);
+    request.extend_from_slice(b"\x1b[c");
+    let mut lock = terminal.lock();
+    let response = lock.query(&request, ends_with_device_attributes, TIMEOUT)?;
+    println!("{}", escape(&response));
+    Ok(())
+}
+
+fn ends_with_device_attributes(response: &[u8]) -> bool {
+    response.ends_with(b"c")
+        && response
+            .rsplit(|&b| b == 0x1b)
+            .next()
+            .is_some_and(|last| last.starts_with(b"[?"))
+}
+
+fn raw_test(terminal: &Terminal, _args: &[&str]) -> io::Result<()> {
+    let mut lock = terminal.lock();
+    println!("raw mode before: {}", lock.is_raw_mode_enabled()?);
+    {
+        let mut raw_mode = lock.enable_raw_mode()?;
+        // The key is available without pressing enter if raw mode works.
+        write!(raw_mode, "press any key... ")?;
+        raw_mode.flush()?;
+        let mut buf = [0; 16];
+        let len = raw_mode.read(&mut buf)?;
+        write!(raw_mode, "{}\r\n", escape(&buf[..len]))?;
+    }
+    println!("raw mode after:  {}", lock.is_raw_mode_enabled()?);
+    Ok(())
+}
+
+fn keys(terminal: &Terminal, _args: &[&str]) -> io::Result<()> {
+    const CTRL_C: u8 = 0x03;
+
+    let mut lock = terminal.lock();
+    let mut raw_mode = lock.enable_raw_mode()?;
+    write!(
+        raw_mode,
+        "press keys to see their bytes, Ctrl+C to exit\r\n"
+    )?;
+    let mut buf = [0; 64];
+    loop {
+        // A key press that sends multiple bytes (e.g. an escape sequence) usually arrives in a single read.
+        let len = raw_mode.read(&mut buf)?;
+        let bytes = &buf[..len];
+        let hex: Vec<_> = bytes.iter().map(|b| format!("{b:02x}")).collect();
+        write!(raw_mode, "{:<24} {}\r\n", escape(bytes), hex.join(" "))?;
+        if len == 0 || bytes.contains(&CTRL_C) {
+            return Ok(());
+        }
+    }
+}
+
+fn attrs(terminal: &Terminal, _args: &[&str]) -> io::Result<()> {
+    let mut lock = terminal.lock();
+    let attributes = lock.attributes()?;
+    println!("echo:                        {}", attributes.echo());
+    println!("canonical:                   {}", attributes.canonical());
+    println!("signals:                     {}", attributes.signals());
+    println!(
+        "virtual terminal processing: {}",
+        attributes.virtual_terminal_processing()
+    );
+    println!(
+        "raw mode:                    {}",
+        lock.is_raw_mode_enabled()?
+    );
+    println!("flags: {}", flags(&attributes.flags()));
+    println!();
+    platform_attrs(&mut lock)
+}
+
+#[cfg(all(unix, not(terminal_trx_test_unsupported)))]
+fn platform_attrs(lock: &mut terminal_trx::TerminalLock<'_>) -> io::Result<()> {
+    let termios = lock.with_termios(|termios| *termios)?;
+    println!(
+        "min = {}; time = {}",
+        termios.c_cc[libc::VMIN],
+        termios.c_cc[libc::VTIME]
+    );
+    Ok(())
+}
+
+#[cfg(any(not(unix), terminal_trx_test_unsupported))]
+fn platform_attrs(_lock: &mut terminal_trx::TerminalLock<'_>) -> io::Result<()> {
+    Ok(())
+}
+
+/// Formats the flags like `stty`, prefixing flags that are not set with `-`.
+fn flags(flags: &[(&str, bool)]) -> String {
+    let flags: Vec<_> = flags
+        .iter()
+        .map(|&(name, set)| {
+            let name = name.to_lowercase();
+            if set {
+                name
+            } else {
+                format!("-{name}")
+            }
+        })
+        .collect();
+    flags.join(" ")
+}
+
+fn unescape(text: &str) -> Vec<u8> {
+    text.replace("\\e", "\x1b")
+        .replace("\\a", "\x07")
+        .into_bytes()
+}
+
+fn escape(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            0x1b => "\\e".to_owned(),
+            0x07 => "\\a".to_owned(),
+            b' '..=b'~' => char::from(b).to_string(),
+            b => format!("\\x{b:02x}"),
+        })
+        .collect()
+}
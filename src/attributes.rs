@@ -0,0 +1,231 @@
+use crate::imp;
+use std::fmt;
+
+/// A snapshot of the terminal's mode, such as whether input is echoed.
+/// Use [`TerminalLock::attributes`](`crate::TerminalLock::attributes`) to retrieve
+/// and [`TerminalLock::set_attributes`](`crate::TerminalLock::set_attributes`) to apply them.
+///
+/// This wraps the `termios` structure on Unix and the console modes of the input and screen buffer on Windows.
+/// Modifying the attributes has no effect until they are applied to the terminal.
+///
+/// ## Example
+/// ```no_run
+/// use terminal_trx::terminal;
+///
+/// let mut tty = terminal().unwrap();
+/// let mut lock = tty.lock();
+/// let mut attributes = lock.attributes().unwrap();
+/// attributes.set_signals(false);
+/// lock.set_attributes(&attributes).unwrap();
+/// ```
+#[derive(Clone)]
+pub struct TerminalAttributes(pub(crate) imp::TerminalAttributes);
+
+impl TerminalAttributes {
+    /// Whether input typed into the terminal is echoed back.
+    pub fn echo(&self) -> bool {
+        self.0.echo()
+    }
+
+    /// Enables or disables echo.
+    ///
+    /// ### Windows
+    /// Echo is only available together with canonical mode.
+    pub fn set_echo(&mut self, enabled: bool) {
+        self.0.set_echo(enabled)
+    }
+
+    /// Whether input is only made available line by line (i.e. after a newline character).
+    pub fn canonical(&self) -> bool {
+        self.0.canonical()
+    }
+
+    /// Enables or disables canonical mode.
+    pub fn set_canonical(&mut self, enabled: bool) {
+        self.0.set_canonical(enabled)
+    }
+
+    /// Whether special characters such as Ctrl+C generate signals.
+    pub fn signals(&self) -> bool {
+        self.0.signals()
+    }
+
+    /// Enables or disables generating signals for special characters.
+    pub fn set_signals(&mut self, enabled: bool) {
+        self.0.set_signals(enabled)
+    }
+
+    /// Whether XON/XOFF (software) flow control is enabled (`IXON`, `IXOFF`):
+    /// Output is stopped by Ctrl+S and restarted by Ctrl+Q and (on serial lines)
+    /// the terminal sends these characters itself when its input queue is about to overflow.
+    ///
+    /// ### Windows
+    /// This is always `false`.
+    pub fn flow_control(&self) -> bool {
+        self.0.flow_control()
+    }
+
+    /// Enables or disables XON/XOFF (software) flow control. Disabling flow control
+    /// makes Ctrl+S and Ctrl+Q available as regular input.
+    ///
+    /// ### Windows
+    /// This does nothing.
+    pub fn set_flow_control(&mut self, enabled: bool) {
+        self.0.set_flow_control(enabled)
+    }
+
+    /// Whether VT sequences written to the terminal are processed.
+    ///
+    /// ### Unix
+    /// This is always `true`.
+    pub fn virtual_terminal_processing(&self) -> bool {
+        self.0.virtual_terminal_processing()
+    }
+
+    /// Enables or disables processing of VT sequences.
+    ///
+    /// ### Unix
+    /// This does nothing.
+    pub fn set_virtual_terminal_processing(&mut self, enabled: bool) {
+        self.0.set_virtual_terminal_processing(enabled)
+    }
+
+    /// The character that triggers the given [`ControlChar`] or [`None`] if it's disabled.
+    ///
+    /// ### Windows
+    /// The characters are fixed: Ctrl+C interrupts, Ctrl+Z ends the input
+    /// and backspace erases. There's no character for suspending.
+    pub fn control_char(&self, control_char: ControlChar) -> Option<u8> {
+        self.0.control_char(control_char)
+    }
+
+    /// Sets the character that triggers the given [`ControlChar`] or disables it if [`None`].
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use terminal_trx::{terminal, ControlChar};
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// let mut lock = tty.lock();
+    /// let mut attributes = lock.attributes().unwrap();
+    /// // Interrupt using Ctrl+G, so that Ctrl+C can be read as input.
+    /// attributes.set_control_char(ControlChar::Interrupt, Some(0x07));
+    /// lock.set_attributes(&attributes).unwrap();
+    /// ```
+    ///
+    /// ### Windows
+    /// This does nothing.
+    pub fn set_control_char(&mut self, control_char: ControlChar, value: Option<u8>) {
+        self.0.set_control_char(control_char, value)
+    }
+
+    /// The names of the platform's mode flags (e.g. `ECHO` on Unix or `ENABLE_ECHO_INPUT` on Windows)
+    /// together with whether they're set.
+    pub fn flags(&self) -> Vec<(&'static str, bool)> {
+        self.0.flags()
+    }
+}
+
+/// A special character recognized by the terminal, see [`TerminalAttributes::control_char`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ControlChar {
+    /// Sends `SIGINT` when signals are enabled (`VINTR`, Ctrl+C by default).
+    Interrupt,
+    /// Ends the input in canonical mode (`VEOF`, Ctrl+D by default).
+    EndOfFile,
+    /// Sends `SIGTSTP` when signals are enabled (`VSUSP`, Ctrl+Z by default).
+    Suspend,
+    /// Erases the previous character in canonical mode (`VERASE`, usually backspace).
+    Erase,
+}
+
+/// Serial line settings, these only have an effect on terminals that are serial devices (e.g. `/dev/ttyUSB0`).
+#[cfg(all(unix, not(terminal_trx_test_unsupported)))]
+#[cfg_attr(docsrs, doc(cfg(unix)))]
+impl TerminalAttributes {
+    /// The (output) baud rate or [`None`] if the rate is not one of the standard rates.
+    /// A baud rate of zero means that the modem line is hung up.
+    pub fn baud_rate(&self) -> Option<u32> {
+        self.0.baud_rate()
+    }
+
+    /// Sets both the input and output baud rate.
+    ///
+    /// Returns an [`Err`] with [`ErrorKind::InvalidInput`](`std::io::ErrorKind::InvalidInput`)
+    /// if the rate is not one of the standard rates (e.g. 9600 or 115200).
+    pub fn set_baud_rate(&mut self, baud_rate: u32) -> std::io::Result<()> {
+        self.0.set_baud_rate(baud_rate)
+    }
+
+    /// Whether a parity bit is generated for output and checked on input (`PARENB`, `PARODD`).
+    pub fn parity(&self) -> Parity {
+        self.0.parity()
+    }
+
+    /// Sets the parity.
+    pub fn set_parity(&mut self, parity: Parity) {
+        self.0.set_parity(parity)
+    }
+
+    /// The number of stop bits (`CSTOPB`).
+    pub fn stop_bits(&self) -> StopBits {
+        self.0.stop_bits()
+    }
+
+    /// Sets the number of stop bits.
+    pub fn set_stop_bits(&mut self, stop_bits: StopBits) {
+        self.0.set_stop_bits(stop_bits)
+    }
+
+    /// Whether RTS/CTS (hardware) flow control is enabled (`CRTSCTS`).
+    pub fn hardware_flow_control(&self) -> bool {
+        self.0.hardware_flow_control()
+    }
+
+    /// Enables or disables RTS/CTS (hardware) flow control.
+    /// See [`TerminalAttributes::set_flow_control`] for XON/XOFF (software) flow control.
+    pub fn set_hardware_flow_control(&mut self, enabled: bool) {
+        self.0.set_hardware_flow_control(enabled)
+    }
+}
+
+/// The parity of a serial line, see [`TerminalAttributes::parity`].
+#[cfg(all(unix, not(terminal_trx_test_unsupported)))]
+#[cfg_attr(docsrs, doc(cfg(unix)))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Parity {
+    /// No parity bit.
+    None,
+    /// Even parity.
+    Even,
+    /// Odd parity.
+    Odd,
+}
+
+/// The number of stop bits of a serial line, see [`TerminalAttributes::stop_bits`].
+#[cfg(all(unix, not(terminal_trx_test_unsupported)))]
+#[cfg_attr(docsrs, doc(cfg(unix)))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum StopBits {
+    /// One stop bit.
+    One,
+    /// Two stop bits.
+    Two,
+}
+
+impl fmt::Debug for TerminalAttributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TerminalAttributes")
+            .field("echo", &self.echo())
+            .field("canonical", &self.canonical())
+            .field("signals", &self.signals())
+            .field(
+                "virtual_terminal_processing",
+                &self.virtual_terminal_processing(),
+            )
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,125 @@
+use crate::{identity, query, RawModeGuard, TerminalIdentity};
+use std::env;
+use std::io;
+
+/// The colors a terminal is able to display, see [`TerminalLock::color_support`](`crate::TerminalLock::color_support`).
+///
+/// Variants are ordered by the number of colors, so that e.g. `support >= ColorSupport::Ansi256` can be used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum ColorSupport {
+    /// The 16 ANSI colors (`SGR 30–37`, `SGR 90–97` and their background counterparts).
+    Ansi16,
+    /// The 256-color palette (`SGR 38;5;<index>`).
+    Ansi256,
+    /// 24-bit colors (`SGR 38;2;<r>;<g>;<b>`).
+    TrueColor,
+}
+
+impl ColorSupport {
+    /// Detects the color support using environment variables (such as `COLORTERM` and `TERM`) only.
+    ///
+    /// Like [`TerminalIdentity::from_env`], this doesn't talk to the terminal,
+    /// but environment variables are easily inherited by processes that run in a different terminal.
+    pub fn from_env() -> Self {
+        from_env_vars(|name| env::var(name).ok())
+    }
+}
+
+/// Combines the proof found by querying the terminal with the environment.
+/// Falls back to the environment if the terminal doesn't answer.
+pub(crate) fn detect(raw_mode: &mut RawModeGuard<'_>) -> io::Result<ColorSupport> {
+    let from_env = ColorSupport::from_env();
+    match query::color_support(raw_mode) {
+        Ok(queried) => Ok(queried.map_or(from_env, |queried| queried.max(from_env))),
+        Err(error) if query::is_unanswered(&error) => Ok(from_env),
+        Err(error) => Err(error),
+    }
+}
+
+fn from_env_vars(var: impl Fn(&str) -> Option<String>) -> ColorSupport {
+    if matches!(var("COLORTERM").as_deref(), Some("truecolor" | "24bit")) {
+        return ColorSupport::TrueColor;
+    }
+
+    let term = var("TERM").unwrap_or_default();
+    if term.ends_with("-direct") {
+        return ColorSupport::TrueColor;
+    }
+
+    match identity::from_env_vars(&var) {
+        TerminalIdentity::Alacritty
+        | TerminalIdentity::Contour
+        | TerminalIdentity::Foot
+        | TerminalIdentity::Ghostty
+        | TerminalIdentity::ITerm2
+        | TerminalIdentity::Kitty
+        | TerminalIdentity::Konsole
+        | TerminalIdentity::Mintty
+        | TerminalIdentity::Vte
+        | TerminalIdentity::VsCode
+        | TerminalIdentity::WezTerm
+        | TerminalIdentity::WindowsTerminal => ColorSupport::TrueColor,
+        TerminalIdentity::AppleTerminal => ColorSupport::Ansi256,
+        TerminalIdentity::LinuxConsole => ColorSupport::Ansi16,
+        // Multiplexers and xterm support whatever TERM advertises.
+        _ if term.contains("256color") => ColorSupport::Ansi256,
+        _ => ColorSupport::Ansi16,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_vars(vars: &[(&str, &str)]) -> ColorSupport {
+        from_env_vars(|name| {
+            vars.iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, value)| (*value).to_owned())
+        })
+    }
+
+    #[test]
+    fn colorterm_indicates_true_color() {
+        assert_eq!(
+            ColorSupport::TrueColor,
+            from_vars(&[("COLORTERM", "truecolor"), ("TERM", "xterm")])
+        );
+        assert_eq!(
+            ColorSupport::TrueColor,
+            from_vars(&[("TERM", "xterm-direct")])
+        );
+    }
+
+    #[test]
+    fn term_suggests_palette_size() {
+        assert_eq!(
+            ColorSupport::Ansi256,
+            from_vars(&[("TERM", "screen-256color"), ("STY", "1234.pts-0")])
+        );
+        assert_eq!(ColorSupport::Ansi16, from_vars(&[("TERM", "xterm")]));
+        assert_eq!(ColorSupport::Ansi16, from_vars(&[]));
+    }
+
+    #[test]
+    fn known_terminals_support_true_color() {
+        assert_eq!(
+            ColorSupport::TrueColor,
+            from_vars(&[("TERM", "xterm-kitty")])
+        );
+        assert_eq!(
+            ColorSupport::Ansi256,
+            from_vars(&[
+                ("TERM_PROGRAM", "Apple_Terminal"),
+                ("TERM", "xterm-256color")
+            ])
+        );
+    }
+
+    #[test]
+    fn variants_are_ordered_by_number_of_colors() {
+        assert!(ColorSupport::Ansi16 < ColorSupport::Ansi256);
+        assert!(ColorSupport::Ansi256 < ColorSupport::TrueColor);
+    }
+}
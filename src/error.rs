@@ -0,0 +1,108 @@
+use std::{error, fmt, io};
+
+/// The error type returned when retrieving the terminal or enabling raw mode.
+///
+/// It is convertible to [`io::Error`] (and back), so `?` can be used in functions returning [`io::Result`].
+///
+/// ## Example
+/// ```no_run
+/// use terminal_trx::{terminal, Error};
+///
+/// match terminal() {
+///     Ok(terminal) => { /* ... */ }
+///     Err(Error::NotATty) => eprintln!("not running in a terminal"),
+///     Err(error) => panic!("failed to open terminal: {error}"),
+/// }
+/// ```
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The process has no terminal (Unix) or console (Windows).
+    NotATty,
+    /// The operation is not supported on this platform or terminal.
+    Unsupported {
+        /// Describes what is not supported.
+        reason: &'static str,
+    },
+    /// Any other I/O error.
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotATty => write!(f, "no terminal is available"),
+            Error::Unsupported { reason } => write!(f, "unsupported: {reason}"),
+            Error::Io(error) => error.fmt(f),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    /// Wraps the I/O error in [`Error::Io`] unless it was created from an [`Error`],
+    /// in which case the original error is returned.
+    fn from(error: io::Error) -> Self {
+        if !error.get_ref().is_some_and(|e| e.is::<Error>()) {
+            return Error::Io(error);
+        }
+        let kind = error.kind();
+        match error.into_inner().map(|e| e.downcast::<Error>()) {
+            Some(Ok(error)) => *error,
+            Some(Err(error)) => Error::Io(io::Error::new(kind, error)),
+            None => Error::Io(kind.into()),
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Io(error) => error,
+            Error::Unsupported { .. } => io::Error::new(io::ErrorKind::Unsupported, error),
+            Error::NotATty => io::Error::new(io::ErrorKind::Other, error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_through_io_error() {
+        let error = io::Error::from(Error::NotATty);
+        assert!(matches!(Error::from(error), Error::NotATty));
+    }
+
+    #[test]
+    fn io_error_is_not_wrapped_twice() {
+        let error = Error::from(io::Error::from(io::ErrorKind::TimedOut));
+        let error = io::Error::from(error);
+        assert_eq!(io::ErrorKind::TimedOut, error.kind());
+        assert!(error.get_ref().is_none());
+    }
+
+    #[test]
+    fn io_error_is_the_source() {
+        use std::error::Error as _;
+
+        let error = Error::Io(io::Error::from(io::ErrorKind::TimedOut));
+        let source = error.source().and_then(|e| e.downcast_ref::<io::Error>());
+        assert_eq!(Some(io::ErrorKind::TimedOut), source.map(|e| e.kind()));
+    }
+
+    #[test]
+    fn unsupported_has_unsupported_kind() {
+        let error = io::Error::from(Error::Unsupported { reason: "test" });
+        assert_eq!(io::ErrorKind::Unsupported, error.kind());
+    }
+}
@@ -0,0 +1,331 @@
+//! Decodes the input read from the terminal into [`Event`]s such as key presses, mouse events or pastes.
+//!
+//! Use [`TerminalLock::read_event`](`crate::TerminalLock::read_event`) or
+//! [`RawModeGuard::read_event`](`crate::RawModeGuard::read_event`) to read events.
+//!
+//! The following input is recognized:
+//! * UTF-8 encoded text and control characters (e.g. `Ctrl+A`),
+//! * `CSI` and `SS3` sequences sent for special keys (e.g. arrow keys, function keys) including modifiers,
+//! * keys reported using the [kitty keyboard protocol](https://sw.kovidgoyal.net/kitty/keyboard-protocol/),
+//! * SGR mouse events (mode `1006`) and legacy (X10) mouse events,
+//! * bracketed paste (mode `2004`),
+//! * focus events (mode `1004`).
+//!
+//! Note that mouse reporting, bracketed paste and focus events need to be enabled by the application.
+//!
+//! ## Escape Key
+//! The escape key sends a lone `ESC` byte, which is also the start of most escape sequences.
+//! When an incomplete sequence is read, the rest is waited for up to the escape timeout
+//! (100ms by default, see [`ReadEventOptions::escape_timeout`]) before the input is reported
+//! as separate key presses.
+//!
+//! ## Example
+//! ```no_run
+//! use terminal_trx::events::{Event, KeyCode};
+//! use terminal_trx::terminal;
+//!
+//! let tty = terminal().unwrap();
+//! let mut lock = tty.lock();
+//! let mut raw_mode = lock.enable_raw_mode().unwrap();
+//! loop {
+//!     match raw_mode.read_event(None).unwrap() {
+//!         Some(Event::Key(key)) if key.code() == KeyCode::Char('q') => break,
+//!         Some(event) => println!("{event:?}\r"),
+//!         None => {}
+//!     }
+//! }
+//! ```
+
+use crate::RawModeGuard;
+use std::io::{self, Read as _};
+use std::time::{Duration, Instant};
+
+mod parser;
+use parser::Parsed;
+
+/// An event decoded from the terminal's input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Event {
+    /// A key was pressed (or released, if reported by the terminal).
+    Key(KeyEvent),
+    /// A mouse button was pressed or released, the mouse was moved or the wheel was scrolled.
+    Mouse(MouseEvent),
+    /// Text was pasted (requires bracketed paste).
+    Paste(String),
+    /// The terminal gained (`true`) or lost (`false`) focus.
+    Focus(bool),
+    /// Input that could not be decoded, e.g. an unsupported escape sequence or invalid UTF-8.
+    Unknown(Vec<u8>),
+}
+
+/// A key event, see [`Event::Key`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeyEvent {
+    code: KeyCode,
+    modifiers: u8,
+    key_down: bool,
+}
+
+impl KeyEvent {
+    /// The key.
+    pub fn code(&self) -> KeyCode {
+        self.code
+    }
+
+    /// Returns `true` if the key was pressed (or repeated) and `false` if it was released.
+    /// Releases are only reported by terminals using the kitty keyboard protocol.
+    pub fn is_key_down(&self) -> bool {
+        self.key_down
+    }
+
+    /// Returns `true` if shift is held down.
+    ///
+    /// Note that shift is usually not reported for characters, instead
+    /// the shifted character (e.g. `A` instead of `a`) is reported.
+    pub fn shift(&self) -> bool {
+        self.modifiers & parser::SHIFT != 0
+    }
+
+    /// Returns `true` if alt (or meta) is held down.
+    pub fn alt(&self) -> bool {
+        self.modifiers & parser::ALT != 0
+    }
+
+    /// Returns `true` if control is held down.
+    pub fn ctrl(&self) -> bool {
+        self.modifiers & parser::CTRL != 0
+    }
+}
+
+/// A key, see [`KeyEvent::code`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum KeyCode {
+    /// A key producing a character. Control characters are reported as
+    /// the corresponding character with control held down, e.g. `Ctrl+A` is `Char('a')`.
+    Char(char),
+    /// Enter (or return).
+    Enter,
+    /// Tab, reported together with shift for back tab.
+    Tab,
+    /// Backspace.
+    Backspace,
+    /// Escape.
+    Esc,
+    /// Arrow up.
+    Up,
+    /// Arrow down.
+    Down,
+    /// Arrow left.
+    Left,
+    /// Arrow right.
+    Right,
+    /// Home.
+    Home,
+    /// End.
+    End,
+    /// Page up.
+    PageUp,
+    /// Page down.
+    PageDown,
+    /// Insert.
+    Insert,
+    /// Delete.
+    Delete,
+    /// A function key, e.g. `F(1)` for F1.
+    F(u8),
+}
+
+/// A mouse event, see [`Event::Mouse`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MouseEvent {
+    kind: MouseEventKind,
+    column: u16,
+    row: u16,
+    modifiers: u8,
+}
+
+impl MouseEvent {
+    /// What happened.
+    pub fn kind(&self) -> MouseEventKind {
+        self.kind
+    }
+
+    /// The (zero-based) column of the mouse cursor.
+    pub fn column(&self) -> u16 {
+        self.column
+    }
+
+    /// The (zero-based) row of the mouse cursor.
+    pub fn row(&self) -> u16 {
+        self.row
+    }
+
+    /// Returns `true` if shift is held down.
+    pub fn shift(&self) -> bool {
+        self.modifiers & parser::SHIFT != 0
+    }
+
+    /// Returns `true` if alt (or meta) is held down.
+    pub fn alt(&self) -> bool {
+        self.modifiers & parser::ALT != 0
+    }
+
+    /// Returns `true` if control is held down.
+    pub fn ctrl(&self) -> bool {
+        self.modifiers & parser::CTRL != 0
+    }
+}
+
+/// The kind of a mouse event, see [`MouseEvent::kind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum MouseEventKind {
+    /// A button was pressed.
+    Press(MouseButton),
+    /// A button was released. Legacy (X10) mouse events don't report which button was released.
+    Release(Option<MouseButton>),
+    /// The mouse was moved while a button was held down.
+    Drag(MouseButton),
+    /// The mouse was moved without a button being held down.
+    Moved,
+    /// The wheel was scrolled up.
+    ScrollUp,
+    /// The wheel was scrolled down.
+    ScrollDown,
+    /// The wheel was scrolled left.
+    ScrollLeft,
+    /// The wheel was scrolled right.
+    ScrollRight,
+}
+
+/// A mouse button, see [`MouseEventKind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum MouseButton {
+    /// The left button.
+    Left,
+    /// The middle button (or wheel).
+    Middle,
+    /// The right button.
+    Right,
+    /// An additional button (e.g. back or forward), numbered from `8` like in xterm.
+    Other(u8),
+}
+
+/// Options for reading events.
+/// Used with [`TerminalLock::read_event_with`](`crate::TerminalLock::read_event_with`).
+///
+/// ## Example
+/// ```no_run
+/// use std::time::Duration;
+/// use terminal_trx::events::ReadEventOptions;
+/// use terminal_trx::terminal;
+///
+/// let mut tty = terminal().unwrap();
+/// let mut lock = tty.lock();
+/// // Connections with a high latency (e.g. SSH) may need more time for sequences to arrive.
+/// let options = ReadEventOptions::new().escape_timeout(Duration::from_millis(300));
+/// let event = lock.read_event_with(options).unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadEventOptions {
+    timeout: Option<Duration>,
+    escape_timeout: Duration,
+}
+
+impl Default for ReadEventOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReadEventOptions {
+    /// Creates the default options.
+    pub const fn new() -> Self {
+        ReadEventOptions {
+            timeout: None,
+            escape_timeout: Duration::from_millis(100),
+        }
+    }
+
+    /// How long to wait for an event. Defaults to [`None`], which waits indefinitely.
+    pub const fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How long to wait for the rest of an incomplete escape sequence before
+    /// the input is reported as separate key presses (e.g. a lone escape key). Defaults to 100ms.
+    ///
+    /// Bracketed pastes are always waited for until they're complete (or the [`timeout`](`Self::timeout`) elapses).
+    pub const fn escape_timeout(mut self, escape_timeout: Duration) -> Self {
+        self.escape_timeout = escape_timeout;
+        self
+    }
+}
+
+const READ_BUFFER_SIZE: usize = 256;
+
+/// Reads events until a key is pressed, discarding all other events.
+pub(crate) fn read_key(raw_mode: &mut RawModeGuard<'_>) -> io::Result<KeyEvent> {
+    loop {
+        if let Some(Event::Key(key)) = read_event(raw_mode, ReadEventOptions::new())? {
+            if key.is_key_down() {
+                return Ok(key);
+            }
+        }
+    }
+}
+
+/// Reads input until a complete event is decoded or the timeout elapses.
+/// Input following the event is pushed back to the input buffer.
+pub(crate) fn read_event(
+    raw_mode: &mut RawModeGuard<'_>,
+    options: ReadEventOptions,
+) -> io::Result<Option<Event>> {
+    let deadline = options.timeout.map(|t| Instant::now() + t);
+    let mut input = Vec::new();
+    let mut buf = [0; READ_BUFFER_SIZE];
+    loop {
+        if !input.is_empty() {
+            if let Parsed::Event(event, len) = parser::parse(&input) {
+                raw_mode.unread(&input[len..]);
+                return Ok(Some(event));
+            }
+        }
+
+        // Incomplete escape sequences are only given a short time to complete
+        // (unless they're a paste), so that the escape key is reported promptly.
+        let escape_deadline = (!input.is_empty() && !parser::is_paste(&input))
+            .then(|| Instant::now() + options.escape_timeout);
+        let deadline = [deadline, escape_deadline].into_iter().flatten().min();
+        let remaining = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+        if remaining.is_some_and(|r| r.is_zero()) {
+            return Ok(finish(raw_mode, &input));
+        }
+
+        raw_mode.set_read_behavior(u8::from(remaining.is_none()), remaining)?;
+        match raw_mode.read(&mut buf) {
+            Ok(0) if remaining.is_none() => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(0) => {
+                if escape_deadline.is_some() && escape_deadline == deadline {
+                    return Ok(finish(raw_mode, &input));
+                }
+            }
+            Ok(len) => input.extend_from_slice(&buf[..len]),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn finish(raw_mode: &mut RawModeGuard<'_>, input: &[u8]) -> Option<Event> {
+    if input.is_empty() {
+        return None;
+    }
+    let (event, len) = parser::parse_incomplete(input);
+    raw_mode.unread(&input[len..]);
+    Some(event)
+}
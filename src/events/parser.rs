@@ -0,0 +1,521 @@
+use super::{Event, KeyCode, KeyEvent, MouseButton, MouseEvent, MouseEventKind};
+
+// The modifier bits used by xterm (after subtracting one) and the kitty keyboard protocol.
+pub(super) const SHIFT: u8 = 1;
+pub(super) const ALT: u8 = 2;
+pub(super) const CTRL: u8 = 4;
+
+const ESC: u8 = 0x1b;
+const PASTE_START: &[u8] = b"\x1b[200~";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
+#[derive(Debug, PartialEq, Eq)]
+pub(super) enum Parsed {
+    /// An event and the number of bytes it was decoded from.
+    Event(Event, usize),
+    /// More input is needed to decode the event.
+    Incomplete,
+}
+
+/// Decodes the first event from the (non-empty) input.
+pub(super) fn parse(input: &[u8]) -> Parsed {
+    match input {
+        [] => Parsed::Incomplete,
+        [ESC] => Parsed::Incomplete,
+        [ESC, b'[', ..] => parse_csi(input),
+        [ESC, b'O'] => Parsed::Incomplete,
+        [ESC, b'O', final_byte, ..] => Parsed::Event(parse_ss3(*final_byte), 3),
+        // A second escape can't be the start of an alt-modified key.
+        [ESC, ESC, ..] => key(KeyCode::Esc, 0, 1),
+        [ESC, rest @ ..] => match parse(rest) {
+            Parsed::Event(Event::Key(key), len) => Parsed::Event(
+                Event::Key(KeyEvent {
+                    modifiers: key.modifiers | ALT,
+                    ..key
+                }),
+                len + 1,
+            ),
+            Parsed::Event(..) => key(KeyCode::Esc, 0, 1),
+            Parsed::Incomplete => Parsed::Incomplete,
+        },
+        [byte, ..] if byte.is_ascii() => {
+            let (code, modifiers) = control_or_ascii(*byte);
+            key(code, modifiers, 1)
+        }
+        _ => parse_utf8(input),
+    }
+}
+
+/// Decodes the first event from input that won't be completed (e.g. because a timeout elapsed).
+pub(super) fn parse_incomplete(input: &[u8]) -> (Event, usize) {
+    match parse(input) {
+        Parsed::Event(event, len) => (event, len),
+        Parsed::Incomplete => match input {
+            [ESC] => (key_event(KeyCode::Esc, 0), 1),
+            // e.g. Alt+[ or Alt+O
+            [ESC, byte] if byte.is_ascii() => {
+                let (code, modifiers) = control_or_ascii(*byte);
+                (key_event(code, modifiers | ALT), 2)
+            }
+            _ => (Event::Unknown(input.to_vec()), input.len()),
+        },
+    }
+}
+
+/// Returns `true` if the input starts with an (incomplete) bracketed paste.
+pub(super) fn is_paste(input: &[u8]) -> bool {
+    input.starts_with(PASTE_START)
+}
+
+fn control_or_ascii(byte: u8) -> (KeyCode, u8) {
+    match byte {
+        b'\r' | b'\n' => (KeyCode::Enter, 0),
+        b'\t' => (KeyCode::Tab, 0),
+        0x7f | 0x08 => (KeyCode::Backspace, 0),
+        ESC => (KeyCode::Esc, 0),
+        0x00 => (KeyCode::Char(' '), CTRL),
+        0x01..=0x1a => (KeyCode::Char(char::from(b'a' + byte - 1)), CTRL),
+        0x1c..=0x1f => (KeyCode::Char(char::from(b'4' + byte - 0x1c)), CTRL),
+        _ => (KeyCode::Char(char::from(byte)), 0),
+    }
+}
+
+fn parse_utf8(input: &[u8]) -> Parsed {
+    let len = match input[0] {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return Parsed::Event(Event::Unknown(input[..1].to_vec()), 1),
+    };
+    let Some(bytes) = input.get(..len) else {
+        // Only wait for the rest if what we have so far is valid.
+        return match input[1..].iter().all(|b| b & 0xc0 == 0x80) {
+            true => Parsed::Incomplete,
+            false => Parsed::Event(Event::Unknown(input[..1].to_vec()), 1),
+        };
+    };
+    match std::str::from_utf8(bytes)
+        .ok()
+        .and_then(|s| s.chars().next())
+    {
+        Some(c) => key(KeyCode::Char(c), 0, len),
+        None => Parsed::Event(Event::Unknown(input[..1].to_vec()), 1),
+    }
+}
+
+fn parse_ss3(final_byte: u8) -> Event {
+    match cursor_key(final_byte) {
+        Some(code) => key_event(code, 0),
+        None => Event::Unknown(vec![ESC, b'O', final_byte]),
+    }
+}
+
+fn parse_csi(input: &[u8]) -> Parsed {
+    if input.starts_with(PASTE_START) {
+        return parse_paste(input);
+    }
+
+    // CSI P...P I...I F: parameter bytes, intermediate bytes and the final byte.
+    let body = &input[2..];
+    let Some(final_index) = body.iter().position(|b| !(0x20..=0x3f).contains(b)) else {
+        return Parsed::Incomplete;
+    };
+    // Any other byte (e.g. Ctrl+C) breaks off the sequence and is decoded on its own.
+    if !(0x40..=0x7e).contains(&body[final_index]) {
+        let len = 2 + final_index;
+        return Parsed::Event(Event::Unknown(input[..len].to_vec()), len);
+    }
+    let len = 2 + final_index + 1;
+    let params = &body[..final_index];
+    let final_byte = body[final_index];
+
+    if params.is_empty() && final_byte == b'M' {
+        return parse_x10_mouse(input);
+    }
+
+    let event = std::str::from_utf8(params)
+        .ok()
+        .and_then(|params| csi_event(params, final_byte));
+    Parsed::Event(
+        event.unwrap_or_else(|| Event::Unknown(input[..len].to_vec())),
+        len,
+    )
+}
+
+fn csi_event(params: &str, final_byte: u8) -> Option<Event> {
+    if let Some(params) = params.strip_prefix('<') {
+        return sgr_mouse(params, final_byte);
+    }
+    let mut params = params.split(';');
+    let first = params.next().unwrap_or_default();
+    let (modifiers, key_down) = modifiers_and_event_type(params.next())?;
+    match final_byte {
+        b'I' if first.is_empty() => Some(Event::Focus(true)),
+        b'O' if first.is_empty() => Some(Event::Focus(false)),
+        b'Z' => Some(key_event(KeyCode::Tab, modifiers | SHIFT)),
+        b'~' => {
+            let code = tilde_key(first.parse().ok()?)?;
+            Some(key_event_with_state(code, modifiers, key_down))
+        }
+        b'u' => {
+            // Alternate key codes (e.g. the shifted key) are separated by colons.
+            let code = first.split(':').next()?.parse().ok()?;
+            let code = kitty_key(code)?;
+            Some(key_event_with_state(code, modifiers, key_down))
+        }
+        _ => {
+            let code = cursor_key(final_byte)?;
+            Some(key_event_with_state(code, modifiers, key_down))
+        }
+    }
+}
+
+/// Parses the modifier parameter (`1 + bits`) which may be followed by the kitty event type
+/// (`1` for press, `2` for repeat and `3` for release).
+fn modifiers_and_event_type(param: Option<&str>) -> Option<(u8, bool)> {
+    let Some(param) = param.filter(|p| !p.is_empty()) else {
+        return Some((0, true));
+    };
+    let (modifiers, event_type) = param.split_once(':').unwrap_or((param, "1"));
+    let modifiers = modifiers.parse::<u8>().ok()?.saturating_sub(1);
+    Some((modifiers, event_type != "3"))
+}
+
+fn cursor_key(final_byte: u8) -> Option<KeyCode> {
+    match final_byte {
+        b'A' => Some(KeyCode::Up),
+        b'B' => Some(KeyCode::Down),
+        b'C' => Some(KeyCode::Right),
+        b'D' => Some(KeyCode::Left),
+        b'H' => Some(KeyCode::Home),
+        b'F' => Some(KeyCode::End),
+        b'P' => Some(KeyCode::F(1)),
+        b'Q' => Some(KeyCode::F(2)),
+        b'R' => Some(KeyCode::F(3)),
+        b'S' => Some(KeyCode::F(4)),
+        _ => None,
+    }
+}
+
+fn tilde_key(number: u8) -> Option<KeyCode> {
+    match number {
+        1 | 7 => Some(KeyCode::Home),
+        2 => Some(KeyCode::Insert),
+        3 => Some(KeyCode::Delete),
+        4 | 8 => Some(KeyCode::End),
+        5 => Some(KeyCode::PageUp),
+        6 => Some(KeyCode::PageDown),
+        11..=15 => Some(KeyCode::F(number - 10)),
+        17..=21 => Some(KeyCode::F(number - 11)),
+        23..=26 => Some(KeyCode::F(number - 12)),
+        28 | 29 => Some(KeyCode::F(number - 13)),
+        31..=34 => Some(KeyCode::F(number - 14)),
+        _ => None,
+    }
+}
+
+fn kitty_key(code: u32) -> Option<KeyCode> {
+    match code {
+        13 => Some(KeyCode::Enter),
+        9 => Some(KeyCode::Tab),
+        127 => Some(KeyCode::Backspace),
+        27 => Some(KeyCode::Esc),
+        // Other functional keys (e.g. keypad keys) are in the private use area.
+        code => char::from_u32(code)
+            .filter(|c| !('\u{e000}'..='\u{f8ff}').contains(c))
+            .map(KeyCode::Char),
+    }
+}
+
+fn parse_paste(input: &[u8]) -> Parsed {
+    let content = &input[PASTE_START.len()..];
+    match content
+        .windows(PASTE_END.len())
+        .position(|w| w == PASTE_END)
+    {
+        Some(end) => Parsed::Event(
+            Event::Paste(String::from_utf8_lossy(&content[..end]).into_owned()),
+            PASTE_START.len() + end + PASTE_END.len(),
+        ),
+        None => Parsed::Incomplete,
+    }
+}
+
+/// `CSI < button ; column ; row M` for presses and `m` for releases, coordinates are one-based.
+fn sgr_mouse(params: &str, final_byte: u8) -> Option<Event> {
+    let mut params = params.split(';').map(|p| p.parse::<u16>().ok());
+    let (button, column, row) = (params.next()??, params.next()??, params.next()??);
+    let release = match final_byte {
+        b'M' => false,
+        b'm' => true,
+        _ => return None,
+    };
+    let button = u8::try_from(button).ok()?;
+    Some(mouse_event(
+        button,
+        release,
+        column.saturating_sub(1),
+        row.saturating_sub(1),
+    ))
+}
+
+/// `CSI M button column row` where each value is a single byte offset by 32.
+fn parse_x10_mouse(input: &[u8]) -> Parsed {
+    match input.get(3..6) {
+        Some(&[button, column, row]) => {
+            let button = button.saturating_sub(32);
+            // Releases are reported as button 3 without saying which button was released.
+            let release = button & 0b11 == 3 && button & 0b0110_0000 == 0;
+            Parsed::Event(
+                mouse_event(
+                    button,
+                    release,
+                    u16::from(column.saturating_sub(33)),
+                    u16::from(row.saturating_sub(33)),
+                ),
+                6,
+            )
+        }
+        _ => Parsed::Incomplete,
+    }
+}
+
+fn mouse_event(button: u8, release: bool, column: u16, row: u16) -> Event {
+    const MOTION: u8 = 0b0010_0000;
+    const WHEEL: u8 = 0b0100_0000;
+    const EXTRA: u8 = 0b1000_0000;
+
+    let modifiers = (button >> 2) & (SHIFT | ALT | CTRL);
+    let number = button & 0b11;
+    let motion = button & MOTION != 0;
+    let pressed_button = match (button & (WHEEL | EXTRA), number) {
+        (0, 0) => Some(MouseButton::Left),
+        (0, 1) => Some(MouseButton::Middle),
+        (0, 2) => Some(MouseButton::Right),
+        (EXTRA, n) => Some(MouseButton::Other(8 + n)),
+        _ => None,
+    };
+    let kind = match (button & WHEEL != 0, pressed_button) {
+        (true, _) => match number {
+            0 => MouseEventKind::ScrollUp,
+            1 => MouseEventKind::ScrollDown,
+            2 => MouseEventKind::ScrollLeft,
+            _ => MouseEventKind::ScrollRight,
+        },
+        (false, button) if release => MouseEventKind::Release(button),
+        (false, Some(button)) if motion => MouseEventKind::Drag(button),
+        (false, Some(button)) => MouseEventKind::Press(button),
+        (false, None) => MouseEventKind::Moved,
+    };
+    Event::Mouse(MouseEvent {
+        kind,
+        column,
+        row,
+        modifiers,
+    })
+}
+
+fn key(code: KeyCode, modifiers: u8, len: usize) -> Parsed {
+    Parsed::Event(key_event(code, modifiers), len)
+}
+
+fn key_event(code: KeyCode, modifiers: u8) -> Event {
+    key_event_with_state(code, modifiers, true)
+}
+
+fn key_event_with_state(code: KeyCode, modifiers: u8, key_down: bool) -> Event {
+    Event::Key(KeyEvent {
+        code,
+        modifiers,
+        key_down,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: u8, len: usize) -> Parsed {
+        super::key(code, modifiers, len)
+    }
+
+    #[test]
+    fn parses_text() {
+        assert_eq!(key(KeyCode::Char('a'), 0, 1), parse(b"ab"));
+        assert_eq!(key(KeyCode::Char('ä'), 0, 2), parse("ä".as_bytes()));
+        assert_eq!(key(KeyCode::Char('🦀'), 0, 4), parse("🦀".as_bytes()));
+    }
+
+    #[test]
+    fn waits_for_rest_of_utf8_sequence() {
+        assert_eq!(Parsed::Incomplete, parse(&"🦀".as_bytes()[..3]));
+    }
+
+    #[test]
+    fn invalid_utf8_is_unknown() {
+        assert_eq!(
+            Parsed::Event(Event::Unknown(vec![0xff]), 1),
+            parse(b"\xffa")
+        );
+        assert_eq!(
+            Parsed::Event(Event::Unknown(vec![0xc3]), 1),
+            parse(b"\xc3a")
+        );
+    }
+
+    #[test]
+    fn parses_control_characters() {
+        assert_eq!(key(KeyCode::Enter, 0, 1), parse(b"\r"));
+        assert_eq!(key(KeyCode::Backspace, 0, 1), parse(b"\x7f"));
+        assert_eq!(key(KeyCode::Char('c'), CTRL, 1), parse(b"\x03"));
+        assert_eq!(key(KeyCode::Char(' '), CTRL, 1), parse(b"\x00"));
+    }
+
+    #[test]
+    fn parses_alt_modified_keys() {
+        assert_eq!(key(KeyCode::Char('x'), ALT, 2), parse(b"\x1bx"));
+        assert_eq!(key(KeyCode::Char('c'), ALT | CTRL, 2), parse(b"\x1b\x03"));
+        assert_eq!(key(KeyCode::Esc, 0, 1), parse(b"\x1b\x1b[A"));
+    }
+
+    #[test]
+    fn lone_escape_is_incomplete() {
+        assert_eq!(Parsed::Incomplete, parse(b"\x1b"));
+        assert_eq!(Parsed::Incomplete, parse(b"\x1b["));
+        assert_eq!(Parsed::Incomplete, parse(b"\x1b[1;5"));
+        assert_eq!(Parsed::Incomplete, parse(b"\x1bO"));
+    }
+
+    #[test]
+    fn incomplete_input_is_flushed() {
+        assert_eq!((key_event(KeyCode::Esc, 0), 1), parse_incomplete(b"\x1b"));
+        assert_eq!(
+            (key_event(KeyCode::Char('['), ALT), 2),
+            parse_incomplete(b"\x1b[")
+        );
+        assert_eq!(
+            (Event::Unknown(b"\x1b[1;".to_vec()), 4),
+            parse_incomplete(b"\x1b[1;")
+        );
+    }
+
+    #[test]
+    fn parses_cursor_keys() {
+        assert_eq!(key(KeyCode::Up, 0, 3), parse(b"\x1b[A"));
+        assert_eq!(key(KeyCode::Left, 0, 3), parse(b"\x1bOD"));
+        assert_eq!(key(KeyCode::Right, CTRL, 6), parse(b"\x1b[1;5C"));
+        assert_eq!(key(KeyCode::Home, SHIFT, 6), parse(b"\x1b[1;2H"));
+        assert_eq!(key(KeyCode::F(1), 0, 3), parse(b"\x1bOP"));
+        assert_eq!(key(KeyCode::Tab, SHIFT, 3), parse(b"\x1b[Z"));
+    }
+
+    #[test]
+    fn parses_tilde_keys() {
+        assert_eq!(key(KeyCode::Delete, 0, 4), parse(b"\x1b[3~"));
+        assert_eq!(key(KeyCode::PageDown, ALT, 6), parse(b"\x1b[6;3~"));
+        assert_eq!(key(KeyCode::F(5), 0, 5), parse(b"\x1b[15~"));
+        assert_eq!(key(KeyCode::F(12), 0, 5), parse(b"\x1b[24~"));
+    }
+
+    #[test]
+    fn parses_kitty_keys() {
+        assert_eq!(key(KeyCode::Char('a'), CTRL, 7), parse(b"\x1b[97;5u"));
+        assert_eq!(key(KeyCode::Esc, 0, 5), parse(b"\x1b[27u"));
+        let Parsed::Event(Event::Key(release), 12) = parse(b"\x1b[97:65;2:3u") else {
+            panic!("expected key event");
+        };
+        assert_eq!(KeyCode::Char('a'), release.code());
+        assert!(release.shift());
+        assert!(!release.is_key_down());
+    }
+
+    #[test]
+    fn parses_focus_events() {
+        assert_eq!(Parsed::Event(Event::Focus(true), 3), parse(b"\x1b[I"));
+        assert_eq!(Parsed::Event(Event::Focus(false), 3), parse(b"\x1b[O"));
+    }
+
+    #[test]
+    fn parses_bracketed_paste() {
+        let input = b"\x1b[200~hello\x1b[Aworld\x1b[201~x";
+        assert_eq!(
+            Parsed::Event(Event::Paste("hello\x1b[Aworld".to_owned()), input.len() - 1),
+            parse(input)
+        );
+        assert_eq!(Parsed::Incomplete, parse(b"\x1b[200~hello"));
+        assert_eq!(Parsed::Incomplete, parse(b"\x1b[20"));
+        assert!(is_paste(b"\x1b[200~hello"));
+    }
+
+    #[test]
+    fn parses_sgr_mouse() {
+        let mouse = |kind, column, row, modifiers| {
+            Event::Mouse(MouseEvent {
+                kind,
+                column,
+                row,
+                modifiers,
+            })
+        };
+        assert_eq!(
+            Parsed::Event(mouse(MouseEventKind::Press(MouseButton::Left), 9, 4, 0), 10),
+            parse(b"\x1b[<0;10;5M")
+        );
+        assert_eq!(
+            Parsed::Event(
+                mouse(MouseEventKind::Release(Some(MouseButton::Right)), 0, 0, 0),
+                9
+            ),
+            parse(b"\x1b[<2;1;1m")
+        );
+        assert_eq!(
+            Parsed::Event(
+                mouse(MouseEventKind::Drag(MouseButton::Left), 0, 0, CTRL),
+                10
+            ),
+            parse(b"\x1b[<48;1;1M")
+        );
+        assert_eq!(
+            Parsed::Event(mouse(MouseEventKind::ScrollDown, 0, 0, 0), 10),
+            parse(b"\x1b[<65;1;1M")
+        );
+        assert_eq!(
+            Parsed::Event(mouse(MouseEventKind::Moved, 0, 0, 0), 10),
+            parse(b"\x1b[<35;1;1M")
+        );
+    }
+
+    #[test]
+    fn parses_x10_mouse() {
+        assert_eq!(
+            Parsed::Event(
+                Event::Mouse(MouseEvent {
+                    kind: MouseEventKind::Press(MouseButton::Middle),
+                    column: 0,
+                    row: 1,
+                    modifiers: 0,
+                }),
+                6
+            ),
+            parse(b"\x1b[M!!\"")
+        );
+        assert_eq!(Parsed::Incomplete, parse(b"\x1b[M!"));
+    }
+
+    #[test]
+    fn malformed_sequence_ends_before_breaking_byte() {
+        assert_eq!(
+            Parsed::Event(Event::Unknown(b"\x1b[1".to_vec()), 3),
+            parse(b"\x1b[1\x03abc")
+        );
+        assert_eq!(key(KeyCode::Char('c'), CTRL, 1), parse(b"\x03abc"));
+    }
+
+    #[test]
+    fn unsupported_sequences_are_unknown() {
+        assert_eq!(
+            Parsed::Event(Event::Unknown(b"\x1b[99x".to_vec()), 5),
+            parse(b"\x1b[99xa")
+        );
+    }
+}
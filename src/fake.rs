@@ -0,0 +1,412 @@
+use crate::{sealed, RawModeOptions, TerminalAttributes, Transceive};
+use cfg_if::cfg_if;
+use std::collections::VecDeque;
+use std::io;
+use std::ops::{Deref, DerefMut};
+
+/// An in-memory [`Transceive`] implementation for testing code that talks to the terminal.
+///
+/// Input is queued up front using [`FakeTerminal::push_input`] or in response to output
+/// using [`FakeTerminal::respond_to`]. Reads return `0` once all queued input has been consumed.
+/// Everything that's written is recorded and can be inspected using [`FakeTerminal::output`].
+///
+/// Mode changes made through [`FakeTerminal::enable_raw_mode`] and [`FakeTerminal::set_attributes`]
+/// are recorded and can be inspected using [`FakeTerminal::mode_changes`].
+///
+/// ### Unix
+/// The file descriptor refers to a pseudo-terminal of its own, so changes made to its
+/// attributes directly (e.g. using `tcsetattr`) take effect and are reflected by [`FakeTerminal::attributes`].
+/// They are not recorded as mode changes though.
+///
+/// ### Windows
+/// The console handles refer to the null device and the attributes are kept in memory.
+///
+/// ## Example
+/// ```
+/// use std::io::{Read as _, Write as _};
+/// use terminal_trx::FakeTerminal;
+///
+/// let mut terminal = FakeTerminal::new().unwrap();
+/// terminal.respond_to(b"\x1b[c", b"\x1b[?62c");
+/// terminal.write_all(b"\x1b[c").unwrap();
+///
+/// let mut response = Vec::new();
+/// terminal.read_to_end(&mut response).unwrap();
+/// assert_eq!(b"\x1b[?62c", response.as_slice());
+/// ```
+#[derive(Debug)]
+pub struct FakeTerminal {
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+    responses: Vec<(Vec<u8>, Vec<u8>)>,
+    // Output before this index has already been checked for requests.
+    scanned: usize,
+    mode_changes: Vec<ModeChange>,
+    device: Device,
+}
+
+/// A change to the mode of a [`FakeTerminal`], see [`FakeTerminal::mode_changes`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum ModeChange {
+    /// Raw mode was enabled with the given options.
+    RawModeEnabled(RawModeOptions),
+    /// A [`FakeRawModeGuard`] was dropped, restoring the attributes from before raw mode was enabled.
+    RawModeRestored,
+    /// The attributes were set using [`FakeTerminal::set_attributes`].
+    AttributesSet(TerminalAttributes),
+}
+
+impl FakeTerminal {
+    /// Creates a fake terminal without any input.
+    pub fn new() -> io::Result<Self> {
+        Ok(FakeTerminal {
+            input: VecDeque::new(),
+            output: Vec::new(),
+            responses: Vec::new(),
+            scanned: 0,
+            mode_changes: Vec::new(),
+            device: Device::new()?,
+        })
+    }
+
+    /// Queues input that is returned by subsequent reads.
+    pub fn push_input(&mut self, input: &[u8]) {
+        self.input.extend(input);
+    }
+
+    /// Queues the given response as input whenever the request is written.
+    pub fn respond_to(&mut self, request: &[u8], response: &[u8]) {
+        self.responses.push((request.to_vec(), response.to_vec()));
+    }
+
+    /// Returns everything that has been written so far.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Returns the input that has not been read yet.
+    pub fn remaining_input(&self) -> Vec<u8> {
+        self.input.iter().copied().collect()
+    }
+
+    /// Returns the mode changes made so far, oldest first.
+    pub fn mode_changes(&self) -> &[ModeChange] {
+        &self.mode_changes
+    }
+
+    /// Returns the current attributes. A new fake terminal starts out in cooked mode.
+    pub fn attributes(&self) -> io::Result<TerminalAttributes> {
+        self.device.attributes()
+    }
+
+    /// Sets the attributes and records the change.
+    pub fn set_attributes(&mut self, attributes: &TerminalAttributes) -> io::Result<()> {
+        self.device.set_attributes(attributes)?;
+        self.mode_changes
+            .push(ModeChange::AttributesSet(attributes.clone()));
+        Ok(())
+    }
+
+    /// Enables raw mode with the default options and records the change.
+    /// See [`FakeTerminal::enable_raw_mode_with`].
+    pub fn enable_raw_mode(&mut self) -> io::Result<FakeRawModeGuard<'_>> {
+        self.enable_raw_mode_with(&RawModeOptions::new())
+    }
+
+    /// Enables raw mode and records the change.
+    /// The previous attributes are restored when the returned guard is dropped.
+    pub fn enable_raw_mode_with(
+        &mut self,
+        options: &RawModeOptions,
+    ) -> io::Result<FakeRawModeGuard<'_>> {
+        let original = self.device.attributes()?;
+        let mut raw = original.clone();
+        raw.0.enable_raw_mode(options);
+        self.device.set_attributes(&raw)?;
+        self.mode_changes.push(ModeChange::RawModeEnabled(*options));
+        Ok(FakeRawModeGuard {
+            terminal: self,
+            original,
+        })
+    }
+
+    fn respond(&mut self) {
+        loop {
+            let unscanned = &self.output[self.scanned..];
+            let next = self
+                .responses
+                .iter()
+                .filter(|(request, _)| !request.is_empty())
+                .filter_map(|(request, response)| {
+                    let start = unscanned
+                        .windows(request.len())
+                        .position(|w| w == request.as_slice())?;
+                    Some((start + request.len(), response))
+                })
+                .min_by_key(|(end, _)| *end);
+            let Some((end, response)) = next else {
+                return;
+            };
+            self.input.extend(response);
+            self.scanned += end;
+        }
+    }
+}
+
+impl io::Read for FakeTerminal {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl io::Write for FakeTerminal {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output.extend_from_slice(buf);
+        self.respond();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl sealed::Sealed for FakeTerminal {}
+impl Transceive for FakeTerminal {}
+
+/// Enables raw mode on a [`FakeTerminal`] until dropped, see [`FakeTerminal::enable_raw_mode`].
+#[derive(Debug)]
+pub struct FakeRawModeGuard<'a> {
+    terminal: &'a mut FakeTerminal,
+    original: TerminalAttributes,
+}
+
+impl Deref for FakeRawModeGuard<'_> {
+    type Target = FakeTerminal;
+
+    fn deref(&self) -> &Self::Target {
+        self.terminal
+    }
+}
+
+impl DerefMut for FakeRawModeGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.terminal
+    }
+}
+
+impl Drop for FakeRawModeGuard<'_> {
+    fn drop(&mut self) {
+        _ = self.terminal.device.set_attributes(&self.original);
+        self.terminal.mode_changes.push(ModeChange::RawModeRestored);
+    }
+}
+
+cfg_if! {
+    if #[cfg(terminal_trx_test_unsupported)] {
+        use unsupported::Device;
+    } else if #[cfg(unix)] {
+        use unix::Device;
+    } else if #[cfg(windows)] {
+        use windows::Device;
+    } else {
+        use unsupported::Device;
+    }
+}
+
+#[cfg(all(unix, not(terminal_trx_test_unsupported)))]
+mod unix {
+    use super::FakeTerminal;
+    use crate::{imp, TerminalAttributes};
+    use std::io;
+    use std::os::fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd};
+
+    #[derive(Debug)]
+    pub(super) struct Device {
+        terminal: crate::Terminal,
+        _controlling: OwnedFd,
+    }
+
+    impl Device {
+        pub(super) fn new() -> io::Result<Self> {
+            let (terminal, controlling) = imp::Terminal::open_pty()?;
+            Ok(Device {
+                terminal: crate::Terminal(terminal),
+                _controlling: controlling,
+            })
+        }
+
+        pub(super) fn attributes(&self) -> io::Result<TerminalAttributes> {
+            self.terminal.lock_terminal_only().attributes()
+        }
+
+        pub(super) fn set_attributes(&mut self, attributes: &TerminalAttributes) -> io::Result<()> {
+            self.terminal
+                .lock_terminal_only()
+                .set_attributes(attributes)
+        }
+    }
+
+    impl AsFd for FakeTerminal {
+        fn as_fd(&self) -> BorrowedFd<'_> {
+            self.device.terminal.as_fd()
+        }
+    }
+
+    impl AsRawFd for FakeTerminal {
+        fn as_raw_fd(&self) -> RawFd {
+            self.device.terminal.as_raw_fd()
+        }
+    }
+}
+
+#[cfg(all(windows, not(terminal_trx_test_unsupported)))]
+mod windows {
+    use super::FakeTerminal;
+    use crate::{imp, ConsoleHandles, TerminalAttributes};
+    use std::fs::{File, OpenOptions};
+    use std::io;
+    use std::os::windows::io::{AsHandle as _, BorrowedHandle};
+
+    #[derive(Debug)]
+    pub(super) struct Device {
+        null: File,
+        attributes: TerminalAttributes,
+    }
+
+    impl Device {
+        pub(super) fn new() -> io::Result<Self> {
+            Ok(Device {
+                null: OpenOptions::new().read(true).write(true).open("NUL")?,
+                attributes: TerminalAttributes(imp::TerminalAttributes::cooked()),
+            })
+        }
+
+        pub(super) fn attributes(&self) -> io::Result<TerminalAttributes> {
+            Ok(self.attributes.clone())
+        }
+
+        pub(super) fn set_attributes(&mut self, attributes: &TerminalAttributes) -> io::Result<()> {
+            self.attributes = attributes.clone();
+            Ok(())
+        }
+    }
+
+    impl ConsoleHandles for FakeTerminal {
+        fn input_buffer_handle(&self) -> BorrowedHandle<'_> {
+            self.device.null.as_handle()
+        }
+
+        fn screen_buffer_handle(&self) -> BorrowedHandle<'_> {
+            self.device.null.as_handle()
+        }
+    }
+}
+
+#[cfg(any(terminal_trx_test_unsupported, not(any(unix, windows))))]
+mod unsupported {
+    use crate::TerminalAttributes;
+    use std::io;
+
+    #[derive(Debug)]
+    pub(super) struct Device {}
+
+    impl Device {
+        pub(super) fn new() -> io::Result<Self> {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "there's no fake terminal on this platform",
+            ))
+        }
+
+        pub(super) fn attributes(&self) -> io::Result<TerminalAttributes> {
+            unreachable!()
+        }
+
+        pub(super) fn set_attributes(
+            &mut self,
+            _attributes: &TerminalAttributes,
+        ) -> io::Result<()> {
+            unreachable!()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use std::io::{Read as _, Write as _};
+
+    #[test]
+    fn pushed_input_is_read() {
+        let mut terminal = FakeTerminal::new().unwrap();
+        terminal.push_input(b"foo");
+        let mut buf = [0; 8];
+        assert_eq!(3, terminal.read(&mut buf).unwrap());
+        assert_eq!(b"foo", &buf[..3]);
+        assert_eq!(0, terminal.read(&mut buf).unwrap());
+    }
+
+    #[test]
+    fn responds_to_requests_split_across_writes() {
+        let mut terminal = FakeTerminal::new().unwrap();
+        terminal.respond_to(b"\x1b[c", b"\x1b[?1c");
+        terminal.write_all(b"\x1b[").unwrap();
+        assert!(terminal.remaining_input().is_empty());
+        terminal.write_all(b"c").unwrap();
+        assert_eq!(b"\x1b[?1c", terminal.remaining_input().as_slice());
+    }
+
+    #[test]
+    fn responds_in_order_of_requests() {
+        let mut terminal = FakeTerminal::new().unwrap();
+        terminal.respond_to(b"b", b"2");
+        terminal.respond_to(b"a", b"1");
+        terminal.write_all(b"a b a").unwrap();
+        assert_eq!(b"121", terminal.remaining_input().as_slice());
+        assert_eq!(b"a b a", terminal.output());
+    }
+
+    #[test]
+    fn records_raw_mode_changes() {
+        let mut terminal = FakeTerminal::new().unwrap();
+        assert!(terminal.attributes().unwrap().canonical());
+        {
+            let mut guard = terminal.enable_raw_mode().unwrap();
+            assert!(!guard.attributes().unwrap().canonical());
+            guard.write_all(b"raw").unwrap();
+        }
+        assert!(terminal.attributes().unwrap().canonical());
+        assert!(matches!(
+            terminal.mode_changes(),
+            [ModeChange::RawModeEnabled(_), ModeChange::RawModeRestored]
+        ));
+        assert_eq!(b"raw", terminal.output());
+    }
+
+    #[test]
+    fn records_attribute_changes() {
+        let mut terminal = FakeTerminal::new().unwrap();
+        let mut attributes = terminal.attributes().unwrap();
+        attributes.set_echo(false);
+        terminal.set_attributes(&attributes).unwrap();
+        assert!(!terminal.attributes().unwrap().echo());
+        let [ModeChange::AttributesSet(recorded)] = terminal.mode_changes() else {
+            panic!("unexpected mode changes: {:?}", terminal.mode_changes());
+        };
+        assert!(!recorded.echo());
+    }
+
+    #[cfg(all(unix, not(terminal_trx_test_unsupported)))]
+    #[test]
+    fn file_descriptor_refers_to_a_terminal() {
+        use std::io::IsTerminal as _;
+        use std::os::fd::AsFd as _;
+
+        let terminal = FakeTerminal::new().unwrap();
+        assert!(terminal.as_fd().is_terminal());
+    }
+}
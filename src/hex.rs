@@ -0,0 +1,42 @@
+/// Encodes the bytes as pairs of lowercase hex digits joined by `separator`.
+pub(crate) fn encode(bytes: &[u8], separator: &str) -> String {
+    let pairs: Vec<_> = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    pairs.join(separator)
+}
+
+/// Decodes pairs of hex digits in either case.
+pub(crate) fn decode(hex: &[u8]) -> Option<Vec<u8>> {
+    hex.chunks(2)
+        .map(|pair| {
+            let pair = std::str::from_utf8(pair)
+                .ok()
+                .filter(|p| p.len() == 2 && p.bytes().all(|b| b.is_ascii_hexdigit()))?;
+            u8::from_str_radix(pair, 16).ok()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_with_separator() {
+        assert_eq!("1b5b63", encode(b"\x1b[c", ""));
+        assert_eq!("1b 5b 63", encode(b"\x1b[c", " "));
+    }
+
+    #[test]
+    fn decodes_either_case() {
+        assert_eq!(Some(b"RGB".to_vec()), decode(b"524742"));
+        assert_eq!(Some(b"Co".to_vec()), decode(b"436f"));
+        assert_eq!(Some(b"\xff".to_vec()), decode(b"fF"));
+    }
+
+    #[test]
+    fn rejects_invalid_hex() {
+        assert_eq!(None, decode(b"5247F"));
+        assert_eq!(None, decode(b"zz"));
+        assert_eq!(None, decode(b"+1"));
+    }
+}
@@ -0,0 +1,257 @@
+use crate::{query, RawModeGuard};
+use std::env;
+use std::io;
+
+/// The terminal emulator (or multiplexer) the process is talking to,
+/// see [`TerminalLock::identity`](`crate::TerminalLock::identity`).
+///
+/// Multiplexers such as tmux are reported instead of the terminal they run in,
+/// as they're the ones interpreting the escape sequences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TerminalIdentity {
+    /// [Alacritty](https://alacritty.org)
+    Alacritty,
+    /// Terminal.app on macOS
+    AppleTerminal,
+    /// [Contour](https://contour-terminal.org)
+    Contour,
+    /// [foot](https://codeberg.org/dnkl/foot)
+    Foot,
+    /// [Ghostty](https://ghostty.org)
+    Ghostty,
+    /// [iTerm2](https://iterm2.com)
+    ITerm2,
+    /// [kitty](https://sw.kovidgoyal.net/kitty/)
+    Kitty,
+    /// [Konsole](https://konsole.kde.org)
+    Konsole,
+    /// The Linux virtual console
+    LinuxConsole,
+    /// [mintty](https://mintty.github.io), e.g. used by Git Bash and Cygwin
+    Mintty,
+    /// GNU Screen
+    Screen,
+    /// [tmux](https://github.com/tmux/tmux)
+    Tmux,
+    /// A terminal based on [VTE](https://gitlab.gnome.org/GNOME/vte), e.g. GNOME Terminal
+    Vte,
+    /// The integrated terminal of Visual Studio Code
+    VsCode,
+    /// [WezTerm](https://wezfurlong.org/wezterm/)
+    WezTerm,
+    /// [Windows Terminal](https://github.com/microsoft/terminal)
+    WindowsTerminal,
+    /// [xterm](https://invisible-island.net/xterm/)
+    XTerm,
+    /// The terminal could not be identified.
+    Unknown,
+}
+
+impl TerminalIdentity {
+    /// Identifies the terminal using environment variables (such as `TERM_PROGRAM` and `TERM`) only.
+    ///
+    /// Unlike [`TerminalLock::identity`](`crate::TerminalLock::identity`), this doesn't talk to the terminal,
+    /// but environment variables are easily inherited by processes that run in a different terminal
+    /// (e.g. over SSH or in a multiplexer started from another terminal).
+    pub fn from_env() -> Self {
+        from_env_vars(|name| env::var(name).ok())
+    }
+
+    /// Identifies the terminal from the name and version reported by `XTVERSION`,
+    /// see [`TerminalLock::terminal_version`](`crate::TerminalLock::terminal_version`).
+    ///
+    /// Returns [`None`] for unknown names.
+    pub fn from_version(version: &str) -> Option<Self> {
+        let name = version
+            .split(|c: char| c == '(' || c.is_whitespace())
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let identity = match name.as_str() {
+            "alacritty" => TerminalIdentity::Alacritty,
+            "contour" => TerminalIdentity::Contour,
+            "foot" => TerminalIdentity::Foot,
+            "ghostty" => TerminalIdentity::Ghostty,
+            "iterm2" => TerminalIdentity::ITerm2,
+            "kitty" => TerminalIdentity::Kitty,
+            "konsole" => TerminalIdentity::Konsole,
+            "mintty" => TerminalIdentity::Mintty,
+            "tmux" => TerminalIdentity::Tmux,
+            "vte" => TerminalIdentity::Vte,
+            "wezterm" => TerminalIdentity::WezTerm,
+            "xterm" => TerminalIdentity::XTerm,
+            _ => return None,
+        };
+        Some(identity)
+    }
+
+    /// Identifies the terminal from the parameters of its response to the
+    /// Secondary Device Attributes (DA2) query (`ESC [ > c`).
+    ///
+    /// Many terminals claim to be a VT100 or VT220 here,
+    /// so only terminals with a distinctive response are recognized.
+    fn from_secondary_device_attributes(parameters: &[u16]) -> Option<Self> {
+        match parameters {
+            [41, ..] => Some(TerminalIdentity::XTerm),
+            [77, ..] => Some(TerminalIdentity::Mintty),
+            [83, ..] => Some(TerminalIdentity::Screen),
+            [84, ..] => Some(TerminalIdentity::Tmux),
+            [1, 4000, ..] => Some(TerminalIdentity::Kitty),
+            [65, _, 1] => Some(TerminalIdentity::Vte),
+            _ => None,
+        }
+    }
+}
+
+/// Tries `XTVERSION` first, then the Secondary Device Attributes and finally falls back to the environment,
+/// which is also used if the terminal doesn't answer.
+pub(crate) fn identify(raw_mode: &mut RawModeGuard<'_>) -> io::Result<TerminalIdentity> {
+    match identify_by_queries(raw_mode) {
+        Ok(Some(identity)) => Ok(identity),
+        Ok(None) => Ok(TerminalIdentity::from_env()),
+        Err(error) if query::is_unanswered(&error) => Ok(TerminalIdentity::from_env()),
+        Err(error) => Err(error),
+    }
+}
+
+fn identify_by_queries(raw_mode: &mut RawModeGuard<'_>) -> io::Result<Option<TerminalIdentity>> {
+    if let Some(identity) = query::terminal_version(raw_mode)?
+        .as_deref()
+        .and_then(TerminalIdentity::from_version)
+    {
+        return Ok(Some(identity));
+    }
+    Ok(query::secondary_device_attributes(raw_mode)?
+        .as_deref()
+        .and_then(TerminalIdentity::from_secondary_device_attributes))
+}
+
+pub(crate) fn from_env_vars(var: impl Fn(&str) -> Option<String>) -> TerminalIdentity {
+    let is_set = |name: &str| var(name).is_some();
+    let term = var("TERM").unwrap_or_default();
+
+    // Multiplexers come first as the variables of the outer terminal are usually still set.
+    if is_set("TMUX") || term.starts_with("tmux") {
+        return TerminalIdentity::Tmux;
+    }
+    if is_set("STY") {
+        return TerminalIdentity::Screen;
+    }
+
+    let from_term_program = match var("TERM_PROGRAM").as_deref() {
+        Some("Apple_Terminal") => Some(TerminalIdentity::AppleTerminal),
+        Some("ghostty") => Some(TerminalIdentity::Ghostty),
+        Some("iTerm.app") => Some(TerminalIdentity::ITerm2),
+        Some("mintty") => Some(TerminalIdentity::Mintty),
+        Some("tmux") => Some(TerminalIdentity::Tmux),
+        Some("vscode") => Some(TerminalIdentity::VsCode),
+        Some("WezTerm") => Some(TerminalIdentity::WezTerm),
+        _ => None,
+    };
+    if let Some(identity) = from_term_program {
+        return identity;
+    }
+
+    let from_term = match term.as_str() {
+        "alacritty" => Some(TerminalIdentity::Alacritty),
+        "contour" => Some(TerminalIdentity::Contour),
+        "foot" | "foot-extra" => Some(TerminalIdentity::Foot),
+        "linux" => Some(TerminalIdentity::LinuxConsole),
+        "wezterm" => Some(TerminalIdentity::WezTerm),
+        "xterm-ghostty" => Some(TerminalIdentity::Ghostty),
+        "xterm-kitty" => Some(TerminalIdentity::Kitty),
+        _ => None,
+    };
+    if let Some(identity) = from_term {
+        return identity;
+    }
+
+    [
+        ("KITTY_WINDOW_ID", TerminalIdentity::Kitty),
+        ("WEZTERM_EXECUTABLE", TerminalIdentity::WezTerm),
+        ("ALACRITTY_WINDOW_ID", TerminalIdentity::Alacritty),
+        ("KONSOLE_VERSION", TerminalIdentity::Konsole),
+        ("VTE_VERSION", TerminalIdentity::Vte),
+        ("WT_SESSION", TerminalIdentity::WindowsTerminal),
+        ("XTERM_VERSION", TerminalIdentity::XTerm),
+    ]
+    .into_iter()
+    .find(|(name, _)| is_set(name))
+    .map_or(TerminalIdentity::Unknown, |(_, identity)| identity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_vars(vars: &[(&str, &str)]) -> TerminalIdentity {
+        from_env_vars(|name| {
+            vars.iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, value)| (*value).to_owned())
+        })
+    }
+
+    #[test]
+    fn identifies_terminal_from_version() {
+        assert_eq!(
+            Some(TerminalIdentity::Kitty),
+            TerminalIdentity::from_version("kitty(0.35.2)")
+        );
+        assert_eq!(
+            Some(TerminalIdentity::WezTerm),
+            TerminalIdentity::from_version("WezTerm 20240203-110809-5046fc22")
+        );
+        assert_eq!(
+            Some(TerminalIdentity::XTerm),
+            TerminalIdentity::from_version("XTerm(388)")
+        );
+        assert_eq!(None, TerminalIdentity::from_version("unheard-of 1.0"));
+        assert_eq!(None, TerminalIdentity::from_version(""));
+    }
+
+    #[test]
+    fn identifies_terminal_from_secondary_device_attributes() {
+        assert_eq!(
+            Some(TerminalIdentity::Tmux),
+            TerminalIdentity::from_secondary_device_attributes(&[84, 0, 0])
+        );
+        assert_eq!(
+            Some(TerminalIdentity::Kitty),
+            TerminalIdentity::from_secondary_device_attributes(&[1, 4000, 29])
+        );
+        assert_eq!(
+            None,
+            TerminalIdentity::from_secondary_device_attributes(&[1, 95, 0])
+        );
+    }
+
+    #[test]
+    fn multiplexer_takes_precedence_over_outer_terminal() {
+        assert_eq!(
+            TerminalIdentity::Tmux,
+            from_vars(&[("TMUX", "/tmp/tmux-1000/default"), ("KITTY_WINDOW_ID", "1")])
+        );
+    }
+
+    #[test]
+    fn identifies_terminal_from_env() {
+        assert_eq!(
+            TerminalIdentity::ITerm2,
+            from_vars(&[("TERM_PROGRAM", "iTerm.app")])
+        );
+        assert_eq!(
+            TerminalIdentity::LinuxConsole,
+            from_vars(&[("TERM", "linux")])
+        );
+        assert_eq!(
+            TerminalIdentity::WindowsTerminal,
+            from_vars(&[("WT_SESSION", "0d4e1c5a")])
+        );
+        assert_eq!(
+            TerminalIdentity::Unknown,
+            from_vars(&[("TERM", "xterm-256color")])
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use std::io;
+
+const CAPACITY: usize = 1024;
+
+/// Input that has been read from the terminal (or pushed back using `unread`) but not yet consumed.
+///
+/// The buffer is shared by all handles to the terminal. It is moved into a terminal lock
+/// while the lock is held and moved back once it's dropped.
+/// Reads are only served from the buffer if it's non-empty, otherwise they go straight
+/// to the terminal so that the read behaviour (e.g. `VMIN` / `VTIME`) is unaffected.
+#[derive(Debug)]
+pub(crate) struct InputBuffer {
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl InputBuffer {
+    pub(crate) const fn new() -> Self {
+        InputBuffer {
+            buf: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    fn available(&self) -> &[u8] {
+        &self.buf[self.pos..]
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.available().is_empty()
+    }
+
+    pub(crate) fn read(&mut self, reader: &mut impl io::Read, buf: &mut [u8]) -> io::Result<usize> {
+        if self.available().is_empty() {
+            return reader.read(buf);
+        }
+        let len = buf.len().min(self.available().len());
+        buf[..len].copy_from_slice(&self.available()[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+
+    pub(crate) fn read_vectored(
+        &mut self,
+        reader: &mut impl io::Read,
+        bufs: &mut [io::IoSliceMut<'_>],
+    ) -> io::Result<usize> {
+        if self.available().is_empty() {
+            return reader.read_vectored(bufs);
+        }
+        let mut total = 0;
+        for buf in bufs {
+            let len = self.read(reader, buf)?;
+            total += len;
+            if self.available().is_empty() {
+                break;
+            }
+        }
+        Ok(total)
+    }
+
+    pub(crate) fn fill_buf(&mut self, reader: &mut impl io::Read) -> io::Result<&[u8]> {
+        if self.available().is_empty() {
+            self.buf.clear();
+            self.pos = 0;
+            self.buf.resize(CAPACITY, 0);
+            let result = reader.read(&mut self.buf);
+            self.buf.truncate(*result.as_ref().unwrap_or(&0));
+            result?;
+        }
+        Ok(self.available())
+    }
+
+    pub(crate) fn consume(&mut self, amount: usize) {
+        self.pos = (self.pos + amount).min(self.buf.len());
+    }
+
+    /// Pushes the given bytes back to the front of the buffer,
+    /// so that they're returned by the next read.
+    pub(crate) fn unread(&mut self, bytes: &[u8]) {
+        self.buf.drain(..self.pos);
+        self.pos = 0;
+        self.buf.splice(0..0, bytes.iter().copied());
+    }
+
+    /// Appends the unconsumed bytes of `other` to the end of the buffer.
+    pub(crate) fn append(&mut self, other: &InputBuffer) {
+        if !other.available().is_empty() {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+            self.buf.extend_from_slice(other.available());
+        }
+    }
+
+    pub(crate) fn take(&mut self) -> InputBuffer {
+        std::mem::replace(self, InputBuffer::new())
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.buf.clear();
+        self.pos = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+
+    #[test]
+    fn reads_go_to_reader_when_empty() {
+        let mut buffer = InputBuffer::new();
+        let mut reader: &[u8] = b"foo";
+        let mut buf = [0; 8];
+        assert_eq!(3, buffer.read(&mut reader, &mut buf).unwrap());
+        assert_eq!(b"foo", &buf[..3]);
+    }
+
+    #[test]
+    fn unread_bytes_are_read_first() {
+        let mut buffer = InputBuffer::new();
+        let mut reader: &[u8] = b"bar";
+        buffer.unread(b"foo");
+        let mut buf = [0; 8];
+        assert_eq!(3, buffer.read(&mut reader, &mut buf).unwrap());
+        assert_eq!(b"foo", &buf[..3]);
+        assert_eq!(3, buffer.read(&mut reader, &mut buf).unwrap());
+        assert_eq!(b"bar", &buf[..3]);
+    }
+
+    #[test]
+    fn unread_after_partial_consume_prepends() {
+        let mut buffer = InputBuffer::new();
+        let mut reader: &[u8] = b"\x1b[Ax";
+        assert_eq!(b"\x1b[Ax", buffer.fill_buf(&mut reader).unwrap());
+        buffer.consume(3);
+        buffer.unread(b"\x1b[A");
+        let mut buf = [0; 8];
+        assert_eq!(4, buffer.read(&mut reader, &mut buf).unwrap());
+        assert_eq!(b"\x1b[Ax", &buf[..4]);
+    }
+
+    #[test]
+    fn append_adds_unconsumed_bytes_to_end() {
+        let mut buffer = InputBuffer::new();
+        let mut other = InputBuffer::new();
+        buffer.unread(b"foo");
+        other.unread(b"xbar");
+        other.consume(1);
+        buffer.append(&other);
+        let mut buf = [0; 8];
+        assert_eq!(6, buffer.read(&mut &b""[..], &mut buf).unwrap());
+        assert_eq!(b"foobar", &buf[..6]);
+    }
+
+    #[test]
+    fn vectored_reads_are_served_from_buffer() {
+        let mut buffer = InputBuffer::new();
+        let mut reader: &[u8] = b"";
+        buffer.unread(b"foobar");
+        let (mut first, mut second) = ([0; 3], [0; 8]);
+        let read = buffer
+            .read_vectored(
+                &mut reader,
+                &mut [
+                    io::IoSliceMut::new(&mut first),
+                    io::IoSliceMut::new(&mut second),
+                ],
+            )
+            .unwrap();
+        assert_eq!(6, read);
+        assert_eq!(b"foo", &first);
+        assert_eq!(b"bar", &second[..3]);
+    }
+}
@@ -30,7 +30,11 @@
 use cfg_if::cfg_if;
 use std::io;
 use std::marker::PhantomData;
-use std::sync::{Mutex, MutexGuard};
+use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
+use std::process::{Command, ExitStatus};
+use std::sync::OnceLock;
+use std::time::Duration;
 
 cfg_if! {
     if #[cfg(all(unix, not(terminal_trx_test_unsupported)))] {
@@ -45,11 +49,149 @@ cfg_if! {
     }
 }
 
+mod attributes;
+mod color;
+pub use color::ColorSupport;
+mod error;
+pub use error::Error;
+#[cfg(feature = "events")]
+#[cfg_attr(docsrs, doc(cfg(feature = "events")))]
+pub mod events;
+#[cfg(feature = "test-util")]
+mod fake;
+#[cfg(feature = "test-util")]
+#[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+pub use fake::{FakeRawModeGuard, FakeTerminal, ModeChange};
+mod hex;
+mod identity;
+pub use identity::TerminalIdentity;
+mod input_buffer;
+mod lock;
+pub use attributes::{ControlChar, TerminalAttributes};
+#[cfg(all(unix, not(terminal_trx_test_unsupported)))]
+pub use attributes::{Parity, StopBits};
+use input_buffer::InputBuffer;
+use lock::{DeviceState, ReentrantLockGuard};
+mod mode_guard;
+use mode_guard::Mode;
+pub use mode_guard::ModeGuard;
+mod mode_stack;
+mod owned_guard;
+pub use mode_stack::{ModeStack, ModeStackOptions};
+pub use owned_guard::OwnedRawModeGuard;
+mod password;
+mod query;
+pub use query::{DeviceAttributes, ModeState, Passthrough, Query, QueryPolicy};
+mod raw_mode;
+use raw_mode::ReapplyOnDrop;
+pub use raw_mode::{ExternalModeChanges, ModeChangeTiming, RawModeOptions};
+#[cfg(all(unix, not(terminal_trx_test_unsupported)))]
+mod process_lock;
+#[cfg(all(unix, feature = "pty", not(terminal_trx_test_unsupported)))]
+#[cfg_attr(docsrs, doc(cfg(all(unix, feature = "pty"))))]
+pub mod pty;
+#[cfg(all(unix, not(terminal_trx_test_unsupported)))]
+#[cfg_attr(docsrs, doc(cfg(unix)))]
+pub use process_lock::ProcessLockGuard;
+mod readiness;
+pub use readiness::Readiness;
+mod record;
+pub use record::{Recorder, Replay};
+mod restore;
+mod state;
+pub use state::{StateDiff, TerminalState};
+mod title;
+pub use title::TitleGuard;
+mod utf8;
+mod writer;
+#[cfg(all(windows, not(terminal_trx_test_unsupported)))]
+pub use imp::{
+    CodePageGuard, InputEvent, KeyEvent, MouseEvent, ScreenBufferInfo,
+    VirtualTerminalProcessingGuard,
+};
+#[cfg(all(unix, not(terminal_trx_test_unsupported)))]
+pub use restore::install_restore_on_suspend;
+pub use restore::{install_restore_on_panic, install_restore_on_signals};
+pub use writer::TerminalWriter;
+
 #[doc = include_str!("../readme.md")]
 #[cfg(doctest)]
 pub mod readme_doctests {}
 
-static TERMINAL_LOCK: Mutex<()> = Mutex::new(());
+/// The device's input buffer, moved out of the [`DeviceState`] while its input half is locked.
+#[derive(Debug)]
+struct LockedInputBuffer<'a> {
+    buffer: InputBuffer,
+    state: &'a DeviceState,
+    guard: ReentrantLockGuard<'a>,
+}
+
+impl<'a> LockedInputBuffer<'a> {
+    fn lock(state: &'a DeviceState) -> Self {
+        let guard = state.input.lock();
+        Self::new(state, guard)
+    }
+
+    /// Like [`LockedInputBuffer::lock`] but fails with [`io::ErrorKind::WouldBlock`]
+    /// instead of waiting for the input half while the current thread only holds the output half.
+    /// Waiting in that case deadlocks if the other thread tries to lock the output half.
+    fn lock_in_order(state: &'a DeviceState) -> io::Result<Self> {
+        let guard = if state.output.is_held_by_current_thread() {
+            state.input.try_lock().ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    "the input half of the terminal is locked by another thread \
+                     while this thread holds the output half",
+                )
+            })?
+        } else {
+            state.input.lock()
+        };
+        Ok(Self::new(state, guard))
+    }
+
+    fn new(state: &'a DeviceState, guard: ReentrantLockGuard<'a>) -> Self {
+        LockedInputBuffer {
+            buffer: state.input_buffer().take(),
+            state,
+            guard,
+        }
+    }
+
+    /// The standard I/O streams are only locked by the outermost lock,
+    /// as the standard input's lock is not reentrant.
+    fn lock_stdin(&self, terminal: &imp::Terminal) -> StdioLocks {
+        if self.guard.is_outermost() {
+            terminal.lock_stdin()
+        } else {
+            StdioLocks::default()
+        }
+    }
+}
+
+impl Deref for LockedInputBuffer<'_> {
+    type Target = InputBuffer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+
+impl DerefMut for LockedInputBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buffer
+    }
+}
+
+impl Drop for LockedInputBuffer<'_> {
+    fn drop(&mut self) {
+        // Anything buffered by a nested lock was read after our input.
+        let mut shared = self.state.input_buffer();
+        let nested = shared.take();
+        *shared = self.buffer.take();
+        shared.append(&nested);
+    }
+}
 
 /// Creates a readable and writable handle to the terminal (or TTY) if available.
 ///
@@ -68,13 +210,102 @@ static TERMINAL_LOCK: Mutex<()> = Mutex::new(());
 /// * the standard error,
 /// * standard output,
 /// * and finally `CONOUT$`.
-pub fn terminal() -> io::Result<Terminal> {
+///
+/// The result of this lookup is cached, so that subsequent calls only
+/// need to duplicate or open a single descriptor (or handle).
+/// Use [`invalidate`] if the standard I/O streams of the process change.
+///
+/// ## Errors
+/// * [`Error::NotATty`] if the process has no terminal.
+/// * [`Error::Unsupported`] if the platform is not supported.
+pub fn terminal() -> Result<Terminal, Error> {
     imp::terminal().map(Terminal)
 }
 
+/// Forgets where [`terminal()`] found the terminal, so that the next call looks it up again.
+///
+/// This is useful for daemons that redirect their standard I/O streams (e.g. using `dup2`)
+/// or lose their controlling terminal. Terminals that were already created
+/// (including the one returned by [`global()`]) are not affected.
+pub fn invalidate() {
+    imp::invalidate_discovery()
+}
+
+/// Like [`terminal()`] but attaches to a console first if the process doesn't have one.
+///
+/// Processes using the GUI subsystem (`#![windows_subsystem = "windows"]`) don't get a console,
+/// even when they're launched from a shell. Such processes can use this function to
+/// attach to their parent's console (using `AttachConsole`) or allocate a new console (using `AllocConsole`).
+///
+/// Nothing is attached if [`terminal()`] succeeds on its own.
+#[cfg(all(windows, not(terminal_trx_test_unsupported)))]
+#[cfg_attr(docsrs, doc(cfg(windows)))]
+pub fn terminal_with_console_fallback(fallback: ConsoleFallback) -> Result<Terminal, Error> {
+    imp::terminal()
+        .or_else(|error| {
+            imp::attach_console(fallback).map_err(|_| error)?;
+            imp::terminal()
+        })
+        .map(Terminal)
+}
+
+/// What to do when the process has no console, see [`terminal_with_console_fallback`].
+#[cfg(all(windows, not(terminal_trx_test_unsupported)))]
+#[cfg_attr(docsrs, doc(cfg(windows)))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ConsoleFallback {
+    /// Attach to the console of the parent process (e.g. the shell the process was started from).
+    AttachParent,
+    /// Attach to the console of the parent process or allocate a new console
+    /// if the parent has none (e.g. when started from the Explorer).
+    AttachParentOrAllocate,
+}
+
+/// Returns a process-wide handle to the terminal, which is created on first use (see [`terminal()`]).
+///
+/// This is useful for libraries that want to talk to the terminal without
+/// requiring a handle to be passed around. Access is synchronized using [`Terminal::lock`] as usual.
+/// Unlike [`terminal()`], the lookup is only performed once.
+/// Errors are not cached, so a failed lookup is retried on the next call.
+///
+/// ## Example
+/// ```no_run
+/// use std::io::Write as _;
+///
+/// let mut terminal = terminal_trx::global().unwrap();
+/// write!(terminal, "hello world").unwrap();
+/// ```
+pub fn global() -> Result<&'static Terminal, Error> {
+    static GLOBAL: OnceLock<Terminal> = OnceLock::new();
+
+    if let Some(terminal) = GLOBAL.get() {
+        return Ok(terminal);
+    }
+
+    let terminal = terminal()?;
+    Ok(GLOBAL.get_or_init(|| terminal))
+}
+
 macro_rules! impl_transceive {
     ($($extra_supertraits:tt)*) => {
         /// A trait for objects that are both [`io::Read`] and [`io::Write`].
+        ///
+        /// The trait is object safe, so different kinds of terminals can be stored as `Box<dyn Transceive>`,
+        /// which in turn implements [`Transceive`].
+        ///
+        /// ## Example
+        /// ```no_run
+        /// use terminal_trx::{terminal, Transceive};
+        /// use std::io::Write as _;
+        ///
+        /// fn greet(mut terminal: impl Transceive) {
+        ///     write!(terminal, "hello world").unwrap();
+        /// }
+        ///
+        /// let terminal: Box<dyn Transceive> = Box::new(terminal().unwrap());
+        /// greet(terminal);
+        /// ```
         pub trait Transceive: io::Read + io::Write $($extra_supertraits)* + sealed::Sealed {}
     };
 }
@@ -100,6 +331,31 @@ pub trait ConsoleHandles {
 
     /// Returns a handle to the consoles's screen buffer `CONOUT$`.
     fn screen_buffer_handle(&self) -> std::os::windows::io::BorrowedHandle<'_>;
+
+    /// Returns a handle that can be waited on (e.g. using `WaitForMultipleObjects`) for console input.
+    ///
+    /// The handle is signaled while the console's input buffer contains unread input records.
+    /// Besides key presses, these include records that reading ignores (e.g. key releases,
+    /// focus and window size changes), so a read after a successful wait may still block.
+    /// Use [`RawModeGuard::set_read_behavior`] with a zero timeout to read without blocking
+    /// or [`RawModeGuard::read_input_events`] to consume all records.
+    ///
+    /// Input that has already been read from the console but not yet returned
+    /// (e.g. input pushed back using [`TerminalLock::unread`]) does not signal the handle.
+    ///
+    /// The handle is only waitable for consoles, see [`Terminal::kind`].
+    /// MSYS/Cygwin terminals are pipes which can't be waited on for readiness.
+    fn input_wait_handle(&self) -> std::os::windows::io::BorrowedHandle<'_> {
+        self.input_buffer_handle()
+    }
+
+    /// Returns the window hosting the console (using `GetConsoleWindow`), e.g. to flash or focus it.
+    ///
+    /// Returns [`None`] if there's no such window, e.g. for pseudo consoles (ConPTY)
+    /// where the hosting terminal owns the window.
+    fn console_window(&self) -> Option<windows_sys::Win32::Foundation::HWND> {
+        imp::console_window()
+    }
 }
 
 mod sealed {
@@ -116,22 +372,229 @@ pub struct Terminal(imp::Terminal);
 #[cfg(test)]
 static_assertions::assert_impl_all!(Terminal: Send, Sync, std::panic::UnwindSafe, std::panic::RefUnwindSafe);
 
+#[cfg(test)]
+static_assertions::assert_impl_all!(&Terminal: io::Read, io::Write);
+
+#[cfg(test)]
+static_assertions::assert_obj_safe!(Transceive);
+
+#[cfg(test)]
+static_assertions::assert_impl_all!(&mut TerminalLock<'static>: Transceive);
+
+impl sealed::Sealed for Box<dyn Transceive + '_> {}
+impl Transceive for Box<dyn Transceive + '_> {}
+
+// A blanket implementation for `&mut T` is not possible
+// because `AsRawFd` can't be implemented for `&mut T` outside of the standard library.
+macro_rules! impl_transceive_for_mut {
+    ($($ty:ty),*) => {
+        $(
+            impl sealed::Sealed for &mut $ty {}
+            impl Transceive for &mut $ty {}
+        )*
+    };
+}
+
+impl_transceive_for_mut!(
+    Terminal,
+    TerminalLock<'_>,
+    RawModeGuard<'_>,
+    Box<dyn Transceive + '_>
+);
+#[cfg(feature = "test-util")]
+impl_transceive_for_mut!(FakeTerminal);
+
 impl sealed::Sealed for Terminal {}
 impl Transceive for Terminal {}
 
+impl Terminal {
+    /// Restores the given [`TerminalAttributes`], e.g. the ones returned by [`RawModeGuard::persist`].
+    ///
+    /// This locks the terminal, see [`TerminalLock::set_attributes`].
+    ///
+    /// ## Errors
+    /// [`io::ErrorKind::WouldBlock`] if the current thread holds a [`TerminalWriteLock`]
+    /// while another thread holds the input half, see [`Terminal::lock`].
+    pub fn restore(&self, attributes: &TerminalAttributes) -> io::Result<()> {
+        self.lock_in_order()?.set_attributes(attributes)
+    }
+
+    /// Returns what kind of terminal this is.
+    ///
+    /// Useful to adapt behaviour before attempting e.g. raw mode,
+    /// which is implemented differently for MSYS/Cygwin terminals.
+    pub fn kind(&self) -> TerminalKind {
+        self.0.kind()
+    }
+
+    /// Returns how the handle to the terminal was obtained, see [`terminal()`].
+    ///
+    /// Note that changes to the file status flags (e.g. `O_NONBLOCK`) of a [`Provenance::Stdio`] handle
+    /// also affect the standard I/O streams.
+    pub fn provenance(&self) -> Provenance {
+        self.0.provenance()
+    }
+
+    /// Returns the path of the terminal device, e.g. `/dev/pts/3`.
+    ///
+    /// ### Windows
+    /// Consoles are not files, this always returns the pseudo-path `\\.\CONIN$`.
+    pub fn path(&self) -> io::Result<PathBuf> {
+        self.0.path()
+    }
+
+    /// Returns `true` if the process belongs to the terminal's foreground process group.
+    ///
+    /// Reading from the terminal while in the background (e.g. after being started with `&` in a shell)
+    /// stops the process with `SIGTTIN`, so tools should avoid prompting in that case.
+    /// Job control only applies to the process's controlling terminal,
+    /// so this returns `true` for any other terminal.
+    ///
+    /// ### Windows
+    /// There's no job control on Windows, this always returns `true`.
+    pub fn is_foreground(&self) -> io::Result<bool> {
+        self.0.is_foreground()
+    }
+
+    /// Sets whether the terminal's file descriptor (or handles on Windows) is inherited by child processes.
+    ///
+    /// Terminals opened by this crate are not inherited by default (`O_CLOEXEC` on Unix,
+    /// no `HANDLE_FLAG_INHERIT` on Windows), as leaking them into children can keep the terminal
+    /// busy after the child was expected to release it. This is unrelated to the standard I/O
+    /// of child processes, which is inherited as usual.
+    ///
+    /// Terminals that use standard I/O (see [`Provenance::Stdio`]) use a duplicate of the
+    /// standard I/O descriptor, so this doesn't affect the standard I/O of the process.
+    pub fn set_inheritable(&self, inheritable: bool) -> io::Result<()> {
+        self.0.set_inheritable(inheritable)
+    }
+
+    /// Returns a [`Readiness`] to wait for input or to register the terminal with an event loop.
+    ///
+    /// This doesn't lock the terminal, so other threads can keep writing while waiting for input.
+    pub fn readiness(&self) -> Readiness<'_> {
+        Readiness(&self.0)
+    }
+
+    /// Sets whether reads and writes are retried when they're interrupted by a signal. Defaults to `true`.
+    ///
+    /// Without retrying, a signal (e.g. `SIGWINCH` when the window is resized) arriving while a read
+    /// is waiting for input makes the read fail with [`ErrorKind::Interrupted`](`io::ErrorKind::Interrupted`).
+    /// Disable this if you rely on signals to interrupt blocking reads.
+    /// Changing the terminal's attributes is always retried.
+    ///
+    /// [`TerminalLock::read_password`] is never retried so that the user can abort the prompt.
+    ///
+    /// ### Windows
+    /// Reads and writes are not interrupted by signals on Windows, this has no effect.
+    pub fn set_retry_interrupted(&self, retry: bool) {
+        self.0.set_retry_interrupted(retry)
+    }
+
+    /// Returns `true` if the standard input refers to the same terminal.
+    ///
+    /// Reads from the standard input then compete with reads from this terminal,
+    /// which is why the standard input is locked by [`Terminal::lock`].
+    pub fn is_stdin(&self) -> bool {
+        self.0.is_stdin()
+    }
+
+    /// Returns `true` if the standard output refers to the same terminal.
+    ///
+    /// Output written using e.g. [`println!`] then interleaves with output written to this terminal,
+    /// which is why the standard output is locked by [`Terminal::lock`].
+    pub fn is_stdout(&self) -> bool {
+        self.0.is_stdout()
+    }
+
+    /// Returns `true` if the standard error refers to the same terminal.
+    /// See [`Terminal::is_stdout`].
+    pub fn is_stderr(&self) -> bool {
+        self.0.is_stderr()
+    }
+}
+
+/// How the handle to the terminal was obtained, see [`Terminal::provenance`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Provenance {
+    /// A duplicate of a standard I/O stream that refers to the terminal is used.
+    /// On Windows, this is only reported if both the input and output handles are standard I/O handles.
+    Stdio,
+    /// (Unix) The terminal of a standard I/O stream was opened again by name
+    /// because the stream is not open for both reading and writing.
+    Reopened,
+    /// (Unix) The process's controlling terminal was opened using `/dev/tty`.
+    ControllingTerminal,
+    /// (Windows) At least one of `CONIN$` or `CONOUT$` was opened.
+    Console,
+    /// (Unix) A pseudo-terminal created using `pty::open` (requires the `pty` feature).
+    Pty,
+}
+
+/// The kind of a terminal, see [`Terminal::kind`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TerminalKind {
+    /// (Unix) A terminal device, e.g. a pseudo-terminal.
+    Tty,
+    /// (Windows) A Windows console.
+    Console,
+    /// (Windows) A MSYS/Cygwin pseudo-terminal (e.g. mintty, the Git Bash terminal),
+    /// which is a pipe rather than a console.
+    Msys,
+}
+
 impl io::Read for Terminal {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.lock().read(buf)
+        self.lock_read_in_order()?.read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        self.lock_read_in_order()?.read_vectored(bufs)
     }
 }
 
 impl io::Write for Terminal {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.lock().write(buf)
+        self.lock_write().write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.lock_write().write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock_write().flush()
+    }
+}
+
+/// Like [`File`](`std::fs::File`), the terminal can be read from and written to through a shared reference.
+/// Each call locks the respective half of the terminal, see [`Terminal::lock_read`] and [`Terminal::lock_write`].
+///
+/// Reading fails with [`io::ErrorKind::WouldBlock`] if the current thread holds a [`TerminalWriteLock`]
+/// while another thread holds the input half, see [`Terminal::lock`].
+impl io::Read for &Terminal {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.lock_read_in_order()?.read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        self.lock_read_in_order()?.read_vectored(bufs)
+    }
+}
+
+impl io::Write for &Terminal {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock_write().write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.lock_write().write_vectored(bufs)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.lock().flush()
+        self.lock_write().flush()
     }
 }
 
@@ -140,13 +603,94 @@ impl Terminal {
     ///
     /// Until the returned [`TerminalLock`] is dropped, all standard I/O streams
     /// that refer to the same terminal will be locked.
-    pub fn lock(&mut self) -> TerminalLock<'_> {
-        let mutex_guard = TERMINAL_LOCK.lock().unwrap_or_else(|e| e.into_inner());
-        let stdio_locks = self.0.lock_stdio();
+    ///
+    /// Unlike [`std::sync::Mutex`], the lock does not become unusable when a thread panics while holding it.
+    /// Terminal state that is changed by guards (e.g. raw mode) is restored while unwinding.
+    ///
+    /// The lock is reentrant: a thread that already holds a lock can lock the terminal again
+    /// (e.g. when calling into a library that also locks the terminal) without deadlocking.
+    /// Input that was buffered (e.g. using [`TerminalLock::unread`]) by an outer lock is not visible
+    /// to the inner lock until the outer lock is dropped.
+    /// The standard I/O streams are only locked by the outermost lock.
+    ///
+    /// ## Deadlocks
+    /// The input half is locked before the output half, so that threads can't deadlock each other.
+    /// A thread that already holds a [`TerminalWriteLock`] (but not the input half)
+    /// has to lock the halves in the opposite order. This waits for other threads that hold the input half
+    /// and deadlocks if one of them tries to lock the output half in the meantime
+    /// (e.g. by writing through a [`TerminalReadLock`]).
+    pub fn lock(&self) -> TerminalLock<'_> {
+        self.lock_with(LockedInputBuffer::lock(self.0.device_state()))
+    }
+
+    /// Like [`Terminal::lock`] but fails with [`io::ErrorKind::WouldBlock`] instead of
+    /// locking the halves in the opposite order while another thread holds the input half.
+    fn lock_in_order(&self) -> io::Result<TerminalLock<'_>> {
+        Ok(self.lock_with(LockedInputBuffer::lock_in_order(self.0.device_state())?))
+    }
+
+    fn lock_with<'a>(&'a self, input_buffer: LockedInputBuffer<'a>) -> TerminalLock<'a> {
+        let stdin_lock = input_buffer.lock_stdin(&self.0);
         TerminalLock {
-            inner: &mut self.0,
-            _stdio_locks: stdio_locks,
-            _mutex_guard: mutex_guard,
+            inner: &self.0,
+            input_buffer,
+            _stdin_lock: stdin_lock,
+            _output_lock: OutputLock::lock(&self.0),
+            _phantom_data: PhantomData,
+        }
+    }
+
+    /// Like [`Terminal::lock`] but doesn't lock the standard I/O streams.
+    ///
+    /// This is useful when the caller already holds a lock on standard input
+    /// (i.e. [`io::Stdin::lock`]) which is not reentrant, so [`Terminal::lock`] would deadlock.
+    /// Note that output written to the standard I/O streams by other threads
+    /// may interleave with output written through the returned guard.
+    pub fn lock_terminal_only(&self) -> TerminalLock<'_> {
+        TerminalLock {
+            inner: &self.0,
+            input_buffer: LockedInputBuffer::lock(self.0.device_state()),
+            _stdin_lock: StdioLocks::default(),
+            _output_lock: OutputLock::without_stdio(&self.0),
+            _phantom_data: PhantomData,
+        }
+    }
+
+    /// Locks only the input half of this terminal, returning a guard that is readable.
+    ///
+    /// Unlike [`Terminal::lock`], this doesn't prevent other threads from writing to the terminal
+    /// (using [`Terminal::lock_write`]) while this thread waits for input.
+    /// Standard input is locked if it refers to the same terminal.
+    ///
+    /// ## Deadlocks
+    /// See [`Terminal::lock`] for locking the input half while holding a [`TerminalWriteLock`].
+    pub fn lock_read(&self) -> TerminalReadLock<'_> {
+        self.lock_read_with(LockedInputBuffer::lock(self.0.device_state()))
+    }
+
+    fn lock_read_in_order(&self) -> io::Result<TerminalReadLock<'_>> {
+        Ok(self.lock_read_with(LockedInputBuffer::lock_in_order(self.0.device_state())?))
+    }
+
+    fn lock_read_with<'a>(&'a self, input_buffer: LockedInputBuffer<'a>) -> TerminalReadLock<'a> {
+        let stdin_lock = input_buffer.lock_stdin(&self.0);
+        TerminalReadLock {
+            inner: &self.0,
+            input_buffer,
+            _stdin_lock: stdin_lock,
+            _phantom_data: PhantomData,
+        }
+    }
+
+    /// Locks only the output half of this terminal, returning a guard that is writable.
+    ///
+    /// Unlike [`Terminal::lock`], this doesn't wait for other threads that are reading from the terminal
+    /// (using [`Terminal::lock_read`]).
+    /// Standard output and standard error are locked if they refer to the same terminal.
+    pub fn lock_write(&self) -> TerminalWriteLock<'_> {
+        TerminalWriteLock {
+            inner: &self.0,
+            _output_lock: OutputLock::lock(&self.0),
             _phantom_data: PhantomData,
         }
     }
@@ -156,80 +700,1474 @@ impl Terminal {
 /// Can be created using [`Terminal::lock`].
 #[derive(Debug)]
 pub struct TerminalLock<'a> {
-    inner: &'a mut imp::Terminal,
-    _mutex_guard: MutexGuard<'static, ()>,
-    _stdio_locks: StdioLocks,
+    inner: &'a imp::Terminal,
+    input_buffer: LockedInputBuffer<'a>,
+    _stdin_lock: StdioLocks,
+    _output_lock: OutputLock<'a>,
     _phantom_data: PhantomData<*mut ()>,
 }
 
 #[cfg(test)]
 static_assertions::assert_not_impl_any!(TerminalLock<'_>: Send, Sync);
 
-impl TerminalLock<'_> {
+/// Guard for exclusive read access to the terminal.
+/// Can be created using [`Terminal::lock_read`].
+#[derive(Debug)]
+pub struct TerminalReadLock<'a> {
+    inner: &'a imp::Terminal,
+    input_buffer: LockedInputBuffer<'a>,
+    _stdin_lock: StdioLocks,
+    _phantom_data: PhantomData<*mut ()>,
+}
+
+#[cfg(test)]
+static_assertions::assert_not_impl_any!(TerminalReadLock<'_>: Send, Sync);
+
+impl TerminalReadLock<'_> {
     /// Enables raw mode on this terminal for the lifetime of the returned guard.
+    /// See [`TerminalLock::enable_raw_mode`].
     ///
-    /// Raw mode has two effects:
-    /// * Input typed into the terminal is not visible.
-    /// * Input is can be read immediately (usually input is only available after a newline character).
-    /// * (Windows) Ensures that VT sequences are processed in both input and output.
-    ///
-    /// ### Windows
-    /// This function returns an [`Err`] with [`ErrorKind::Unsupported`](`io::ErrorKind::Unsupported`) if the standard input is
-    /// connected to a MSYS/Cygwin terminal.
-    pub fn enable_raw_mode(&mut self) -> io::Result<RawModeGuard<'_>> {
-        self.inner.enable_raw_mode().map(RawModeGuard)
+    /// Other threads can keep writing to the terminal (using [`Terminal::lock_write`]) while the guard is alive.
+    /// Writing through the guard locks the output half for the duration of each write.
+    pub fn enable_raw_mode(&mut self) -> Result<RawModeGuard<'_>, Error> {
+        self.enable_raw_mode_with(RawModeOptions::default())
     }
-}
 
-impl sealed::Sealed for TerminalLock<'_> {}
-impl Transceive for TerminalLock<'_> {}
+    /// Enables raw mode with the given [`RawModeOptions`] for the lifetime of the returned guard.
+    /// See [`TerminalReadLock::enable_raw_mode`].
+    pub fn enable_raw_mode_with(
+        &mut self,
+        options: RawModeOptions,
+    ) -> Result<RawModeGuard<'_>, Error> {
+        let input_buffer = &mut *self.input_buffer;
+        self.inner
+            .enable_raw_mode(&options)
+            .map(|guard| RawModeGuard(guard, input_buffer))
+            .map_err(Error::from)
+    }
 
-impl<'a> io::Read for TerminalLock<'a> {
+    /// Sends a query to the terminal and reads its response.
+    /// See [`TerminalLock::query`].
+    pub fn query(
+        &mut self,
+        request: &[u8],
+        is_complete: impl FnMut(&[u8]) -> bool,
+        timeout: Duration,
+    ) -> io::Result<Vec<u8>> {
+        query::query(&mut self.enable_raw_mode()?, request, is_complete, timeout)
+    }
+
+    /// Sends several queries at once and reads their responses in order.
+    /// See [`TerminalLock::query_many`].
+    pub fn query_many(&mut self, queries: &mut [Query<'_>]) -> io::Result<Vec<Option<Vec<u8>>>> {
+        query::query_many(&mut self.enable_raw_mode()?, queries)
+    }
+
+    /// Pushes bytes back into the input buffer so that they're returned by the next read.
+    /// See [`TerminalLock::unread`].
+    pub fn unread(&mut self, bytes: &[u8]) {
+        self.input_buffer.unread(bytes)
+    }
+}
+
+impl io::Read for TerminalReadLock<'_> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.inner.read(buf)
+        self.input_buffer.read(&mut self.inner, buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        self.input_buffer.read_vectored(&mut self.inner, bufs)
     }
 }
 
-impl<'a> io::Write for TerminalLock<'a> {
+impl io::BufRead for TerminalReadLock<'_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.input_buffer.fill_buf(&mut self.inner)
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.input_buffer.consume(amount)
+    }
+}
+
+/// Guard for exclusive write access to the terminal.
+/// Can be created using [`Terminal::lock_write`].
+#[derive(Debug)]
+pub struct TerminalWriteLock<'a> {
+    inner: &'a imp::Terminal,
+    _output_lock: OutputLock<'a>,
+    _phantom_data: PhantomData<*mut ()>,
+}
+
+#[cfg(test)]
+static_assertions::assert_not_impl_any!(TerminalWriteLock<'_>: Send, Sync);
+
+impl io::Write for TerminalWriteLock<'_> {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         self.inner.write(buf)
     }
 
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.inner.write_vectored(bufs)
+    }
+
     fn flush(&mut self) -> io::Result<()> {
         self.inner.flush()
     }
 }
 
 #[derive(Debug)]
-struct StdioLocks {
-    #[allow(dead_code)]
-    stdin_lock: Option<io::StdinLock<'static>>,
-    #[allow(dead_code)]
-    stdout_lock: Option<io::StdoutLock<'static>>,
-    #[allow(dead_code)]
-    stderr_lock: Option<io::StderrLock<'static>>,
+struct OutputLock<'a> {
+    _guard: ReentrantLockGuard<'a>,
+    _stdio_locks: StdioLocks,
 }
 
-/// Guard for raw mode on the terminal, disables raw mode on drop.
-/// Can be crated using [`TerminalLock::enable_raw_mode`].
-#[derive(Debug)]
-pub struct RawModeGuard<'a>(imp::RawModeGuard<'a>);
-
-impl sealed::Sealed for RawModeGuard<'_> {}
-impl Transceive for RawModeGuard<'_> {}
+impl<'a> OutputLock<'a> {
+    fn lock(terminal: &'a imp::Terminal) -> Self {
+        let guard = terminal.device_state().output.lock();
+        let stdio_locks = if guard.is_outermost() {
+            terminal.lock_stdout()
+        } else {
+            StdioLocks::default()
+        };
+        OutputLock {
+            _guard: guard,
+            _stdio_locks: stdio_locks,
+        }
+    }
 
-impl<'a> io::Read for RawModeGuard<'a> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.0.read(buf)
+    fn without_stdio(terminal: &'a imp::Terminal) -> Self {
+        OutputLock {
+            _guard: terminal.device_state().output.lock(),
+            _stdio_locks: StdioLocks::default(),
+        }
     }
 }
 
-impl<'a> io::Write for RawModeGuard<'a> {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.0.write(buf)
+impl TerminalLock<'_> {
+    /// Enables raw mode on this terminal for the lifetime of the returned guard.
+    ///
+    /// Raw mode has two effects:
+    /// * Input typed into the terminal is not visible.
+    /// * Input is can be read immediately (usually input is only available after a newline character).
+    /// * (Windows) Ensures that VT sequences are processed in both input and output.
+    ///
+    /// ### Windows
+    /// On MSYS/Cygwin terminals (which are not consoles), raw mode is enabled by running `stty`
+    /// and fails with [`Error::Unsupported`] if `stty` is not available.
+    pub fn enable_raw_mode(&mut self) -> Result<RawModeGuard<'_>, Error> {
+        self.enable_raw_mode_with(RawModeOptions::default())
+    }
+
+    /// Enables raw mode with the given [`RawModeOptions`] for the lifetime of the returned guard.
+    ///
+    /// See [`TerminalLock::enable_raw_mode`] for details.
+    pub fn enable_raw_mode_with(
+        &mut self,
+        options: RawModeOptions,
+    ) -> Result<RawModeGuard<'_>, Error> {
+        let input_buffer = &mut *self.input_buffer;
+        self.inner
+            .enable_raw_mode(&options)
+            .map(|guard| RawModeGuard(guard, input_buffer))
+            .map_err(Error::from)
+    }
+
+    /// Enables several modes (such as raw mode and the alternate screen) at once and
+    /// reverts them in reverse order when the returned [`ModeStack`] is dropped.
+    ///
+    /// If enabling a mode fails, the modes enabled so far are reverted.
+    pub fn enable_modes(&mut self, options: ModeStackOptions) -> io::Result<ModeStack<'_>> {
+        ModeStack::new(self.inner, &mut self.input_buffer, options)
+    }
+
+    /// Enables raw mode while running `f`, restoring the previous mode afterwards
+    /// (even if `f` panics).
+    ///
+    /// This is a convenience for [`TerminalLock::enable_raw_mode`] for when the guard
+    /// is only needed within a single scope.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use std::io::{Read as _, Write as _};
+    /// use terminal_trx::terminal;
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// let key = tty
+    ///     .lock()
+    ///     .with_raw_mode(|raw_mode| {
+    ///         write!(raw_mode, "Press any key to continue")?;
+    ///         let mut buf = [0; 1];
+    ///         raw_mode.read_exact(&mut buf).map(|_| buf[0])
+    ///     })
+    ///     .unwrap();
+    /// ```
+    pub fn with_raw_mode<R>(
+        &mut self,
+        f: impl FnOnce(&mut RawModeGuard<'_>) -> R,
+    ) -> Result<R, Error> {
+        let mut raw_mode = self.enable_raw_mode()?;
+        Ok(f(&mut raw_mode))
+    }
+
+    /// Sends a query to the terminal and reads its response.
+    ///
+    /// Raw mode is enabled while the query is in progress.
+    /// The response is read until `is_complete` returns `true` for the bytes received so far
+    /// (e.g. when the terminator of an escape sequence is received) or until the `timeout` elapses.
+    /// Input is read byte by byte, so that no input following the response is consumed.
+    /// The response is passed to `is_complete` one complete escape sequence at a time.
+    /// Keys typed while waiting for the response (i.e. bytes outside of escape sequences and
+    /// sequences sent by keys such as `ESC [ A` that don't complete the response)
+    /// are not part of the response and can be read again afterwards, see [`TerminalLock::unread`].
+    ///
+    /// ## Errors
+    /// * [`ErrorKind::TimedOut`](`io::ErrorKind::TimedOut`) if the response is not complete before the timeout elapses.
+    ///   This is usually the case when the terminal does not support the query.
+    /// * [`Error::Unsupported`] if the terminal is known not to answer queries, see [`QueryPolicy`].
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use terminal_trx::terminal;
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// // Cursor Position Report: ESC [ <row> ; <column> R
+    /// let response = tty
+    ///     .lock()
+    ///     .query(b"\x1b[6n", |r| r.ends_with(b"R"), Duration::from_secs(1))
+    ///     .unwrap();
+    /// ```
+    pub fn query(
+        &mut self,
+        request: &[u8],
+        is_complete: impl FnMut(&[u8]) -> bool,
+        timeout: Duration,
+    ) -> io::Result<Vec<u8>> {
+        query::query(&mut self.enable_raw_mode()?, request, is_complete, timeout)
+    }
+
+    /// Sends several queries at once and reads their responses in order.
+    ///
+    /// Compared to sending the queries one after another, this only waits for a single round trip,
+    /// which is noticeable over slow connections (e.g. SSH).
+    /// The response of each query is [`None`] if it's not complete before the query's timeout elapses,
+    /// in which case the bytes received so far are treated as the beginning of the next query's response.
+    ///
+    /// Responses are only told apart by their order: should a query go unanswered and the next response
+    /// also satisfy its `is_complete` (e.g. both end with `c`), that response is credited to the unanswered query.
+    /// Match the parts of the response that identify the query (such as the mode number of a `DECRQM` response)
+    /// or send queries that may go unanswered using [`TerminalLock::query`] instead.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use terminal_trx::{terminal, Query};
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// let mode = 2026;
+    /// let responses = tty
+    ///     .lock()
+    ///     .query_many(&mut [
+    ///         // Cursor Position Report
+    ///         Query::new(b"\x1b[6n", |r| r.ends_with(b"R")),
+    ///         // Report the state of synchronized output
+    ///         Query::new(b"\x1b[?2026$p", |r| {
+    ///             r.starts_with(format!("\x1b[?{mode};").as_bytes()) && r.ends_with(b"$y")
+    ///         }),
+    ///     ])
+    ///     .unwrap();
+    /// ```
+    pub fn query_many(&mut self, queries: &mut [Query<'_>]) -> io::Result<Vec<Option<Vec<u8>>>> {
+        query::query_many(&mut self.enable_raw_mode()?, queries)
+    }
+
+    /// Like [`TerminalLock::query`] but forwards the request through a terminal multiplexer
+    /// such as tmux, which would otherwise drop queries it doesn't understand.
+    ///
+    /// `is_complete` and the returned response don't include the passthrough wrapping.
+    /// Note that queries answered by the multiplexer itself (such as [`TerminalLock::device_attributes`])
+    /// shouldn't be forwarded, as both would respond.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use terminal_trx::{terminal, Passthrough};
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// let response = tty.lock().query_with_passthrough(
+    ///     Passthrough::detect(),
+    ///     b"\x1b]11;?\x07",
+    ///     |response| response.ends_with(b"\x07") || response.ends_with(b"\x1b\\"),
+    ///     Duration::from_secs(1),
+    /// );
+    /// ```
+    pub fn query_with_passthrough(
+        &mut self,
+        passthrough: Passthrough,
+        request: &[u8],
+        is_complete: impl FnMut(&[u8]) -> bool,
+        timeout: Duration,
+    ) -> io::Result<Vec<u8>> {
+        query::query_with_passthrough(
+            &mut self.enable_raw_mode()?,
+            passthrough,
+            request,
+            is_complete,
+            timeout,
+        )
+    }
+
+    /// Queries the terminal's Primary Device Attributes (DA1) by sending `ESC [ c`.
+    ///
+    /// Virtually all terminals respond to this query, which makes it useful
+    /// for detecting whether a terminal responds to queries at all.
+    /// Waits up to the [default timeout](`QueryPolicy::default_timeout`) for the response, see [`TerminalLock::query`].
+    pub fn device_attributes(&mut self) -> io::Result<DeviceAttributes> {
+        query::device_attributes(&mut self.enable_raw_mode()?)
+    }
+
+    /// Queries the terminal's name and version using `XTVERSION` (`CSI > 0 q`).
+    ///
+    /// Returns [`None`] if the terminal does not support the query.
+    /// To avoid waiting for the timeout on such terminals, the query is followed by
+    /// a Primary Device Attributes query (see [`TerminalLock::device_attributes`]) which virtually all terminals answer.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use terminal_trx::terminal;
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// let version = tty.lock().terminal_version().unwrap();
+    /// if let Some(version) = version {
+    ///     println!("running in {version}");
+    /// }
+    /// ```
+    pub fn terminal_version(&mut self) -> io::Result<Option<String>> {
+        query::terminal_version(&mut self.enable_raw_mode()?)
+    }
+
+    /// Identifies the terminal emulator, e.g. to select terminal-specific protocols.
+    ///
+    /// The terminal is asked for its name using `XTVERSION` (see [`TerminalLock::terminal_version`])
+    /// and, failing that, for its Secondary Device Attributes (`ESC [ > c`).
+    /// If neither response is recognized or the terminal doesn't answer (see [`QueryPolicy`]),
+    /// the terminal is identified using environment variables, see [`TerminalIdentity::from_env`].
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use terminal_trx::{terminal, TerminalIdentity};
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// if tty.lock().identity().unwrap() == TerminalIdentity::Kitty {
+    ///     // Use the kitty keyboard protocol
+    /// }
+    /// ```
+    pub fn identity(&mut self) -> io::Result<TerminalIdentity> {
+        identity::identify(&mut self.enable_raw_mode()?)
+    }
+
+    /// Detects how many colors the terminal supports.
+    ///
+    /// The terminal is asked to set a true color background which is then read back using `DECRQSS`
+    /// and the terminfo capabilities `RGB`, `Tc` and `colors` are queried using `XTGETTCAP`.
+    /// The graphic rendition (i.e. the current text style) is read before the probe and re-applied
+    /// afterwards. It is reset if the terminal doesn't report it.
+    /// Like [`TerminalLock::terminal_version`], the queries are followed by a Primary Device Attributes query
+    /// to avoid waiting for the timeout on terminals that don't respond.
+    ///
+    /// Many terminals support neither query, so the result is never less
+    /// than what the environment suggests, see [`ColorSupport::from_env`].
+    /// The environment alone is used if the terminal doesn't answer (see [`QueryPolicy`]).
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use terminal_trx::{terminal, ColorSupport};
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// if tty.lock().color_support().unwrap() >= ColorSupport::Ansi256 {
+    ///     // Use the 256-color palette
+    /// }
+    /// ```
+    pub fn color_support(&mut self) -> io::Result<ColorSupport> {
+        color::detect(&mut self.enable_raw_mode()?)
+    }
+
+    /// Queries the values of terminfo capabilities directly from the terminal using `XTGETTCAP` (`DCS + q <names> ST`).
+    ///
+    /// Returns the value for each of the given names in the same order.
+    /// The value is [`None`] if the terminal doesn't know the capability or does not support the query at all.
+    /// Boolean capabilities have an empty value.
+    /// Like [`TerminalLock::terminal_version`], the query is followed by a Primary Device Attributes query
+    /// to avoid waiting for the timeout on terminals that don't respond.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use terminal_trx::terminal;
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// let values = tty.lock().query_termcap(&["RGB", "Smulx"]).unwrap();
+    /// let supports_true_color = values[0].is_some();
+    /// ```
+    pub fn query_termcap(&mut self, names: &[&str]) -> io::Result<Vec<Option<String>>> {
+        query::termcap(&mut self.enable_raw_mode()?, names)
+    }
+
+    /// Queries the state of a private mode using `DECRQM` (`CSI ? <mode> $ p`),
+    /// e.g. `2026` for synchronized output or `2004` for bracketed paste.
+    ///
+    /// This is the standard way of detecting support for a mode.
+    /// Returns [`ModeState::NotRecognized`] if the terminal does not support the query.
+    /// Like [`TerminalLock::terminal_version`], the query is followed by a Primary Device Attributes query
+    /// to avoid waiting for the timeout on terminals that don't respond.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use terminal_trx::terminal;
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// let supports_bracketed_paste = tty.lock().query_mode(2004).unwrap().is_recognized();
+    /// ```
+    pub fn query_mode(&mut self, mode: u16) -> io::Result<ModeState> {
+        query::mode(&mut self.enable_raw_mode()?, mode)
+    }
+
+    /// Begins a synchronized update (`CSI ? 2026 h`), which is ended when the returned guard is dropped (`CSI ? 2026 l`).
+    ///
+    /// Terminals that support synchronized output don't render output written during the update until it is finished,
+    /// which avoids flickering and tearing when drawing a frame.
+    /// Since the guard holds on to the lock, no other writer can interleave with the frame.
+    ///
+    /// Terminals that don't support the mode ignore the sequences.
+    /// Use [`TerminalLock::begin_synchronized_update_if_supported`] to check for support first.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use std::io::Write as _;
+    /// use terminal_trx::terminal;
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// let mut lock = tty.lock();
+    /// let mut update = lock.begin_synchronized_update().unwrap();
+    /// write!(update, "\x1b[2J\x1b[Hhello world").unwrap();
+    /// ```
+    pub fn begin_synchronized_update(&mut self) -> io::Result<ModeGuard<'_, Self>> {
+        ModeGuard::new(self, Mode::SYNCHRONIZED_UPDATE)
+    }
+
+    /// Like [`TerminalLock::begin_synchronized_update`] but queries the mode using `DECRQM` first
+    /// and returns [`None`] without writing anything if the terminal doesn't support it.
+    pub fn begin_synchronized_update_if_supported(
+        &mut self,
+    ) -> io::Result<Option<ModeGuard<'_, Self>>> {
+        if !self.query_mode(2026)?.can_be_enabled() {
+            return Ok(None);
+        }
+        self.begin_synchronized_update().map(Some)
+    }
+
+    /// Enables application cursor keys mode (`DECCKM`, `CSI ? 1 h`) until the returned guard is dropped (`CSI ? 1 l`).
+    ///
+    /// In this mode, the cursor keys send `ESC O <key>` instead of `ESC [ <key>`.
+    pub fn enable_application_cursor_keys(&mut self) -> io::Result<ModeGuard<'_, Self>> {
+        ModeGuard::new(self, Mode::APPLICATION_CURSOR_KEYS)
+    }
+
+    /// Enables application keypad mode (`DECKPAM`, `ESC =`) until the returned guard is dropped (`DECKPNM`, `ESC >`).
+    ///
+    /// In this mode, the keys on the numeric keypad send escape sequences instead of the characters printed on them.
+    pub fn enable_application_keypad(&mut self) -> io::Result<ModeGuard<'_, Self>> {
+        ModeGuard::new(self, Mode::APPLICATION_KEYPAD)
+    }
+
+    /// Sets the terminal's title.
+    ///
+    /// Use [`TerminalLock::save_title`] to restore the previous title afterwards.
+    ///
+    /// ### Unix
+    /// The title is set using `OSC 2`. Control characters are removed from the title.
+    ///
+    /// ### Windows
+    /// The title is set using `SetConsoleTitleW`.
+    pub fn set_title(&mut self, title: &str) -> io::Result<()> {
+        self.inner.set_title(title)
+    }
+
+    /// Saves the terminal's title, which is restored when the returned guard is dropped.
+    ///
+    /// ### Unix
+    /// The title is saved on the terminal's title stack (`CSI 22 ; 0 t`) and restored using `CSI 23 ; 0 t` (`XTWINOPS`).
+    /// Terminals that don't support the title stack ignore these sequences.
+    ///
+    /// ### Windows
+    /// The title is saved using `GetConsoleTitleW` and restored using `SetConsoleTitleW`.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use terminal_trx::terminal;
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// let mut lock = tty.lock();
+    /// let mut title = lock.save_title().unwrap();
+    /// title.set_title("My App").unwrap();
+    /// ```
+    pub fn save_title(&mut self) -> io::Result<TitleGuard<'_, Self>> {
+        let terminal = self.inner;
+        TitleGuard::new(self, terminal)
+    }
+
+    /// Returns a writer that accumulates output until it is presented, see [`TerminalWriter`].
+    pub fn buffered(&mut self) -> TerminalWriter<'_, Self> {
+        TerminalWriter::new(self)
+    }
+
+    /// Mirrors all bytes read from and written to the terminal as annotated hex dumps to `log`,
+    /// see [`Recorder::hex_dump`].
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use std::io::Write as _;
+    /// use terminal_trx::terminal;
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// let mut lock = tty.lock();
+    /// let mut tap = lock.debug_tap(std::io::stderr());
+    /// write!(tap, "\x1b[c").unwrap();
+    /// ```
+    pub fn debug_tap<W: io::Write>(&mut self, log: W) -> Recorder<&mut Self, W> {
+        Recorder::hex_dump(self, log)
+    }
+
+    /// Reads input in raw mode until an [`Event`](`events::Event`) (e.g. a key press) is decoded,
+    /// see the [`events`] module for details.
+    ///
+    /// Returns [`None`] if no event was received within the timeout.
+    /// Waits indefinitely if the timeout is [`None`].
+    /// Input following the event is kept for subsequent reads.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use terminal_trx::events::Event;
+    /// use terminal_trx::terminal;
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// let mut lock = tty.lock();
+    /// if let Some(Event::Key(key)) = lock.read_event(Some(Duration::from_secs(5))).unwrap() {
+    ///     println!("pressed {:?}", key.code());
+    /// }
+    /// ```
+    #[cfg(feature = "events")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "events")))]
+    pub fn read_event(&mut self, timeout: Option<Duration>) -> io::Result<Option<events::Event>> {
+        self.read_event_with(events::ReadEventOptions::new().timeout(timeout))
+    }
+
+    /// Reads an event with the given [`ReadEventOptions`](`events::ReadEventOptions`).
+    ///
+    /// See [`TerminalLock::read_event`] for details.
+    #[cfg(feature = "events")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "events")))]
+    pub fn read_event_with(
+        &mut self,
+        options: events::ReadEventOptions,
+    ) -> io::Result<Option<events::Event>> {
+        events::read_event(&mut self.enable_raw_mode()?, options)
+    }
+
+    /// Waits for a key press in raw mode and returns it, discarding any other events
+    /// (see [`TerminalLock::read_event`]). The previous mode is restored before returning.
+    ///
+    /// ## Errors
+    /// [`ErrorKind::UnexpectedEof`](`io::ErrorKind::UnexpectedEof`) if the input ends before a key is pressed.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use std::io::Write as _;
+    /// use terminal_trx::terminal;
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// let mut lock = tty.lock();
+    /// write!(lock, "Press any key to continue...").unwrap();
+    /// lock.read_key().unwrap();
+    /// ```
+    #[cfg(feature = "events")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "events")))]
+    pub fn read_key(&mut self) -> io::Result<events::KeyEvent> {
+        events::read_key(&mut self.enable_raw_mode()?)
+    }
+
+    /// Disables echo on this terminal for the lifetime of the returned guard.
+    ///
+    /// Unlike raw mode, input is still line-buffered and can be edited (e.g. using backspace)
+    /// before it is submitted. This makes it a good fit for password prompts.
+    /// On Unix, the final newline is still echoed so that the cursor advances to the next line.
+    ///
+    /// ### Windows
+    /// On MSYS/Cygwin terminals (which are not consoles), raw mode is enabled by running `stty`
+    /// and fails with [`Error::Unsupported`] if `stty` is not available.
+    pub fn disable_echo(&mut self) -> Result<RawModeGuard<'_>, Error> {
+        self.enable_raw_mode_with(RawModeOptions::echo_disabled())
+    }
+
+    /// Reads a line of input from the terminal with echo disabled (see [`TerminalLock::disable_echo`])
+    /// and returns it without the trailing newline.
+    ///
+    /// Echo is restored before this function returns, regardless of whether reading succeeded.
+    ///
+    /// ## Errors
+    /// * [`ErrorKind::UnexpectedEof`](`io::ErrorKind::UnexpectedEof`) if the input ends (e.g. using Ctrl+D) before anything was entered.
+    /// * [`ErrorKind::InvalidData`](`io::ErrorKind::InvalidData`) if the input is not valid UTF-8.
+    /// * [`ErrorKind::Interrupted`](`io::ErrorKind::Interrupted`) if reading was interrupted by a signal (e.g. Ctrl+C with a signal handler installed).
+    ///   Unlike most reads, this is not retried so that the user can abort the prompt.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use terminal_trx::terminal;
+    /// use std::io::Write as _;
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// let mut lock = tty.lock();
+    /// write!(lock, "Password: ").unwrap();
+    /// let password = lock.read_password().unwrap();
+    /// ```
+    pub fn read_password(&mut self) -> io::Result<String> {
+        let mut guard = self.disable_echo()?;
+        password::read_line(&mut password::Interruptible(&mut guard))
+    }
+
+    /// Reads a single character, consuming no input after it.
+    ///
+    /// Characters are decoded from UTF-8 even when split across multiple reads.
+    /// Invalid input is returned as [`char::REPLACEMENT_CHARACTER`].
+    /// Unless raw mode is enabled, this waits until a line has been entered.
+    ///
+    /// ## Errors
+    /// [`ErrorKind::UnexpectedEof`](`io::ErrorKind::UnexpectedEof`) if the input ends.
+    ///
+    /// ### Windows
+    /// Console input is read as UTF-16 and converted to UTF-8, so characters are
+    /// decoded correctly regardless of the console's input code page.
+    pub fn read_char(&mut self) -> io::Result<char> {
+        utf8::read_char(self)
+    }
+
+    /// Discards input that has been received but not yet read.
+    ///
+    /// This is useful to drop type-ahead before a prompt or stale bytes before sending a query.
+    /// Input that was buffered (see [`TerminalLock::unread`]) is discarded as well.
+    pub fn discard_input(&mut self) -> io::Result<()> {
+        self.input_buffer.clear();
+        self.inner.discard(Queue::Input)
+    }
+
+    /// Discards output that has been written but not yet transmitted.
+    ///
+    /// ### Windows
+    /// Output is written to the console synchronously, so this does nothing.
+    pub fn discard_output(&mut self) -> io::Result<()> {
+        self.inner.discard(Queue::Output)
+    }
+
+    /// Discards both pending input and output.
+    /// See [`TerminalLock::discard_input`] and [`TerminalLock::discard_output`].
+    pub fn discard_both(&mut self) -> io::Result<()> {
+        self.input_buffer.clear();
+        self.inner.discard(Queue::Both)
+    }
+
+    /// Pushes the given bytes back to the input, so that they're returned by the next read.
+    ///
+    /// This is useful to return input that was read by accident (e.g. keystrokes
+    /// received while waiting for the response to a query) or to implement lookahead.
+    /// The bytes are kept in a buffer that is shared by all handles to the terminal.
+    /// Use the [`io::BufRead`] implementation to peek at the input without consuming it.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use std::io::Read as _;
+    /// use terminal_trx::terminal;
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// let mut lock = tty.lock();
+    /// let mut raw_mode = lock.enable_raw_mode().unwrap();
+    ///
+    /// let mut buf = [0; 1];
+    /// raw_mode.read_exact(&mut buf).unwrap();
+    /// if buf[0] != b'\x1b' {
+    ///     raw_mode.unread(&buf);
+    /// }
+    /// ```
+    pub fn unread(&mut self, bytes: &[u8]) {
+        self.input_buffer.unread(bytes)
+    }
+
+    /// Writes as much of the buffer as possible until the timeout elapses, returning the number of bytes written.
+    ///
+    /// Unlike [`io::Write::write`], this doesn't block indefinitely when the terminal stops accepting output,
+    /// e.g. after the user paused output with Ctrl+S or when the connection (e.g. SSH) hangs.
+    /// This allows applications such as progress reporters to skip updates instead.
+    ///
+    /// ## Errors
+    /// [`ErrorKind::TimedOut`](`io::ErrorKind::TimedOut`) if nothing could be written before the timeout elapsed.
+    ///
+    /// ### Unix
+    /// The terminal device is opened a second time (non-blocking) on first use.
+    /// If that isn't possible (e.g. because the device has no path), a write that has started may
+    /// still block past the timeout.
+    ///
+    /// ### Windows
+    /// There's no way to wait for the console to accept output, so this is a regular write.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use std::io;
+    /// use std::time::Duration;
+    /// use terminal_trx::terminal;
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// let mut lock = tty.lock();
+    /// match lock.write_timeout(b"\r50%", Duration::from_millis(10)) {
+    ///     Err(e) if e.kind() == io::ErrorKind::TimedOut => { /* drop this frame */ }
+    ///     result => _ = result.unwrap(),
+    /// }
+    /// ```
+    pub fn write_timeout(&mut self, buf: &[u8], timeout: Duration) -> io::Result<usize> {
+        self.inner.write_timeout(buf, timeout)
+    }
+
+    /// Blocks until all output written to the terminal has been transmitted.
+    ///
+    /// ### Windows
+    /// Writes to the console are synchronous, so this only waits
+    /// when the terminal is not a console (e.g. a MSYS/Cygwin terminal).
+    pub fn drain(&mut self) -> io::Result<()> {
+        self.inner.drain()
+    }
+
+    /// Restarts output that was stopped using flow control (e.g. when the user pressed Ctrl+S).
+    ///
+    /// Stopped output makes the application look frozen as writes block once the
+    /// terminal's output queue is full. See [`TerminalAttributes::set_flow_control`] to disable flow control.
+    ///
+    /// ### Windows
+    /// This does nothing.
+    pub fn resume_output(&mut self) -> io::Result<()> {
+        self.inner.resume_output()
+    }
+
+    /// Sets the size of the terminal in rows and columns.
+    ///
+    /// This is mostly useful for pseudo-terminals (see the `pty` module), where a program
+    /// proxying another terminal needs to pass on size changes to the program running inside.
+    /// The foreground process group is notified using `SIGWINCH`.
+    ///
+    /// ### Windows
+    /// Both the console's window and screen buffer are resized, which discards the scrollback.
+    /// Fails with [`ErrorKind::InvalidInput`](`io::ErrorKind::InvalidInput`) if the size is zero or too large
+    /// and with [`ErrorKind::Unsupported`](`io::ErrorKind::Unsupported`) for MSYS/Cygwin terminals.
+    pub fn set_size(&mut self, rows: u16, columns: u16) -> io::Result<()> {
+        self.inner.set_size(rows, columns)
+    }
+
+    /// Retrieves the current [`TerminalAttributes`] of this terminal.
+    pub fn attributes(&mut self) -> io::Result<TerminalAttributes> {
+        self.inner.attributes().map(TerminalAttributes)
+    }
+
+    /// Applies the given [`TerminalAttributes`] to this terminal.
+    ///
+    /// The change takes effect after all pending output has been transmitted
+    /// (see [`ModeChangeTiming::Drain`]).
+    pub fn set_attributes(&mut self, attributes: &TerminalAttributes) -> io::Result<()> {
+        self.inner.set_attributes(&attributes.0)
+    }
+
+    /// Restores a [`TerminalState`] captured earlier.
+    ///
+    /// The alternate screen is left (or entered) and the cursor is shown (or hidden)
+    /// before the attributes are applied.
+    pub fn restore(&mut self, state: &TerminalState) -> io::Result<()> {
+        state.write_modes(self)?;
+        self.set_attributes(state.attributes())
+    }
+
+    /// Runs `command` to completion in cooked mode (i.e. with canonical mode, echo and signals enabled),
+    /// restoring this terminal's attributes afterwards (e.g. when the child crashed and left the terminal in raw mode).
+    ///
+    /// Pending output is transmitted before the child is started.
+    /// Use [`RawModeGuard::run_child`] to restore the mode from before raw mode was enabled instead.
+    ///
+    /// The terminal stays locked while the child is running. The locks (including the ones
+    /// of the standard I/O streams) only exclude other threads of this process, so the child is not affected
+    /// but other threads are kept from writing over the child's output.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use std::process::Command;
+    /// use terminal_trx::terminal;
+    ///
+    /// let tty = terminal().unwrap();
+    /// let editor = std::env::var_os("EDITOR").unwrap_or_else(|| "vi".into());
+    /// let status = tty.lock().run_child(Command::new(editor).arg("notes.txt")).unwrap();
+    /// ```
+    pub fn run_child(&mut self, command: &mut Command) -> io::Result<ExitStatus> {
+        io::Write::flush(self)?;
+        let attributes = self.attributes()?;
+        let mut cooked = attributes.clone();
+        cooked.set_canonical(true);
+        cooked.set_echo(true);
+        cooked.set_signals(true);
+        self.set_attributes(&cooked)?;
+        let status = command.status();
+        self.set_attributes(&attributes)?;
+        status
+    }
+
+    /// Enables or disables generating signals for special characters such as Ctrl+C,
+    /// without otherwise changing the terminal's mode (unlike raw mode).
+    ///
+    /// With signals disabled, Ctrl+C is read as input (`0x03`) while canonical mode
+    /// (i.e. line editing) keeps working.
+    /// This is a shorthand for [`TerminalAttributes::set_signals`].
+    ///
+    /// ### Unix
+    /// This sets `ISIG`.
+    ///
+    /// ### Windows
+    /// This sets `ENABLE_PROCESSED_INPUT`.
+    pub fn set_signal_generation(&mut self, enabled: bool) -> io::Result<()> {
+        let mut attributes = self.attributes()?;
+        attributes.set_signals(enabled);
+        self.set_attributes(&attributes)
+    }
+
+    /// Checks whether the terminal is currently in raw mode,
+    /// regardless of whether raw mode was enabled by this crate or someone else.
+    ///
+    /// The terminal is considered to be in raw mode if both echo and canonical mode are disabled.
+    pub fn is_raw_mode_enabled(&mut self) -> io::Result<bool> {
+        self.attributes()
+            .map(|attributes| !attributes.echo() && !attributes.canonical())
+    }
+
+    /// Performs a soft reset (`DECSTR`) and restores sane attributes (like `stty sane`),
+    /// e.g. after a child program crashed and left the terminal in raw mode.
+    ///
+    /// A soft reset shows the cursor and resets the character attributes, the scrolling region and
+    /// modes such as application cursor keys without clearing the screen.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use std::process::Command;
+    /// use terminal_trx::terminal;
+    ///
+    /// let tty = terminal().unwrap();
+    /// let status = Command::new("htop").status().unwrap();
+    /// if !status.success() {
+    ///     tty.lock().soft_reset().unwrap();
+    /// }
+    /// ```
+    pub fn soft_reset(&mut self) -> io::Result<()> {
+        self.reset_with(b"\x1b[!p")
+    }
+
+    /// Performs a full reset (`RIS`) and restores sane attributes (like `stty sane`),
+    /// the programmatic equivalent of running `reset`.
+    ///
+    /// Unlike [`TerminalLock::soft_reset`], this also clears the screen and the scrollback
+    /// and leaves the alternate screen.
+    pub fn reset(&mut self) -> io::Result<()> {
+        self.reset_with(b"\x1bc")
+    }
+
+    fn reset_with(&mut self, sequence: &[u8]) -> io::Result<()> {
+        io::Write::write_all(self, sequence)?;
+        io::Write::flush(self)?;
+        let mut attributes = self.attributes()?;
+        attributes.0.make_sane();
+        self.set_attributes(&attributes)
+    }
+}
+
+#[cfg(all(unix, not(terminal_trx_test_unsupported)))]
+#[cfg_attr(docsrs, doc(cfg(unix)))]
+impl TerminalLock<'_> {
+    /// Reads the terminal's `termios` structure, lets `f` modify it and applies the result.
+    ///
+    /// This is an escape hatch for settings not covered by [`TerminalAttributes`],
+    /// such as `IUTF8` or special characters.
+    /// The change takes effect after all pending output has been transmitted (see [`ModeChangeTiming::Drain`]).
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use terminal_trx::terminal;
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// let mut lock = tty.lock();
+    /// lock.with_termios(|termios| termios.c_iflag |= libc::IUTF8).unwrap();
+    /// ```
+    pub fn with_termios<R>(&mut self, f: impl FnOnce(&mut libc::termios) -> R) -> io::Result<R> {
+        self.inner.with_termios(f)
+    }
+}
+
+#[cfg(all(windows, not(terminal_trx_test_unsupported)))]
+#[cfg_attr(docsrs, doc(cfg(windows)))]
+impl TerminalLock<'_> {
+    /// Enables processing of VT sequences (`ENABLE_VIRTUAL_TERMINAL_PROCESSING`) on the console's screen buffer
+    /// until the returned guard is dropped.
+    ///
+    /// Without this, VT sequences written to older consoles are printed verbatim.
+    /// If `disable_newline_auto_return` is `true`, `DISABLE_NEWLINE_AUTO_RETURN` is set as well,
+    /// so that a newline does not implicitly move the cursor to the start of the line.
+    ///
+    /// Note that raw mode already enables VT processing.
+    pub fn enable_virtual_terminal_processing(
+        &mut self,
+        disable_newline_auto_return: bool,
+    ) -> io::Result<VirtualTerminalProcessingGuard<'_, Self>> {
+        let terminal = self.inner;
+        VirtualTerminalProcessingGuard::new(self, terminal, disable_newline_auto_return)
+    }
+
+    /// Returns the console's input code page, which is used to encode
+    /// input that's read as bytes (e.g. from a MSYS/Cygwin pipe or by other libraries).
+    ///
+    /// Note that reads through this crate always use UTF-8 when talking to a console.
+    pub fn input_code_page(&self) -> io::Result<u32> {
+        imp::input_code_page()
+    }
+
+    /// Returns the console's output code page, which is used to decode bytes written to the console.
+    pub fn output_code_page(&self) -> io::Result<u32> {
+        imp::output_code_page()
+    }
+
+    /// Sets the console's input and output code pages to UTF-8 (65001)
+    /// until the returned guard is dropped.
+    ///
+    /// The code pages are shared by all processes attached to the console,
+    /// so they should not be changed for longer than necessary.
+    pub fn enable_utf8_code_page(&mut self) -> io::Result<CodePageGuard<'_, Self>> {
+        CodePageGuard::new(self)
+    }
+
+    /// Reads events from the console's input buffer (using `ReadConsoleInputW`).
+    /// Blocks until at least one event is available.
+    ///
+    /// Unlike reading bytes, this reports key releases and the state of modifier keys,
+    /// as well as mouse, resize and focus events (if enabled in the console's input mode).
+    /// Events are removed from the input buffer, i.e. they are not returned by subsequent reads.
+    pub fn read_input_events(&mut self) -> io::Result<Vec<InputEvent>> {
+        self.inner.read_input_events()
+    }
+
+    /// Returns information about the console's screen buffer such as the cursor position
+    /// and the visible window (using `GetConsoleScreenBufferInfo`).
+    pub fn screen_buffer_info(&self) -> io::Result<ScreenBufferInfo> {
+        self.inner.screen_buffer_info()
+    }
+}
+
+impl sealed::Sealed for TerminalLock<'_> {}
+impl Transceive for TerminalLock<'_> {}
+
+impl<'a> io::Read for TerminalLock<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input_buffer.read(&mut self.inner, buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        self.input_buffer.read_vectored(&mut self.inner, bufs)
+    }
+}
+
+impl<'a> io::BufRead for TerminalLock<'a> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.input_buffer.fill_buf(&mut self.inner)
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.input_buffer.consume(amount)
+    }
+}
+
+impl<'a> io::Write for TerminalLock<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.inner.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// The queue(s) affected by a discard operation.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Queue {
+    Input,
+    Output,
+    Both,
+}
+
+#[derive(Debug, Default)]
+struct StdioLocks {
+    #[allow(dead_code)]
+    stdin_lock: Option<io::StdinLock<'static>>,
+    #[allow(dead_code)]
+    stdout_lock: Option<io::StdoutLock<'static>>,
+    #[allow(dead_code)]
+    stderr_lock: Option<io::StderrLock<'static>>,
+}
+
+/// Guard for raw mode on the terminal, disables raw mode on drop.
+/// Can be crated using [`TerminalLock::enable_raw_mode`], [`TerminalLock::enable_raw_mode_with`]
+/// or [`TerminalLock::disable_echo`].
+///
+/// The previous mode is only restored once all pending output has been transmitted
+/// (see [`TerminalLock::drain`]), so that output written in raw mode is not affected.
+#[derive(Debug)]
+pub struct RawModeGuard<'a>(imp::RawModeGuard<'a>, &'a mut InputBuffer);
+
+impl RawModeGuard<'_> {
+    /// Enables raw mode again while this guard is alive, e.g. with different [`RawModeOptions`].
+    ///
+    /// Nested guards restore the mode they replaced when dropped.
+    /// The original mode is only restored once all guards are dropped.
+    pub fn enable_raw_mode(&mut self) -> Result<RawModeGuard<'_>, Error> {
+        self.enable_raw_mode_with(RawModeOptions::default())
+    }
+
+    /// Enables raw mode with the given [`RawModeOptions`] while this guard is alive.
+    /// See [`RawModeGuard::enable_raw_mode`].
+    pub fn enable_raw_mode_with(
+        &mut self,
+        options: RawModeOptions,
+    ) -> Result<RawModeGuard<'_>, Error> {
+        self.0
+            .enable_raw_mode(&options)
+            .map(|guard| RawModeGuard(guard, self.1))
+            .map_err(Error::from)
+    }
+
+    /// Consumes this guard without restoring the previous mode, leaving raw mode enabled.
+    ///
+    /// Returns the attributes that this guard would have restored.
+    /// Restore them explicitly using [`Terminal::restore`] once you're done.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use terminal_trx::terminal;
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// let cooked_mode = tty.lock().enable_raw_mode().unwrap().persist();
+    ///
+    /// // ...
+    ///
+    /// tty.restore(&cooked_mode).unwrap();
+    /// ```
+    pub fn persist(self) -> TerminalAttributes {
+        TerminalAttributes(self.0.persist())
+    }
+
+    /// Temporarily restores the original mode (i.e. the mode before the first guard was created)
+    /// while running `f`, e.g. to spawn an editor.
+    ///
+    /// The current mode is re-applied once `f` returns, even if `f` panics.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use std::process::Command;
+    /// use terminal_trx::terminal;
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// let mut lock = tty.lock();
+    /// let mut raw_mode = lock.enable_raw_mode().unwrap();
+    ///
+    /// let status = raw_mode
+    ///     .suspend(|| Command::new("vi").status())
+    ///     .unwrap();
+    /// ```
+    pub fn suspend<R>(&mut self, f: impl FnOnce() -> R) -> io::Result<R> {
+        let current = self.0.attributes()?;
+        let original = self.0.original_attributes();
+        self.0.set_attributes(&original)?;
+        let reapply = ReapplyOnDrop::new(&self.0, current);
+        let result = f();
+        reapply.finish()?;
+        Ok(result)
+    }
+
+    /// Runs `command` to completion while the original mode is restored (see [`RawModeGuard::suspend`]),
+    /// e.g. to launch `$EDITOR`, `$PAGER` or a shell.
+    ///
+    /// Pending output is transmitted before the child is started and the current
+    /// mode is re-applied once the child exits, even if it changed the terminal's mode.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use std::process::Command;
+    /// use terminal_trx::terminal;
+    ///
+    /// let mut tty = terminal().unwrap();
+    /// let mut lock = tty.lock();
+    /// let mut raw_mode = lock.enable_raw_mode().unwrap();
+    /// let status = raw_mode.run_child(&mut Command::new("less")).unwrap();
+    /// ```
+    pub fn run_child(&mut self, command: &mut Command) -> io::Result<ExitStatus> {
+        io::Write::flush(self)?;
+        self.suspend(|| command.status())?
+    }
+
+    /// Sends a query to the terminal and reads its response.
+    /// See [`TerminalLock::query`].
+    pub fn query(
+        &mut self,
+        request: &[u8],
+        is_complete: impl FnMut(&[u8]) -> bool,
+        timeout: Duration,
+    ) -> io::Result<Vec<u8>> {
+        query::query(&mut self.enable_raw_mode()?, request, is_complete, timeout)
+    }
+
+    /// Sends several queries at once and reads their responses in order.
+    /// See [`TerminalLock::query_many`].
+    pub fn query_many(&mut self, queries: &mut [Query<'_>]) -> io::Result<Vec<Option<Vec<u8>>>> {
+        query::query_many(&mut self.enable_raw_mode()?, queries)
+    }
+
+    /// Sends a query through a terminal multiplexer and reads its response.
+    /// See [`TerminalLock::query_with_passthrough`].
+    pub fn query_with_passthrough(
+        &mut self,
+        passthrough: Passthrough,
+        request: &[u8],
+        is_complete: impl FnMut(&[u8]) -> bool,
+        timeout: Duration,
+    ) -> io::Result<Vec<u8>> {
+        query::query_with_passthrough(
+            &mut self.enable_raw_mode()?,
+            passthrough,
+            request,
+            is_complete,
+            timeout,
+        )
+    }
+
+    /// Queries the terminal's Primary Device Attributes (DA1).
+    /// See [`TerminalLock::device_attributes`].
+    pub fn device_attributes(&mut self) -> io::Result<DeviceAttributes> {
+        query::device_attributes(&mut self.enable_raw_mode()?)
+    }
+
+    /// Queries the terminal's name and version using `XTVERSION`.
+    /// See [`TerminalLock::terminal_version`].
+    pub fn terminal_version(&mut self) -> io::Result<Option<String>> {
+        query::terminal_version(&mut self.enable_raw_mode()?)
+    }
+
+    /// Identifies the terminal emulator.
+    /// See [`TerminalLock::identity`].
+    pub fn identity(&mut self) -> io::Result<TerminalIdentity> {
+        identity::identify(&mut self.enable_raw_mode()?)
+    }
+
+    /// Detects how many colors the terminal supports.
+    /// See [`TerminalLock::color_support`].
+    pub fn color_support(&mut self) -> io::Result<ColorSupport> {
+        color::detect(&mut self.enable_raw_mode()?)
+    }
+
+    /// Queries the values of terminfo capabilities using `XTGETTCAP`.
+    /// See [`TerminalLock::query_termcap`].
+    pub fn query_termcap(&mut self, names: &[&str]) -> io::Result<Vec<Option<String>>> {
+        query::termcap(&mut self.enable_raw_mode()?, names)
+    }
+
+    /// Queries the state of a private mode using `DECRQM`.
+    /// See [`TerminalLock::query_mode`].
+    pub fn query_mode(&mut self, mode: u16) -> io::Result<ModeState> {
+        query::mode(&mut self.enable_raw_mode()?, mode)
+    }
+
+    /// Begins a synchronized update.
+    /// See [`TerminalLock::begin_synchronized_update`].
+    pub fn begin_synchronized_update(&mut self) -> io::Result<ModeGuard<'_, Self>> {
+        ModeGuard::new(self, Mode::SYNCHRONIZED_UPDATE)
+    }
+
+    /// Begins a synchronized update if the terminal supports it.
+    /// See [`TerminalLock::begin_synchronized_update_if_supported`].
+    pub fn begin_synchronized_update_if_supported(
+        &mut self,
+    ) -> io::Result<Option<ModeGuard<'_, Self>>> {
+        if !self.query_mode(2026)?.can_be_enabled() {
+            return Ok(None);
+        }
+        self.begin_synchronized_update().map(Some)
+    }
+
+    /// Enables application cursor keys mode (`DECCKM`).
+    /// See [`TerminalLock::enable_application_cursor_keys`].
+    pub fn enable_application_cursor_keys(&mut self) -> io::Result<ModeGuard<'_, Self>> {
+        ModeGuard::new(self, Mode::APPLICATION_CURSOR_KEYS)
+    }
+
+    /// Enables application keypad mode (`DECKPAM`).
+    /// See [`TerminalLock::enable_application_keypad`].
+    pub fn enable_application_keypad(&mut self) -> io::Result<ModeGuard<'_, Self>> {
+        ModeGuard::new(self, Mode::APPLICATION_KEYPAD)
+    }
+
+    /// Sets the terminal's title.
+    /// See [`TerminalLock::set_title`].
+    pub fn set_title(&mut self, title: &str) -> io::Result<()> {
+        self.0.set_title(title)
+    }
+
+    /// Saves the terminal's title, which is restored when the returned guard is dropped.
+    /// See [`TerminalLock::save_title`].
+    pub fn save_title(&mut self) -> io::Result<TitleGuard<'_, Self>> {
+        let terminal = self.0.terminal();
+        TitleGuard::new(self, terminal)
+    }
+
+    /// Returns a writer that accumulates output until it is presented, see [`TerminalWriter`].
+    pub fn buffered(&mut self) -> TerminalWriter<'_, Self> {
+        TerminalWriter::new(self)
+    }
+
+    /// Mirrors all bytes read from and written to the terminal as annotated hex dumps to `log`.
+    /// See [`TerminalLock::debug_tap`].
+    pub fn debug_tap<W: io::Write>(&mut self, log: W) -> Recorder<&mut Self, W> {
+        Recorder::hex_dump(self, log)
+    }
+
+    /// Reads input until an [`Event`](`events::Event`) is decoded or the timeout elapses.
+    /// See [`TerminalLock::read_event`].
+    #[cfg(feature = "events")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "events")))]
+    pub fn read_event(&mut self, timeout: Option<Duration>) -> io::Result<Option<events::Event>> {
+        self.read_event_with(events::ReadEventOptions::new().timeout(timeout))
+    }
+
+    /// Reads an event with the given [`ReadEventOptions`](`events::ReadEventOptions`).
+    /// See [`TerminalLock::read_event_with`].
+    #[cfg(feature = "events")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "events")))]
+    pub fn read_event_with(
+        &mut self,
+        options: events::ReadEventOptions,
+    ) -> io::Result<Option<events::Event>> {
+        events::read_event(&mut self.enable_raw_mode()?, options)
+    }
+
+    /// Waits for a key press, discarding any other events.
+    /// See [`TerminalLock::read_key`].
+    #[cfg(feature = "events")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "events")))]
+    pub fn read_key(&mut self) -> io::Result<events::KeyEvent> {
+        events::read_key(&mut self.enable_raw_mode()?)
+    }
+
+    /// Reads a single character, consuming no input after it.
+    /// See [`TerminalLock::read_char`].
+    pub fn read_char(&mut self) -> io::Result<char> {
+        utf8::read_char(self)
+    }
+
+    /// Reads without retrying when interrupted by a signal, see [`Terminal::set_retry_interrupted`].
+    fn read_interruptible(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.1.read(&mut self.0.interruptible(), buf)
+    }
+
+    /// Configures when reads return while in raw mode.
+    ///
+    /// * `min` is the minimum number of bytes a read waits for.
+    /// * `timeout` is the time a read waits for input (with a granularity of tenths of a second, up to 25.5 seconds).
+    ///   A read that times out returns `Ok(0)`, use [`RawModeGuard::read_timeout`] to tell it apart from the end of input.
+    ///
+    /// With a `min` of `0` and no `timeout`, reads return immediately with whatever input is available.
+    /// The previous behaviour is restored when this guard is dropped.
+    ///
+    /// ### Unix
+    /// This sets `VMIN` and `VTIME` (see `man termios`).
+    ///
+    /// ### Windows
+    /// This is emulated by waiting for the console input buffer before reading.
+    /// `min` is only distinguished between zero and non-zero.
+    /// Note that the input buffer also signals non-keyboard events (such as focus changes)
+    /// in which case a read may still block.
+    pub fn set_read_behavior(&mut self, min: u8, timeout: Option<Duration>) -> io::Result<()> {
+        self.0.set_read_behavior(min, timeout)
+    }
+
+    /// Like [`io::Read::read`] but waits at most `timeout` for input to arrive.
+    ///
+    /// ## Errors
+    /// [`ErrorKind::TimedOut`](`io::ErrorKind::TimedOut`) if no input arrived before the timeout elapsed.
+    /// Unlike the `Ok(0)` returned by reads that time out according to [`RawModeGuard::set_read_behavior`],
+    /// this can be told apart from the end of input.
+    ///
+    /// ### Windows
+    /// The console input buffer also signals non-keyboard events (such as focus changes)
+    /// in which case the read may still block.
+    pub fn read_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> io::Result<usize> {
+        if self.1.is_empty() && !self.0.wait_for_input(Some(timeout))? {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        io::Read::read(self, buf)
+    }
+
+    /// Checks whether someone else (e.g. a child process) changed the terminal's mode
+    /// since this guard enabled raw mode.
+    ///
+    /// See [`RawModeOptions::external_changes`] for how such changes are treated
+    /// when this guard is dropped.
+    ///
+    /// ### Windows
+    /// Fails with [`ErrorKind::Unsupported`](`io::ErrorKind::Unsupported`) for MSYS/Cygwin terminals.
+    pub fn has_external_changes(&mut self) -> io::Result<bool> {
+        self.0.has_external_changes()
+    }
+
+    /// Discards input that has been received but not yet read.
+    /// See [`TerminalLock::discard_input`].
+    pub fn discard_input(&mut self) -> io::Result<()> {
+        self.1.clear();
+        self.0.discard(Queue::Input)
+    }
+
+    /// Discards output that has been written but not yet transmitted.
+    /// See [`TerminalLock::discard_output`].
+    pub fn discard_output(&mut self) -> io::Result<()> {
+        self.0.discard(Queue::Output)
+    }
+
+    /// Discards both pending input and output.
+    /// See [`TerminalLock::discard_both`].
+    pub fn discard_both(&mut self) -> io::Result<()> {
+        self.1.clear();
+        self.0.discard(Queue::Both)
+    }
+
+    /// Pushes the given bytes back to the input, so that they're returned by the next read.
+    /// See [`TerminalLock::unread`].
+    pub fn unread(&mut self, bytes: &[u8]) {
+        self.1.unread(bytes)
+    }
+
+    /// Writes as much of the buffer as possible until the timeout elapses.
+    /// See [`TerminalLock::write_timeout`].
+    pub fn write_timeout(&mut self, buf: &[u8], timeout: Duration) -> io::Result<usize> {
+        self.0.write_timeout(buf, timeout)
+    }
+
+    /// Blocks until all output written to the terminal has been transmitted.
+    /// See [`TerminalLock::drain`].
+    pub fn drain(&mut self) -> io::Result<()> {
+        self.0.drain()
+    }
+
+    /// Restarts output that was stopped using flow control.
+    /// See [`TerminalLock::resume_output`].
+    pub fn resume_output(&mut self) -> io::Result<()> {
+        self.0.resume_output()
+    }
+
+    /// Sets the size of the terminal in rows and columns.
+    /// See [`TerminalLock::set_size`].
+    pub fn set_size(&mut self, rows: u16, columns: u16) -> io::Result<()> {
+        self.0.set_size(rows, columns)
+    }
+}
+
+#[cfg(all(windows, not(terminal_trx_test_unsupported)))]
+#[cfg_attr(docsrs, doc(cfg(windows)))]
+impl RawModeGuard<'_> {
+    /// Enables processing of VT sequences on the console's screen buffer until the returned guard is dropped.
+    /// See [`TerminalLock::enable_virtual_terminal_processing`].
+    pub fn enable_virtual_terminal_processing(
+        &mut self,
+        disable_newline_auto_return: bool,
+    ) -> io::Result<VirtualTerminalProcessingGuard<'_, Self>> {
+        let terminal = self.0.terminal();
+        VirtualTerminalProcessingGuard::new(self, terminal, disable_newline_auto_return)
+    }
+
+    /// Returns the console's input code page.
+    /// See [`TerminalLock::input_code_page`].
+    pub fn input_code_page(&self) -> io::Result<u32> {
+        imp::input_code_page()
+    }
+
+    /// Returns the console's output code page.
+    /// See [`TerminalLock::output_code_page`].
+    pub fn output_code_page(&self) -> io::Result<u32> {
+        imp::output_code_page()
+    }
+
+    /// Sets the console's input and output code pages to UTF-8 until the returned guard is dropped.
+    /// See [`TerminalLock::enable_utf8_code_page`].
+    pub fn enable_utf8_code_page(&mut self) -> io::Result<CodePageGuard<'_, Self>> {
+        CodePageGuard::new(self)
+    }
+
+    /// Reads events from the console's input buffer, blocking until at least one event is available.
+    /// See [`TerminalLock::read_input_events`].
+    pub fn read_input_events(&mut self) -> io::Result<Vec<InputEvent>> {
+        self.0.read_input_events()
+    }
+
+    /// Returns information about the console's screen buffer.
+    /// See [`TerminalLock::screen_buffer_info`].
+    pub fn screen_buffer_info(&self) -> io::Result<ScreenBufferInfo> {
+        self.0.screen_buffer_info()
+    }
+}
+
+impl sealed::Sealed for RawModeGuard<'_> {}
+impl Transceive for RawModeGuard<'_> {}
+
+impl<'a> io::Read for RawModeGuard<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.1.read(&mut self.0, buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        self.1.read_vectored(&mut self.0, bufs)
+    }
+}
+
+impl<'a> io::BufRead for RawModeGuard<'a> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.1.fill_buf(&mut self.0)
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.1.consume(amount)
+    }
+}
+
+// The output half is locked for each call, as the guard may have been created
+// using a `TerminalReadLock`. This is cheap when the thread already holds the lock.
+impl<'a> io::Write for RawModeGuard<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let _output_lock = self.0.terminal().device_state().output.lock();
+        self.0.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        let _output_lock = self.0.terminal().device_state().output.lock();
+        self.0.write_vectored(bufs)
     }
 
     fn flush(&mut self) -> io::Result<()> {
+        let _output_lock = self.0.terminal().device_state().output.lock();
         self.0.flush()
     }
 }
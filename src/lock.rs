@@ -0,0 +1,226 @@
+use crate::input_buffer::InputBuffer;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, Weak};
+use std::thread::{self, ThreadId};
+
+/// The locks and the input buffer shared by all handles to the same terminal device.
+///
+/// The input and output halves are locked separately so that a thread
+/// waiting for input doesn't block other threads from writing.
+/// When both are needed, the input half is always locked first.
+#[derive(Debug)]
+pub(crate) struct DeviceState {
+    pub(crate) input: ReentrantLock,
+    pub(crate) output: ReentrantLock,
+    // Only locked briefly to move the buffer in and out of a `TerminalLock`.
+    input_buffer: Mutex<InputBuffer>,
+}
+
+#[cfg_attr(terminal_trx_test_unsupported, allow(dead_code))]
+impl DeviceState {
+    pub(crate) const fn new() -> Self {
+        DeviceState {
+            input: ReentrantLock::new(),
+            output: ReentrantLock::new(),
+            input_buffer: Mutex::new(InputBuffer::new()),
+        }
+    }
+
+    pub(crate) fn input_buffer(&self) -> MutexGuard<'_, InputBuffer> {
+        self.input_buffer.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Process-wide registry of the [`DeviceState`] for each terminal device (identified by `K`).
+///
+/// The state is dropped once the last handle to the device is dropped.
+#[derive(Debug)]
+#[cfg_attr(terminal_trx_test_unsupported, allow(dead_code))]
+pub(crate) struct DeviceStates<K>(Mutex<Vec<(K, Weak<DeviceState>)>>);
+
+#[cfg_attr(terminal_trx_test_unsupported, allow(dead_code))]
+impl<K: PartialEq> DeviceStates<K> {
+    pub(crate) const fn new() -> Self {
+        DeviceStates(Mutex::new(Vec::new()))
+    }
+
+    pub(crate) fn get(&self, key: K) -> Arc<DeviceState> {
+        let mut states = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        states.retain(|(_, state)| state.strong_count() > 0);
+        if let Some(state) = states
+            .iter()
+            .find(|(k, _)| *k == key)
+            .and_then(|(_, state)| state.upgrade())
+        {
+            return state;
+        }
+        let state = Arc::new(DeviceState::new());
+        states.push((key, Arc::downgrade(&state)));
+        state
+    }
+}
+
+/// A lock that can be acquired again by the thread that already holds it.
+///
+/// The lock is only released once all guards of the owning thread are dropped.
+#[derive(Debug)]
+pub(crate) struct ReentrantLock {
+    state: Mutex<LockState>,
+    released: Condvar,
+}
+
+#[derive(Debug)]
+struct LockState {
+    owner: Option<ThreadId>,
+    count: usize,
+}
+
+#[cfg_attr(terminal_trx_test_unsupported, allow(dead_code))]
+impl ReentrantLock {
+    pub(crate) const fn new() -> Self {
+        ReentrantLock {
+            state: Mutex::new(LockState {
+                owner: None,
+                count: 0,
+            }),
+            released: Condvar::new(),
+        }
+    }
+
+    pub(crate) fn lock(&self) -> ReentrantLockGuard<'_> {
+        let current = thread::current().id();
+        let mut state = self.state();
+        while state.owner.is_some_and(|owner| owner != current) {
+            state = self.released.wait(state).unwrap_or_else(|e| e.into_inner());
+        }
+        self.acquire(state, current)
+    }
+
+    /// Like [`ReentrantLock::lock`] but returns `None` instead of waiting for another thread.
+    pub(crate) fn try_lock(&self) -> Option<ReentrantLockGuard<'_>> {
+        let current = thread::current().id();
+        let state = self.state();
+        if state.owner.is_some_and(|owner| owner != current) {
+            return None;
+        }
+        Some(self.acquire(state, current))
+    }
+
+    fn acquire(
+        &self,
+        mut state: MutexGuard<'_, LockState>,
+        owner: ThreadId,
+    ) -> ReentrantLockGuard<'_> {
+        state.owner = Some(owner);
+        state.count += 1;
+        ReentrantLockGuard {
+            lock: self,
+            outermost: state.count == 1,
+        }
+    }
+
+    /// Returns `true` if the current thread holds the lock.
+    pub(crate) fn is_held_by_current_thread(&self) -> bool {
+        self.state().owner == Some(thread::current().id())
+    }
+
+    // The state is never left inconsistent, so it's fine to ignore poisoning.
+    fn state(&self) -> MutexGuard<'_, LockState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ReentrantLockGuard<'a> {
+    lock: &'a ReentrantLock,
+    outermost: bool,
+}
+
+impl ReentrantLockGuard<'_> {
+    /// Returns `true` if the thread didn't hold the lock before this guard was created.
+    pub(crate) fn is_outermost(&self) -> bool {
+        self.outermost
+    }
+}
+
+impl Drop for ReentrantLockGuard<'_> {
+    fn drop(&mut self) {
+        let mut state = self.lock.state();
+        state.count -= 1;
+        if state.count == 0 {
+            state.owner = None;
+            drop(state);
+            self.lock.released.notify_one();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn can_be_locked_again_by_same_thread() {
+        let lock = ReentrantLock::new();
+        let _outer = lock.lock();
+        let _inner = lock.lock();
+    }
+
+    #[test]
+    fn only_first_guard_is_outermost() {
+        let lock = ReentrantLock::new();
+        let outer = lock.lock();
+        let inner = lock.lock();
+        assert!(outer.is_outermost());
+        assert!(!inner.is_outermost());
+        assert!(lock.is_held_by_current_thread());
+        drop((inner, outer));
+        assert!(!lock.is_held_by_current_thread());
+    }
+
+    #[test]
+    fn try_lock_fails_while_another_thread_holds_the_lock() {
+        let lock = ReentrantLock::new();
+        let guard = lock.lock();
+        assert!(lock.try_lock().is_some());
+        thread::scope(|s| {
+            assert!(s
+                .spawn(|| lock.try_lock().is_none())
+                .join()
+                .unwrap_or(false));
+            drop(guard);
+            assert!(s
+                .spawn(|| lock.try_lock().is_some())
+                .join()
+                .unwrap_or(false));
+        });
+    }
+
+    #[test]
+    fn devices_have_separate_state() {
+        let states = DeviceStates::new();
+        let first = states.get(1);
+        assert!(Arc::ptr_eq(&first, &states.get(1)));
+        assert!(!Arc::ptr_eq(&first, &states.get(2)));
+    }
+
+    #[test]
+    fn other_threads_wait_until_all_guards_are_dropped() {
+        let lock = ReentrantLock::new();
+        let released = AtomicBool::new(false);
+        thread::scope(|s| {
+            let outer = lock.lock();
+            let inner = lock.lock();
+            let handle = s.spawn(|| {
+                let _guard = lock.lock();
+                assert!(released.load(Ordering::SeqCst));
+            });
+            drop(inner);
+            thread::sleep(Duration::from_millis(50));
+            released.store(true, Ordering::SeqCst);
+            drop(outer);
+            assert!(handle.join().is_ok());
+        });
+    }
+}
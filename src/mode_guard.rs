@@ -0,0 +1,120 @@
+use std::io;
+use std::ops::{Deref, DerefMut};
+
+/// Enables a terminal mode (by writing an escape sequence) for the lifetime of the guard.
+/// The mode is reset when the guard is dropped, errors that occur while doing so are ignored.
+///
+/// The guard holds on to the [`TerminalLock`](`crate::TerminalLock`) or [`RawModeGuard`](`crate::RawModeGuard`)
+/// it was created from and can be used in its place (e.g. for writing).
+#[derive(Debug)]
+pub struct ModeGuard<'a, T: io::Write> {
+    inner: Inner<'a, T>,
+    reset: &'static [u8],
+}
+
+#[derive(Debug)]
+enum Inner<'a, T> {
+    Borrowed(&'a mut T),
+    // Used by `ModeStack` which holds several guards for the same terminal.
+    Owned(T),
+}
+
+/// A mode that is enabled and disabled by writing an escape sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Mode {
+    set: &'static [u8],
+    reset: &'static [u8],
+}
+
+impl Mode {
+    pub(crate) const SYNCHRONIZED_UPDATE: Mode = Mode::new(b"\x1b[?2026h", b"\x1b[?2026l");
+    pub(crate) const APPLICATION_CURSOR_KEYS: Mode = Mode::new(b"\x1b[?1h", b"\x1b[?1l");
+    pub(crate) const APPLICATION_KEYPAD: Mode = Mode::new(b"\x1b=", b"\x1b>");
+    pub(crate) const ALTERNATE_SCREEN: Mode = Mode::new(b"\x1b[?1049h", b"\x1b[?1049l");
+    pub(crate) const HIDDEN_CURSOR: Mode = Mode::new(b"\x1b[?25l", b"\x1b[?25h");
+    pub(crate) const MOUSE_CAPTURE: Mode = Mode::new(
+        b"\x1b[?1000h\x1b[?1002h\x1b[?1006h",
+        b"\x1b[?1006l\x1b[?1002l\x1b[?1000l",
+    );
+    pub(crate) const BRACKETED_PASTE: Mode = Mode::new(b"\x1b[?2004h", b"\x1b[?2004l");
+    pub(crate) const FOCUS_EVENTS: Mode = Mode::new(b"\x1b[?1004h", b"\x1b[?1004l");
+
+    const fn new(set: &'static [u8], reset: &'static [u8]) -> Self {
+        Mode { set, reset }
+    }
+}
+
+impl<'a, T: io::Write> ModeGuard<'a, T> {
+    pub(crate) fn new(inner: &'a mut T, mode: Mode) -> io::Result<Self> {
+        Self::enable(Inner::Borrowed(inner), mode)
+    }
+
+    pub(crate) fn owned(inner: T, mode: Mode) -> io::Result<Self> {
+        Self::enable(Inner::Owned(inner), mode)
+    }
+
+    fn enable(mut inner: Inner<'a, T>, mode: Mode) -> io::Result<Self> {
+        let writer = inner.get_mut();
+        writer.write_all(mode.set)?;
+        writer.flush()?;
+        Ok(ModeGuard {
+            inner,
+            reset: mode.reset,
+        })
+    }
+}
+
+impl<T> Inner<'_, T> {
+    fn get(&self) -> &T {
+        match self {
+            Inner::Borrowed(inner) => inner,
+            Inner::Owned(inner) => inner,
+        }
+    }
+
+    fn get_mut(&mut self) -> &mut T {
+        match self {
+            Inner::Borrowed(inner) => inner,
+            Inner::Owned(inner) => inner,
+        }
+    }
+}
+
+impl<T: io::Write> Deref for ModeGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.inner.get()
+    }
+}
+
+impl<T: io::Write> DerefMut for ModeGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.inner.get_mut()
+    }
+}
+
+impl<T: io::Write> Drop for ModeGuard<'_, T> {
+    fn drop(&mut self) {
+        let inner = self.inner.get_mut();
+        _ = inner.write_all(self.reset);
+        _ = inner.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use std::io::Write as _;
+
+    #[test]
+    fn mode_is_set_and_reset() {
+        let mut output = Vec::new();
+        let mut guard = ModeGuard::new(&mut output, Mode::new(b"set", b"reset")).unwrap();
+        guard.write_all(b" frame ").unwrap();
+        drop(guard);
+        assert_eq!(b"set frame reset", output.as_slice());
+    }
+}
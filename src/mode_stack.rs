@@ -0,0 +1,172 @@
+use crate::input_buffer::InputBuffer;
+use crate::mode_guard::Mode;
+use crate::{imp, Error, ModeGuard, RawModeGuard, RawModeOptions};
+use std::io;
+
+/// The modes enabled by [`TerminalLock::enable_modes`](`crate::TerminalLock::enable_modes`).
+///
+/// All modes are disabled by default.
+///
+/// ## Example
+/// ```no_run
+/// use terminal_trx::{terminal, ModeStackOptions, RawModeOptions};
+///
+/// let mut tty = terminal().unwrap();
+/// let mut lock = tty.lock();
+/// let options = ModeStackOptions::new()
+///     .raw_mode(RawModeOptions::new())
+///     .alternate_screen(true)
+///     .hide_cursor(true)
+///     .bracketed_paste(true);
+/// let mut modes = lock.enable_modes(options).unwrap();
+/// // All modes are reverted when `modes` is dropped.
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ModeStackOptions {
+    raw_mode: Option<RawModeOptions>,
+    alternate_screen: bool,
+    hide_cursor: bool,
+    mouse_capture: bool,
+    bracketed_paste: bool,
+    focus_events: bool,
+}
+
+impl ModeStackOptions {
+    /// Creates options with all modes disabled.
+    pub const fn new() -> Self {
+        ModeStackOptions {
+            raw_mode: None,
+            alternate_screen: false,
+            hide_cursor: false,
+            mouse_capture: false,
+            bracketed_paste: false,
+            focus_events: false,
+        }
+    }
+
+    /// Enables raw mode with the given options,
+    /// see [`TerminalLock::enable_raw_mode_with`](`crate::TerminalLock::enable_raw_mode_with`).
+    pub const fn raw_mode(mut self, options: RawModeOptions) -> Self {
+        self.raw_mode = Some(options);
+        self
+    }
+
+    /// Switches to the alternate screen (mode `1049`).
+    pub const fn alternate_screen(mut self, enabled: bool) -> Self {
+        self.alternate_screen = enabled;
+        self
+    }
+
+    /// Hides the cursor (mode `25`).
+    pub const fn hide_cursor(mut self, enabled: bool) -> Self {
+        self.hide_cursor = enabled;
+        self
+    }
+
+    /// Reports mouse button presses and drags using SGR encoding (modes `1000`, `1002` and `1006`).
+    pub const fn mouse_capture(mut self, enabled: bool) -> Self {
+        self.mouse_capture = enabled;
+        self
+    }
+
+    /// Brackets pasted text so that it can be told apart from typed input (mode `2004`).
+    pub const fn bracketed_paste(mut self, enabled: bool) -> Self {
+        self.bracketed_paste = enabled;
+        self
+    }
+
+    /// Reports when the terminal gains or loses focus (mode `1004`).
+    pub const fn focus_events(mut self, enabled: bool) -> Self {
+        self.focus_events = enabled;
+        self
+    }
+
+    /// The modes to enable (except for raw mode), in the order they're enabled.
+    fn modes(&self) -> impl Iterator<Item = Mode> {
+        [
+            (self.alternate_screen, Mode::ALTERNATE_SCREEN),
+            (self.hide_cursor, Mode::HIDDEN_CURSOR),
+            (self.mouse_capture, Mode::MOUSE_CAPTURE),
+            (self.bracketed_paste, Mode::BRACKETED_PASTE),
+            (self.focus_events, Mode::FOCUS_EVENTS),
+        ]
+        .into_iter()
+        .filter(|(enabled, _)| *enabled)
+        .map(|(_, mode)| mode)
+    }
+}
+
+/// Several modes enabled using [`TerminalLock::enable_modes`](`crate::TerminalLock::enable_modes`).
+///
+/// The modes are reverted in reverse order when the guard is dropped.
+/// Raw mode is enabled first and therefore disabled last.
+/// Errors that occur while reverting the modes are ignored.
+///
+/// The guard can be used for writing to the terminal. Use [`ModeStack::raw_mode`] for reading in raw mode.
+#[derive(Debug)]
+pub struct ModeStack<'a> {
+    terminal: &'a imp::Terminal,
+    raw_mode: Option<RawModeGuard<'a>>,
+    // Keeps the lock borrowed when raw mode is not enabled.
+    _input_buffer: Option<&'a mut InputBuffer>,
+    modes: Vec<ModeGuard<'a, &'a imp::Terminal>>,
+}
+
+impl<'a> ModeStack<'a> {
+    pub(crate) fn new(
+        terminal: &'a imp::Terminal,
+        input_buffer: &'a mut InputBuffer,
+        options: ModeStackOptions,
+    ) -> io::Result<Self> {
+        let (raw_mode, input_buffer) = match options.raw_mode {
+            Some(raw_mode_options) => {
+                let guard = terminal
+                    .enable_raw_mode(&raw_mode_options)
+                    .map_err(Error::from)?;
+                (Some(RawModeGuard(guard, input_buffer)), None)
+            }
+            None => (None, Some(input_buffer)),
+        };
+        let mut stack = ModeStack {
+            terminal,
+            raw_mode,
+            _input_buffer: input_buffer,
+            modes: Vec::new(),
+        };
+        // Modes enabled so far are reverted by `Drop` if enabling a mode fails.
+        for mode in options.modes() {
+            stack.modes.push(ModeGuard::owned(terminal, mode)?);
+        }
+        Ok(stack)
+    }
+
+    /// The raw mode guard if raw mode was enabled, e.g. for reading events.
+    pub fn raw_mode(&mut self) -> Option<&mut RawModeGuard<'a>> {
+        self.raw_mode.as_mut()
+    }
+}
+
+impl io::Write for ModeStack<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.terminal.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.terminal.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.terminal.flush()
+    }
+}
+
+impl Drop for ModeStack<'_> {
+    fn drop(&mut self) {
+        // A `Vec` drops its elements front to back, but the modes must be reverted in reverse order.
+        while let Some(mode) = self.modes.pop() {
+            drop(mode);
+        }
+        // Raw mode is disabled last, after the other modes have been reverted.
+        self.raw_mode.take();
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{Error, RawModeOptions, Terminal, TerminalAttributes};
+use std::borrow::Borrow;
+use std::io;
+
+/// Guard for raw mode that owns (or shares ownership of) the [`Terminal`] instead of borrowing it,
+/// so that it can be stored in a struct field alongside other state.
+///
+/// The previous mode is restored when the guard is dropped, errors that occur while doing so are ignored.
+/// Use [`OwnedRawModeGuard::disable`] to surface them.
+///
+/// Unlike [`RawModeGuard`](`crate::RawModeGuard`), this guard does not keep the terminal locked.
+/// Use [`Terminal::lock`] (e.g. via [`OwnedRawModeGuard::terminal`]) for reading and writing.
+///
+/// The guard is [`Send`] if `T` is (e.g. `Terminal` or `Arc<Terminal>`), so that it can be
+/// created on one thread and dropped on another, e.g. in an async task that moves between threads.
+///
+/// ## Example
+/// ```no_run
+/// use std::sync::Arc;
+/// use terminal_trx::{terminal, OwnedRawModeGuard, RawModeOptions, Terminal};
+///
+/// struct App {
+///     terminal: Arc<Terminal>,
+///     raw_mode: OwnedRawModeGuard<Arc<Terminal>>,
+/// }
+///
+/// let terminal = Arc::new(terminal().unwrap());
+/// let raw_mode = OwnedRawModeGuard::new(terminal.clone(), RawModeOptions::new()).unwrap();
+/// let app = App { terminal, raw_mode };
+/// ```
+#[derive(Debug)]
+pub struct OwnedRawModeGuard<T: Borrow<Terminal>> {
+    // Only taken by `disable`, which consumes the guard.
+    terminal: Option<T>,
+    attributes: TerminalAttributes,
+}
+
+const TAKEN: &str = "the terminal is only taken when the guard is consumed";
+
+#[cfg(test)]
+static_assertions::assert_impl_all!(OwnedRawModeGuard<Terminal>: Send, Sync);
+
+#[cfg(test)]
+static_assertions::assert_impl_all!(OwnedRawModeGuard<std::sync::Arc<Terminal>>: Send, Sync);
+
+impl<T: Borrow<Terminal>> OwnedRawModeGuard<T> {
+    /// Enables raw mode with the given [`RawModeOptions`] until the returned guard is dropped.
+    ///
+    /// See [`TerminalLock::enable_raw_mode`](`crate::TerminalLock::enable_raw_mode`) for details.
+    /// Note that this guard always restores the mode it replaced, even when
+    /// [`RawModeGuard`](`crate::RawModeGuard`)s are created and dropped in between.
+    pub fn new(terminal: T, options: RawModeOptions) -> Result<Self, Error> {
+        let attributes = terminal
+            .borrow()
+            .lock()
+            .enable_raw_mode_with(options)?
+            .persist();
+        Ok(OwnedRawModeGuard {
+            terminal: Some(terminal),
+            attributes,
+        })
+    }
+
+    /// The terminal in raw mode.
+    pub fn terminal(&self) -> &Terminal {
+        self.terminal.as_ref().expect(TAKEN).borrow()
+    }
+
+    /// Restores the previous mode and returns the terminal.
+    pub fn disable(mut self) -> io::Result<T> {
+        let result = self.terminal().restore(&self.attributes);
+        // Taking the terminal tells `Drop` that the mode has already been restored.
+        let terminal = self.terminal.take().expect(TAKEN);
+        result.map(|_| terminal)
+    }
+}
+
+impl<T: Borrow<Terminal>> Drop for OwnedRawModeGuard<T> {
+    fn drop(&mut self) {
+        if let Some(terminal) = &self.terminal {
+            _ = terminal.borrow().restore(&self.attributes);
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use crate::RawModeGuard;
+use std::io;
+
+/// Reads from a [`RawModeGuard`] without retrying when interrupted by a signal.
+pub(crate) struct Interruptible<'a, 'b>(pub(crate) &'a mut RawModeGuard<'b>);
+
+impl io::Read for Interruptible<'_, '_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read_interruptible(buf)
+    }
+}
+
+/// Reads a single line byte by byte, so that no input after the newline is consumed.
+pub(crate) fn read_line(reader: &mut impl io::Read) -> io::Result<String> {
+    let mut line = Vec::new();
+    let mut byte = [0];
+
+    loop {
+        match reader.read(&mut byte)? {
+            0 if line.is_empty() => return Err(io::ErrorKind::UnexpectedEof.into()),
+            0 => break,
+            _ if byte[0] == b'\n' => break,
+            _ => line.push(byte[0]),
+        }
+    }
+
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+
+    String::from_utf8(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+
+    #[test]
+    fn strips_trailing_newline() {
+        assert_eq!("hunter2", read_line(&mut &b"hunter2\n"[..]).unwrap());
+        assert_eq!("hunter2", read_line(&mut &b"hunter2\r\n"[..]).unwrap());
+    }
+
+    #[test]
+    fn stops_reading_after_newline() {
+        let mut input = &b"first\nsecond\n"[..];
+        assert_eq!("first", read_line(&mut input).unwrap());
+        assert_eq!(b"second\n", input);
+    }
+
+    #[test]
+    fn returns_partial_line_on_eof() {
+        assert_eq!("partial", read_line(&mut &b"partial"[..]).unwrap());
+    }
+
+    #[test]
+    fn fails_on_empty_input() {
+        let error = read_line(&mut &b""[..]).unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, error.kind());
+    }
+
+    #[test]
+    fn fails_on_invalid_utf8() {
+        let error = read_line(&mut &b"\xff\n"[..]).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, error.kind());
+    }
+}
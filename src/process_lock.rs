@@ -0,0 +1,61 @@
+use crate::{Terminal, TerminalLock};
+use std::fs::File;
+use std::io;
+use std::ops::{Deref, DerefMut};
+
+impl Terminal {
+    /// Like [`Terminal::lock`] but additionally acquires an advisory lock (using `flock`)
+    /// on the terminal device, so that cooperating processes sharing the terminal
+    /// can serialize their access (e.g. to avoid interleaving escape sequences).
+    ///
+    /// Blocks until the lock is available. The lock is released when the returned guard is dropped.
+    /// Processes that don't use this function are not affected by the lock.
+    pub fn lock_across_processes(&self) -> io::Result<ProcessLockGuard<'_>> {
+        // The in-process lock is always acquired first, so that two threads
+        // of the same process can't deadlock on the two locks.
+        let lock = self.lock();
+        let file = self.0.lock_across_processes()?;
+        Ok(ProcessLockGuard { lock, _file: file })
+    }
+}
+
+/// Guard for exclusive access to the terminal across processes,
+/// see [`Terminal::lock_across_processes`].
+///
+/// The guard can be used in place of the [`TerminalLock`] it holds on to.
+#[derive(Debug)]
+pub struct ProcessLockGuard<'a> {
+    lock: TerminalLock<'a>,
+    // Closing the file releases the lock.
+    _file: File,
+}
+
+impl<'a> Deref for ProcessLockGuard<'a> {
+    type Target = TerminalLock<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.lock
+    }
+}
+
+impl DerefMut for ProcessLockGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.lock
+    }
+}
+
+impl io::Read for ProcessLockGuard<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.lock.read(buf)
+    }
+}
+
+impl io::Write for ProcessLockGuard<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.lock.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock.flush()
+    }
+}
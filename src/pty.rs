@@ -0,0 +1,72 @@
+//! Pseudo-terminals for testing code written against [`Terminal`] in environments
+//! where no terminal is available (e.g. CI).
+//!
+//! ## Example
+//! ```no_run
+//! use std::io::{Read as _, Write as _};
+//!
+//! let (mut terminal, mut controller) = terminal_trx::pty::open().unwrap();
+//! write!(terminal, "hello").unwrap();
+//!
+//! let mut output = [0; 5];
+//! controller.read_exact(&mut output).unwrap();
+//! assert_eq!(b"hello", &output);
+//! ```
+
+use crate::{imp, Terminal};
+use std::fs::File;
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
+
+/// Creates a new pseudo-terminal pair.
+///
+/// Returns a [`Terminal`] for the user side (the side a program normally talks to)
+/// and a [`PtyController`] for the controlling side (the side a terminal emulator normally talks to).
+/// The pseudo-terminal does not become the controlling terminal of the process.
+pub fn open() -> io::Result<(Terminal, PtyController)> {
+    let (terminal, controlling) = imp::Terminal::open_pty()?;
+    Ok((Terminal(terminal), PtyController(File::from(controlling))))
+}
+
+/// The controlling side of a pseudo-terminal created using [`open`].
+///
+/// Input written to the controller can be read from the terminal
+/// and output written to the terminal can be read from the controller.
+#[derive(Debug)]
+pub struct PtyController(File);
+
+impl io::Read for PtyController {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        self.0.read_vectored(bufs)
+    }
+}
+
+impl io::Write for PtyController {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.0.write_vectored(bufs)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl AsFd for PtyController {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl AsRawFd for PtyController {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
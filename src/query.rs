@@ -0,0 +1,360 @@
+use crate::{ColorSupport, RawModeGuard};
+use std::io::{self, Read as _, Write as _};
+use std::time::{Duration, Instant};
+
+mod color;
+mod device_attributes;
+pub use device_attributes::DeviceAttributes;
+mod many;
+pub(crate) use many::query_many;
+pub use many::Query;
+mod mode;
+pub use mode::ModeState;
+mod passthrough;
+pub use passthrough::Passthrough;
+mod policy;
+pub use policy::QueryPolicy;
+mod secondary_device_attributes;
+mod termcap;
+mod terminal_version;
+
+pub(crate) fn device_attributes(raw_mode: &mut RawModeGuard<'_>) -> io::Result<DeviceAttributes> {
+    let response = query(
+        raw_mode,
+        device_attributes::REQUEST,
+        device_attributes::is_complete,
+        policy::default_timeout(),
+    )?;
+    device_attributes::parse(&response)
+}
+
+pub(crate) fn terminal_version(raw_mode: &mut RawModeGuard<'_>) -> io::Result<Option<String>> {
+    let response = query_with_sentinel(raw_mode, terminal_version::REQUEST)?;
+    Ok(terminal_version::parse(&response))
+}
+
+/// Queries the Secondary Device Attributes (DA2), returning [`None`] if the terminal doesn't respond.
+pub(crate) fn secondary_device_attributes(
+    raw_mode: &mut RawModeGuard<'_>,
+) -> io::Result<Option<Vec<u16>>> {
+    let response = query_with_sentinel(raw_mode, secondary_device_attributes::REQUEST)?;
+    Ok(secondary_device_attributes::parse(&response))
+}
+
+pub(crate) fn termcap(
+    raw_mode: &mut RawModeGuard<'_>,
+    names: &[&str],
+) -> io::Result<Vec<Option<String>>> {
+    let response = query_with_sentinel(raw_mode, &termcap::request(names))?;
+    Ok(termcap::parse(&response, names))
+}
+
+/// Queries the terminal for proof of its color support, returning [`None`] if there is none.
+pub(crate) fn color_support(raw_mode: &mut RawModeGuard<'_>) -> io::Result<Option<ColorSupport>> {
+    policy::check(raw_mode.0.terminal().provenance())?;
+    let response = query_with_sentinel(raw_mode, &color::request());
+    // The probe changed the graphic rendition, which needs to be restored even if the query failed.
+    raw_mode.write_all(&color::restore_rendition(
+        response.as_deref().unwrap_or_default(),
+    ))?;
+    raw_mode.flush()?;
+    Ok(color::parse(&response?))
+}
+
+/// Returns `true` if the query failed because the terminal didn't answer it
+/// or is known not to answer queries at all.
+pub(crate) fn is_unanswered(error: &io::Error) -> bool {
+    matches!(
+        error.kind(),
+        io::ErrorKind::TimedOut | io::ErrorKind::Unsupported
+    )
+}
+
+pub(crate) fn mode(raw_mode: &mut RawModeGuard<'_>, mode: u16) -> io::Result<ModeState> {
+    let response = query_with_sentinel(raw_mode, &mode::request(mode))?;
+    Ok(mode::parse(&response, mode))
+}
+
+/// Sends the request followed by a Primary Device Attributes (DA1) query, which virtually all terminals answer.
+/// Terminals respond in order, so receiving the DA1 response means that the response
+/// to our request (if supported at all) has been received as well.
+///
+/// Returns everything that was received before the DA1 response.
+fn query_with_sentinel(raw_mode: &mut RawModeGuard<'_>, request: &[u8]) -> io::Result<Vec<u8>> {
+    let request = [request, device_attributes::REQUEST].concat();
+    let mut response = query(
+        raw_mode,
+        &request,
+        device_attributes::is_complete,
+        policy::default_timeout(),
+    )?;
+    if let Some(sentinel_start) = device_attributes::find_response(&response) {
+        response.truncate(sentinel_start);
+    }
+    Ok(response)
+}
+
+/// Writes the request and reads the response byte by byte (so that no input after
+/// the response is consumed) until `is_complete` returns `true` or the timeout elapses.
+///
+/// Input typed by the user while waiting (i.e. bytes outside of escape sequences
+/// and escape sequences sent by keys that don't complete the response)
+/// is not part of the response and pushed back to the input afterwards.
+pub(crate) fn query(
+    raw_mode: &mut RawModeGuard<'_>,
+    request: &[u8],
+    is_complete: impl FnMut(&[u8]) -> bool,
+    timeout: Duration,
+) -> io::Result<Vec<u8>> {
+    policy::check(raw_mode.0.terminal().provenance())?;
+    let mut typed = Vec::new();
+    let result = read_response(raw_mode, request, is_complete, timeout, &mut typed);
+    raw_mode.unread(&typed);
+    result
+}
+
+fn read_response(
+    raw_mode: &mut RawModeGuard<'_>,
+    request: &[u8],
+    mut is_complete: impl FnMut(&[u8]) -> bool,
+    timeout: Duration,
+    typed: &mut Vec<u8>,
+) -> io::Result<Vec<u8>> {
+    let deadline = Instant::now() + timeout;
+
+    raw_mode.write_all(request)?;
+    raw_mode.flush()?;
+
+    let mut response = Vec::new();
+    let mut splitter = SequenceSplitter::default();
+    while let Some(sequence) = read_sequence(raw_mode, &mut splitter, deadline, typed)? {
+        if append_to_response(&mut response, sequence, &mut is_complete, typed) {
+            return Ok(response);
+        }
+    }
+    splitter.finish(typed);
+    Err(timed_out())
+}
+
+/// Appends the sequence to the response unless it was sent by a key and doesn't complete the response,
+/// in which case it's considered to be typed. Returns `true` if the response is complete.
+fn append_to_response(
+    response: &mut Vec<u8>,
+    sequence: Vec<u8>,
+    mut is_complete: impl FnMut(&[u8]) -> bool,
+    typed: &mut Vec<u8>,
+) -> bool {
+    let len = response.len();
+    response.extend_from_slice(&sequence);
+    if is_complete(response) {
+        return true;
+    }
+    if is_key_sequence(&sequence) {
+        response.truncate(len);
+        typed.extend(sequence);
+    }
+    false
+}
+
+/// Reads until an escape sequence is complete, returning [`None`] once the deadline has passed.
+/// Bytes outside of escape sequences are added to `typed`.
+fn read_sequence(
+    raw_mode: &mut RawModeGuard<'_>,
+    splitter: &mut SequenceSplitter,
+    deadline: Instant,
+    typed: &mut Vec<u8>,
+) -> io::Result<Option<Vec<u8>>> {
+    while let Some(byte) = read_byte(raw_mode, deadline)? {
+        if let Some(sequence) = splitter.push(byte, typed) {
+            return Ok(Some(sequence));
+        }
+    }
+    Ok(None)
+}
+
+/// Reads a single byte, returning [`None`] once the deadline has passed.
+fn read_byte(raw_mode: &mut RawModeGuard<'_>, deadline: Instant) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Ok(None);
+        }
+        raw_mode.set_read_behavior(0, Some(remaining))?;
+        match raw_mode.read(&mut byte) {
+            Ok(0) => continue,
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Like [`query`] but forwards the request through a terminal multiplexer.
+/// `is_complete` sees the response with the passthrough wrapping removed.
+pub(crate) fn query_with_passthrough(
+    raw_mode: &mut RawModeGuard<'_>,
+    passthrough: Passthrough,
+    request: &[u8],
+    mut is_complete: impl FnMut(&[u8]) -> bool,
+    timeout: Duration,
+) -> io::Result<Vec<u8>> {
+    let response = query(
+        raw_mode,
+        &passthrough.wrap(request),
+        |response| is_complete(&passthrough.unwrap(response)),
+        timeout,
+    )?;
+    Ok(passthrough.unwrap(&response))
+}
+
+/// Splits input into complete escape sequences and other (typed) bytes.
+#[derive(Debug, Default)]
+struct SequenceSplitter {
+    state: SequenceState,
+    sequence: Vec<u8>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum SequenceState {
+    #[default]
+    Ground,
+    Escape,
+    /// `ESC [ ...` ends with a byte in the range `@` to `~`.
+    ControlSequence,
+    /// `ESC O x`
+    SingleShift,
+    /// `OSC`, `DCS`, `APC`, `PM` and `SOS` end with `BEL` or `ESC \`.
+    ControlString,
+    ControlStringEscape,
+}
+
+impl SequenceSplitter {
+    /// Returns the escape sequence completed by this byte.
+    ///
+    /// Bytes outside of escape sequences are added to `typed`, as are sequences
+    /// that are interrupted by another escape character (e.g. when the Escape key is pressed).
+    fn push(&mut self, byte: u8, typed: &mut Vec<u8>) -> Option<Vec<u8>> {
+        use SequenceState::*;
+        const ESC: u8 = 0x1b;
+        let (state, complete) = match (self.state, byte) {
+            (Ground, ESC) => (Escape, false),
+            (Ground, _) => {
+                typed.push(byte);
+                return None;
+            }
+            (Escape | ControlSequence | SingleShift, ESC) => {
+                typed.append(&mut self.sequence);
+                (Escape, false)
+            }
+            (Escape, b'[') => (ControlSequence, false),
+            (Escape, b'O') => (SingleShift, false),
+            (Escape, b']' | b'P' | b'_' | b'^' | b'X') => (ControlString, false),
+            (Escape, _) => (Ground, true),
+            (ControlSequence, b'@'..=b'~') => (Ground, true),
+            (ControlSequence, _) => (ControlSequence, false),
+            (SingleShift, _) => (Ground, true),
+            (ControlString, 0x07) => (Ground, true),
+            (ControlString, ESC) => (ControlStringEscape, false),
+            (ControlString, _) => (ControlString, false),
+            (ControlStringEscape, b'\\') => (Ground, true),
+            (ControlStringEscape, _) => (ControlString, false),
+        };
+        self.state = state;
+        self.sequence.push(byte);
+        complete.then(|| std::mem::take(&mut self.sequence))
+    }
+
+    /// Adds an unfinished lone escape character (i.e. the Escape key) to `typed`.
+    fn finish(&mut self, typed: &mut Vec<u8>) {
+        if self.state == SequenceState::Escape {
+            typed.append(&mut self.sequence);
+        }
+    }
+}
+
+/// Returns `true` for escape sequences that are sent by keys (or the mouse) rather than in response to a query:
+/// `ESC x` (Alt + key), `ESC O x` and control sequences with the final bytes used by keys.
+/// Control sequences with a private marker (e.g. `ESC [ ? ... c`) and control strings are responses.
+fn is_key_sequence(sequence: &[u8]) -> bool {
+    match sequence {
+        [0x1b, b'[', b'?' | b'>' | b'=', ..] => false,
+        [0x1b, b'[', .., final_byte] => b"ABCDEFHPQRSZ~uMm".contains(final_byte),
+        [0x1b, b']' | b'P' | b'_' | b'^' | b'X', ..] => false,
+        _ => true,
+    }
+}
+
+const STRING_TERMINATOR: &[u8] = b"\x1b\\";
+
+/// Iterates over the payloads of all (complete) device control strings (`DCS <payload> ST`) in the response.
+fn dcs_strings(mut response: &[u8]) -> impl Iterator<Item = &[u8]> {
+    const PREFIX: &[u8] = b"\x1bP";
+    std::iter::from_fn(move || {
+        let start = find(response, PREFIX)? + PREFIX.len();
+        let len = find(&response[start..], STRING_TERMINATOR)?;
+        let payload = &response[start..start + len];
+        response = &response[start + len + STRING_TERMINATOR.len()..];
+        Some(payload)
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn timed_out() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        "the terminal did not respond to the query in time",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(input: &[u8]) -> (Vec<Vec<u8>>, Vec<u8>) {
+        let mut splitter = SequenceSplitter::default();
+        let mut typed = Vec::new();
+        let sequences = input
+            .iter()
+            .filter_map(|&byte| splitter.push(byte, &mut typed))
+            .collect();
+        splitter.finish(&mut typed);
+        (sequences, typed)
+    }
+
+    #[test]
+    fn splitter_separates_typed_input() {
+        let (sequences, typed) = split(b"a\x1b[1;2Rb\x1bP>|xterm\x1b\\c\x1b]11;?\x07d");
+        assert_eq!(
+            vec![
+                b"\x1b[1;2R".to_vec(),
+                b"\x1bP>|xterm\x1b\\".to_vec(),
+                b"\x1b]11;?\x07".to_vec()
+            ],
+            sequences
+        );
+        assert_eq!(b"abcd", typed.as_slice());
+    }
+
+    #[test]
+    fn escape_character_restarts_sequence() {
+        let (sequences, typed) = split(b"\x1b\x1b[1;2R\x1b[1\x1b[?1c\x1b");
+        assert_eq!(vec![b"\x1b[1;2R".to_vec(), b"\x1b[?1c".to_vec()], sequences);
+        assert_eq!(b"\x1b\x1b[1\x1b", typed.as_slice());
+    }
+
+    #[test]
+    fn key_sequences_are_recognized() {
+        assert!(is_key_sequence(b"\x1b[A"));
+        assert!(is_key_sequence(b"\x1bOP"));
+        assert!(is_key_sequence(b"\x1bx"));
+        assert!(is_key_sequence(b"\x1b[15~"));
+        assert!(!is_key_sequence(b"\x1b[?62;22c"));
+        assert!(!is_key_sequence(b"\x1b[?1u"));
+        assert!(!is_key_sequence(b"\x1b[0n"));
+        assert!(!is_key_sequence(b"\x1b]11;rgb:0000/0000/0000\x07"));
+    }
+}
@@ -0,0 +1,132 @@
+use super::{dcs_strings, termcap};
+use crate::ColorSupport;
+
+// An arbitrary true color background that is unlikely to be in use already.
+const SET_PROBE: &[u8] = b"\x1b[48:2:1:2:3m";
+const RESET: &[u8] = b"\x1b[m";
+// DECRQSS for the current graphic rendition (SGR).
+const REQUEST_SGR: &[u8] = b"\x1bP$qm\x1b\\";
+const CAPABILITIES: &[&str] = &["RGB", "Tc", "colors"];
+
+/// Reads the current graphic rendition, sets a true color background and reads it back using `DECRQSS`,
+/// followed by an `XTGETTCAP` query for the terminfo capabilities describing the supported colors.
+pub(crate) fn request() -> Vec<u8> {
+    [
+        REQUEST_SGR,
+        SET_PROBE,
+        REQUEST_SGR,
+        &termcap::request(CAPABILITIES),
+    ]
+    .concat()
+}
+
+/// Returns the color support that the response proves, or [`None`] if it proves nothing.
+pub(crate) fn parse(response: &[u8]) -> Option<ColorSupport> {
+    let capabilities = termcap::parse(response, CAPABILITIES);
+    let (rgb, tc, colors) = (&capabilities[0], &capabilities[1], &capabilities[2]);
+    if rgb.is_some() || tc.is_some() || sgr_reports(response).skip(1).any(reports_probe) {
+        Some(ColorSupport::TrueColor)
+    } else if colors.as_deref().and_then(|c| c.parse::<u32>().ok()) >= Some(256) {
+        Some(ColorSupport::Ansi256)
+    } else {
+        None
+    }
+}
+
+/// Returns the sequence that re-applies the graphic rendition from before the probe
+/// (i.e. the first `DECRQSS` response) or resets it if the terminal didn't report it.
+pub(crate) fn restore_rendition(response: &[u8]) -> Vec<u8> {
+    match sgr_reports(response).next() {
+        Some(parameters)
+            if !parameters.is_empty()
+                && parameters
+                    .iter()
+                    .all(|b| b.is_ascii_digit() || *b == b';' || *b == b':') =>
+        {
+            [b"\x1b[", parameters, b"m"].concat()
+        }
+        _ => RESET.to_vec(),
+    }
+}
+
+/// Iterates over the parameters of all `DECRQSS` responses for the graphic rendition (`DCS 1 $ r <parameters> m ST`).
+///
+/// The validity flag is ignored as older versions of xterm report it inverted.
+fn sgr_reports(response: &[u8]) -> impl Iterator<Item = &[u8]> {
+    dcs_strings(response).filter_map(|payload| {
+        payload
+            .get(1..)
+            .and_then(|p| p.strip_prefix(b"$r"))
+            .and_then(|p| p.strip_suffix(b"m"))
+    })
+}
+
+/// Checks if the reported graphic rendition contains the probe's color.
+fn reports_probe(parameters: &[u8]) -> bool {
+    let parameters: Vec<&[u8]> = parameters.split(|b| *b == b';' || *b == b':').collect();
+    (0..parameters.len()).any(|i| {
+        let Some(color) = parameters[i..].strip_prefix(PROBE_BACKGROUND) else {
+            return false;
+        };
+        // The color may be preceded by a (possibly empty) color space identifier.
+        color.starts_with(PROBE_COLOR) || color.get(1..).is_some_and(|c| c.starts_with(PROBE_COLOR))
+    })
+}
+
+const PROBE_BACKGROUND: &[&[u8]] = &[b"48", b"2"];
+const PROBE_COLOR: &[&[u8]] = &[b"1", b"2", b"3"];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::STRING_TERMINATOR;
+
+    #[test]
+    fn request_reads_rendition_before_and_after_probe() {
+        let request = request();
+        assert!(request.starts_with(&[REQUEST_SGR, SET_PROBE, REQUEST_SGR].concat()));
+        assert!(request.ends_with(STRING_TERMINATOR));
+    }
+
+    #[test]
+    fn detects_probe_in_sgr_response() {
+        let original = b"\x1bP1$r0m\x1b\\".as_slice();
+        assert_eq!(
+            Some(ColorSupport::TrueColor),
+            parse(&[original, b"\x1bP1$r0;48:2::1:2:3m\x1b\\"].concat())
+        );
+        assert_eq!(
+            Some(ColorSupport::TrueColor),
+            parse(&[original, b"\x1bP1$r48;2;1;2;3m\x1b\\"].concat())
+        );
+        assert_eq!(
+            None,
+            parse(&[original, b"\x1bP1$r0;48;5;1m\x1b\\"].concat())
+        );
+        assert_eq!(None, parse(b"\x1bP0$r\x1b\\\x1bP0$r\x1b\\"));
+    }
+
+    #[test]
+    fn restores_reported_rendition() {
+        assert_eq!(
+            b"\x1b[0;1;31m".to_vec(),
+            restore_rendition(b"\x1bP1$r0;1;31m\x1b\\\x1bP1$r0;1;31;48:2::1:2:3m\x1b\\")
+        );
+        assert_eq!(RESET.to_vec(), restore_rendition(b""));
+        assert_eq!(RESET.to_vec(), restore_rendition(b"\x1bP0$r\x1b\\"));
+    }
+
+    #[test]
+    fn detects_colors_from_termcap_response() {
+        assert_eq!(
+            Some(ColorSupport::TrueColor),
+            parse(b"\x1bP1+r524742=382F382F38\x1b\\")
+        );
+        assert_eq!(
+            Some(ColorSupport::Ansi256),
+            parse(b"\x1bP1+r636F6C6F7273=323536\x1b\\")
+        );
+        assert_eq!(None, parse(b"\x1bP1+r636F6C6F7273=38\x1b\\"));
+        assert_eq!(None, parse(b""));
+    }
+}
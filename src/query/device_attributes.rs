@@ -0,0 +1,119 @@
+use std::io;
+
+pub(crate) const REQUEST: &[u8] = b"\x1b[c";
+
+/// The response to the Primary Device Attributes (DA1) query,
+/// see [`TerminalLock::device_attributes`](`crate::TerminalLock::device_attributes`).
+///
+/// The terminal responds with `ESC [ ? <class> ; <capability> ; ... c`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceAttributes {
+    terminal_class: u16,
+    capabilities: Vec<u16>,
+}
+
+impl DeviceAttributes {
+    /// The first parameter of the response which identifies the terminal's class
+    /// (or conformance level), e.g. `62` for a VT220 or `65` for a VT500-series terminal.
+    pub fn terminal_class(&self) -> u16 {
+        self.terminal_class
+    }
+
+    /// The remaining parameters of the response, e.g. `4` for Sixel graphics or `22` for ANSI color.
+    pub fn capabilities(&self) -> &[u16] {
+        &self.capabilities
+    }
+
+    /// Returns `true` if the given capability is reported by the terminal.
+    pub fn has_capability(&self, capability: u16) -> bool {
+        self.capabilities.contains(&capability)
+    }
+}
+
+pub(crate) fn is_complete(response: &[u8]) -> bool {
+    response.ends_with(b"c") && find_start(response).is_some()
+}
+
+pub(crate) fn parse(response: &[u8]) -> io::Result<DeviceAttributes> {
+    let parameters = find_start(response)
+        .and_then(|start| response[start..].strip_suffix(b"c"))
+        .ok_or_else(|| invalid_response(response))?;
+    let mut parameters = parameters
+        .split(|b| *b == b';')
+        .map(|p| parse_parameter(p).ok_or_else(|| invalid_response(response)));
+    let terminal_class = parameters
+        .next()
+        .ok_or_else(|| invalid_response(response))??;
+    Ok(DeviceAttributes {
+        terminal_class,
+        capabilities: parameters.collect::<io::Result<_>>()?,
+    })
+}
+
+const PREFIX: &[u8] = b"\x1b[?";
+
+/// Returns the index at which the (last) response starts.
+pub(super) fn find_response(response: &[u8]) -> Option<usize> {
+    response.windows(PREFIX.len()).rposition(|w| w == PREFIX)
+}
+
+/// Returns the index of the first parameter.
+fn find_start(response: &[u8]) -> Option<usize> {
+    find_response(response).map(|index| index + PREFIX.len())
+}
+
+fn parse_parameter(parameter: &[u8]) -> Option<u16> {
+    std::str::from_utf8(parameter).ok()?.parse().ok()
+}
+
+fn invalid_response(response: &[u8]) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "invalid device attributes response: {:?}",
+            response.escape_ascii().to_string()
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+
+    #[test]
+    fn parses_class_and_capabilities() {
+        let attributes = parse(b"\x1b[?65;1;4;22c").unwrap();
+        assert_eq!(65, attributes.terminal_class());
+        assert_eq!(&[1, 4, 22], attributes.capabilities());
+        assert!(attributes.has_capability(4));
+        assert!(!attributes.has_capability(2));
+    }
+
+    #[test]
+    fn parses_response_without_capabilities() {
+        let attributes = parse(b"\x1b[?1c").unwrap();
+        assert_eq!(1, attributes.terminal_class());
+        assert!(attributes.capabilities().is_empty());
+    }
+
+    #[test]
+    fn is_only_complete_with_prefix_and_terminator() {
+        assert!(!is_complete(b"c"));
+        assert!(!is_complete(b"\x1b[?62;"));
+        assert!(is_complete(b"\x1b[?62;22c"));
+    }
+
+    #[test]
+    fn rejects_invalid_response() {
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            parse(b"\x1b[?62;xc").unwrap_err().kind()
+        );
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            parse(b"\x1b[?c").unwrap_err().kind()
+        );
+    }
+}
@@ -0,0 +1,105 @@
+use super::{append_to_response, policy, read_sequence, SequenceSplitter};
+use crate::RawModeGuard;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Write as _};
+use std::time::{Duration, Instant};
+
+/// A query sent together with others using [`TerminalLock::query_many`](`crate::TerminalLock::query_many`).
+pub struct Query<'a> {
+    request: &'a [u8],
+    is_complete: IsComplete<'a>,
+    timeout: Option<Duration>,
+}
+
+type IsComplete<'a> = Box<dyn FnMut(&[u8]) -> bool + 'a>;
+
+impl fmt::Debug for Query<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Query")
+            .field("request", &self.request)
+            .field("timeout", &self.timeout)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<'a> Query<'a> {
+    /// Creates a query whose response is complete once `is_complete` returns `true`,
+    /// see [`TerminalLock::query`](`crate::TerminalLock::query`).
+    ///
+    /// As responses are only told apart by their order, `is_complete` should
+    /// be as specific as possible, see [`TerminalLock::query_many`](`crate::TerminalLock::query_many`).
+    pub fn new(request: &'a [u8], is_complete: impl FnMut(&[u8]) -> bool + 'a) -> Self {
+        Query {
+            request,
+            is_complete: Box::new(is_complete),
+            timeout: None,
+        }
+    }
+
+    /// How long to wait for the response, counted from when the queries are sent.
+    /// Defaults to the [default timeout](`crate::QueryPolicy::default_timeout`).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+/// Writes all requests at once and then reads the responses in order.
+///
+/// Should a query time out, whatever was received for it is
+/// considered to be the beginning of the next query's response.
+pub(crate) fn query_many(
+    raw_mode: &mut RawModeGuard<'_>,
+    queries: &mut [Query<'_>],
+) -> io::Result<Vec<Option<Vec<u8>>>> {
+    policy::check(raw_mode.0.terminal().provenance())?;
+    let mut typed = Vec::new();
+    let result = read_responses(raw_mode, queries, &mut typed);
+    raw_mode.unread(&typed);
+    result
+}
+
+fn read_responses(
+    raw_mode: &mut RawModeGuard<'_>,
+    queries: &mut [Query<'_>],
+    typed: &mut Vec<u8>,
+) -> io::Result<Vec<Option<Vec<u8>>>> {
+    let start = Instant::now();
+    let requests: Vec<u8> = queries.iter().flat_map(|q| q.request).copied().collect();
+    raw_mode.write_all(&requests)?;
+    raw_mode.flush()?;
+
+    let mut splitter = SequenceSplitter::default();
+    let mut carried_over = VecDeque::new();
+    let mut responses = Vec::with_capacity(queries.len());
+    for query in queries {
+        let deadline = start + query.timeout.unwrap_or_else(policy::default_timeout);
+        let mut response = Vec::new();
+        let mut sequences = Vec::new();
+        let complete = loop {
+            let sequence = match carried_over.pop_front() {
+                Some(sequence) => sequence,
+                None => match read_sequence(raw_mode, &mut splitter, deadline, typed)? {
+                    Some(sequence) => sequence,
+                    None => break false,
+                },
+            };
+            let len = response.len();
+            if append_to_response(&mut response, sequence, &mut query.is_complete, typed) {
+                break true;
+            }
+            if response.len() > len {
+                sequences.push(response[len..].to_vec());
+            }
+        };
+        if complete {
+            responses.push(Some(response));
+        } else {
+            carried_over.extend(sequences);
+            responses.push(None);
+        }
+    }
+    splitter.finish(typed);
+    Ok(responses)
+}
@@ -0,0 +1,83 @@
+/// The state of a private mode as reported by the terminal,
+/// see [`TerminalLock::query_mode`](`crate::TerminalLock::query_mode`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ModeState {
+    /// The terminal does not recognize the mode (or does not support the query).
+    NotRecognized,
+    /// The mode is set (enabled).
+    Set,
+    /// The mode is reset (disabled).
+    Reset,
+    /// The mode is set and can't be changed.
+    PermanentlySet,
+    /// The mode is reset and can't be changed.
+    PermanentlyReset,
+}
+
+impl ModeState {
+    /// Returns `true` if the mode is set, regardless of whether it can be changed.
+    pub fn is_set(self) -> bool {
+        matches!(self, ModeState::Set | ModeState::PermanentlySet)
+    }
+
+    /// Returns `true` if the terminal recognizes the mode.
+    pub fn is_recognized(self) -> bool {
+        self != ModeState::NotRecognized
+    }
+
+    /// Returns `true` if the mode is recognized and not permanently reset.
+    pub(crate) fn can_be_enabled(self) -> bool {
+        matches!(
+            self,
+            ModeState::Set | ModeState::Reset | ModeState::PermanentlySet
+        )
+    }
+}
+
+/// Builds a request of the form `CSI ? <mode> $ p` (`DECRQM`).
+pub(crate) fn request(mode: u16) -> Vec<u8> {
+    format!("\x1b[?{mode}$p").into_bytes()
+}
+
+/// Parses a response of the form `CSI ? <mode> ; <state> $ y` (`DECRPM`).
+pub(crate) fn parse(response: &[u8], mode: u16) -> ModeState {
+    let prefix = format!("\x1b[?{mode};");
+    let state = response
+        .windows(prefix.len())
+        .position(|w| w == prefix.as_bytes())
+        .map(|index| &response[index + prefix.len()..])
+        .and_then(|rest| rest.get(..3));
+    match state {
+        Some(b"1$y") => ModeState::Set,
+        Some(b"2$y") => ModeState::Reset,
+        Some(b"3$y") => ModeState::PermanentlySet,
+        Some(b"4$y") => ModeState::PermanentlyReset,
+        _ => ModeState::NotRecognized,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_request() {
+        assert_eq!(b"\x1b[?2026$p".as_slice(), request(2026));
+    }
+
+    #[test]
+    fn parses_states() {
+        assert_eq!(ModeState::NotRecognized, parse(b"\x1b[?2026;0$y", 2026));
+        assert_eq!(ModeState::Set, parse(b"\x1b[?2026;1$y", 2026));
+        assert_eq!(ModeState::Reset, parse(b"\x1b[?2004;2$y", 2004));
+        assert_eq!(ModeState::PermanentlySet, parse(b"\x1b[?1;3$y", 1));
+        assert_eq!(ModeState::PermanentlyReset, parse(b"\x1b[?1;4$y", 1));
+    }
+
+    #[test]
+    fn missing_or_mismatched_response_is_not_recognized() {
+        assert_eq!(ModeState::NotRecognized, parse(b"", 2026));
+        assert_eq!(ModeState::NotRecognized, parse(b"\x1b[?2004;1$y", 2026));
+    }
+}
@@ -0,0 +1,142 @@
+use crate::TerminalIdentity;
+
+/// Forwards queries through a terminal multiplexer to the terminal it runs in,
+/// see [`TerminalLock::query_with_passthrough`](`crate::TerminalLock::query_with_passthrough`).
+///
+/// Multiplexers answer some queries themselves and silently drop the ones they don't understand
+/// (e.g. `OSC 11` for the background color), so such queries need to be wrapped
+/// in a device control string (`DCS`) which the multiplexer passes on unchanged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum Passthrough {
+    /// The query is sent as is.
+    #[default]
+    Disabled,
+    /// The query is wrapped in `ESC P tmux; ... ESC \` with all escape characters doubled.
+    ///
+    /// tmux only forwards these sequences if the `allow-passthrough` option is enabled.
+    Tmux,
+    /// The query is wrapped in `ESC P ... ESC \`, split into chunks that GNU Screen accepts.
+    ///
+    /// Screen ends the passthrough at the first string terminator (`ESC \`),
+    /// so queries should be terminated with `BEL` instead.
+    Screen,
+}
+
+impl Passthrough {
+    /// Detects the multiplexer using environment variables, see [`TerminalIdentity::from_env`].
+    pub fn detect() -> Self {
+        match TerminalIdentity::from_env() {
+            TerminalIdentity::Tmux => Passthrough::Tmux,
+            TerminalIdentity::Screen => Passthrough::Screen,
+            _ => Passthrough::Disabled,
+        }
+    }
+
+    /// Wraps the request so that the multiplexer forwards it.
+    pub fn wrap(self, request: &[u8]) -> Vec<u8> {
+        match self {
+            Passthrough::Disabled => request.to_vec(),
+            Passthrough::Tmux => {
+                let mut wrapped = TMUX_PREFIX.to_vec();
+                for &byte in request {
+                    if byte == ESC {
+                        wrapped.push(ESC);
+                    }
+                    wrapped.push(byte);
+                }
+                wrapped.extend_from_slice(STRING_TERMINATOR);
+                wrapped
+            }
+            Passthrough::Screen => request
+                .chunks(SCREEN_MAX_CHUNK_LEN)
+                .flat_map(|chunk| [DCS_PREFIX, chunk, STRING_TERMINATOR].concat())
+                .collect(),
+        }
+    }
+
+    /// Removes the passthrough wrapping from a response, should the multiplexer have kept it.
+    /// Responses that aren't wrapped are returned unchanged.
+    pub fn unwrap(self, response: &[u8]) -> Vec<u8> {
+        match self {
+            Passthrough::Tmux => unwrap_tmux(response).unwrap_or_else(|| response.to_vec()),
+            // Screen forwards responses as they are, we can't distinguish
+            // a wrapping DCS from a response that is a DCS (e.g. `XTVERSION`).
+            Passthrough::Disabled | Passthrough::Screen => response.to_vec(),
+        }
+    }
+}
+
+const ESC: u8 = 0x1b;
+const DCS_PREFIX: &[u8] = b"\x1bP";
+const TMUX_PREFIX: &[u8] = b"\x1bPtmux;";
+const STRING_TERMINATOR: &[u8] = b"\x1b\\";
+
+// Screen's buffer for device control strings holds 768 bytes.
+const SCREEN_MAX_CHUNK_LEN: usize = 768;
+
+fn unwrap_tmux(response: &[u8]) -> Option<Vec<u8>> {
+    let mut bytes = response.strip_prefix(TMUX_PREFIX)?.iter();
+    let mut unwrapped = Vec::new();
+    while let Some(&byte) = bytes.next() {
+        if byte == ESC {
+            match bytes.next() {
+                Some(&ESC) => unwrapped.push(ESC),
+                Some(b'\\') => return Some(unwrapped),
+                Some(&other) => unwrapped.extend([ESC, other]),
+                None => unwrapped.push(ESC),
+            }
+        } else {
+            unwrapped.push(byte);
+        }
+    }
+    // The wrapping is incomplete, more of the response has yet to be read.
+    Some(unwrapped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tmux_doubles_escape_characters() {
+        assert_eq!(
+            b"\x1bPtmux;\x1b\x1b]11;?\x1b\x1b\\\x1b\\".to_vec(),
+            Passthrough::Tmux.wrap(b"\x1b]11;?\x1b\\")
+        );
+    }
+
+    #[test]
+    fn tmux_unwrap_reverses_wrap() {
+        let response = b"\x1b]11;rgb:0000/0000/0000\x1b\\";
+        let wrapped = Passthrough::Tmux.wrap(response);
+        assert_eq!(response.to_vec(), Passthrough::Tmux.unwrap(&wrapped));
+    }
+
+    #[test]
+    fn unwrapped_response_is_returned_unchanged() {
+        let response = b"\x1b]11;rgb:0000/0000/0000\x07";
+        assert_eq!(response.to_vec(), Passthrough::Tmux.unwrap(response));
+    }
+
+    #[test]
+    fn screen_splits_long_requests() {
+        let request = vec![b'x'; SCREEN_MAX_CHUNK_LEN + 1];
+        let wrapped = Passthrough::Screen.wrap(&request);
+        let expected = [
+            DCS_PREFIX,
+            &request[..SCREEN_MAX_CHUNK_LEN],
+            STRING_TERMINATOR,
+            DCS_PREFIX,
+            b"x",
+            STRING_TERMINATOR,
+        ]
+        .concat();
+        assert_eq!(expected, wrapped);
+    }
+
+    #[test]
+    fn disabled_passthrough_leaves_request_unchanged() {
+        assert_eq!(b"\x1b[c".to_vec(), Passthrough::Disabled.wrap(b"\x1b[c"));
+    }
+}
@@ -0,0 +1,139 @@
+use crate::Provenance;
+use std::env;
+use std::io;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+/// Process-wide settings that decide whether and how long queries wait for a response.
+///
+/// Terminals that never answer queries would otherwise make every query wait for its full timeout.
+/// Queries fail with [`Error::Unsupported`](`crate::Error::Unsupported`) without being sent if
+/// * `TERM` is `dumb` or
+/// * `TERM` or `TERM_PROGRAM` is one of the [non-responsive terminals](`QueryPolicy::non_responsive`).
+///
+/// Queries are only ever sent to a [`Terminal`](`crate::Terminal`), which [`terminal()`](`crate::terminal()`)
+/// doesn't return if the process has no terminal at all.
+/// Pseudo-terminals created by this crate ([`Provenance::Pty`]) are exempt
+/// as the environment variables describe the process's own terminal.
+///
+/// ## Example
+/// ```
+/// use std::time::Duration;
+/// use terminal_trx::QueryPolicy;
+///
+/// QueryPolicy::new()
+///     .default_timeout(Duration::from_millis(200))
+///     .non_responsive(["vt100"])
+///     .install();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryPolicy {
+    default_timeout: Duration,
+    non_responsive: Vec<String>,
+}
+
+impl Default for QueryPolicy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+static POLICY: Mutex<QueryPolicy> = Mutex::new(QueryPolicy::new());
+
+fn policy() -> MutexGuard<'static, QueryPolicy> {
+    POLICY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl QueryPolicy {
+    /// Creates the default policy: a timeout of one second and no additional non-responsive terminals.
+    pub const fn new() -> Self {
+        QueryPolicy {
+            default_timeout: Duration::from_secs(1),
+            non_responsive: Vec::new(),
+        }
+    }
+
+    /// The currently installed policy.
+    pub fn current() -> Self {
+        policy().clone()
+    }
+
+    /// Replaces the current policy for the entire process.
+    pub fn install(self) {
+        *policy() = self;
+    }
+
+    /// How long queries that don't take an explicit timeout
+    /// (e.g. [`TerminalLock::device_attributes`](`crate::TerminalLock::device_attributes`)) wait for a response.
+    /// Defaults to one second.
+    pub fn default_timeout(mut self, timeout: Duration) -> Self {
+        self.default_timeout = timeout;
+        self
+    }
+
+    /// Terminals (matched against the value of `TERM` or `TERM_PROGRAM`) that are known not to answer queries.
+    pub fn non_responsive(
+        mut self,
+        terminals: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.non_responsive = terminals.into_iter().map(Into::into).collect();
+        self
+    }
+
+    fn is_non_responsive(&self, var: impl Fn(&str) -> Option<String>) -> bool {
+        let term = var("TERM");
+        let term_program = var("TERM_PROGRAM");
+        term.as_deref() == Some("dumb")
+            || [term, term_program]
+                .into_iter()
+                .flatten()
+                .any(|value| self.non_responsive.contains(&value))
+    }
+}
+
+pub(crate) fn default_timeout() -> Duration {
+    policy().default_timeout
+}
+
+/// Fails if the policy forbids sending queries to the terminal.
+pub(crate) fn check(provenance: Provenance) -> io::Result<()> {
+    if provenance != Provenance::Pty && policy().is_non_responsive(|name| env::var(name).ok()) {
+        Err(crate::Error::Unsupported {
+            reason: "the terminal is known not to answer queries",
+        }
+        .into())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars<'a>(vars: &'a [(&str, &str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        |name| {
+            vars.iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, value)| (*value).to_owned())
+        }
+    }
+
+    #[test]
+    fn dumb_terminal_is_non_responsive() {
+        assert!(QueryPolicy::new().is_non_responsive(vars(&[("TERM", "dumb")])));
+        assert!(!QueryPolicy::new().is_non_responsive(vars(&[("TERM", "xterm")])));
+        assert!(!QueryPolicy::new().is_non_responsive(vars(&[])));
+    }
+
+    #[test]
+    fn configured_terminals_are_non_responsive() {
+        let policy = QueryPolicy::new().non_responsive(["vt100", "SomeTerminal"]);
+        assert!(policy.is_non_responsive(vars(&[("TERM", "vt100")])));
+        assert!(policy.is_non_responsive(vars(&[
+            ("TERM", "xterm-256color"),
+            ("TERM_PROGRAM", "SomeTerminal")
+        ])));
+        assert!(!policy.is_non_responsive(vars(&[("TERM", "xterm-256color")])));
+    }
+}
@@ -0,0 +1,30 @@
+pub(crate) const REQUEST: &[u8] = b"\x1b[>c";
+
+const PREFIX: &[u8] = b"\x1b[>";
+
+/// Extracts the parameters from a response of the form `ESC [ > <type> ; <version> ; <rom> c`.
+pub(crate) fn parse(response: &[u8]) -> Option<Vec<u16>> {
+    let start = response.windows(PREFIX.len()).rposition(|w| w == PREFIX)? + PREFIX.len();
+    let end = start + response[start..].iter().position(|b| *b == b'c')?;
+    response[start..end]
+        .split(|b| *b == b';')
+        .map(|p| std::str::from_utf8(p).ok()?.parse().ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_parameters() {
+        assert_eq!(Some(vec![41, 388, 0]), parse(b"\x1b[>41;388;0c"));
+    }
+
+    #[test]
+    fn returns_none_without_response() {
+        assert_eq!(None, parse(b""));
+        assert_eq!(None, parse(b"\x1b[>41;388"));
+        assert_eq!(None, parse(b"\x1b[>41;x;0c"));
+    }
+}
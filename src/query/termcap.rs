@@ -0,0 +1,81 @@
+use super::{dcs_strings, STRING_TERMINATOR};
+use crate::hex;
+
+/// Builds a request of the form `DCS + q <name> ; <name> ... ST` with hex-encoded names.
+pub(crate) fn request(names: &[&str]) -> Vec<u8> {
+    let names = names
+        .iter()
+        .map(|name| hex::encode(name.as_bytes(), "").to_ascii_uppercase())
+        .collect::<Vec<_>>()
+        .join(";");
+    [b"\x1bP+q", names.as_bytes(), STRING_TERMINATOR].concat()
+}
+
+/// Extracts the values for the given names from responses of the form
+/// `DCS 1 + r <name> = <value> ST` (known capability) or `DCS 0 + r <name> ST` (unknown capability).
+/// A response may contain more than one `<name> = <value>` pair separated by `;`.
+pub(crate) fn parse(response: &[u8], names: &[&str]) -> Vec<Option<String>> {
+    let entries: Vec<(String, String)> = dcs_strings(response)
+        .filter_map(|s| s.strip_prefix(b"1+r"))
+        .flat_map(|s| s.split(|b| *b == b';'))
+        .filter_map(parse_entry)
+        .collect();
+    names
+        .iter()
+        .map(|name| {
+            entries
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, value)| value.clone())
+        })
+        .collect()
+}
+
+fn parse_entry(entry: &[u8]) -> Option<(String, String)> {
+    let mut parts = entry.splitn(2, |b| *b == b'=');
+    let name = hex_decode(parts.next()?)?;
+    let value = parts.next().map_or(Some(String::new()), hex_decode)?;
+    Some((name, value))
+}
+
+fn hex_decode(hex: &[u8]) -> Option<String> {
+    String::from_utf8(hex::decode(hex)?).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_names_as_hex() {
+        assert_eq!(
+            b"\x1bP+q524742;436F\x1b\\".as_slice(),
+            request(&["RGB", "Co"])
+        );
+    }
+
+    #[test]
+    fn decodes_hex() {
+        assert_eq!(Some("RGB".to_owned()), hex_decode(b"524742"));
+        assert_eq!(Some("Co".to_owned()), hex_decode(b"436f"));
+        assert_eq!(None, hex_decode(b"ff"));
+    }
+
+    #[test]
+    fn parses_separate_responses() {
+        let response = b"\x1bP1+r436F=323536\x1b\\\x1bP0+r536D756C78\x1b\\";
+        assert_eq!(
+            vec![Some("256".to_owned()), None],
+            parse(response, &["Co", "Smulx"])
+        );
+    }
+
+    #[test]
+    fn parses_combined_response() {
+        let response = b"\x1bP1+r436F=323536;524742\x1b\\";
+        assert_eq!(
+            vec![Some(String::new()), Some("256".to_owned())],
+            parse(response, &["RGB", "Co"])
+        );
+    }
+}
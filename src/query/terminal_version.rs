@@ -0,0 +1,29 @@
+use super::dcs_strings;
+
+pub(crate) const REQUEST: &[u8] = b"\x1b[>0q";
+
+/// Extracts the name and version from a response of the form `DCS > | <text> ST`.
+pub(crate) fn parse(response: &[u8]) -> Option<String> {
+    dcs_strings(response)
+        .find_map(|s| s.strip_prefix(b">|"))
+        .map(|s| String::from_utf8_lossy(s).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_version() {
+        assert_eq!(
+            Some("xterm(388)".to_owned()),
+            parse(b"\x1bP>|xterm(388)\x1b\\")
+        );
+    }
+
+    #[test]
+    fn returns_none_without_response() {
+        assert_eq!(None, parse(b""));
+        assert_eq!(None, parse(b"\x1bP>|unterminated"));
+    }
+}
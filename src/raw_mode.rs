@@ -0,0 +1,236 @@
+#[cfg(all(any(unix, windows), not(terminal_trx_test_unsupported)))]
+mod ref_count;
+#[cfg(all(any(unix, windows), not(terminal_trx_test_unsupported)))]
+pub(crate) use ref_count::{RawModeRefCounts, Restore};
+
+use crate::imp;
+use std::io;
+use std::time::Duration;
+
+/// Fine-grained control over the effects of raw mode.
+/// Used with [`TerminalLock::enable_raw_mode_with`](`crate::TerminalLock::enable_raw_mode_with`).
+///
+/// Each option describes whether a feature of the terminal stays enabled while in raw mode.
+/// Setting an option to `true` leaves the terminal's current setting untouched.
+///
+/// The default options are the ones used by [`TerminalLock::enable_raw_mode`](`crate::TerminalLock::enable_raw_mode`).
+///
+/// ## Example
+/// ```no_run
+/// use terminal_trx::{terminal, RawModeOptions};
+///
+/// let mut tty = terminal().unwrap();
+/// let mut lock = tty.lock();
+/// let options = RawModeOptions::new().signals(false);
+/// let mut raw_mode = lock.enable_raw_mode_with(options).unwrap();
+///
+/// // Ctrl+C is now read as input instead of interrupting the process.
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawModeOptions {
+    pub(crate) echo: bool,
+    pub(crate) canonical: bool,
+    pub(crate) signals: bool,
+    pub(crate) flow_control: bool,
+    pub(crate) output_processing: bool,
+    pub(crate) virtual_terminal_input: bool,
+    pub(crate) echo_newline: bool,
+    pub(crate) read_behavior: Option<(u8, Option<Duration>)>,
+    pub(crate) timing: ModeChangeTiming,
+    pub(crate) external_changes: ExternalModeChanges,
+}
+
+impl Default for RawModeOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RawModeOptions {
+    /// Creates the default options.
+    pub const fn new() -> Self {
+        RawModeOptions {
+            echo: false,
+            canonical: false,
+            signals: true,
+            flow_control: true,
+            output_processing: true,
+            virtual_terminal_input: true,
+            echo_newline: false,
+            read_behavior: None,
+            timing: ModeChangeTiming::Drain,
+            external_changes: ExternalModeChanges::Overwrite,
+        }
+    }
+
+    /// Options used by [`TerminalLock::disable_echo`](`crate::TerminalLock::disable_echo`):
+    /// Only echo is disabled, but newlines are still echoed so that the cursor advances
+    /// when the user submits their input.
+    pub(crate) const fn echo_disabled() -> Self {
+        RawModeOptions {
+            echo: false,
+            canonical: true,
+            signals: true,
+            flow_control: true,
+            output_processing: true,
+            virtual_terminal_input: false,
+            echo_newline: true,
+            read_behavior: None,
+            timing: ModeChangeTiming::Drain,
+            external_changes: ExternalModeChanges::Overwrite,
+        }
+    }
+
+    /// Whether input typed into the terminal is echoed back. Defaults to `false`.
+    ///
+    /// ### Windows
+    /// Echo is only available together with canonical mode.
+    pub const fn echo(mut self, enabled: bool) -> Self {
+        self.echo = enabled;
+        self
+    }
+
+    /// Whether input is only made available line by line (i.e. after a newline character).
+    /// Defaults to `false`.
+    pub const fn canonical(mut self, enabled: bool) -> Self {
+        self.canonical = enabled;
+        self
+    }
+
+    /// Whether special characters such as Ctrl+C generate signals. Defaults to `true`.
+    pub const fn signals(mut self, enabled: bool) -> Self {
+        self.signals = enabled;
+        self
+    }
+
+    /// (Unix) Whether software flow control (Ctrl+S / Ctrl+Q) is active, see [`TerminalAttributes::flow_control`](`crate::TerminalAttributes::flow_control`).
+    /// Defaults to `true`.
+    pub const fn flow_control(mut self, enabled: bool) -> Self {
+        self.flow_control = enabled;
+        self
+    }
+
+    /// Whether output is post-processed, e.g. translating `\n` to `\r\n`. Defaults to `true`.
+    ///
+    /// Keeping this enabled is useful for line-oriented tools that only need unbuffered input,
+    /// as they can keep writing `\n` instead of `\r\n`.
+    ///
+    /// ### Unix
+    /// This controls `OPOST`, which enables all other output flags such as `ONLCR`.
+    ///
+    /// ### Windows
+    /// Processed output is always enabled as it's required for VT sequences,
+    /// so this controls `DISABLE_NEWLINE_AUTO_RETURN` instead.
+    pub const fn output_processing(mut self, enabled: bool) -> Self {
+        self.output_processing = enabled;
+        self
+    }
+
+    /// (Windows) Whether input is delivered as VT sequences. Defaults to `true`.
+    ///
+    /// With this enabled (`ENABLE_VIRTUAL_TERMINAL_INPUT`), keys such as the arrow keys
+    /// arrive as the same escape sequences as on Unix (e.g. `ESC [ A`), so that
+    /// a single input parser can be used on all platforms.
+    /// This option has no effect on other platforms as terminals there always send VT sequences.
+    pub const fn virtual_terminal_input(mut self, enabled: bool) -> Self {
+        self.virtual_terminal_input = enabled;
+        self
+    }
+
+    /// Configures when reads return, together with the other changes made when raw mode is enabled.
+    /// Defaults to leaving the read behaviour untouched.
+    ///
+    /// This is the same as calling [`RawModeGuard::set_read_behavior`](`crate::RawModeGuard::set_read_behavior`)
+    /// right after enabling raw mode, except that the terminal's mode is only changed once.
+    /// This matters on slow serial lines where every mode change takes time and may drop input.
+    pub const fn read_behavior(mut self, min: u8, timeout: Option<Duration>) -> Self {
+        self.read_behavior = Some((min, timeout));
+        self
+    }
+
+    /// When enabling raw mode and restoring the previous mode takes effect.
+    /// Defaults to [`ModeChangeTiming::Drain`].
+    pub const fn timing(mut self, timing: ModeChangeTiming) -> Self {
+        self.timing = timing;
+        self
+    }
+
+    /// What happens to changes made by someone else (e.g. a child process or readline)
+    /// while in raw mode when the previous mode is restored.
+    /// Defaults to [`ExternalModeChanges::Overwrite`].
+    ///
+    /// Use [`RawModeGuard::has_external_changes`](`crate::RawModeGuard::has_external_changes`)
+    /// to detect such changes.
+    pub const fn external_changes(mut self, external_changes: ExternalModeChanges) -> Self {
+        self.external_changes = external_changes;
+        self
+    }
+}
+
+/// When a change of the terminal's mode takes effect, see [`RawModeOptions::timing`].
+///
+/// ### Windows
+/// Writes to the console are synchronous, so [`ModeChangeTiming::Now`]
+/// and [`ModeChangeTiming::Drain`] behave the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum ModeChangeTiming {
+    /// The change takes effect immediately (`TCSANOW`).
+    Now,
+    /// The change takes effect after all pending output has been transmitted (`TCSADRAIN`).
+    #[default]
+    Drain,
+    /// The change takes effect after all pending output has been transmitted
+    /// and all pending input is discarded (`TCSAFLUSH`).
+    Flush,
+}
+
+/// How changes to the terminal's mode made by someone else while in raw mode
+/// are treated when the previous mode is restored, see [`RawModeOptions::external_changes`].
+///
+/// ### Windows
+/// MSYS/Cygwin terminals always use [`ExternalModeChanges::Overwrite`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[non_exhaustive]
+pub enum ExternalModeChanges {
+    /// The previous mode is restored, discarding the changes.
+    #[default]
+    Overwrite,
+    /// The previous mode is not restored if the mode was changed,
+    /// leaving the terminal as it was configured by the other component.
+    Keep,
+    /// Only the flags changed when enabling raw mode are restored,
+    /// all other changes are kept.
+    Merge,
+}
+
+/// Sets the terminal's attributes when dropped, which includes unwinding from a panic.
+pub(crate) struct ReapplyOnDrop<'a> {
+    terminal: &'a imp::Terminal,
+    attributes: Option<imp::TerminalAttributes>,
+}
+
+impl<'a> ReapplyOnDrop<'a> {
+    pub(crate) fn new(terminal: &'a imp::Terminal, attributes: imp::TerminalAttributes) -> Self {
+        ReapplyOnDrop {
+            terminal,
+            attributes: Some(attributes),
+        }
+    }
+
+    /// Sets the attributes right away, surfacing errors that [`Drop`] would ignore.
+    pub(crate) fn finish(mut self) -> io::Result<()> {
+        match self.attributes.take() {
+            Some(attributes) => self.terminal.set_attributes(&attributes),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ReapplyOnDrop<'_> {
+    fn drop(&mut self) {
+        if let Some(attributes) = self.attributes.take() {
+            _ = self.terminal.set_attributes(&attributes);
+        }
+    }
+}
@@ -0,0 +1,156 @@
+/// Process-wide bookkeeping of the active raw mode guards for each terminal device (identified by `K`).
+#[derive(Debug)]
+pub(crate) struct RawModeRefCounts<K, T>(Vec<(K, RawModeRefCount<T>)>);
+
+impl<K: PartialEq, T> RawModeRefCounts<K, T> {
+    pub(crate) const fn new() -> Self {
+        RawModeRefCounts(Vec::new())
+    }
+
+    pub(crate) fn acquire(&mut self, key: K, current: T) -> usize {
+        match self.0.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, ref_count)) => ref_count.acquire(current),
+            None => {
+                let mut ref_count = RawModeRefCount::new();
+                let depth = ref_count.acquire(current);
+                self.0.push((key, ref_count));
+                depth
+            }
+        }
+    }
+
+    pub(crate) fn release(&mut self, key: &K, depth: usize, modified: bool) -> Restore<T> {
+        let Some(index) = self.0.iter().position(|(k, _)| k == key) else {
+            return Restore::Nothing;
+        };
+        let restore = self.0[index].1.release(depth, modified);
+        if self.0[index].1.count == 0 {
+            self.0.swap_remove(index);
+        }
+        restore
+    }
+
+    /// The mode before the first guard for the given device was created.
+    pub(crate) fn original(&self, key: &K) -> Option<&T> {
+        self.0
+            .iter()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, ref_count)| ref_count.original.as_ref())
+    }
+}
+
+/// Bookkeeping of the active raw mode guards for a single terminal device.
+///
+/// Guards usually restore the mode they replaced when dropped.
+/// Should guards be dropped out of order, the original mode (i.e. the mode before the first guard was created)
+/// is only restored once the last guard is dropped.
+#[derive(Debug)]
+pub(crate) struct RawModeRefCount<T> {
+    count: usize,
+    original: Option<T>,
+    modified: bool,
+}
+
+/// What a guard should restore when it's dropped.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Restore<T> {
+    /// The mode before the first guard was created.
+    Original(T),
+    /// The mode the guard itself replaced.
+    Previous,
+    Nothing,
+}
+
+impl<T> RawModeRefCount<T> {
+    pub(crate) const fn new() -> Self {
+        RawModeRefCount {
+            count: 0,
+            original: None,
+            modified: false,
+        }
+    }
+
+    /// Registers a new guard, returning its depth which needs to be passed to [`RawModeRefCount::release`].
+    pub(crate) fn acquire(&mut self, current: T) -> usize {
+        if self.count == 0 {
+            self.original = Some(current);
+            self.modified = false;
+        }
+        self.count += 1;
+        self.count
+    }
+
+    pub(crate) fn release(&mut self, depth: usize, modified: bool) -> Restore<T> {
+        let is_innermost = depth == self.count;
+        self.count = self.count.saturating_sub(1);
+        if self.count == 0 {
+            match self.original.take() {
+                Some(original) if modified || self.modified => Restore::Original(original),
+                _ => Restore::Nothing,
+            }
+        } else if is_innermost {
+            Restore::Previous
+        } else {
+            // The modifications of a guard dropped out of order
+            // can only be undone by restoring the original mode.
+            self.modified |= modified;
+            Restore::Nothing
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guards_dropped_in_order_restore_previous_mode() {
+        let mut ref_count = RawModeRefCount::new();
+        let outer = ref_count.acquire("cooked");
+        let inner = ref_count.acquire("raw");
+        assert_eq!(Restore::Previous, ref_count.release(inner, true));
+        assert_eq!(Restore::Original("cooked"), ref_count.release(outer, true));
+    }
+
+    #[test]
+    fn guards_dropped_out_of_order_restore_original_mode_last() {
+        let mut ref_count = RawModeRefCount::new();
+        let outer = ref_count.acquire("cooked");
+        let inner = ref_count.acquire("raw");
+        assert_eq!(Restore::Nothing, ref_count.release(outer, true));
+        assert_eq!(Restore::Original("cooked"), ref_count.release(inner, false));
+    }
+
+    #[test]
+    fn devices_are_counted_separately() {
+        let mut ref_counts = RawModeRefCounts::new();
+        let first = ref_counts.acquire(1, "first");
+        let second = ref_counts.acquire(2, "second");
+        assert_eq!(
+            Restore::Original("first"),
+            ref_counts.release(&1, first, true)
+        );
+        assert_eq!(
+            Restore::Original("second"),
+            ref_counts.release(&2, second, true)
+        );
+    }
+
+    #[test]
+    fn original_mode_is_kept_while_guards_are_alive() {
+        let mut ref_counts = RawModeRefCounts::new();
+        let outer = ref_counts.acquire(1, "cooked");
+        let inner = ref_counts.acquire(1, "raw");
+        assert_eq!(Some(&"cooked"), ref_counts.original(&1));
+        _ = ref_counts.release(&1, inner, true);
+        _ = ref_counts.release(&1, outer, true);
+        assert_eq!(None, ref_counts.original(&1));
+    }
+
+    #[test]
+    fn unmodified_mode_is_not_restored() {
+        let mut ref_count = RawModeRefCount::new();
+        let guard = ref_count.acquire("raw");
+        assert_eq!(Restore::Nothing, ref_count.release(guard, false));
+    }
+}
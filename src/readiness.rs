@@ -0,0 +1,53 @@
+use crate::imp;
+use std::io;
+use std::time::Duration;
+
+/// Tells when input can be read from the terminal, created using [`Terminal::readiness`](`crate::Terminal::readiness`).
+///
+/// Use [`Readiness::wait`] to block until input arrives, or register the underlying
+/// file descriptor (Unix, using `AsFd`) or handle (Windows, using `AsHandle`) with an event loop.
+/// This is also how input is read from async code: register the file descriptor with the runtime's reactor
+/// (e.g. tokio's `AsyncFd`) and read (or decode events) through a [`RawModeGuard`](`crate::RawModeGuard`)
+/// once it becomes readable.
+///
+/// Readiness only reflects input that hasn't been read from the terminal yet.
+/// Input pushed back using [`TerminalLock::unread`](`crate::TerminalLock::unread`) is not taken into account.
+///
+/// ### Windows
+/// The handle is signaled for all console input records, including those that reading ignores
+#[cfg_attr(
+    windows,
+    doc = "(see [`ConsoleHandles::input_wait_handle`](`crate::ConsoleHandles::input_wait_handle`))."
+)]
+#[cfg_attr(not(windows), doc = "(see `ConsoleHandles::input_wait_handle`).")]
+///
+/// MSYS/Cygwin terminals are pipes which are never signaled, so the handle can't be registered
+/// with an event loop. [`Readiness::wait`] still works as it checks the pipe for input at a short interval.
+///
+/// ## Example
+/// ```no_run
+/// use std::io::Read as _;
+/// use std::time::Duration;
+/// use terminal_trx::terminal;
+///
+/// let tty = terminal().unwrap();
+/// let mut lock = tty.lock();
+/// let mut raw_mode = lock.enable_raw_mode().unwrap();
+/// if tty.readiness().wait(Some(Duration::from_secs(1))).unwrap() {
+///     let mut buf = [0; 64];
+///     let len = raw_mode.read(&mut buf).unwrap();
+/// }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct Readiness<'a>(pub(crate) &'a imp::Terminal);
+
+impl Readiness<'_> {
+    /// Waits until input is available or the timeout elapses.
+    /// Waits indefinitely if the timeout is [`None`].
+    ///
+    /// Returns `true` if input is available and `false` if the timeout elapsed.
+    /// The end of input (e.g. a hangup) counts as available as the next read won't block.
+    pub fn wait(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        self.0.wait_for_input(timeout)
+    }
+}
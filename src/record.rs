@@ -0,0 +1,394 @@
+use crate::hex;
+use std::collections::VecDeque;
+use std::io;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Records all reads from and writes to the wrapped terminal (with timestamps) to a log,
+/// e.g. to capture the escape sequences exchanged with a user's terminal for debugging.
+/// The log can be fed back using [`Replay`].
+///
+/// Each read and write is logged as one line of the form `<seconds> <direction> <data>`
+/// where the direction is `i` for input and `o` for output, and the data is hex-encoded.
+/// Errors writing to the log don't interrupt the terminal I/O, the first one is returned by [`io::Write::flush`].
+///
+/// Use [`Recorder::asciicast`] to record in the [asciicast v2](https://docs.asciinema.org/manual/asciicast/v2/) format
+/// instead, which can be played back using asciinema.
+/// Use [`Recorder::hex_dump`] for a human-readable log.
+///
+/// ## Example
+/// ```no_run
+/// use std::fs::File;
+/// use std::io::Write as _;
+/// use terminal_trx::{terminal, Recorder};
+///
+/// let tty = terminal().unwrap();
+/// let mut lock = tty.lock();
+/// let log = File::create("session.log").unwrap();
+/// let mut recorder = Recorder::new(&mut lock, log);
+/// write!(recorder, "hello world").unwrap();
+/// recorder.flush().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct Recorder<T, W: io::Write> {
+    inner: T,
+    log: W,
+    format: Format,
+    start: Instant,
+    log_error: Option<io::Error>,
+}
+
+#[derive(Debug)]
+enum Format {
+    Log,
+    // Holds incomplete UTF-8 sequences of the input and output until they're completed by the next read / write.
+    Asciicast { incomplete: [Vec<u8>; 2] },
+    HexDump,
+}
+
+impl<T, W: io::Write> Recorder<T, W> {
+    /// Wraps the terminal (or any other reader and writer), logging to `log`.
+    pub fn new(inner: T, log: W) -> Self {
+        Recorder {
+            inner,
+            log,
+            format: Format::Log,
+            start: Instant::now(),
+            log_error: None,
+        }
+    }
+
+    /// Wraps the terminal, logging in the asciicast v2 format.
+    ///
+    /// The size of the terminal (in columns and rows) is written to the header,
+    /// which is written immediately. Output is recorded as `"o"` events and input as `"i"` events.
+    pub fn asciicast(inner: T, mut log: W, columns: u16, rows: u16) -> io::Result<Self> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        writeln!(
+            log,
+            r#"{{"version": 2, "width": {columns}, "height": {rows}, "timestamp": {timestamp}}}"#
+        )?;
+        Ok(Recorder {
+            inner,
+            log,
+            format: Format::Asciicast {
+                incomplete: Default::default(),
+            },
+            start: Instant::now(),
+            log_error: None,
+        })
+    }
+
+    /// Wraps the terminal, logging annotated hex dumps of all bytes read and written.
+    /// This is also available as [`TerminalLock::debug_tap`](`crate::TerminalLock::debug_tap`).
+    ///
+    /// Each line contains up to 16 bytes together with the time, the direction (`i` or `o`)
+    /// and the bytes with control characters escaped (e.g. `\e` for `ESC`):
+    ///
+    /// ```text
+    ///    0.000012 o 1b 5b 63                                         \e[c
+    ///    0.031337 i 1b 5b 3f 36 32 3b 32 32 63                       \e[?62;22c
+    /// ```
+    ///
+    /// The log can't be fed back using [`Replay`].
+    pub fn hex_dump(inner: T, log: W) -> Self {
+        Recorder {
+            inner,
+            log,
+            format: Format::HexDump,
+            start: Instant::now(),
+            log_error: None,
+        }
+    }
+
+    /// Returns the wrapped terminal and the log.
+    pub fn into_inner(self) -> (T, W) {
+        (self.inner, self.log)
+    }
+
+    fn record(&mut self, direction: Direction, data: &[u8]) {
+        if data.is_empty() || self.log_error.is_some() {
+            return;
+        }
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let line = match &mut self.format {
+            Format::Log => format!(
+                "{elapsed:.6} {} {}\n",
+                direction.as_str(),
+                hex::encode(data, "")
+            ),
+            Format::Asciicast { incomplete } => {
+                let incomplete = &mut incomplete[direction as usize];
+                incomplete.extend_from_slice(data);
+                let text = take_utf8(incomplete);
+                if text.is_empty() {
+                    return;
+                }
+                format!(
+                    "[{elapsed:.6}, \"{}\", \"{}\"]\n",
+                    direction.as_str(),
+                    json_escape(&text)
+                )
+            }
+            Format::HexDump => data
+                .chunks(16)
+                .map(|chunk| {
+                    format!(
+                        "{elapsed:>11.6} {} {:<48} {}\n",
+                        direction.as_str(),
+                        hex::encode(chunk, " "),
+                        escape_bytes(chunk)
+                    )
+                })
+                .collect(),
+        };
+        if let Err(error) = self.log.write_all(line.as_bytes()) {
+            self.log_error = Some(error);
+        }
+    }
+}
+
+impl<T: io::Read, W: io::Write> io::Read for Recorder<T, W> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.record(Direction::Input, &buf[..len]);
+        Ok(len)
+    }
+}
+
+impl<T: io::Write, W: io::Write> io::Write for Recorder<T, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.record(Direction::Output, &buf[..len]);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        if let Some(error) = self.log_error.take() {
+            return Err(error);
+        }
+        self.log.flush()
+    }
+}
+
+/// Feeds the input of a log created by [`Recorder`] back to the reader.
+/// Output written to the replay is discarded.
+///
+/// Reads return `0` once all recorded input has been consumed.
+/// The input is returned in the same chunks as it was originally read,
+/// which makes it possible to reproduce bugs that depend on how input is split across reads.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    input: VecDeque<(Duration, Vec<u8>)>,
+}
+
+impl Replay {
+    /// Parses a log created by [`Recorder`].
+    pub fn new(log: impl io::BufRead) -> io::Result<Self> {
+        let mut input = VecDeque::new();
+        for line in log.lines() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            let (timestamp, direction, data) = parse_line(&line).ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid recording: {line}"),
+                )
+            })?;
+            if direction == Direction::Input {
+                input.push_back((timestamp, data));
+            }
+        }
+        Ok(Replay { input })
+    }
+
+    /// Returns the time (relative to the start of the recording) at which
+    /// the next chunk of input was read or [`None`] if all input has been consumed.
+    pub fn next_input_at(&self) -> Option<Duration> {
+        self.input.front().map(|(timestamp, _)| *timestamp)
+    }
+}
+
+impl io::Read for Replay {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let Some((_, chunk)) = self.input.front_mut() else {
+            return Ok(0);
+        };
+        let len = buf.len().min(chunk.len());
+        buf[..len].copy_from_slice(&chunk[..len]);
+        chunk.drain(..len);
+        if chunk.is_empty() {
+            self.input.pop_front();
+        }
+        Ok(len)
+    }
+}
+
+impl io::Write for Replay {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Input,
+    Output,
+}
+
+impl Direction {
+    fn as_str(self) -> &'static str {
+        match self {
+            Direction::Input => "i",
+            Direction::Output => "o",
+        }
+    }
+}
+
+fn parse_line(line: &str) -> Option<(Duration, Direction, Vec<u8>)> {
+    let mut parts = line.split(' ');
+    let timestamp = parts.next()?.parse::<f64>().ok()?;
+    let timestamp = Duration::try_from_secs_f64(timestamp).ok()?;
+    let direction = match parts.next()? {
+        "i" => Direction::Input,
+        "o" => Direction::Output,
+        _ => return None,
+    };
+    let data = hex::decode(parts.next()?.as_bytes())?;
+    parts
+        .next()
+        .is_none()
+        .then_some((timestamp, direction, data))
+}
+
+/// Removes and returns the longest valid UTF-8 prefix, keeping a trailing incomplete sequence.
+/// Invalid sequences are replaced with U+FFFD.
+fn take_utf8(bytes: &mut Vec<u8>) -> String {
+    let mut text = String::new();
+    loop {
+        let (valid_up_to, error_len) = match std::str::from_utf8(bytes) {
+            Ok(_) => (bytes.len(), None),
+            Err(error) => (error.valid_up_to(), error.error_len()),
+        };
+        text.push_str(&String::from_utf8_lossy(&bytes[..valid_up_to]));
+        match error_len {
+            Some(len) => {
+                text.push(char::REPLACEMENT_CHARACTER);
+                bytes.drain(..valid_up_to + len);
+            }
+            None => {
+                bytes.drain(..valid_up_to);
+                return text;
+            }
+        }
+    }
+}
+
+fn json_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_bytes(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| match b {
+            0x1b => "\\e".to_owned(),
+            0x07 => "\\a".to_owned(),
+            b'\r' => "\\r".to_owned(),
+            b'\n' => "\\n".to_owned(),
+            b'\t' => "\\t".to_owned(),
+            b'\\' => "\\\\".to_owned(),
+            b' '..=b'~' => char::from(b).to_string(),
+            b => format!("\\x{b:02x}"),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use std::io::{Read as _, Write as _};
+
+    #[test]
+    fn records_reads_and_writes() {
+        let mut terminal = io::Cursor::new(b"\x1b[?62c".to_vec());
+        let mut recorder = Recorder::new(&mut terminal, Vec::new());
+        recorder.read_exact(&mut [0; 3]).unwrap();
+        recorder.write_all(b"\x1b[c").unwrap();
+        let (_, log) = recorder.into_inner();
+        let log = String::from_utf8(log).unwrap();
+        let lines: Vec<_> = log.lines().map(|l| l.split_once(' ').unwrap().1).collect();
+        assert_eq!(vec!["i 1b5b3f", "o 1b5b63"], lines);
+    }
+
+    #[test]
+    fn records_asciicast() {
+        let mut terminal = io::Cursor::new(Vec::new());
+        let mut recorder = Recorder::asciicast(&mut terminal, Vec::new(), 80, 24).unwrap();
+        recorder.write_all(b"\x1b[1m\"\xc3").unwrap();
+        recorder.write_all(b"\xa4\"\n").unwrap();
+        let (_, log) = recorder.into_inner();
+        let log = String::from_utf8(log).unwrap();
+        let lines: Vec<_> = log.lines().collect();
+        assert!(lines[0].starts_with(r#"{"version": 2, "width": 80, "height": 24, "timestamp": "#));
+        let events: Vec<_> = lines[1..]
+            .iter()
+            .map(|l| l.split_once(", ").unwrap().1)
+            .collect();
+        assert_eq!(vec![r#""o", "\u001b[1m\""]"#, r#""o", "ä\"\n"]"#], events);
+    }
+
+    #[test]
+    fn records_hex_dump() {
+        let mut terminal = io::Cursor::new(b"\x1b[?62c\r\n".to_vec());
+        let mut recorder = Recorder::hex_dump(&mut terminal, Vec::new());
+        recorder.read_exact(&mut [0; 8]).unwrap();
+        let (_, log) = recorder.into_inner();
+        let log = String::from_utf8(log).unwrap();
+        let line = log.trim_start().split_once(' ').unwrap().1;
+        assert_eq!(
+            format!("i {:<48} \\e[?62c\\r\\n\n", "1b 5b 3f 36 32 63 0d 0a"),
+            line
+        );
+    }
+
+    #[test]
+    fn replays_recorded_input_in_chunks() {
+        let log = "0.000001 o 1b5b63\n0.5 i 1b5b\n1.0 i 3f3632\n";
+        let mut replay = Replay::new(log.as_bytes()).unwrap();
+        assert_eq!(Some(Duration::from_millis(500)), replay.next_input_at());
+        let mut buf = [0; 8];
+        assert_eq!(2, replay.read(&mut buf).unwrap());
+        assert_eq!(3, replay.read(&mut buf).unwrap());
+        assert_eq!(b"?62", &buf[..3]);
+        assert_eq!(0, replay.read(&mut buf).unwrap());
+    }
+
+    #[test]
+    fn rejects_invalid_recordings() {
+        assert!(Replay::new("0.1 x 00\n".as_bytes()).is_err());
+        assert!(Replay::new("0.1 i 0\n".as_bytes()).is_err());
+    }
+}
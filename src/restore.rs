@@ -0,0 +1,92 @@
+use crate::{imp, terminal, ModeChangeTiming};
+use std::io;
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Installs a panic hook that restores the terminal's current mode
+/// before the previously installed hook (usually the one printing the panic message) runs.
+///
+/// This ensures that a panic while in raw mode doesn't leave the user with an unusable shell.
+/// Call this function early in your program, before enabling raw mode.
+/// Calling this function more than once has no effect.
+///
+/// Every panic restores the mode, including panics on other threads that are caught (e.g. using [`panic::catch_unwind`]).
+/// The mode is changed immediately (see [`ModeChangeTiming::Now`](`crate::ModeChangeTiming::Now`))
+/// so that the hook doesn't block while output is pending.
+///
+/// ## Example
+/// ```no_run
+/// terminal_trx::install_restore_on_panic().unwrap();
+///
+/// let mut tty = terminal_trx::terminal().unwrap();
+/// let mut lock = tty.lock();
+/// let mut raw_mode = lock.enable_raw_mode().unwrap();
+///
+/// panic!("the terminal is restored before this message is printed");
+/// ```
+pub fn install_restore_on_panic() -> io::Result<()> {
+    static INSTALLED: AtomicBool = AtomicBool::new(false);
+
+    if INSTALLED.load(Ordering::Acquire) {
+        return Ok(());
+    }
+
+    let terminal = terminal()?;
+    let attributes = terminal.lock().attributes()?.0;
+
+    if INSTALLED.swap(true, Ordering::AcqRel) {
+        return Ok(());
+    }
+
+    // The panicking thread might be holding the terminal lock,
+    // so we bypass it and talk to the terminal directly.
+    let terminal = terminal.0;
+    let previous_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        _ = terminal.set_attributes_with(&attributes, ModeChangeTiming::Now);
+        previous_hook(info);
+    }));
+
+    Ok(())
+}
+
+/// Installs signal handlers that restore the terminal's current mode
+/// before the process is terminated by Ctrl+C or similar.
+///
+/// Call this function early in your program, before enabling raw mode.
+/// Calling this function more than once has no effect.
+///
+/// ## Unix
+/// Handlers are installed for `SIGINT`, `SIGTERM` and `SIGQUIT`.
+/// The terminal is only restored if the process is about to be terminated by the signal's default action.
+/// If a handler for one of the signals was installed before calling this function, the signal is forwarded to that handler.
+/// Signals that are ignored (e.g. when the process was started using `nohup`) stay ignored.
+///
+/// ## Windows
+/// A console control handler is installed, which restores the terminal's mode on
+/// Ctrl+C, Ctrl+Break, closing the console window, logoff and shutdown.
+/// Handlers installed after calling this function run first and may prevent the process from being terminated.
+pub fn install_restore_on_signals() -> io::Result<()> {
+    let terminal = terminal()?;
+    let attributes = terminal.lock().attributes()?.0;
+    imp::install_restore_on_signals(terminal.0, attributes)
+}
+
+/// Installs handlers for job control that restore the terminal's current mode
+/// when the process is suspended (e.g. using Ctrl+Z) and re-apply the mode
+/// that was active at the time of suspension (e.g. raw mode) once the process continues.
+///
+/// Call this function early in your program, before enabling raw mode.
+/// Calling this function more than once has no effect.
+///
+/// Nothing is installed if a handler for `SIGTSTP` was installed
+/// (or the signal is ignored) before calling this function, as
+/// the application is assumed to handle suspension on its own.
+/// A previously installed handler for `SIGCONT` is called after the mode is re-applied.
+#[cfg(all(unix, not(terminal_trx_test_unsupported)))]
+#[cfg_attr(docsrs, doc(cfg(unix)))]
+pub fn install_restore_on_suspend() -> io::Result<()> {
+    let terminal = terminal()?;
+    let attributes = terminal.lock().attributes()?.0;
+    imp::install_restore_on_suspend(terminal.0, attributes)
+}
@@ -0,0 +1,253 @@
+use crate::{imp, TerminalAttributes, TerminalLock};
+use std::fmt;
+use std::io::{self, Write};
+
+// DECTCEM: Whether the cursor is visible.
+const CURSOR_VISIBLE_MODE: u16 = 25;
+// Whether the alternate screen is active (saving and restoring the cursor when switching).
+const ALTERNATE_SCREEN_MODE: u16 = 1049;
+
+const FORMAT_VERSION: u8 = 1;
+
+/// A snapshot of the terminal's state that can be restored later using [`TerminalLock::restore`].
+///
+/// This is useful when running code (e.g. a child process) that may leave the terminal in
+/// an unusable state. The snapshot always includes the terminal's attributes (see [`TerminalAttributes`]).
+/// [`TerminalState::capture_with_modes`] additionally queries the cursor visibility
+/// and whether the alternate screen is active.
+///
+/// The state can be converted to bytes (see [`TerminalState::to_bytes`]), e.g. to pass it
+/// to a process that is started using `exec`. The bytes are only meant to be read
+/// by the same version of this crate on the same platform.
+///
+/// ## Example
+/// ```no_run
+/// use std::process::Command;
+/// use terminal_trx::{terminal, TerminalState};
+///
+/// let tty = terminal().unwrap();
+/// let mut lock = tty.lock();
+/// let state = TerminalState::capture(&mut lock).unwrap();
+/// let status = Command::new("vim").status();
+/// lock.restore(&state).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct TerminalState {
+    attributes: TerminalAttributes,
+    cursor_visible: Option<bool>,
+    alternate_screen: Option<bool>,
+}
+
+impl TerminalState {
+    /// Captures the terminal's attributes.
+    pub fn capture(lock: &mut TerminalLock<'_>) -> io::Result<Self> {
+        Ok(TerminalState {
+            attributes: lock.attributes()?,
+            cursor_visible: None,
+            alternate_screen: None,
+        })
+    }
+
+    /// Captures the terminal's attributes, the cursor visibility and whether the alternate screen is active.
+    ///
+    /// The modes are queried from the terminal (see [`TerminalLock::query_mode`]).
+    /// Modes that the terminal doesn't recognize are not restored.
+    pub fn capture_with_modes(lock: &mut TerminalLock<'_>) -> io::Result<Self> {
+        let attributes = lock.attributes()?;
+        let mut query = |mode| {
+            lock.query_mode(mode)
+                .map(|state| state.is_recognized().then(|| state.is_set()))
+        };
+        Ok(TerminalState {
+            cursor_visible: query(CURSOR_VISIBLE_MODE)?,
+            alternate_screen: query(ALTERNATE_SCREEN_MODE)?,
+            attributes,
+        })
+    }
+
+    /// The captured attributes.
+    pub fn attributes(&self) -> &TerminalAttributes {
+        &self.attributes
+    }
+
+    /// Whether the cursor was visible or [`None`] if it wasn't captured.
+    pub fn cursor_visible(&self) -> Option<bool> {
+        self.cursor_visible
+    }
+
+    /// Whether the alternate screen was active or [`None`] if it wasn't captured.
+    pub fn alternate_screen(&self) -> Option<bool> {
+        self.alternate_screen
+    }
+
+    /// Converts the state to bytes that can be read using [`TerminalState::from_bytes`].
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![
+            FORMAT_VERSION,
+            encode_mode(self.cursor_visible),
+            encode_mode(self.alternate_screen),
+        ];
+        bytes.extend_from_slice(&self.attributes.0.to_bytes());
+        bytes
+    }
+
+    /// Reads a state from the bytes returned by [`TerminalState::to_bytes`].
+    ///
+    /// ## Errors
+    /// [`ErrorKind::InvalidData`](`io::ErrorKind::InvalidData`) if the bytes were not
+    /// created by [`TerminalState::to_bytes`] on the same platform.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let invalid_data = || io::Error::new(io::ErrorKind::InvalidData, "invalid terminal state");
+        match bytes {
+            [FORMAT_VERSION, cursor_visible, alternate_screen, attributes @ ..] => {
+                Ok(TerminalState {
+                    attributes: imp::TerminalAttributes::from_bytes(attributes)
+                        .map(TerminalAttributes)
+                        .ok_or_else(invalid_data)?,
+                    cursor_visible: decode_mode(*cursor_visible).ok_or_else(invalid_data)?,
+                    alternate_screen: decode_mode(*alternate_screen).ok_or_else(invalid_data)?,
+                })
+            }
+            _ => Err(invalid_data()),
+        }
+    }
+
+    /// Compares this state to a later one, e.g. to find out which flags
+    /// a child process changed without restoring them.
+    ///
+    /// The returned [`StateDiff`] is displayed in symbolic form, e.g. `-ECHO +ICANON`,
+    /// using the names of the termios flags on Unix and the console mode flags on Windows.
+    /// The cursor visibility and the alternate screen (`?25` and `?1049`) are only
+    /// compared if both states captured them.
+    ///
+    /// ## Example
+    /// ```no_run
+    /// use terminal_trx::{terminal, TerminalState};
+    ///
+    /// let tty = terminal().unwrap();
+    /// let mut lock = tty.lock();
+    /// let before = TerminalState::capture(&mut lock).unwrap();
+    /// // ...
+    /// let after = TerminalState::capture(&mut lock).unwrap();
+    /// eprintln!("changed: {}", before.diff(&after));
+    /// ```
+    pub fn diff(&self, other: &TerminalState) -> StateDiff {
+        let flags = self.attributes.0.flags().into_iter();
+        let other_flags = other.attributes.0.flags().into_iter();
+        let modes = [
+            (self.cursor_visible, other.cursor_visible, "?25"),
+            (self.alternate_screen, other.alternate_screen, "?1049"),
+        ]
+        .into_iter()
+        .filter_map(|(mode, other_mode, name)| Some(((name, mode?), (name, other_mode?))));
+        let changes = flags
+            .zip(other_flags)
+            .chain(modes)
+            .filter(|((_, set), (_, other_set))| set != other_set)
+            .map(|(_, (name, set))| (name, set))
+            .collect();
+        StateDiff { changes }
+    }
+
+    /// Writes the sequences restoring the captured modes (if any).
+    pub(crate) fn write_modes(&self, writer: &mut impl Write) -> io::Result<()> {
+        let set_mode = |mode, enabled| format!("\x1b[?{mode}{}", if enabled { 'h' } else { 'l' });
+        let mut sequences = String::new();
+        if let Some(alternate_screen) = self.alternate_screen {
+            sequences.push_str(&set_mode(ALTERNATE_SCREEN_MODE, alternate_screen));
+        }
+        if let Some(cursor_visible) = self.cursor_visible {
+            sequences.push_str(&set_mode(CURSOR_VISIBLE_MODE, cursor_visible));
+        }
+        if !sequences.is_empty() {
+            writer.write_all(sequences.as_bytes())?;
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// The difference between two [`TerminalState`]s, see [`TerminalState::diff`].
+///
+/// Displays the changed flags in symbolic form, prefixed by `+` if the
+/// flag was set and `-` if it was cleared (e.g. `-ECHO +ICANON`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateDiff {
+    changes: Vec<(&'static str, bool)>,
+}
+
+impl StateDiff {
+    /// Returns `true` if the states don't differ.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// The names of the changed flags together with their new value.
+    pub fn changes(&self) -> impl Iterator<Item = (&'static str, bool)> + '_ {
+        self.changes.iter().copied()
+    }
+}
+
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, (name, set)) in self.changes.iter().enumerate() {
+            if index > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{}{name}", if *set { '+' } else { '-' })?;
+        }
+        Ok(())
+    }
+}
+
+fn encode_mode(mode: Option<bool>) -> u8 {
+    match mode {
+        None => 0,
+        Some(false) => 1,
+        Some(true) => 2,
+    }
+}
+
+fn decode_mode(byte: u8) -> Option<Option<bool>> {
+    match byte {
+        0 => Some(None),
+        1 => Some(Some(false)),
+        2 => Some(Some(true)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+
+    #[test]
+    fn mode_round_trips() {
+        for mode in [None, Some(false), Some(true)] {
+            assert_eq!(Some(mode), decode_mode(encode_mode(mode)));
+        }
+    }
+
+    #[test]
+    fn displays_diff_symbolically() {
+        let diff = StateDiff {
+            changes: vec![("ECHO", false), ("ICANON", true)],
+        };
+        assert_eq!("-ECHO +ICANON", diff.to_string());
+    }
+
+    #[test]
+    fn rejects_invalid_bytes() {
+        for bytes in [
+            &[][..],
+            &[0, 0, 0],
+            &[FORMAT_VERSION, 3, 0],
+            &[FORMAT_VERSION, 0, 0, 1],
+        ] {
+            let error = TerminalState::from_bytes(bytes).unwrap_err();
+            assert_eq!(io::ErrorKind::InvalidData, error.kind());
+        }
+    }
+}
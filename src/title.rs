@@ -0,0 +1,46 @@
+use crate::imp;
+use std::ops::{Deref, DerefMut};
+
+/// Restores the terminal's title when dropped, see [`TerminalLock::save_title`](`crate::TerminalLock::save_title`).
+///
+/// The guard holds on to the [`TerminalLock`](`crate::TerminalLock`) or [`RawModeGuard`](`crate::RawModeGuard`)
+/// it was created from and can be used in its place (e.g. for writing).
+#[derive(Debug)]
+pub struct TitleGuard<'a, T> {
+    inner: &'a mut T,
+    terminal: &'a imp::Terminal,
+    saved: Option<imp::SavedTitle>,
+}
+
+impl<'a, T> TitleGuard<'a, T> {
+    pub(crate) fn new(inner: &'a mut T, terminal: &'a imp::Terminal) -> std::io::Result<Self> {
+        let saved = terminal.save_title()?;
+        Ok(TitleGuard {
+            inner,
+            terminal,
+            saved: Some(saved),
+        })
+    }
+}
+
+impl<T> Deref for TitleGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.inner
+    }
+}
+
+impl<T> DerefMut for TitleGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.inner
+    }
+}
+
+impl<T> Drop for TitleGuard<'_, T> {
+    fn drop(&mut self) {
+        if let Some(saved) = self.saved.take() {
+            _ = self.terminal.restore_title(saved);
+        }
+    }
+}
@@ -1,31 +1,140 @@
-use crate::StdioLocks;
-use libc::{c_int, fcntl, termios, F_GETFL, O_RDWR};
-use std::ffi::{CStr, CString, OsStr};
+use crate::lock::{DeviceState, DeviceStates};
+use crate::raw_mode::{RawModeRefCounts, Restore};
+use crate::{
+    ExternalModeChanges, ModeChangeTiming, Provenance, Queue, RawModeOptions, StdioLocks,
+    TerminalKind,
+};
+use libc::{c_int, fcntl, termios, FD_CLOEXEC, F_GETFD, F_GETFL, F_SETFD, O_RDWR};
+use std::ffi::{CString, OsStr};
 use std::fmt;
 use std::fs::{File, OpenOptions};
 use std::io::{self, stderr, stdin, stdout, IsTerminal};
-use std::mem::{self, ManuallyDrop};
-use std::ops::{Deref, DerefMut};
-use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd as _};
+use std::mem;
+use std::ops::Deref;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd};
 use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::OpenOptionsExt as _;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+use std::time::{Duration, Instant};
 
 mod attr;
-#[cfg(test)]
+pub(crate) use attr::TerminalAttributes;
+mod serial;
+mod signals;
+pub(crate) use signals::{install_restore_on_signals, install_restore_on_suspend};
+#[cfg(any(test, feature = "pty", feature = "test-util"))]
 mod pty_utils;
 #[cfg(test)]
 mod tests;
 
-pub(crate) fn terminal() -> io::Result<Terminal> {
-    None.or_else(|| reuse_tty_from_stdio(stderr).transpose())
-        .or_else(|| reuse_tty_from_stdio(stdout).transpose())
-        .or_else(|| reuse_tty_from_stdio(stdin).transpose())
-        .map(|r| r.and_then(Terminal::from_stdio))
-        .unwrap_or_else(|| Ok(Terminal::from_controlling(open_controlling_tty()?)))
+pub(crate) fn terminal() -> Result<Terminal, crate::Error> {
+    let mut cached = cached_discovery();
+    if let Some(discovery) = &*cached {
+        return discovery.open();
+    }
+    let (terminal, source) = discover()?;
+    *cached = Some(Discovery {
+        source,
+        device: terminal.device,
+        same_as_stdin: terminal.same_as_stdin,
+        same_as_stdout: terminal.same_as_stdout,
+        same_as_stderr: terminal.same_as_stderr,
+    });
+    Ok(terminal)
+}
+
+pub(crate) fn invalidate_discovery() {
+    cached_discovery().take();
+}
+
+static DISCOVERY: Mutex<Option<Discovery>> = Mutex::new(None);
+
+fn cached_discovery() -> MutexGuard<'static, Option<Discovery>> {
+    DISCOVERY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Where [`terminal`] found the terminal, so that subsequent calls
+/// only need to duplicate or open a single file descriptor.
+#[derive(Debug, Clone)]
+struct Discovery {
+    source: Source,
+    device: DeviceId,
+    same_as_stdin: bool,
+    same_as_stdout: bool,
+    same_as_stderr: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Source {
+    Stdio(StdioStream),
+    Reopened(PathBuf),
+    ControllingTerminal,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum StdioStream {
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+impl StdioStream {
+    fn try_clone_file(self) -> io::Result<File> {
+        // The descriptor is duplicated (with `O_CLOEXEC`) so that we own it
+        // and can change its descriptor flags without affecting standard I/O.
+        match self {
+            StdioStream::Stdin => stdin().as_fd().try_clone_to_owned(),
+            StdioStream::Stdout => stdout().as_fd().try_clone_to_owned(),
+            StdioStream::Stderr => stderr().as_fd().try_clone_to_owned(),
+        }
+        .map(File::from)
+    }
+}
+
+impl Discovery {
+    fn open(&self) -> Result<Terminal, crate::Error> {
+        let (file, provenance) = match &self.source {
+            Source::Stdio(stream) => (
+                TerminalFile::Stdio(stream.try_clone_file()?),
+                Provenance::Stdio,
+            ),
+            Source::Reopened(path) => (TerminalFile::Owned(open_tty(path)?), Provenance::Reopened),
+            Source::ControllingTerminal => {
+                (open_controlling_tty()?, Provenance::ControllingTerminal)
+            }
+        };
+        Ok(Terminal {
+            file,
+            provenance,
+            device: self.device,
+            device_state: DEVICE_STATES.get(self.device),
+            same_as_stdin: self.same_as_stdin,
+            same_as_stdout: self.same_as_stdout,
+            same_as_stderr: self.same_as_stderr,
+            retry_interrupted: AtomicBool::new(true),
+            nonblocking_file: OnceLock::new(),
+        })
+    }
+}
+
+fn discover() -> Result<(Terminal, Source), crate::Error> {
+    let from_stdio = |(file, source)| Ok((Terminal::from_stdio(file)?, source));
+    None.or_else(|| reuse_tty_from_stdio(stderr, StdioStream::Stderr).transpose())
+        .or_else(|| reuse_tty_from_stdio(stdout, StdioStream::Stdout).transpose())
+        .or_else(|| reuse_tty_from_stdio(stdin, StdioStream::Stdin).transpose())
+        .map(|r| r.and_then(from_stdio).map_err(crate::Error::from))
+        .unwrap_or_else(|| {
+            let terminal = Terminal::from_controlling(open_controlling_tty()?)?;
+            Ok((terminal, Source::ControllingTerminal))
+        })
 }
 
 fn reuse_tty_from_stdio<S: IsTerminal + AsFd>(
     stream: impl FnOnce() -> S,
-) -> io::Result<Option<TerminalFile>> {
+    which: StdioStream,
+) -> io::Result<Option<(TerminalFile, Source)>> {
     let stream = stream();
 
     if stream.is_terminal() {
@@ -34,28 +143,29 @@ fn reuse_tty_from_stdio<S: IsTerminal + AsFd>(
         // But I don't quite understand what the benefit of that is. Is it to have as little fds open as possible?
         // Is it a lot faster than opening the tty ourselves?
         if is_read_write(stream.as_fd())? {
-            // SAFETY: We know that the file descriptor is valid.
-            // However we break the assumption that the file descriptor is owned.
-            // That's why the file is immediately wrapped in a ManuallyDrop to prevent
-            // the standard I/O descriptor from being closed.
-            let file = unsafe { File::from_raw_fd(stream.as_fd().as_raw_fd()) };
-            Ok(Some(TerminalFile::Borrowed(ManuallyDrop::new(file))))
+            let file = which.try_clone_file()?;
+            Ok(Some((TerminalFile::Stdio(file), Source::Stdio(which))))
         } else {
-            reopen_tty(stream.as_fd())
-                .map(TerminalFile::Owned)
-                .map(Some)
+            let path = tty_path(stream.as_fd())?;
+            let file = open_tty(&path)?;
+            Ok(Some((TerminalFile::Owned(file), Source::Reopened(path))))
         }
     } else {
         Ok(None)
     }
 }
 
-fn open_controlling_tty() -> io::Result<TerminalFile> {
+fn open_controlling_tty() -> Result<TerminalFile, crate::Error> {
     OpenOptions::new()
         .read(true)
         .write(true)
         .open("/dev/tty")
         .map(TerminalFile::Owned)
+        .map_err(|error| match error.raw_os_error() {
+            // The process has no controlling terminal.
+            Some(libc::ENXIO) => crate::Error::NotATty,
+            _ => crate::Error::Io(error),
+        })
 }
 
 fn is_read_write(fd: BorrowedFd) -> io::Result<bool> {
@@ -64,20 +174,33 @@ fn is_read_write(fd: BorrowedFd) -> io::Result<bool> {
     Ok(mode & O_RDWR == O_RDWR)
 }
 
+#[cfg(any(test, feature = "pty", feature = "test-util"))]
 fn reopen_tty(fd: BorrowedFd) -> io::Result<File> {
-    let name = ttyname_r(fd)?;
-    OpenOptions::new()
-        .read(true)
-        .write(true)
-        .open(OsStr::from_bytes(name.as_bytes()))
+    open_tty(&tty_path(fd)?)
+}
+
+fn open_tty(path: &Path) -> io::Result<File> {
+    OpenOptions::new().read(true).write(true).open(path)
 }
 
-fn is_same_file(a: BorrowedFd, b: BorrowedFd) -> io::Result<bool> {
-    Ok(a.as_raw_fd() == b.as_raw_fd() || {
-        let stat_a = fstat(a)?;
-        let stat_b = fstat(b)?;
-        stat_a.st_dev == stat_b.st_dev && stat_a.st_ino == stat_b.st_ino
-    })
+/// Resolves the path of the terminal device using `ttyname_r`.
+/// On Linux, we fall back to `/proc/self/fd` which works even when `ttyname_r` fails
+/// (e.g. when `/dev` in a container doesn't match the devpts instance of the terminal).
+fn tty_path(fd: BorrowedFd) -> io::Result<PathBuf> {
+    let name = ttyname_r(fd);
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let name = name.or_else(|e| proc_fd_path(fd).ok_or(e));
+    name.map(|name| PathBuf::from(OsStr::from_bytes(name.as_bytes())))
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn proc_fd_path(fd: BorrowedFd) -> Option<CString> {
+    let path = std::fs::read_link(format!("/proc/self/fd/{}", fd.as_raw_fd())).ok()?;
+    // Unreachable files (e.g. from another mount namespace) are reported as `(unreachable)/...`.
+    if !path.is_absolute() {
+        return None;
+    }
+    CString::new(path.as_os_str().as_bytes()).ok()
 }
 
 fn fstat(fd: BorrowedFd) -> io::Result<libc::stat> {
@@ -91,80 +214,343 @@ fn fstat(fd: BorrowedFd) -> io::Result<libc::stat> {
 #[derive(Debug)]
 pub(crate) struct Terminal {
     file: TerminalFile,
+    provenance: Provenance,
+    device: DeviceId,
+    device_state: Arc<DeviceState>,
     same_as_stdin: bool,
     same_as_stdout: bool,
     same_as_stderr: bool,
+    retry_interrupted: AtomicBool,
+    // Opened on first use by `write_timeout`, `None` if the device couldn't be opened.
+    nonblocking_file: OnceLock<Option<File>>,
 }
 
+// Handles to the same device share their locks and input buffer.
+static DEVICE_STATES: DeviceStates<DeviceId> = DeviceStates::new();
+
 impl Terminal {
-    pub(crate) fn lock_stdio(&self) -> StdioLocks {
+    pub(crate) fn provenance(&self) -> Provenance {
+        self.provenance
+    }
+
+    pub(crate) fn is_foreground(&self) -> io::Result<bool> {
+        // SAFETY: File descriptor is valid.
+        match to_io_result(unsafe { libc::tcgetpgrp(self.file.as_raw_fd()) }) {
+            // SAFETY: This function always succeeds.
+            Ok(foreground) => Ok(foreground == unsafe { libc::getpgrp() }),
+            // Job control only applies to the controlling terminal.
+            Err(e) if e.raw_os_error() == Some(libc::ENOTTY) => Ok(true),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub(crate) fn path(&self) -> io::Result<PathBuf> {
+        tty_path(self.file.as_fd())
+    }
+
+    pub(crate) fn set_inheritable(&self, inheritable: bool) -> io::Result<()> {
+        let fd = self.file.as_raw_fd();
+        // SAFETY: File descriptor is valid.
+        let flags = retry_interrupted(|| to_io_result(unsafe { fcntl(fd, F_GETFD) }))?;
+        let flags = if inheritable {
+            flags & !FD_CLOEXEC
+        } else {
+            flags | FD_CLOEXEC
+        };
+        // SAFETY: File descriptor is valid.
+        retry_interrupted(|| to_io_result(unsafe { fcntl(fd, F_SETFD, flags) })).and(Ok(()))
+    }
+
+    pub(crate) fn lock_across_processes(&self) -> io::Result<File> {
+        // `flock` locks belong to the open file description, which is shared
+        // with other processes when it was inherited (e.g. standard I/O).
+        // Opening the device again gives us a description of our own.
+        let file = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NOCTTY)
+            .open(self.path()?)?;
+        loop {
+            // SAFETY: The file descriptor is valid.
+            match to_io_result(unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) }) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => return result.map(|_| file),
+            }
+        }
+    }
+
+    pub(crate) fn device_state(&self) -> &DeviceState {
+        &self.device_state
+    }
+
+    pub(crate) fn is_stdin(&self) -> bool {
+        self.same_as_stdin
+    }
+
+    pub(crate) fn is_stdout(&self) -> bool {
+        self.same_as_stdout
+    }
+
+    pub(crate) fn is_stderr(&self) -> bool {
+        self.same_as_stderr
+    }
+
+    pub(crate) fn lock_stdin(&self) -> StdioLocks {
         StdioLocks {
             stdin_lock: self.same_as_stdin.then(|| stdin().lock()),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn lock_stdout(&self) -> StdioLocks {
+        StdioLocks {
             stdout_lock: self.same_as_stdout.then(|| stdout().lock()),
             stderr_lock: self.same_as_stderr.then(|| stderr().lock()),
+            ..Default::default()
         }
     }
 
-    pub(crate) fn enable_raw_mode(&mut self) -> io::Result<RawModeGuard<'_>> {
+    pub(crate) fn kind(&self) -> TerminalKind {
+        TerminalKind::Tty
+    }
+
+    pub(crate) fn enable_raw_mode(&self, options: &RawModeOptions) -> io::Result<RawModeGuard<'_>> {
         let fd = self.file.as_fd();
+        let device = self.device;
         let old_termios = attr::get_terminal_attr(fd)?;
+        let modified = !attr::is_raw_mode_enabled(&old_termios, options);
+
+        let mut applied_termios = old_termios;
+        if modified {
+            attr::enable_raw_mode(&mut applied_termios, options);
+            attr::set_terminal_attr(fd, &applied_termios, options.timing)?;
+        }
 
-        if !attr::is_raw_mode_enabled(&old_termios) {
-            let mut termios = old_termios;
-            attr::enable_raw_mode(&mut termios);
-            attr::set_terminal_attr(fd, &termios)?;
-            Ok(RawModeGuard {
-                inner: self,
-                old_termios: Some(old_termios),
-            })
+        Ok(RawModeGuard {
+            inner: self,
+            old_termios,
+            applied_termios,
+            modified,
+            timing: options.timing,
+            external_changes: options.external_changes,
+            device,
+            depth: raw_mode_ref_counts().acquire(device, old_termios),
+        })
+    }
+}
+
+impl Terminal {
+    pub(crate) fn wait_for_input(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        poll(
+            self.file.as_fd(),
+            libc::POLLIN,
+            timeout.map(|t| Instant::now() + t),
+        )
+    }
+
+    pub(crate) fn write_timeout(&self, buf: &[u8], timeout: Duration) -> io::Result<usize> {
+        let deadline = Instant::now() + timeout;
+        // `POLLOUT` only promises room for some bytes, so a blocking write could still
+        // hang past the deadline. `O_NONBLOCK` is a property of the open file description
+        // which `dup` shares with everyone else using it (e.g. our parent's shell),
+        // so we open the device again to get a non-blocking description of our own.
+        // If that's not possible (e.g. the device has no path) we fall back to blocking writes.
+        let mut file: &File = self
+            .nonblocking_file
+            .get_or_init(|| self.open_nonblocking().ok())
+            .as_ref()
+            .unwrap_or(&self.file);
+        let mut written = 0;
+        while written < buf.len() && poll(file.as_fd(), libc::POLLOUT, Some(deadline))? {
+            match io::Write::write(&mut file, &buf[written..]) {
+                Ok(len) => written += len,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                // Someone else filled the queue between the poll and our write.
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                Err(e) if written == 0 => return Err(e),
+                Err(_) => break,
+            }
+        }
+        if written == 0 && !buf.is_empty() {
+            Err(io::ErrorKind::TimedOut.into())
         } else {
-            Ok(RawModeGuard {
-                inner: self,
-                old_termios: None,
-            })
+            Ok(written)
         }
     }
+
+    fn open_nonblocking(&self) -> io::Result<File> {
+        OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NOCTTY | libc::O_NONBLOCK)
+            .open(self.path()?)
+    }
+
+    pub(crate) fn discard(&self, queue: Queue) -> io::Result<()> {
+        attr::flush(self.file.as_fd(), queue)
+    }
+
+    pub(crate) fn drain(&self) -> io::Result<()> {
+        attr::drain(self.file.as_fd())
+    }
+
+    pub(crate) fn resume_output(&self) -> io::Result<()> {
+        attr::resume_output(self.file.as_fd())
+    }
+
+    pub(crate) fn set_size(&self, rows: u16, columns: u16) -> io::Result<()> {
+        let size = libc::winsize {
+            ws_row: rows,
+            ws_col: columns,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        retry_interrupted(|| {
+            // SAFETY: File descriptor is valid and we pass a pointer to a valid winsize.
+            to_io_result(unsafe { libc::ioctl(self.file.as_raw_fd(), libc::TIOCSWINSZ, &size) })
+        })
+        .and(Ok(()))
+    }
+
+    pub(crate) fn attributes(&self) -> io::Result<TerminalAttributes> {
+        attr::get_terminal_attr(self.file.as_fd()).map(TerminalAttributes)
+    }
+
+    pub(crate) fn set_attributes(&self, attributes: &TerminalAttributes) -> io::Result<()> {
+        self.set_attributes_with(attributes, ModeChangeTiming::Drain)
+    }
+
+    pub(crate) fn set_attributes_with(
+        &self,
+        attributes: &TerminalAttributes,
+        timing: ModeChangeTiming,
+    ) -> io::Result<()> {
+        attr::set_terminal_attr(self.file.as_fd(), &attributes.0, timing)
+    }
+
+    pub(crate) fn set_title(&self, title: &str) -> io::Result<()> {
+        let title: String = title.chars().filter(|c| !c.is_control()).collect();
+        io::Write::write_all(&mut &*self.file, format!("\x1b]2;{title}\x1b\\").as_bytes())
+    }
+
+    /// Pushes the title onto the terminal's title stack (`XTWINOPS`).
+    pub(crate) fn save_title(&self) -> io::Result<SavedTitle> {
+        io::Write::write_all(&mut &*self.file, b"\x1b[22;0t")?;
+        Ok(SavedTitle)
+    }
+
+    /// Pops the title from the terminal's title stack (`XTWINOPS`).
+    pub(crate) fn restore_title(&self, _saved: SavedTitle) -> io::Result<()> {
+        io::Write::write_all(&mut &*self.file, b"\x1b[23;0t")
+    }
+
+    pub(crate) fn with_termios<R>(&self, f: impl FnOnce(&mut termios) -> R) -> io::Result<R> {
+        let fd = self.file.as_fd();
+        let mut termios = attr::get_terminal_attr(fd)?;
+        let result = f(&mut termios);
+        attr::set_terminal_attr(fd, &termios, ModeChangeTiming::Drain)?;
+        Ok(result)
+    }
 }
 
 impl Terminal {
     fn from_stdio(file: TerminalFile) -> io::Result<Self> {
+        let provenance = match file {
+            TerminalFile::Stdio(_) => Provenance::Stdio,
+            TerminalFile::Owned(_) => Provenance::Reopened,
+        };
+        // The terminal is only stat'ed once and then compared against each stream.
+        let device = DeviceId::of(file.as_fd())?;
         Ok(Terminal {
-            same_as_stdin: is_same_file(file.as_fd(), stdin().as_fd())?,
-            same_as_stdout: is_same_file(file.as_fd(), stdout().as_fd())?,
-            same_as_stderr: is_same_file(file.as_fd(), stderr().as_fd())?,
+            provenance,
+            device,
+            device_state: DEVICE_STATES.get(device),
+            same_as_stdin: device.is_same_as(stdin().as_fd()),
+            same_as_stdout: device.is_same_as(stdout().as_fd()),
+            same_as_stderr: device.is_same_as(stderr().as_fd()),
+            retry_interrupted: AtomicBool::new(true),
+            nonblocking_file: OnceLock::new(),
             file,
         })
     }
 
-    fn from_controlling(file: TerminalFile) -> Self {
-        Terminal {
+    /// Creates a new pseudo-terminal, returning the terminal (user side) and the controlling side.
+    #[cfg(any(feature = "pty", feature = "test-util"))]
+    pub(crate) fn open_pty() -> io::Result<(Self, std::os::fd::OwnedFd)> {
+        let pty = pty_utils::pty_pair()?;
+        let file = reopen_tty(pty.user.as_fd())?;
+        let terminal = Terminal {
+            provenance: Provenance::Pty,
+            ..Terminal::from_controlling(TerminalFile::Owned(file))?
+        };
+        Ok((terminal, pty._controlling))
+    }
+
+    fn from_controlling(file: TerminalFile) -> io::Result<Self> {
+        let device = DeviceId::of(file.as_fd())?;
+        Ok(Terminal {
+            device,
+            device_state: DEVICE_STATES.get(device),
             file,
+            provenance: Provenance::ControllingTerminal,
             same_as_stdin: false,
             same_as_stdout: false,
             same_as_stderr: false,
-        }
+            retry_interrupted: AtomicBool::new(true),
+            nonblocking_file: OnceLock::new(),
+        })
     }
 }
 
+/// The title is saved by the terminal itself.
+#[derive(Debug)]
+pub(crate) struct SavedTitle;
+
 #[derive(Debug)]
 enum TerminalFile {
     Owned(File),
-    Borrowed(ManuallyDrop<File>),
+    /// A duplicate of a standard I/O descriptor.
+    Stdio(File),
 }
 
-impl io::Write for Terminal {
+impl Terminal {
+    pub(crate) fn set_retry_interrupted(&self, retry: bool) {
+        self.retry_interrupted.store(retry, Ordering::Relaxed);
+    }
+
+    /// Reads without retrying when interrupted by a signal.
+    pub(crate) fn interruptible(&self) -> impl io::Read + '_ {
+        &*self.file
+    }
+
+    fn retry<T>(&self, mut f: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        if self.retry_interrupted.load(Ordering::Relaxed) {
+            retry_interrupted(f)
+        } else {
+            f()
+        }
+    }
+}
+
+impl io::Write for &Terminal {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.file.write(buf)
+        self.retry(|| (&*self.file).write(buf))
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.retry(|| (&*self.file).write_vectored(bufs))
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.file.flush()
+        (&*self.file).flush()
     }
 }
 
-impl io::Read for Terminal {
+impl io::Read for &Terminal {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.file.read(buf)
+        self.retry(|| (&*self.file).read(buf))
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        self.retry(|| (&*self.file).read_vectored(bufs))
     }
 }
 
@@ -174,16 +560,7 @@ impl Deref for TerminalFile {
     fn deref(&self) -> &Self::Target {
         match self {
             TerminalFile::Owned(f) => f,
-            TerminalFile::Borrowed(f) => f,
-        }
-    }
-}
-
-impl DerefMut for TerminalFile {
-    fn deref_mut(&mut self) -> &mut Self::Target {
-        match self {
-            TerminalFile::Owned(f) => f,
-            TerminalFile::Borrowed(f) => f,
+            TerminalFile::Stdio(f) => f,
         }
     }
 }
@@ -206,6 +583,12 @@ impl AsFd for super::RawModeGuard<'_> {
     }
 }
 
+impl AsFd for crate::Readiness<'_> {
+    fn as_fd(&self) -> std::os::unix::prelude::BorrowedFd<'_> {
+        self.0.file.as_fd()
+    }
+}
+
 impl AsRawFd for super::Terminal {
     fn as_raw_fd(&self) -> std::os::unix::prelude::RawFd {
         self.0.file.as_raw_fd()
@@ -224,9 +607,154 @@ impl AsRawFd for super::RawModeGuard<'_> {
     }
 }
 
+impl AsRawFd for crate::Readiness<'_> {
+    fn as_raw_fd(&self) -> std::os::unix::prelude::RawFd {
+        self.0.file.as_raw_fd()
+    }
+}
+
+// The standard library only implements `AsRawFd` for boxes of sized types.
+impl AsRawFd for Box<dyn super::Transceive + '_> {
+    fn as_raw_fd(&self) -> std::os::unix::prelude::RawFd {
+        (**self).as_raw_fd()
+    }
+}
+
+// The standard library doesn't implement `AsRawFd` for mutable references.
+macro_rules! impl_as_raw_fd_for_mut {
+    ($($ty:ty),*) => {
+        $(
+            impl AsRawFd for &mut $ty {
+                fn as_raw_fd(&self) -> std::os::unix::prelude::RawFd {
+                    (**self).as_raw_fd()
+                }
+            }
+        )*
+    };
+}
+
+impl_as_raw_fd_for_mut!(
+    super::Terminal,
+    super::TerminalLock<'_>,
+    super::RawModeGuard<'_>,
+    Box<dyn super::Transceive + '_>
+);
+#[cfg(feature = "test-util")]
+impl_as_raw_fd_for_mut!(crate::FakeTerminal);
+
 pub(crate) struct RawModeGuard<'a> {
-    inner: &'a mut Terminal,
-    old_termios: Option<termios>,
+    inner: &'a Terminal,
+    old_termios: termios,
+    // The attributes as set by this guard, used to detect changes made by someone else.
+    applied_termios: termios,
+    modified: bool,
+    timing: ModeChangeTiming,
+    external_changes: ExternalModeChanges,
+    device: DeviceId,
+    depth: usize,
+}
+
+static RAW_MODE_REF_COUNTS: Mutex<RawModeRefCounts<DeviceId, termios>> =
+    Mutex::new(RawModeRefCounts::new());
+
+fn raw_mode_ref_counts() -> MutexGuard<'static, RawModeRefCounts<DeviceId, termios>> {
+    RAW_MODE_REF_COUNTS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Identifies a terminal device independent of the file descriptor used to access it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct DeviceId {
+    dev: libc::dev_t,
+    ino: libc::ino_t,
+}
+
+impl DeviceId {
+    fn of(fd: BorrowedFd) -> io::Result<Self> {
+        let stat = fstat(fd)?;
+        Ok(DeviceId {
+            dev: stat.st_dev,
+            ino: stat.st_ino,
+        })
+    }
+
+    /// Whether the file descriptor refers to this device.
+    /// Closed or otherwise invalid descriptors never do.
+    fn is_same_as(self, fd: BorrowedFd) -> bool {
+        DeviceId::of(fd).is_ok_and(|other| other == self)
+    }
+}
+
+impl<'a> RawModeGuard<'a> {
+    pub(crate) fn terminal(&self) -> &'a Terminal {
+        self.inner
+    }
+
+    pub(crate) fn set_read_behavior(
+        &mut self,
+        min: u8,
+        timeout: Option<Duration>,
+    ) -> io::Result<()> {
+        let fd = self.inner.file.as_fd();
+        let current = attr::get_terminal_attr(fd)?;
+        let mut termios = current;
+        attr::set_read_behavior(&mut termios, min, timeout);
+        if attr::same_attributes(&termios, &current) {
+            return Ok(());
+        }
+        attr::set_terminal_attr(fd, &termios, self.timing)?;
+        self.applied_termios = termios;
+        self.modified = true;
+        Ok(())
+    }
+
+    pub(crate) fn has_external_changes(&self) -> io::Result<bool> {
+        let current = attr::get_terminal_attr(self.inner.file.as_fd())?;
+        Ok(!attr::same_attributes(&current, &self.applied_termios))
+    }
+
+    /// Decides what to restore given the `previous` attributes, see [`ExternalModeChanges`].
+    fn resolve_external_changes(&self, previous: termios) -> Option<termios> {
+        if self.external_changes == ExternalModeChanges::Overwrite {
+            return Some(previous);
+        }
+        let Ok(current) = attr::get_terminal_attr(self.inner.file.as_fd()) else {
+            return Some(previous);
+        };
+        match self.external_changes {
+            ExternalModeChanges::Keep
+                if !attr::same_attributes(&current, &self.applied_termios) =>
+            {
+                None
+            }
+            ExternalModeChanges::Merge => {
+                Some(attr::merge(&current, &self.applied_termios, &previous))
+            }
+            _ => Some(previous),
+        }
+    }
+
+    /// The mode before the first guard was created.
+    pub(crate) fn original_attributes(&self) -> TerminalAttributes {
+        let original = raw_mode_ref_counts().original(&self.device).copied();
+        TerminalAttributes(original.unwrap_or(self.old_termios))
+    }
+
+    pub(crate) fn persist(self) -> TerminalAttributes {
+        _ = raw_mode_ref_counts().release(&self.device, self.depth, false);
+        let old_attributes = TerminalAttributes(self.old_termios);
+        mem::forget(self);
+        old_attributes
+    }
+}
+
+impl Deref for RawModeGuard<'_> {
+    type Target = Terminal;
+
+    fn deref(&self) -> &Self::Target {
+        self.inner
+    }
 }
 
 impl fmt::Debug for RawModeGuard<'_> {
@@ -242,6 +770,10 @@ impl io::Write for RawModeGuard<'_> {
         self.inner.write(buf)
     }
 
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.inner.write_vectored(bufs)
+    }
+
     fn flush(&mut self) -> io::Result<()> {
         self.inner.flush()
     }
@@ -251,12 +783,63 @@ impl io::Read for RawModeGuard<'_> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         self.inner.read(buf)
     }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        self.inner.read_vectored(bufs)
+    }
 }
 
 impl Drop for RawModeGuard<'_> {
     fn drop(&mut self) {
-        if let Some(old_termios) = self.old_termios {
-            _ = attr::set_terminal_attr(self.inner.file.as_fd(), &old_termios);
+        let restore = raw_mode_ref_counts().release(&self.device, self.depth, self.modified);
+        let termios = match restore {
+            Restore::Original(termios) => Some(termios),
+            Restore::Previous => self.modified.then_some(self.old_termios),
+            Restore::Nothing => None,
+        };
+        if let Some(termios) = termios.and_then(|t| self.resolve_external_changes(t)) {
+            // Nested guards that are dropped together often leave nothing to restore.
+            // A flush still has to discard the pending input though.
+            let fd = self.inner.file.as_fd();
+            let unchanged = self.timing != ModeChangeTiming::Flush
+                && attr::get_terminal_attr(fd)
+                    .is_ok_and(|current| attr::same_attributes(&current, &termios));
+            if !unchanged {
+                _ = attr::set_terminal_attr(fd, &termios, self.timing);
+            }
+        }
+    }
+}
+
+/// Waits until the file descriptor is ready for the given events or the deadline passes.
+/// Hangups and errors count as ready as the next read or write won't block.
+fn poll(fd: BorrowedFd, events: libc::c_short, deadline: Option<Instant>) -> io::Result<bool> {
+    loop {
+        let mut pollfd = libc::pollfd {
+            fd: fd.as_raw_fd(),
+            events,
+            revents: 0,
+        };
+        let timeout = deadline.map_or(-1, |d| {
+            // Rounded up so that we don't return before the deadline.
+            let millis = (d.saturating_duration_since(Instant::now()).as_micros() + 999) / 1000;
+            c_int::try_from(millis).unwrap_or(c_int::MAX)
+        });
+        // SAFETY: We pass exactly one valid pollfd.
+        match unsafe { libc::poll(&mut pollfd, 1, timeout) } {
+            -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => continue,
+            -1 => return Err(io::Error::last_os_error()),
+            ready => return Ok(ready > 0),
+        }
+    }
+}
+
+/// Calls `f` until it no longer fails with [`io::ErrorKind::Interrupted`].
+fn retry_interrupted<T>(mut f: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    loop {
+        match f() {
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            result => return result,
         }
     }
 }
@@ -272,6 +855,8 @@ fn to_io_result(value: c_int) -> io::Result<c_int> {
 /// `ttyname_r` returns the path to the terminal device.
 #[cfg(not(target_os = "macos"))]
 fn ttyname_r(fd: BorrowedFd) -> io::Result<CString> {
+    use std::ffi::CStr;
+
     let mut buf = Vec::with_capacity(64);
 
     loop {
@@ -280,7 +865,9 @@ fn ttyname_r(fd: BorrowedFd) -> io::Result<CString> {
         match code {
             // SAFETY: We own the pointer and we know that if ttyname_r is successful, it returns a null-terminated string.
             0 => return Ok(unsafe { CStr::from_ptr(buf.as_ptr()) }.to_owned()),
-            libc::ERANGE => buf.reserve(64),
+            // `reserve` is relative to the length (which is always zero), so we need to ask for more than the capacity.
+            libc::ERANGE if buf.capacity() < MAX_PATH_LEN => buf.reserve(buf.capacity() * 2),
+            libc::ERANGE => return Err(path_too_long()),
             code => return Err(io::Error::from_raw_os_error(code)),
         }
     }
@@ -291,16 +878,30 @@ fn ttyname_r(fd: BorrowedFd) -> io::Result<CString> {
 fn ttyname_r(fd: BorrowedFd) -> io::Result<CString> {
     use libc::{F_GETPATH, PATH_MAX};
 
-    // the buffer size must be >= MAXPATHLEN, see `man fcntl`
-    let buf: [i8; PATH_MAX as usize] = [0; PATH_MAX as usize];
+    // The buffer size must be >= MAXPATHLEN, see `man fcntl`.
+    let mut buf = [0_u8; PATH_MAX as usize];
 
-    unsafe {
-        match fcntl(fd.as_raw_fd(), F_GETPATH as c_int, &buf) {
-            0 => {
-                let res = CStr::from_ptr(buf.as_ptr()).to_owned();
-                Ok(res)
-            }
-            _ => Err(io::Error::last_os_error()),
-        }
-    }
+    // SAFETY: Our buffer is big enough according to the docs.
+    to_io_result(unsafe { fcntl(fd.as_raw_fd(), F_GETPATH, buf.as_mut_ptr()) })?;
+    path_from_buf(&buf)
+}
+
+/// Copies the path that the kernel wrote into `buf`.
+/// Don't trust the kernel to null-terminate the path, a truncated path is useless anyway.
+#[cfg(target_os = "macos")]
+fn path_from_buf(buf: &[u8]) -> io::Result<CString> {
+    std::ffi::CStr::from_bytes_until_nul(buf)
+        .map(std::ffi::CStr::to_owned)
+        .map_err(|_| path_too_long())
+}
+
+/// Generous upper bound for the length of a terminal device's path (`PATH_MAX` on Linux).
+#[cfg(not(target_os = "macos"))]
+const MAX_PATH_LEN: usize = 4096;
+
+fn path_too_long() -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        "the path of the terminal device is too long",
+    )
 }
@@ -1,37 +1,429 @@
-use super::to_io_result;
+use super::{retry_interrupted, to_io_result};
+use crate::{ControlChar, ModeChangeTiming, Queue, RawModeOptions};
 use libc::{tcflag_t, termios};
 use std::os::fd::{AsRawFd, BorrowedFd};
+use std::time::Duration;
 use std::{io, mem};
 
 pub(super) fn get_terminal_attr(fd: BorrowedFd) -> io::Result<termios> {
     // SAFETY: The termios structure is filled by tcgetattr if it returns successfully.
     unsafe {
         let mut termios = mem::zeroed();
-        to_io_result(libc::tcgetattr(fd.as_raw_fd(), &mut termios))?;
+        retry_interrupted(|| to_io_result(libc::tcgetattr(fd.as_raw_fd(), &mut termios)))?;
         Ok(termios)
     }
 }
 
-pub(super) fn set_terminal_attr(fd: BorrowedFd, termios: &termios) -> io::Result<()> {
+pub(super) fn set_terminal_attr(
+    fd: BorrowedFd,
+    termios: &termios,
+    timing: ModeChangeTiming,
+) -> io::Result<()> {
     // From the man page:
+    // TCSANOW
+    //     the change occurs immediately.
     // TCSADRAIN
     //     the change occurs after all output written to fd has been transmitted.
     //     This function should be used when changing parameters that affect output.
-    // SAFETY: File descriptor is valid.
-    to_io_result(unsafe { libc::tcsetattr(fd.as_raw_fd(), libc::TCSADRAIN, termios) }).and(Ok(()))
+    // TCSAFLUSH
+    //     the change occurs after all output written to the object referred by fd has been transmitted,
+    //     and all input that has been received but not read will be discarded before the change is made.
+    let optional_actions = match timing {
+        ModeChangeTiming::Now => libc::TCSANOW,
+        ModeChangeTiming::Drain => libc::TCSADRAIN,
+        ModeChangeTiming::Flush => libc::TCSAFLUSH,
+    };
+    retry_interrupted(|| {
+        // SAFETY: File descriptor is valid.
+        to_io_result(unsafe { libc::tcsetattr(fd.as_raw_fd(), optional_actions, termios) })
+    })
+    .and(Ok(()))
 }
 
-// We disable two flags:
+pub(super) fn flush(fd: BorrowedFd, queue: Queue) -> io::Result<()> {
+    let queue_selector = match queue {
+        Queue::Input => libc::TCIFLUSH,
+        Queue::Output => libc::TCOFLUSH,
+        Queue::Both => libc::TCIOFLUSH,
+    };
+    retry_interrupted(|| {
+        // SAFETY: File descriptor is valid.
+        to_io_result(unsafe { libc::tcflush(fd.as_raw_fd(), queue_selector) })
+    })
+    .and(Ok(()))
+}
+
+pub(super) fn resume_output(fd: BorrowedFd) -> io::Result<()> {
+    retry_interrupted(|| {
+        // SAFETY: File descriptor is valid.
+        to_io_result(unsafe { libc::tcflow(fd.as_raw_fd(), libc::TCOON) })
+    })
+    .and(Ok(()))
+}
+
+pub(super) fn drain(fd: BorrowedFd) -> io::Result<()> {
+    retry_interrupted(|| {
+        // SAFETY: File descriptor is valid.
+        to_io_result(unsafe { libc::tcdrain(fd.as_raw_fd()) })
+    })
+    .and(Ok(()))
+}
+
+// By default we disable two flags:
 // ECHO
 //     to disable input characters from being echoed.
 // ICANON
 //     to disable canonical mode (we want input to be available immediately and not wait for a line terminator).
-const FLAGS_DISABLED_IN_RAW_MODE: tcflag_t = libc::ICANON | libc::ECHO;
+//
+// The remaining flags are only disabled when requested:
+// ISIG
+//     to disable generating signals for special characters such as Ctrl+C.
+// IXON, IXOFF
+//     to disable software flow control (Ctrl+S / Ctrl+Q).
+// OPOST
+//     to disable output post-processing (such as translating \n to \r\n).
+//
+// When only echo is disabled (e.g. for password prompts) we enable one flag:
+// ECHONL
+//     to echo the newline character even though ECHO is disabled.
+struct Flags {
+    iflag: tcflag_t,
+    oflag: tcflag_t,
+    lflag: tcflag_t,
+}
 
-pub(super) fn enable_raw_mode(termios: &mut termios) {
-    termios.c_lflag &= !FLAGS_DISABLED_IN_RAW_MODE;
+fn flags_disabled_in_raw_mode(options: &RawModeOptions) -> Flags {
+    let disabled_if = |enabled: bool, flags: tcflag_t| if enabled { 0 } else { flags };
+    Flags {
+        iflag: disabled_if(options.flow_control, libc::IXON | libc::IXOFF),
+        oflag: disabled_if(options.output_processing, libc::OPOST),
+        lflag: disabled_if(options.echo, libc::ECHO)
+            | disabled_if(options.canonical, libc::ICANON)
+            | disabled_if(options.signals, libc::ISIG),
+    }
 }
 
-pub(super) fn is_raw_mode_enabled(termios: &termios) -> bool {
-    termios.c_lflag & FLAGS_DISABLED_IN_RAW_MODE == 0
+pub(super) fn enable_raw_mode(termios: &mut termios, options: &RawModeOptions) {
+    let flags = flags_disabled_in_raw_mode(options);
+    termios.c_iflag &= !flags.iflag;
+    termios.c_oflag &= !flags.oflag;
+    termios.c_lflag &= !flags.lflag;
+    if options.echo_newline {
+        termios.c_lflag |= libc::ECHONL;
+    }
+    if let Some((min, timeout)) = options.read_behavior {
+        set_read_behavior(termios, min, timeout);
+    }
+}
+
+pub(super) fn is_raw_mode_enabled(termios: &termios, options: &RawModeOptions) -> bool {
+    let flags = flags_disabled_in_raw_mode(options);
+    termios.c_iflag & flags.iflag == 0
+        && termios.c_oflag & flags.oflag == 0
+        && termios.c_lflag & flags.lflag == 0
+        && (!options.echo_newline || termios.c_lflag & libc::ECHONL != 0)
+        && has_read_behavior(termios, options.read_behavior)
+}
+
+fn has_read_behavior(termios: &termios, read_behavior: Option<(u8, Option<Duration>)>) -> bool {
+    let Some((min, timeout)) = read_behavior else {
+        return true;
+    };
+    let mut expected = *termios;
+    set_read_behavior(&mut expected, min, timeout);
+    expected.c_cc[libc::VMIN] == termios.c_cc[libc::VMIN]
+        && expected.c_cc[libc::VTIME] == termios.c_cc[libc::VTIME]
+}
+
+// VMIN
+//     the minimum number of bytes a read waits for.
+// VTIME
+//     the read timeout in tenths of a second, zero means no timeout.
+pub(super) fn set_read_behavior(termios: &mut termios, min: u8, timeout: Option<Duration>) {
+    const MAX_DECISECONDS: u128 = u8::MAX as u128;
+    // Round up so that short timeouts don't turn into "no timeout".
+    let deciseconds = timeout.map_or(0, |t| {
+        ((t.as_millis() + 99) / 100).clamp(1, MAX_DECISECONDS)
+    });
+    termios.c_cc[libc::VMIN] = min;
+    termios.c_cc[libc::VTIME] = deciseconds as u8;
+}
+
+#[derive(Clone, Copy)]
+pub(crate) struct TerminalAttributes(pub(super) termios);
+
+impl TerminalAttributes {
+    pub(crate) fn echo(&self) -> bool {
+        self.0.c_lflag & libc::ECHO != 0
+    }
+
+    pub(crate) fn set_echo(&mut self, enabled: bool) {
+        set_flags(&mut self.0.c_lflag, libc::ECHO, enabled)
+    }
+
+    pub(crate) fn canonical(&self) -> bool {
+        self.0.c_lflag & libc::ICANON != 0
+    }
+
+    pub(crate) fn set_canonical(&mut self, enabled: bool) {
+        set_flags(&mut self.0.c_lflag, libc::ICANON, enabled)
+    }
+
+    pub(crate) fn signals(&self) -> bool {
+        self.0.c_lflag & libc::ISIG != 0
+    }
+
+    pub(crate) fn set_signals(&mut self, enabled: bool) {
+        set_flags(&mut self.0.c_lflag, libc::ISIG, enabled)
+    }
+
+    pub(crate) fn flow_control(&self) -> bool {
+        self.0.c_iflag & (libc::IXON | libc::IXOFF) != 0
+    }
+
+    pub(crate) fn set_flow_control(&mut self, enabled: bool) {
+        set_flags(&mut self.0.c_iflag, libc::IXON | libc::IXOFF, enabled)
+    }
+
+    pub(crate) fn virtual_terminal_processing(&self) -> bool {
+        true
+    }
+
+    pub(crate) fn set_virtual_terminal_processing(&mut self, _enabled: bool) {}
+
+    #[cfg(feature = "test-util")]
+    pub(crate) fn enable_raw_mode(&mut self, options: &RawModeOptions) {
+        enable_raw_mode(&mut self.0, options)
+    }
+}
+
+// The flags and speeds are stored as `u64` as their size differs between platforms,
+// followed by the control characters.
+const SERIALIZED_FIELDS_LEN: usize = 6 * mem::size_of::<u64>();
+
+impl TerminalAttributes {
+    pub(crate) fn to_bytes(self) -> Vec<u8> {
+        let termios = &self.0;
+        // SAFETY: We pass a valid termios structure.
+        let (ispeed, ospeed) = unsafe { (libc::cfgetispeed(termios), libc::cfgetospeed(termios)) };
+        let fields = [
+            u64::from(termios.c_iflag),
+            u64::from(termios.c_oflag),
+            u64::from(termios.c_cflag),
+            u64::from(termios.c_lflag),
+            u64::from(ispeed),
+            u64::from(ospeed),
+        ];
+        let mut bytes: Vec<_> = fields.iter().flat_map(|f| f.to_le_bytes()).collect();
+        bytes.extend_from_slice(&termios.c_cc);
+        bytes
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() != SERIALIZED_FIELDS_LEN + libc::NCCS {
+            return None;
+        }
+        let (fields, control_chars) = bytes.split_at(SERIALIZED_FIELDS_LEN);
+        let mut fields = fields
+            .chunks_exact(mem::size_of::<u64>())
+            .map(|chunk| chunk.try_into().ok().map(u64::from_le_bytes));
+        let mut next = || fields.next().flatten();
+        // SAFETY: An all-zero termios structure is valid.
+        let mut termios: termios = unsafe { mem::zeroed() };
+        termios.c_iflag = tcflag_t::try_from(next()?).ok()?;
+        termios.c_oflag = tcflag_t::try_from(next()?).ok()?;
+        termios.c_cflag = tcflag_t::try_from(next()?).ok()?;
+        termios.c_lflag = tcflag_t::try_from(next()?).ok()?;
+        let ispeed = libc::speed_t::try_from(next()?).ok()?;
+        let ospeed = libc::speed_t::try_from(next()?).ok()?;
+        // SAFETY: We pass a valid termios structure.
+        unsafe {
+            to_io_result(libc::cfsetispeed(&mut termios, ispeed)).ok()?;
+            to_io_result(libc::cfsetospeed(&mut termios, ospeed)).ok()?;
+        }
+        termios.c_cc.copy_from_slice(control_chars);
+        Some(TerminalAttributes(termios))
+    }
+
+    pub(crate) fn control_char(&self, control_char: ControlChar) -> Option<u8> {
+        let value = self.0.c_cc[control_char_index(control_char)];
+        (value != VDISABLE).then_some(value)
+    }
+
+    pub(crate) fn set_control_char(&mut self, control_char: ControlChar, value: Option<u8>) {
+        self.0.c_cc[control_char_index(control_char)] = value.unwrap_or(VDISABLE);
+    }
+
+    /// Resets the flags and special characters to sane values, like `stty sane`.
+    pub(crate) fn make_sane(&mut self) {
+        let termios = &mut self.0;
+        set_flags(
+            &mut termios.c_iflag,
+            libc::BRKINT | libc::ICRNL | libc::IXON,
+            true,
+        );
+        set_flags(
+            &mut termios.c_iflag,
+            libc::IGNBRK | libc::INLCR | libc::IGNCR | libc::IXOFF | libc::ISTRIP,
+            false,
+        );
+        set_flags(&mut termios.c_oflag, libc::OPOST | libc::ONLCR, true);
+        set_flags(
+            &mut termios.c_oflag,
+            libc::OCRNL | libc::ONOCR | libc::ONLRET,
+            false,
+        );
+        set_flags(&mut termios.c_cflag, libc::CREAD, true);
+        set_flags(
+            &mut termios.c_lflag,
+            libc::ISIG | libc::ICANON | libc::ECHO | libc::ECHOE | libc::ECHOK | libc::IEXTEN,
+            true,
+        );
+        set_flags(
+            &mut termios.c_lflag,
+            libc::ECHONL | libc::NOFLSH | libc::TOSTOP,
+            false,
+        );
+        // VMIN and VTIME are left alone as they share their slots
+        // with VEOF and VEOL on some platforms and only matter in non-canonical mode.
+        let control_chars = [
+            (libc::VINTR, 0x03),  // Ctrl+C
+            (libc::VQUIT, 0x1c),  // Ctrl+\
+            (libc::VERASE, 0x7f), // DEL
+            (libc::VKILL, 0x15),  // Ctrl+U
+            (libc::VEOF, 0x04),   // Ctrl+D
+            (libc::VSTART, 0x11), // Ctrl+Q
+            (libc::VSTOP, 0x13),  // Ctrl+S
+            (libc::VSUSP, 0x1a),  // Ctrl+Z
+        ];
+        for (index, value) in control_chars {
+            termios.c_cc[index] = value;
+        }
+    }
+
+    /// The names of the flags together with whether they're set.
+    pub(crate) fn flags(&self) -> Vec<(&'static str, bool)> {
+        let termios = &self.0;
+        let iflags = [
+            ("IGNBRK", libc::IGNBRK),
+            ("BRKINT", libc::BRKINT),
+            ("IGNPAR", libc::IGNPAR),
+            ("PARMRK", libc::PARMRK),
+            ("INPCK", libc::INPCK),
+            ("ISTRIP", libc::ISTRIP),
+            ("INLCR", libc::INLCR),
+            ("IGNCR", libc::IGNCR),
+            ("ICRNL", libc::ICRNL),
+            ("IXON", libc::IXON),
+            ("IXANY", libc::IXANY),
+            ("IXOFF", libc::IXOFF),
+            ("IMAXBEL", libc::IMAXBEL),
+        ];
+        let oflags = [
+            ("OPOST", libc::OPOST),
+            ("ONLCR", libc::ONLCR),
+            ("OCRNL", libc::OCRNL),
+            ("ONOCR", libc::ONOCR),
+            ("ONLRET", libc::ONLRET),
+        ];
+        let cflags = [
+            ("CSTOPB", libc::CSTOPB),
+            ("CREAD", libc::CREAD),
+            ("PARENB", libc::PARENB),
+            ("PARODD", libc::PARODD),
+            ("HUPCL", libc::HUPCL),
+            ("CLOCAL", libc::CLOCAL),
+        ];
+        let lflags = [
+            ("ISIG", libc::ISIG),
+            ("ICANON", libc::ICANON),
+            ("ECHO", libc::ECHO),
+            ("ECHOE", libc::ECHOE),
+            ("ECHOK", libc::ECHOK),
+            ("ECHONL", libc::ECHONL),
+            ("NOFLSH", libc::NOFLSH),
+            ("TOSTOP", libc::TOSTOP),
+            ("IEXTEN", libc::IEXTEN),
+        ];
+        let named = |target: tcflag_t, flags: &[(&'static str, tcflag_t)]| {
+            flags
+                .iter()
+                .map(move |&(name, flag)| (name, target & flag != 0))
+                .collect::<Vec<_>>()
+        };
+        [
+            named(termios.c_iflag, &iflags),
+            named(termios.c_oflag, &oflags),
+            named(termios.c_cflag, &cflags),
+            named(termios.c_lflag, &lflags),
+        ]
+        .concat()
+    }
+}
+
+/// Compares the attributes that are serialized (i.e. the flags, speeds and control characters).
+pub(super) fn same_attributes(a: &termios, b: &termios) -> bool {
+    TerminalAttributes(*a).to_bytes() == TerminalAttributes(*b).to_bytes()
+}
+
+/// Undoes the changes made from `previous` to `applied` on the `current` attributes,
+/// keeping all other changes.
+pub(super) fn merge(current: &termios, applied: &termios, previous: &termios) -> termios {
+    let merge_flags = |current: tcflag_t, applied: tcflag_t, previous: tcflag_t| {
+        let changed = applied ^ previous;
+        (current & !changed) | (previous & changed)
+    };
+    let mut merged = *current;
+    merged.c_iflag = merge_flags(current.c_iflag, applied.c_iflag, previous.c_iflag);
+    merged.c_oflag = merge_flags(current.c_oflag, applied.c_oflag, previous.c_oflag);
+    merged.c_cflag = merge_flags(current.c_cflag, applied.c_cflag, previous.c_cflag);
+    merged.c_lflag = merge_flags(current.c_lflag, applied.c_lflag, previous.c_lflag);
+    for ((control_char, applied), previous) in
+        merged.c_cc.iter_mut().zip(applied.c_cc).zip(previous.c_cc)
+    {
+        if applied != previous {
+            *control_char = previous;
+        }
+    }
+    merged
+}
+
+// The value that disables a special character (`_POSIX_VDISABLE`),
+// which libc doesn't define consistently across platforms.
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly",
+    target_os = "aix",
+))]
+const VDISABLE: libc::cc_t = 0xff;
+#[cfg(not(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "netbsd",
+    target_os = "openbsd",
+    target_os = "dragonfly",
+    target_os = "aix",
+)))]
+const VDISABLE: libc::cc_t = 0;
+
+fn control_char_index(control_char: ControlChar) -> usize {
+    match control_char {
+        ControlChar::Interrupt => libc::VINTR,
+        ControlChar::EndOfFile => libc::VEOF,
+        ControlChar::Suspend => libc::VSUSP,
+        ControlChar::Erase => libc::VERASE,
+    }
+}
+
+pub(super) fn set_flags(target: &mut tcflag_t, flags: tcflag_t, enabled: bool) {
+    if enabled {
+        *target |= flags;
+    } else {
+        *target &= !flags;
+    }
 }
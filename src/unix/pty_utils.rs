@@ -1,5 +1,10 @@
+#[cfg(target_os = "macos")]
+use super::path_from_buf;
+use super::path_too_long;
+#[cfg(not(target_os = "macos"))]
+use super::MAX_PATH_LEN;
 use libc::{grantpt, posix_openpt, unlockpt, O_CLOEXEC, O_NOCTTY, O_RDWR};
-use std::ffi::{c_int, CStr, CString, OsStr};
+use std::ffi::{c_int, CString, OsStr};
 use std::fs::File;
 use std::io;
 use std::os::fd::{AsFd as _, AsRawFd as _, BorrowedFd, FromRawFd as _, OwnedFd};
@@ -43,6 +48,8 @@ fn openpty() -> io::Result<OwnedFd> {
 
 #[cfg(not(target_os = "macos"))]
 fn ptsname_r(fd: BorrowedFd) -> io::Result<CString> {
+    use std::ffi::CStr;
+
     let mut buf = Vec::with_capacity(64);
 
     loop {
@@ -51,7 +58,9 @@ fn ptsname_r(fd: BorrowedFd) -> io::Result<CString> {
         match code {
             // SAFETY: We own the pointer and we know that if ptsname_r is successful, it returns a null-terminated string.
             0 => return Ok(unsafe { CStr::from_ptr(buf.as_ptr()).to_owned() }),
-            libc::ERANGE => buf.reserve(64),
+            // `reserve` is relative to the length (which is always zero), so we need to ask for more than the capacity.
+            libc::ERANGE if buf.capacity() < MAX_PATH_LEN => buf.reserve(buf.capacity() * 2),
+            libc::ERANGE => return Err(path_too_long()),
             code => return Err(io::Error::from_raw_os_error(code)),
         }
     }
@@ -69,19 +78,11 @@ fn ptsname_r(fd: BorrowedFd) -> io::Result<CString> {
     use libc::{c_ulong, ioctl, TIOCPTYGNAME};
 
     // the buffer size on OSX is 128, defined by sys/ttycom.h
-    let buf: [i8; 128] = [0; 128];
+    let mut buf = [0_u8; 128];
 
     // SAFETY: Our buffer is big enough according to the docs.
-    // Creating the CStr is also ok, since we know that we get back a null-terminated string.
-    unsafe {
-        match ioctl(fd.as_raw_fd(), TIOCPTYGNAME as c_ulong, &buf) {
-            0 => {
-                let res = CStr::from_ptr(buf.as_ptr()).to_owned();
-                Ok(res)
-            }
-            _ => Err(io::Error::last_os_error()),
-        }
-    }
+    to_io_result(unsafe { ioctl(fd.as_raw_fd(), TIOCPTYGNAME as c_ulong, buf.as_mut_ptr()) })?;
+    path_from_buf(&buf)
 }
 
 fn to_io_result(value: c_int) -> io::Result<c_int> {
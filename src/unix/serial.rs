@@ -0,0 +1,105 @@
+use super::to_io_result;
+use super::TerminalAttributes;
+use crate::{Parity, StopBits};
+use libc::speed_t;
+use std::io;
+
+// On Linux, the speed is encoded using `B*` constants,
+// while on the BSDs (and macOS) the constants are equal to the baud rate.
+const BAUD_RATES: &[(u32, speed_t)] = &[
+    (0, libc::B0),
+    (50, libc::B50),
+    (75, libc::B75),
+    (110, libc::B110),
+    (134, libc::B134),
+    (150, libc::B150),
+    (200, libc::B200),
+    (300, libc::B300),
+    (600, libc::B600),
+    (1200, libc::B1200),
+    (1800, libc::B1800),
+    (2400, libc::B2400),
+    (4800, libc::B4800),
+    (9600, libc::B9600),
+    (19200, libc::B19200),
+    (38400, libc::B38400),
+    (57600, libc::B57600),
+    (115200, libc::B115200),
+    (230400, libc::B230400),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    (460800, libc::B460800),
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    (921600, libc::B921600),
+];
+
+impl TerminalAttributes {
+    pub(crate) fn baud_rate(&self) -> Option<u32> {
+        // SAFETY: We pass a valid termios structure.
+        let speed = unsafe { libc::cfgetospeed(&self.0) };
+        BAUD_RATES
+            .iter()
+            .find(|(_, s)| *s == speed)
+            .map(|(rate, _)| *rate)
+    }
+
+    pub(crate) fn set_baud_rate(&mut self, baud_rate: u32) -> io::Result<()> {
+        let speed = BAUD_RATES
+            .iter()
+            .find(|(rate, _)| *rate == baud_rate)
+            .map(|(_, speed)| *speed)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("unsupported baud rate: {baud_rate}"),
+                )
+            })?;
+        // SAFETY: We pass a valid termios structure.
+        to_io_result(unsafe { libc::cfsetispeed(&mut self.0, speed) })?;
+        // SAFETY: See above.
+        to_io_result(unsafe { libc::cfsetospeed(&mut self.0, speed) }).map(drop)
+    }
+
+    pub(crate) fn parity(&self) -> Parity {
+        match (
+            self.0.c_cflag & libc::PARENB != 0,
+            self.0.c_cflag & libc::PARODD != 0,
+        ) {
+            (false, _) => Parity::None,
+            (true, false) => Parity::Even,
+            (true, true) => Parity::Odd,
+        }
+    }
+
+    pub(crate) fn set_parity(&mut self, parity: Parity) {
+        self.0.c_cflag &= !(libc::PARENB | libc::PARODD);
+        self.0.c_cflag |= match parity {
+            Parity::None => 0,
+            Parity::Even => libc::PARENB,
+            Parity::Odd => libc::PARENB | libc::PARODD,
+        };
+    }
+
+    pub(crate) fn stop_bits(&self) -> StopBits {
+        if self.0.c_cflag & libc::CSTOPB != 0 {
+            StopBits::Two
+        } else {
+            StopBits::One
+        }
+    }
+
+    pub(crate) fn set_stop_bits(&mut self, stop_bits: StopBits) {
+        super::attr::set_flags(
+            &mut self.0.c_cflag,
+            libc::CSTOPB,
+            stop_bits == StopBits::Two,
+        );
+    }
+
+    pub(crate) fn hardware_flow_control(&self) -> bool {
+        self.0.c_cflag & libc::CRTSCTS != 0
+    }
+
+    pub(crate) fn set_hardware_flow_control(&mut self, enabled: bool) {
+        super::attr::set_flags(&mut self.0.c_cflag, libc::CRTSCTS, enabled);
+    }
+}
@@ -0,0 +1,239 @@
+use super::{to_io_result, Terminal, TerminalAttributes};
+use libc::{
+    c_int, c_void, sigaction, siginfo_t, termios, SIGCONT, SIGINT, SIGQUIT, SIGTERM, SIGTSTP,
+};
+use std::cell::UnsafeCell;
+use std::os::fd::AsRawFd as _;
+use std::sync::OnceLock;
+use std::{io, mem, ptr};
+
+const SIGNALS: [c_int; 3] = [SIGINT, SIGTERM, SIGQUIT];
+
+struct SavedState {
+    terminal: Terminal,
+    attributes: TerminalAttributes,
+    previous_actions: [sigaction; SIGNALS.len()],
+}
+
+// Only ever read from the signal handler, which is why we can't use a mutex here.
+static SAVED_STATE: OnceLock<SavedState> = OnceLock::new();
+
+pub(crate) fn install_restore_on_signals(
+    terminal: Terminal,
+    attributes: TerminalAttributes,
+) -> io::Result<()> {
+    let mut previous_actions = [empty_action(); SIGNALS.len()];
+    for (signal, previous_action) in SIGNALS.iter().zip(&mut previous_actions) {
+        // SAFETY: Passing a null pointer as new action only queries the current action.
+        to_io_result(unsafe { sigaction(*signal, ptr::null(), previous_action) })?;
+    }
+
+    let state = SavedState {
+        terminal,
+        attributes,
+        previous_actions,
+    };
+    if SAVED_STATE.set(state).is_err() {
+        return Ok(());
+    }
+
+    let action = siginfo_action(handle_signal);
+    for (signal, previous_action) in SIGNALS.iter().zip(&previous_actions) {
+        // Ignored signals (e.g. when started by `nohup`) can't terminate the process.
+        // Installing a handler would reset them to their default action in exec'd children.
+        if previous_action.sa_sigaction == libc::SIG_IGN {
+            continue;
+        }
+        // SAFETY: Our handler only calls async-signal-safe functions.
+        to_io_result(unsafe { sigaction(*signal, &action, ptr::null_mut()) })?;
+    }
+
+    Ok(())
+}
+
+extern "C" fn handle_signal(signal: c_int, info: *mut siginfo_t, context: *mut c_void) {
+    let Some(state) = SAVED_STATE.get() else {
+        return;
+    };
+    let Some(previous_action) = SIGNALS
+        .iter()
+        .position(|s| *s == signal)
+        .map(|index| &state.previous_actions[index])
+    else {
+        return;
+    };
+
+    match previous_action.sa_sigaction {
+        // The process is about to be terminated, so let's restore the terminal
+        // and re-raise the signal with the default action.
+        // The signal is blocked while this handler runs, so it is delivered once we return.
+        libc::SIG_DFL => {
+            // SAFETY: tcsetattr, sigaction and raise are all async-signal-safe.
+            unsafe {
+                libc::tcsetattr(
+                    state.terminal.file.as_raw_fd(),
+                    libc::TCSANOW,
+                    &state.attributes.0,
+                );
+                sigaction(signal, previous_action, ptr::null_mut());
+                libc::raise(signal);
+            }
+        }
+        // A handler that was installed before us decides for itself whether the process terminates.
+        _ => forward_to_handler(previous_action, signal, info, context),
+    }
+}
+
+struct JobControlState {
+    terminal: Terminal,
+    attributes: TerminalAttributes,
+    previous_continue_action: sigaction,
+    suspended_termios: SuspendedTermios,
+}
+
+// Only ever read from the signal handlers, which is why we can't use a mutex here.
+static JOB_CONTROL_STATE: OnceLock<JobControlState> = OnceLock::new();
+
+/// The terminal's mode at the time the process was suspended.
+/// This is only accessed from the `SIGTSTP` and `SIGCONT` handlers which block each other.
+struct SuspendedTermios(UnsafeCell<Option<termios>>);
+
+// SAFETY: Access is serialized by blocking signals, see above.
+unsafe impl Sync for SuspendedTermios {}
+
+pub(crate) fn install_restore_on_suspend(
+    terminal: Terminal,
+    attributes: TerminalAttributes,
+) -> io::Result<()> {
+    let mut previous_suspend_action = empty_action();
+    let mut previous_continue_action = empty_action();
+    // SAFETY: Passing a null pointer as new action only queries the current action.
+    unsafe {
+        to_io_result(sigaction(
+            SIGTSTP,
+            ptr::null(),
+            &mut previous_suspend_action,
+        ))?;
+        to_io_result(sigaction(
+            SIGCONT,
+            ptr::null(),
+            &mut previous_continue_action,
+        ))?;
+    }
+
+    // The application handles (or ignores) suspension on its own.
+    if previous_suspend_action.sa_sigaction != libc::SIG_DFL {
+        return Ok(());
+    }
+
+    let state = JobControlState {
+        terminal,
+        attributes,
+        previous_continue_action,
+        suspended_termios: SuspendedTermios(UnsafeCell::new(None)),
+    };
+    if JOB_CONTROL_STATE.set(state).is_err() {
+        return Ok(());
+    }
+
+    // SAFETY: Our handlers only call async-signal-safe functions.
+    unsafe {
+        to_io_result(sigaction(SIGCONT, &continue_action(), ptr::null_mut()))?;
+        to_io_result(sigaction(SIGTSTP, &suspend_action(), ptr::null_mut()))?;
+    }
+
+    Ok(())
+}
+
+fn suspend_action() -> sigaction {
+    let mut action = siginfo_action(handle_suspend);
+    // SAFETY: We pass a valid pointer to the signal mask.
+    unsafe { libc::sigaddset(&mut action.sa_mask, SIGCONT) };
+    action
+}
+
+fn continue_action() -> sigaction {
+    let mut action = siginfo_action(handle_continue);
+    // SAFETY: We pass a valid pointer to the signal mask.
+    unsafe { libc::sigaddset(&mut action.sa_mask, SIGTSTP) };
+    action
+}
+
+extern "C" fn handle_suspend(_signal: c_int, _info: *mut siginfo_t, _context: *mut c_void) {
+    let Some(state) = JOB_CONTROL_STATE.get() else {
+        return;
+    };
+    let fd = state.terminal.file.as_raw_fd();
+
+    // SAFETY: tcgetattr, tcsetattr, sigaction and raise are all async-signal-safe.
+    // Access to the suspended termios is serialized, see `SuspendedTermios`.
+    unsafe {
+        let mut termios = mem::zeroed();
+        if libc::tcgetattr(fd, &mut termios) == 0 {
+            *state.suspended_termios.0.get() = Some(termios);
+        }
+        libc::tcsetattr(fd, libc::TCSANOW, &state.attributes.0);
+
+        // The signal is blocked while this handler runs, so the process is
+        // suspended by the default action once we return.
+        // Our handler is re-installed when the process continues.
+        sigaction(SIGTSTP, &empty_action(), ptr::null_mut());
+        libc::raise(SIGTSTP);
+    }
+}
+
+extern "C" fn handle_continue(signal: c_int, info: *mut siginfo_t, context: *mut c_void) {
+    let Some(state) = JOB_CONTROL_STATE.get() else {
+        return;
+    };
+
+    // SAFETY: tcsetattr and sigaction are async-signal-safe.
+    // Access to the suspended termios is serialized, see `SuspendedTermios`.
+    unsafe {
+        if let Some(termios) = (*state.suspended_termios.0.get()).take() {
+            libc::tcsetattr(state.terminal.file.as_raw_fd(), libc::TCSANOW, &termios);
+        }
+        // Even if we couldn't save the mode, we still want to handle the next suspension.
+        sigaction(SIGTSTP, &suspend_action(), ptr::null_mut());
+    }
+
+    forward_to_handler(&state.previous_continue_action, signal, info, context);
+}
+
+/// Calls the handler of a previously installed action, if any.
+fn forward_to_handler(
+    action: &sigaction,
+    signal: c_int,
+    info: *mut siginfo_t,
+    context: *mut c_void,
+) {
+    match action.sa_sigaction {
+        libc::SIG_DFL | libc::SIG_IGN => {}
+        handler if action.sa_flags & libc::SA_SIGINFO != 0 => {
+            // SAFETY: The handler was registered with SA_SIGINFO, so it has this signature.
+            let handler: extern "C" fn(c_int, *mut siginfo_t, *mut c_void) =
+                unsafe { mem::transmute(handler) };
+            handler(signal, info, context);
+        }
+        handler => {
+            // SAFETY: The handler was registered without SA_SIGINFO, so it has this signature.
+            let handler: extern "C" fn(c_int) = unsafe { mem::transmute(handler) };
+            handler(signal);
+        }
+    }
+}
+
+fn siginfo_action(handler: extern "C" fn(c_int, *mut siginfo_t, *mut c_void)) -> sigaction {
+    let mut action = empty_action();
+    action.sa_sigaction = handler as *const () as libc::sighandler_t;
+    action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+    action
+}
+
+fn empty_action() -> sigaction {
+    // SAFETY: An all-zero sigaction is valid (it's the default action with no flags).
+    let mut action: sigaction = unsafe { mem::zeroed() };
+    // SAFETY: We pass a valid pointer to the signal mask.
+    unsafe { libc::sigemptyset(&mut action.sa_mask) };
+    action
+}
@@ -1,9 +1,11 @@
 #![allow(clippy::unwrap_used)]
 
 use super::*;
+use crate::{ControlChar, ExternalModeChanges, InputBuffer, Parity, StopBits};
 use pty_utils::pty_pair;
 use std::env;
-use std::io::Write;
+use std::io::{Read as _, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 #[test]
 fn ttyname_r_returns_successfully() {
@@ -14,6 +16,14 @@ fn ttyname_r_returns_successfully() {
     assert!(name_as_str.starts_with("/dev/"));
 }
 
+#[test]
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn proc_fd_path_matches_ttyname_r() {
+    let pty = pty_pair().unwrap();
+    let name = ttyname_r(pty.user.as_fd()).unwrap();
+    assert_eq!(Some(name), proc_fd_path(pty.user.as_fd()));
+}
+
 #[test]
 fn reopened_tty_can_be_written_to() {
     let pty = pty_pair().unwrap();
@@ -44,26 +54,1624 @@ fn is_read_write_returns_false_for_write_only_fd() {
 }
 
 #[test]
-fn is_same_file_with_same_fd() {
-    let file = stdin();
-    let fd = file.as_fd();
-    assert!(is_same_file(fd, fd).unwrap());
+fn is_same_device_with_same_fd() {
+    let file = OpenOptions::new().read(true).open("/dev/null").unwrap();
+    let device = DeviceId::of(file.as_fd()).unwrap();
+    assert!(device.is_same_as(file.as_fd()));
 }
 
 #[test]
-fn is_same_file_with_different_fd_but_same_underlying_file() {
+fn is_same_device_with_different_fd_but_same_underlying_file() {
     let file_1 = OpenOptions::new().read(true).open("/dev/null").unwrap();
     let file_2 = OpenOptions::new().read(true).open("/dev/null").unwrap();
     assert!(file_1.as_raw_fd() != file_2.as_raw_fd());
-    assert!(is_same_file(file_1.as_fd(), file_2.as_fd()).unwrap());
+    let device = DeviceId::of(file_1.as_fd()).unwrap();
+    assert!(device.is_same_as(file_2.as_fd()));
 }
 
 #[test]
-fn is_not_same_file_with_different_underlying_file() {
+fn is_not_same_device_with_different_underlying_file() {
     let file_1 = OpenOptions::new()
         .read(true)
         .open(env::args().next().unwrap())
         .unwrap();
     let file_2 = OpenOptions::new().read(true).open("/dev/null").unwrap();
-    assert!(!is_same_file(file_1.as_fd(), file_2.as_fd()).unwrap());
+    let device = DeviceId::of(file_1.as_fd()).unwrap();
+    assert!(!device.is_same_as(file_2.as_fd()));
+}
+
+#[test]
+fn raw_mode_keeps_output_processing_by_default() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+
+    let _guard = terminal.enable_raw_mode(&RawModeOptions::new()).unwrap();
+    (&terminal).write_all(b"a\n").unwrap();
+    let mut buf = [0; 3];
+    controlling.read_exact(&mut buf).unwrap();
+    assert_eq!(b"a\r\n", &buf);
+}
+
+#[test]
+fn raw_mode_options_are_applied_and_restored() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let fd = pty.user.as_fd();
+    let options = RawModeOptions::new().signals(false);
+
+    let guard = terminal.enable_raw_mode(&options).unwrap();
+    let termios = attr::get_terminal_attr(fd).unwrap();
+    assert_eq!(
+        0,
+        termios.c_lflag & (libc::ECHO | libc::ICANON | libc::ISIG)
+    );
+    assert_ne!(0, termios.c_oflag & libc::OPOST);
+
+    drop(guard);
+    let termios = attr::get_terminal_attr(fd).unwrap();
+    assert_ne!(0, termios.c_lflag & libc::ISIG);
+}
+
+#[test]
+fn disable_echo_keeps_canonical_mode() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let fd = pty.user.as_fd();
+
+    let _guard = terminal
+        .enable_raw_mode(&RawModeOptions::echo_disabled())
+        .unwrap();
+    let termios = attr::get_terminal_attr(fd).unwrap();
+    assert_eq!(0, termios.c_lflag & libc::ECHO);
+    assert_ne!(0, termios.c_lflag & libc::ICANON);
+    assert_ne!(0, termios.c_lflag & libc::ECHONL);
+}
+
+#[test]
+fn read_behavior_is_applied_and_restored() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let fd = pty.user.as_fd();
+    let old_termios = attr::get_terminal_attr(fd).unwrap();
+
+    let mut guard = terminal.enable_raw_mode(&RawModeOptions::new()).unwrap();
+    guard
+        .set_read_behavior(0, Some(Duration::from_millis(10)))
+        .unwrap();
+    let termios = attr::get_terminal_attr(fd).unwrap();
+    assert_eq!(0, termios.c_cc[libc::VMIN]);
+    assert_eq!(1, termios.c_cc[libc::VTIME]);
+    assert_eq!(0, guard.read(&mut [0; 8]).unwrap());
+
+    drop(guard);
+    let termios = attr::get_terminal_attr(fd).unwrap();
+    assert_eq!(old_termios.c_cc, termios.c_cc);
+}
+
+#[test]
+fn read_timeout_tells_timeout_apart_from_input() {
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+
+    let mut lock = terminal.lock();
+    let mut guard = lock.enable_raw_mode().unwrap();
+    let timeout = Duration::from_millis(20);
+    let error = guard.read_timeout(&mut [0; 8], timeout).unwrap_err();
+    assert_eq!(io::ErrorKind::TimedOut, error.kind());
+
+    controlling.write_all(b"x").unwrap();
+    let mut buf = [0; 8];
+    assert_eq!(
+        1,
+        guard
+            .read_timeout(&mut buf, Duration::from_secs(5))
+            .unwrap()
+    );
+    assert_eq!(b'x', buf[0]);
+
+    guard.unread(b"y");
+    assert_eq!(1, guard.read_timeout(&mut buf, timeout).unwrap());
+    assert_eq!(b'y', buf[0]);
+}
+
+#[test]
+fn discard_input_drops_pending_input() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    controlling.write_all(b"stale\n").unwrap();
+    wait_for_input(pty.user.as_fd());
+
+    let mut guard = terminal.enable_raw_mode(&RawModeOptions::new()).unwrap();
+    guard.discard(Queue::Input).unwrap();
+    guard.set_read_behavior(0, None).unwrap();
+    assert_eq!(0, guard.read(&mut [0; 8]).unwrap());
+}
+
+#[test]
+fn vectored_io_reads_and_writes_all_slices() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    let mut guard = terminal.enable_raw_mode(&RawModeOptions::new()).unwrap();
+
+    let written = guard
+        .write_vectored(&[io::IoSlice::new(b"foo"), io::IoSlice::new(b"bar")])
+        .unwrap();
+    assert_eq!(6, written);
+    let mut output = [0; 6];
+    controlling.read_exact(&mut output).unwrap();
+    assert_eq!(b"foobar", &output);
+
+    controlling.write_all(b"bazqux").unwrap();
+    wait_for_input(pty.user.as_fd());
+    let (mut first, mut second) = ([0; 3], [0; 3]);
+    let read = guard
+        .read_vectored(&mut [
+            io::IoSliceMut::new(&mut first),
+            io::IoSliceMut::new(&mut second),
+        ])
+        .unwrap();
+    assert_eq!(6, read);
+    assert_eq!((b"baz", b"qux"), (&first, &second));
+}
+
+#[test]
+fn flush_timing_discards_pending_input() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    controlling.write_all(b"stale\n").unwrap();
+    wait_for_input(pty.user.as_fd());
+
+    let options = RawModeOptions::new().timing(ModeChangeTiming::Flush);
+    let mut guard = terminal.enable_raw_mode(&options).unwrap();
+    guard.set_read_behavior(0, None).unwrap();
+    assert_eq!(0, guard.read(&mut [0; 8]).unwrap());
+}
+
+#[test]
+fn flush_timing_discards_pending_input_on_unchanged_restore() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let fd = pty.user.as_fd();
+    let old_termios = attr::get_terminal_attr(fd).unwrap();
+
+    let options = RawModeOptions::new().timing(ModeChangeTiming::Flush);
+    let guard = terminal.enable_raw_mode(&options).unwrap();
+    attr::set_terminal_attr(fd, &old_termios, ModeChangeTiming::Now).unwrap();
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    controlling.write_all(b"stale\n").unwrap();
+    wait_for_input(fd);
+    drop(guard);
+
+    let options = RawModeOptions::new().read_behavior(0, None);
+    let mut guard = terminal.enable_raw_mode(&options).unwrap();
+    assert_eq!(0, guard.read(&mut [0; 8]).unwrap());
+}
+
+#[test]
+fn read_behavior_is_applied_together_with_raw_mode() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let fd = pty.user.as_fd();
+    let old_termios = attr::get_terminal_attr(fd).unwrap();
+
+    let options = RawModeOptions::new().read_behavior(0, Some(Duration::from_millis(10)));
+    let guard = terminal.enable_raw_mode(&options).unwrap();
+    let termios = attr::get_terminal_attr(fd).unwrap();
+    assert_eq!(0, termios.c_cc[libc::VMIN]);
+    assert_eq!(1, termios.c_cc[libc::VTIME]);
+    assert!(attr::is_raw_mode_enabled(&termios, &options));
+
+    drop(guard);
+    let termios = attr::get_terminal_attr(fd).unwrap();
+    assert_eq!(old_termios.c_cc, termios.c_cc);
+}
+
+#[test]
+fn unchanged_read_behavior_does_not_modify_guard() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let options = RawModeOptions::new().read_behavior(1, None);
+    let guard = terminal.enable_raw_mode(&options).unwrap();
+    let mut nested = guard.terminal().enable_raw_mode(&options).unwrap();
+    nested.set_read_behavior(1, None).unwrap();
+    assert!(!nested.modified);
+}
+
+#[test]
+fn nested_raw_mode_guards_restore_in_order() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let fd = pty.user.as_fd();
+
+    let outer = terminal.enable_raw_mode(&RawModeOptions::new()).unwrap();
+    let inner = outer
+        .enable_raw_mode(&RawModeOptions::new().signals(false))
+        .unwrap();
+    assert_eq!(0, attr::get_terminal_attr(fd).unwrap().c_lflag & libc::ISIG);
+
+    drop(inner);
+    let termios = attr::get_terminal_attr(fd).unwrap();
+    assert_ne!(0, termios.c_lflag & libc::ISIG);
+    assert_eq!(0, termios.c_lflag & libc::ECHO);
+
+    drop(outer);
+    assert_ne!(0, attr::get_terminal_attr(fd).unwrap().c_lflag & libc::ECHO);
+}
+
+#[test]
+fn persisted_raw_mode_outlives_guard() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let fd = pty.user.as_fd();
+
+    let cooked_mode = terminal
+        .enable_raw_mode(&RawModeOptions::new())
+        .unwrap()
+        .persist();
+    assert_eq!(0, attr::get_terminal_attr(fd).unwrap().c_lflag & libc::ECHO);
+
+    terminal.set_attributes(&cooked_mode).unwrap();
+    assert_ne!(0, attr::get_terminal_attr(fd).unwrap().c_lflag & libc::ECHO);
+}
+
+#[test]
+fn suspend_restores_original_mode_temporarily() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let fd = pty.user.as_fd();
+    let original = attr::get_terminal_attr(fd).unwrap();
+
+    let mut input_buffer = InputBuffer::new();
+    let mut guard = crate::RawModeGuard(
+        terminal.enable_raw_mode(&RawModeOptions::new()).unwrap(),
+        &mut input_buffer,
+    );
+    let raw = attr::get_terminal_attr(fd).unwrap();
+    guard
+        .suspend(|| {
+            let suspended = attr::get_terminal_attr(fd).unwrap();
+            assert_eq!(original.c_lflag, suspended.c_lflag);
+        })
+        .unwrap();
+    assert_eq!(raw.c_lflag, attr::get_terminal_attr(fd).unwrap().c_lflag);
+}
+
+#[test]
+fn suspend_reapplies_mode_when_panicking() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let fd = pty.user.as_fd();
+
+    let mut input_buffer = InputBuffer::new();
+    let mut guard = crate::RawModeGuard(
+        terminal.enable_raw_mode(&RawModeOptions::new()).unwrap(),
+        &mut input_buffer,
+    );
+    let raw = attr::get_terminal_attr(fd).unwrap();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        _ = guard.suspend(|| panic!("closure panics"));
+    }));
+    assert!(result.is_err());
+    assert_eq!(raw.c_lflag, attr::get_terminal_attr(fd).unwrap().c_lflag);
+}
+
+#[test]
+fn query_reads_response_until_complete() {
+    let pty = pty_pair().unwrap();
+    let terminal = query_pty_terminal(&pty);
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    let responder = std::thread::spawn(move || {
+        let mut request = [0; 4];
+        controlling.read_exact(&mut request).unwrap();
+        assert_eq!(b"\x1b[6n", &request);
+        controlling.write_all(b"\x1b[1;2Rx").unwrap();
+    });
+
+    let mut input_buffer = InputBuffer::new();
+    let mut guard = crate::RawModeGuard(
+        terminal.enable_raw_mode(&RawModeOptions::new()).unwrap(),
+        &mut input_buffer,
+    );
+    let response = guard
+        .query(b"\x1b[6n", |r| r.ends_with(b"R"), Duration::from_secs(5))
+        .unwrap();
+    responder.join().unwrap();
+    assert_eq!(b"\x1b[1;2R", response.as_slice());
+
+    // Input after the response is not consumed.
+    let mut rest = [0; 1];
+    guard.read_exact(&mut rest).unwrap();
+    assert_eq!(b"x", &rest);
+}
+
+#[test]
+fn query_preserves_input_typed_before_response() {
+    let pty = pty_pair().unwrap();
+    let terminal = query_pty_terminal(&pty);
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    let responder = std::thread::spawn(move || {
+        let mut request = [0; 4];
+        controlling.read_exact(&mut request).unwrap();
+        controlling.write_all(b"ab\x1b[1;2Rc").unwrap();
+    });
+
+    let mut input_buffer = InputBuffer::new();
+    let mut guard = crate::RawModeGuard(
+        terminal.enable_raw_mode(&RawModeOptions::new()).unwrap(),
+        &mut input_buffer,
+    );
+    let response = guard
+        .query(b"\x1b[6n", |r| r.ends_with(b"R"), Duration::from_secs(5))
+        .unwrap();
+    responder.join().unwrap();
+    assert_eq!(b"\x1b[1;2R", response.as_slice());
+
+    let mut rest = [0; 3];
+    guard.read_exact(&mut rest).unwrap();
+    assert_eq!(b"abc", &rest);
+}
+
+fn query_with_typed_input(typed: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let pty = pty_pair().unwrap();
+    let terminal = query_pty_terminal(&pty);
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    let input = [typed, b"\x1b[1;2R"].concat();
+    let responder = std::thread::spawn(move || {
+        let mut request = [0; 4];
+        controlling.read_exact(&mut request).unwrap();
+        controlling.write_all(&input).unwrap();
+    });
+
+    let mut input_buffer = InputBuffer::new();
+    let mut guard = crate::RawModeGuard(
+        terminal.enable_raw_mode(&RawModeOptions::new()).unwrap(),
+        &mut input_buffer,
+    );
+    let response = guard
+        .query(b"\x1b[6n", |r| r.ends_with(b"R"), Duration::from_secs(5))
+        .unwrap();
+    responder.join().unwrap();
+
+    let mut rest = vec![0; typed.len()];
+    guard.read_exact(&mut rest).unwrap();
+    (response, rest)
+}
+
+#[test]
+fn query_preserves_escape_key_typed_before_response() {
+    let (response, typed) = query_with_typed_input(b"\x1b");
+    assert_eq!(b"\x1b[1;2R", response.as_slice());
+    assert_eq!(b"\x1b", typed.as_slice());
+}
+
+#[test]
+fn query_preserves_arrow_key_typed_before_response() {
+    let (response, typed) = query_with_typed_input(b"\x1b[A\x1bx");
+    assert_eq!(b"\x1b[1;2R", response.as_slice());
+    assert_eq!(b"\x1b[A\x1bx", typed.as_slice());
+}
+
+#[test]
+fn query_many_attributes_timeouts_per_query() {
+    let pty = pty_pair().unwrap();
+    let terminal = query_pty_terminal(&pty);
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    let responder = std::thread::spawn(move || {
+        let mut requests = [0; 8];
+        controlling.read_exact(&mut requests).unwrap();
+        assert_eq!(b"\x1b[6n\x1b[5n", &requests);
+        // Only the second query is answered.
+        controlling.write_all(b"x\x1b[0n").unwrap();
+    });
+
+    let mut input_buffer = InputBuffer::new();
+    let mut guard = crate::RawModeGuard(
+        terminal.enable_raw_mode(&RawModeOptions::new()).unwrap(),
+        &mut input_buffer,
+    );
+    let responses = guard
+        .query_many(&mut [
+            crate::Query::new(b"\x1b[6n", |r| r.ends_with(b"R"))
+                .timeout(Duration::from_millis(500)),
+            crate::Query::new(b"\x1b[5n", |r| r.ends_with(b"n")).timeout(Duration::from_secs(5)),
+        ])
+        .unwrap();
+    responder.join().unwrap();
+    assert_eq!(vec![None, Some(b"\x1b[0n".to_vec())], responses);
+
+    let mut typed = [0; 1];
+    guard.read_exact(&mut typed).unwrap();
+    assert_eq!(b"x", &typed);
+}
+
+#[test]
+fn query_many_credits_response_to_matching_query() {
+    let pty = pty_pair().unwrap();
+    let terminal = query_pty_terminal(&pty);
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    let responder = std::thread::spawn(move || {
+        let mut requests = [0; 7];
+        controlling.read_exact(&mut requests).unwrap();
+        // Only the secondary device attributes are answered.
+        controlling.write_all(b"\x1b[>1;10;0c").unwrap();
+    });
+
+    let mut input_buffer = InputBuffer::new();
+    let mut guard = crate::RawModeGuard(
+        terminal.enable_raw_mode(&RawModeOptions::new()).unwrap(),
+        &mut input_buffer,
+    );
+    let is_response =
+        |prefix: &'static [u8]| move |r: &[u8]| r.starts_with(prefix) && r.ends_with(b"c");
+    let responses = guard
+        .query_many(&mut [
+            crate::Query::new(b"\x1b[c", is_response(b"\x1b[?"))
+                .timeout(Duration::from_millis(500)),
+            crate::Query::new(b"\x1b[>c", is_response(b"\x1b[>")).timeout(Duration::from_secs(5)),
+        ])
+        .unwrap();
+    responder.join().unwrap();
+    assert_eq!(vec![None, Some(b"\x1b[>1;10;0c".to_vec())], responses);
+}
+
+/// Reads from the controlling side until `needle` has been received.
+fn read_until(controlling: &mut File, needle: &[u8]) -> Vec<u8> {
+    let mut received = Vec::new();
+    let mut byte = [0];
+    while !received.ends_with(needle) {
+        controlling.read_exact(&mut byte).unwrap();
+        received.push(byte[0]);
+    }
+    received
+}
+
+#[test]
+fn synchronized_update_begins_if_supported() {
+    let pty = pty_pair().unwrap();
+    let terminal = query_pty_terminal(&pty);
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    let responder = std::thread::spawn(move || {
+        read_until(&mut controlling, b"\x1b[?2026$p\x1b[c");
+        controlling.write_all(b"\x1b[?2026;2$y\x1b[?62c").unwrap();
+        read_until(&mut controlling, b"\x1b[?2026h");
+    });
+
+    let mut input_buffer = InputBuffer::new();
+    let mut guard = crate::RawModeGuard(
+        terminal.enable_raw_mode(&RawModeOptions::new()).unwrap(),
+        &mut input_buffer,
+    );
+    let update = guard.begin_synchronized_update_if_supported().unwrap();
+    assert!(update.is_some());
+    responder.join().unwrap();
+}
+
+#[test]
+fn synchronized_update_is_skipped_if_not_supported() {
+    let pty = pty_pair().unwrap();
+    let terminal = query_pty_terminal(&pty);
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    let responder = std::thread::spawn(move || {
+        read_until(&mut controlling, b"\x1b[?2026$p\x1b[c");
+        controlling.write_all(b"\x1b[?2026;0$y\x1b[?62c").unwrap();
+    });
+
+    let mut input_buffer = InputBuffer::new();
+    let mut guard = crate::RawModeGuard(
+        terminal.enable_raw_mode(&RawModeOptions::new()).unwrap(),
+        &mut input_buffer,
+    );
+    let update = guard.begin_synchronized_update_if_supported().unwrap();
+    assert!(update.is_none());
+    responder.join().unwrap();
+    // The mode is not set.
+    let deadline = Instant::now() + Duration::from_millis(50);
+    assert!(!poll(pty._controlling.as_fd(), libc::POLLIN, Some(deadline)).unwrap());
+}
+
+#[test]
+fn color_support_restores_graphic_rendition() {
+    let pty = pty_pair().unwrap();
+    let terminal = query_pty_terminal(&pty);
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    let responder = std::thread::spawn(move || {
+        read_until(&mut controlling, b"\x1b[c");
+        controlling
+            .write_all(b"\x1bP1$r0;1m\x1b\\\x1bP1$r0;1;48:2::1:2:3m\x1b\\\x1b[?62c")
+            .unwrap();
+        read_until(&mut controlling, b"\x1b[0;1m");
+    });
+
+    let mut input_buffer = InputBuffer::new();
+    let mut guard = crate::RawModeGuard(
+        terminal.enable_raw_mode(&RawModeOptions::new()).unwrap(),
+        &mut input_buffer,
+    );
+    assert_eq!(
+        crate::ColorSupport::TrueColor,
+        guard.color_support().unwrap()
+    );
+    responder.join().unwrap();
+}
+
+#[test]
+fn color_support_falls_back_to_env_without_response() {
+    let pty = pty_pair().unwrap();
+    let terminal = query_pty_terminal(&pty);
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    let responder = std::thread::spawn(move || {
+        read_until(&mut controlling, b"\x1b[c");
+        // The probe is reset as the rendition wasn't reported.
+        read_until(&mut controlling, b"\x1b[m");
+    });
+
+    let mut input_buffer = InputBuffer::new();
+    let mut guard = crate::RawModeGuard(
+        terminal.enable_raw_mode(&RawModeOptions::new()).unwrap(),
+        &mut input_buffer,
+    );
+    assert_eq!(
+        crate::ColorSupport::from_env(),
+        guard.color_support().unwrap()
+    );
+    responder.join().unwrap();
+}
+
+#[test]
+fn terminal_version_is_none_when_only_sentinel_is_answered() {
+    let pty = pty_pair().unwrap();
+    let terminal = query_pty_terminal(&pty);
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    let responder = std::thread::spawn(move || {
+        let mut request = [0; 8];
+        controlling.read_exact(&mut request).unwrap();
+        assert_eq!(b"\x1b[>0q\x1b[c", &request);
+        controlling.write_all(b"\x1b[?62;22c").unwrap();
+    });
+
+    let mut input_buffer = InputBuffer::new();
+    let mut guard = crate::RawModeGuard(
+        terminal.enable_raw_mode(&RawModeOptions::new()).unwrap(),
+        &mut input_buffer,
+    );
+    assert_eq!(None, guard.terminal_version().unwrap());
+    responder.join().unwrap();
+}
+
+#[test]
+fn query_times_out_without_response() {
+    let pty = pty_pair().unwrap();
+    let terminal = query_pty_terminal(&pty);
+    let mut input_buffer = InputBuffer::new();
+    let mut guard = crate::RawModeGuard(
+        terminal.enable_raw_mode(&RawModeOptions::new()).unwrap(),
+        &mut input_buffer,
+    );
+    let error = guard
+        .query(b"\x1b[6n", |_| true, Duration::from_millis(100))
+        .unwrap_err();
+    assert_eq!(io::ErrorKind::TimedOut, error.kind());
+}
+
+#[test]
+fn title_is_set_without_control_characters() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+
+    let saved = terminal.save_title().unwrap();
+    terminal.set_title("foo\x07bar").unwrap();
+    terminal.restore_title(saved).unwrap();
+
+    let expected = b"\x1b[22;0t\x1b]2;foobar\x1b\\\x1b[23;0t";
+    let mut output = [0; 26];
+    controlling.read_exact(&mut output).unwrap();
+    assert_eq!(expected, &output);
+}
+
+#[test]
+fn attributes_can_be_modified() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+
+    let mut attributes = terminal.attributes().unwrap();
+    assert!(attributes.echo());
+    attributes.set_echo(false);
+    attributes.set_signals(false);
+    terminal.set_attributes(&attributes).unwrap();
+
+    let attributes = terminal.attributes().unwrap();
+    assert!(!attributes.echo());
+    assert!(!attributes.signals());
+    assert!(attributes.canonical());
+}
+
+#[test]
+fn control_chars_can_be_changed_and_disabled() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+
+    let mut attributes = terminal.attributes().unwrap();
+    assert_eq!(Some(0x03), attributes.control_char(ControlChar::Interrupt));
+    attributes.set_control_char(ControlChar::Interrupt, Some(0x07));
+    attributes.set_control_char(ControlChar::Suspend, None);
+    terminal.set_attributes(&attributes).unwrap();
+
+    let attributes = terminal.attributes().unwrap();
+    assert_eq!(Some(0x07), attributes.control_char(ControlChar::Interrupt));
+    assert_eq!(None, attributes.control_char(ControlChar::Suspend));
+}
+
+#[test]
+fn signal_generation_can_be_disabled() {
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let mut lock = terminal.lock();
+    lock.set_signal_generation(false).unwrap();
+
+    let attributes = lock.attributes().unwrap();
+    assert!(!attributes.signals());
+    assert!(attributes.canonical());
+}
+
+#[test]
+fn flow_control_can_be_disabled() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+
+    let mut attributes = terminal.attributes().unwrap();
+    assert!(attributes.flow_control());
+    attributes.set_flow_control(false);
+    terminal.set_attributes(&attributes).unwrap();
+    assert!(!terminal.attributes().unwrap().flow_control());
+}
+
+#[test]
+fn with_termios_applies_changes() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let fd = pty.user.as_fd();
+
+    let old_vintr = terminal
+        .with_termios(|termios| {
+            let old_vintr = termios.c_cc[libc::VINTR];
+            termios.c_cc[libc::VINTR] = 0x07;
+            old_vintr
+        })
+        .unwrap();
+    assert_ne!(0x07, old_vintr);
+    assert_eq!(0x07, attr::get_terminal_attr(fd).unwrap().c_cc[libc::VINTR]);
+}
+
+#[test]
+fn drain_returns_after_output_is_written() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    (&terminal).write_all(b"foo").unwrap();
+    terminal.drain().unwrap();
+}
+
+#[test]
+fn drain_fails_after_hangup() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    (&terminal).write_all(b"foo").unwrap();
+    drop(pty._controlling);
+    assert!(terminal.drain().is_err());
+}
+
+#[test]
+fn signals_are_forwarded_to_previously_installed_handler() {
+    static RECEIVED: AtomicBool = AtomicBool::new(false);
+    extern "C" fn previous_handler(_signal: c_int) {
+        RECEIVED.store(true, Ordering::SeqCst);
+    }
+
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let attributes = terminal.attributes().unwrap();
+    // SAFETY: The handler only touches an atomic.
+    unsafe {
+        libc::signal(
+            libc::SIGQUIT,
+            previous_handler as *const () as libc::sighandler_t,
+        )
+    };
+
+    install_restore_on_signals(terminal, attributes).unwrap();
+    // SAFETY: SIGQUIT is forwarded to our handler.
+    unsafe { libc::raise(libc::SIGQUIT) };
+    assert!(RECEIVED.load(Ordering::SeqCst));
+}
+
+#[test]
+fn restore_on_signals_leaves_ignored_signals_alone() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let attributes = terminal.attributes().unwrap();
+
+    // The handlers are only installed once per process, so we do it in a child.
+    // SAFETY: The child only calls async-signal-safe functions before exiting.
+    let pid = unsafe { libc::fork() };
+    if pid == 0 {
+        // SAFETY: See above.
+        unsafe {
+            let disposition = |signal| {
+                let mut action = mem::zeroed::<libc::sigaction>();
+                libc::sigaction(signal, std::ptr::null(), &mut action);
+                action.sa_sigaction
+            };
+            libc::signal(libc::SIGTERM, libc::SIG_IGN);
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            if install_restore_on_signals(terminal, attributes).is_err() {
+                libc::_exit(2);
+            }
+            if disposition(libc::SIGTERM) != libc::SIG_IGN
+                || disposition(libc::SIGINT) == libc::SIG_DFL
+            {
+                libc::_exit(1);
+            }
+            libc::_exit(0);
+        }
+    }
+
+    let mut status = 0;
+    // SAFETY: We wait for our own child.
+    assert_eq!(pid, unsafe { libc::waitpid(pid, &mut status, 0) });
+    assert!(
+        libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0,
+        "child failed: {status}"
+    );
+}
+
+#[test]
+fn restore_on_suspend_restores_mode_on_every_suspension() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let cooked = terminal.attributes().unwrap();
+    let fd = terminal.file.as_raw_fd();
+    let mut raw = cooked;
+    raw.set_canonical(false);
+    raw.set_echo(false);
+
+    // Suspending stops the whole process, so we do it in a child. The child gets
+    // its own process group as stop signals are ignored in orphaned process groups.
+    // SAFETY: The child only calls async-signal-safe functions before exiting.
+    let pid = unsafe { libc::fork() };
+    if pid == 0 {
+        // SAFETY: See above.
+        unsafe {
+            libc::setpgid(0, 0);
+            if install_restore_on_suspend(terminal, cooked).is_err()
+                || libc::tcsetattr(fd, libc::TCSANOW, &raw.0) != 0
+            {
+                libc::_exit(2);
+            }
+            for _ in 0..2 {
+                libc::raise(libc::SIGTSTP);
+                let mut termios = mem::zeroed::<termios>();
+                if libc::tcgetattr(fd, &mut termios) != 0 || termios.c_lflag & libc::ICANON != 0 {
+                    libc::_exit(1);
+                }
+            }
+            libc::_exit(0);
+        }
+    }
+
+    let mut status = 0;
+    for _ in 0..2 {
+        // SAFETY: We wait for our own child.
+        assert_eq!(pid, unsafe {
+            libc::waitpid(pid, &mut status, libc::WUNTRACED)
+        });
+        assert!(libc::WIFSTOPPED(status), "child did not stop: {status}");
+        assert!(attr::get_terminal_attr(pty.user.as_fd()).unwrap().c_lflag & libc::ICANON != 0);
+        // SAFETY: The child is stopped, so it's still around.
+        unsafe { libc::kill(pid, libc::SIGCONT) };
+    }
+    // SAFETY: We wait for our own child.
+    assert_eq!(pid, unsafe { libc::waitpid(pid, &mut status, 0) });
+    assert!(
+        libc::WIFEXITED(status) && libc::WEXITSTATUS(status) == 0,
+        "status: {status}"
+    );
+}
+
+/// Input written to the controlling side of a pty arrives asynchronously.
+fn wait_for_input(fd: BorrowedFd) {
+    let mut pollfd = libc::pollfd {
+        fd: fd.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // SAFETY: We pass exactly one valid pollfd.
+    let ready = unsafe { libc::poll(&mut pollfd, 1, 1000) };
+    assert_eq!(1, ready);
+}
+
+#[test]
+fn terminal_unrelated_to_stdio_is_not_stdio() {
+    let pty = pty_pair().unwrap();
+    let file = reopen_tty(pty.user.as_fd()).unwrap();
+    let terminal = Terminal::from_stdio(TerminalFile::Owned(file)).unwrap();
+    assert!(!terminal.is_stdin());
+    assert!(!terminal.is_stdout());
+    assert!(!terminal.is_stderr());
+    assert_eq!(Provenance::Reopened, terminal.provenance());
+}
+
+#[test]
+fn discovery_reopens_terminal_by_path() {
+    let pty = pty_pair().unwrap();
+    let discovery = Discovery {
+        source: Source::Reopened(tty_path(pty.user.as_fd()).unwrap()),
+        device: DeviceId::of(pty.user.as_fd()).unwrap(),
+        same_as_stdin: false,
+        same_as_stdout: false,
+        same_as_stderr: false,
+    };
+    let terminal = discovery.open().unwrap();
+    assert_eq!(Provenance::Reopened, terminal.provenance());
+    assert!(!terminal.is_stdin());
+    assert!(terminal.device.is_same_as(pty.user.as_fd()));
+}
+
+#[test]
+fn invalidate_discovery_looks_up_terminal_again() {
+    let pty = pty_pair().unwrap();
+    *cached_discovery() = Some(Discovery {
+        source: Source::Reopened(tty_path(pty.user.as_fd()).unwrap()),
+        device: DeviceId::of(pty.user.as_fd()).unwrap(),
+        same_as_stdin: false,
+        same_as_stdout: false,
+        same_as_stderr: false,
+    });
+    let terminal = super::terminal().unwrap();
+    assert!(terminal.device.is_same_as(pty.user.as_fd()));
+
+    invalidate_discovery();
+    assert!(cached_discovery().is_none());
+    // The pty is not one of our standard I/O streams nor our controlling terminal.
+    assert!(super::terminal().map_or(true, |t| !t.device.is_same_as(pty.user.as_fd())));
+    invalidate_discovery();
+}
+
+#[test]
+fn is_foreground_for_terminal_other_than_controlling_terminal() {
+    let pty = pty_pair().unwrap();
+    assert!(pty_terminal(&pty).is_foreground().unwrap());
+}
+
+#[test]
+fn path_is_path_of_device() {
+    let pty = pty_pair().unwrap();
+    let name = ttyname_r(pty.user.as_fd()).unwrap();
+    let path = pty_terminal(&pty).path().unwrap();
+    assert_eq!(OsStr::from_bytes(name.as_bytes()), path.as_os_str());
+}
+
+#[test]
+fn serial_settings_round_trip() {
+    let pty = pty_pair().unwrap();
+    let mut attributes = pty_terminal(&pty).attributes().unwrap();
+    attributes.set_baud_rate(115200).unwrap();
+    attributes.set_parity(Parity::Odd);
+    attributes.set_stop_bits(StopBits::Two);
+    attributes.set_hardware_flow_control(true);
+    attributes.set_flow_control(false);
+    assert_eq!(Some(115200), attributes.baud_rate());
+    assert_eq!(Parity::Odd, attributes.parity());
+    assert_eq!(StopBits::Two, attributes.stop_bits());
+    assert!(attributes.hardware_flow_control());
+    assert!(!attributes.flow_control());
+    assert!(attributes.set_baud_rate(12345).is_err());
+}
+
+#[test]
+fn lock_recovers_from_panicking_thread() {
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let panicked = std::thread::scope(|s| {
+        s.spawn(|| {
+            let _lock = terminal.lock();
+            panic!("panicking while holding the lock");
+        })
+        .join()
+        .is_err()
+    });
+    assert!(panicked);
+    terminal.lock().write_all(b"hello").unwrap();
+}
+
+#[test]
+fn lock_is_reentrant() {
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let mut outer = terminal.lock();
+    outer.unread(b"outer");
+    {
+        let mut inner = terminal.lock();
+        inner.write_all(b"hello").unwrap();
+        inner.unread(b"inner");
+    }
+    let mut buf = [0; 5];
+    outer.read_exact(&mut buf).unwrap();
+    assert_eq!(b"outer", &buf);
+    drop(outer);
+    let mut buf = [0; 5];
+    terminal.lock().read_exact(&mut buf).unwrap();
+    assert_eq!(b"inner", &buf);
+}
+
+#[test]
+fn lock_is_reentrant_when_terminal_is_stdin() {
+    let pty = pty_pair().unwrap();
+    let mut terminal = crate::Terminal(pty_terminal(&pty));
+    terminal.0.same_as_stdin = true;
+    terminal.0.same_as_stdout = true;
+    let mut outer = terminal.lock();
+    // Only the outermost lock takes the (non-reentrant) standard input lock.
+    terminal.lock().write_all(b"inner").unwrap();
+    terminal.lock_read().unread(b"read");
+    outer.write_all(b"outer").unwrap();
+}
+
+#[test]
+fn application_cursor_keys_are_reset_on_drop() {
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+
+    let mut lock = terminal.lock();
+    let mut guard = lock.enable_application_cursor_keys().unwrap();
+    guard.write_all(b"x").unwrap();
+    drop(guard);
+    assert_eq!(
+        b"\x1b[?1hx\x1b[?1l",
+        read_until(&mut controlling, b"\x1b[?1l").as_slice()
+    );
+}
+
+#[test]
+fn application_keypad_is_reset_on_panic() {
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+
+    let mut lock = terminal.lock();
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let _guard = lock.enable_application_keypad().unwrap();
+        panic!("drawing fails");
+    }));
+    assert!(result.is_err());
+    assert_eq!(
+        b"\x1b=\x1b>",
+        read_until(&mut controlling, b"\x1b>").as_slice()
+    );
+}
+
+#[test]
+fn read_char_decodes_characters_split_across_reads() {
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+
+    let mut lock = terminal.lock();
+    let mut guard = lock.enable_raw_mode().unwrap();
+    let writer = std::thread::spawn(move || {
+        let euro = "€".as_bytes();
+        controlling.write_all(&euro[..1]).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        controlling.write_all(&euro[1..]).unwrap();
+        controlling.write_all(b"\xffx").unwrap();
+    });
+    assert_eq!('€', guard.read_char().unwrap());
+    assert_eq!(char::REPLACEMENT_CHARACTER, guard.read_char().unwrap());
+    assert_eq!('x', guard.read_char().unwrap());
+    writer.join().unwrap();
+}
+
+#[test]
+fn devices_are_locked_and_buffered_separately() {
+    let first_pty = pty_pair().unwrap();
+    let second_pty = pty_pair().unwrap();
+    let first = crate::Terminal(pty_terminal(&first_pty));
+    let second = crate::Terminal(pty_terminal(&second_pty));
+    let mut first_lock = first.lock();
+    first_lock.unread(b"first");
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let mut lock = second.lock();
+            lock.unread(b"second");
+            let mut buf = [0; 6];
+            lock.read_exact(&mut buf).unwrap();
+            assert_eq!(b"second", &buf);
+        })
+        .join()
+        .unwrap();
+    });
+    let mut buf = [0; 5];
+    first_lock.read_exact(&mut buf).unwrap();
+    assert_eq!(b"first", &buf);
+}
+
+#[test]
+fn handles_to_same_device_share_input_buffer() {
+    let pty = pty_pair().unwrap();
+    let first = crate::Terminal(pty_terminal(&pty));
+    let second = crate::Terminal(pty_terminal(&pty));
+    first.lock().unread(b"hello");
+    let mut buf = [0; 5];
+    second.lock().read_exact(&mut buf).unwrap();
+    assert_eq!(b"hello", &buf);
+}
+
+#[test]
+fn writing_is_not_blocked_by_read_lock() {
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    let _read_lock = terminal.lock_read();
+    std::thread::scope(|s| {
+        s.spawn(|| terminal.lock_write().write_all(b"hello").unwrap())
+            .join()
+            .unwrap();
+    });
+    let mut buf = [0; 5];
+    controlling.read_exact(&mut buf).unwrap();
+    assert_eq!(b"hello", &buf);
+}
+
+#[test]
+fn read_lock_can_query_while_other_thread_writes() {
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(query_pty_terminal(&pty));
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    let mut read_lock = terminal.lock_read();
+    std::thread::scope(|s| {
+        s.spawn(|| terminal.lock_write().write_all(b"hello").unwrap())
+            .join()
+            .unwrap();
+        s.spawn(move || {
+            let mut buf = [0; 9];
+            controlling.read_exact(&mut buf).unwrap();
+            assert_eq!(b"hello\x1b[6n", &buf);
+            controlling.write_all(b"\x1b[1;2R").unwrap();
+        });
+        let response = read_lock
+            .query(b"\x1b[6n", |r| r.ends_with(b"R"), Duration::from_secs(5))
+            .unwrap();
+        assert_eq!(b"\x1b[1;2R", response.as_slice());
+    });
+}
+
+#[test]
+fn locking_input_after_output_acquires_the_input_half() {
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let attributes = terminal.lock().attributes().unwrap();
+    let _write_lock = terminal.lock_write();
+    terminal.lock().write_all(b"hello").unwrap();
+    terminal.restore(&attributes).unwrap();
+}
+
+#[test]
+fn reading_after_output_would_block_while_another_thread_holds_input() {
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let attributes = terminal.lock().attributes().unwrap();
+    let (locked, release) = (std::sync::Barrier::new(2), std::sync::Barrier::new(2));
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let _read_lock = terminal.lock_read();
+            locked.wait();
+            release.wait();
+        });
+        locked.wait();
+        let _write_lock = terminal.lock_write();
+        let error = (&terminal).read(&mut [0; 8]).unwrap_err();
+        assert_eq!(io::ErrorKind::WouldBlock, error.kind());
+        let error = terminal.restore(&attributes).unwrap_err();
+        assert_eq!(io::ErrorKind::WouldBlock, error.kind());
+        release.wait();
+    });
+}
+
+#[test]
+fn lock_terminal_only_does_not_lock_stdin() {
+    let pty = pty_pair().unwrap();
+    let mut terminal = crate::Terminal(pty_terminal(&pty));
+    // Pretend that the terminal is standard input.
+    terminal.0.same_as_stdin = true;
+    let _stdin = std::io::stdin().lock();
+    // This would deadlock with `lock` as the stdin lock is not reentrant.
+    terminal.lock_terminal_only().write_all(b"hello").unwrap();
+}
+
+#[test]
+fn lock_across_processes_excludes_other_open_file_descriptions() {
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let other = File::open(terminal.path().unwrap()).unwrap();
+    let try_lock = || {
+        // SAFETY: The file descriptor is valid.
+        to_io_result(unsafe { libc::flock(other.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) })
+    };
+
+    let guard = terminal.lock_across_processes().unwrap();
+    assert_eq!(io::ErrorKind::WouldBlock, try_lock().unwrap_err().kind());
+    drop(guard);
+    try_lock().unwrap();
+}
+
+#[cfg(feature = "events")]
+#[test]
+fn read_event_decodes_input_and_keeps_the_rest() {
+    use crate::events::{Event, KeyCode};
+
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    let mut lock = terminal.lock();
+    let mut raw_mode = lock.enable_raw_mode().unwrap();
+    controlling.write_all(b"\x1b[Ax\x1b").unwrap();
+    wait_for_input(pty.user.as_fd());
+
+    let mut read_key = || match raw_mode.read_event(Some(Duration::from_secs(1))) {
+        Ok(Some(Event::Key(key))) => key.code(),
+        other => panic!("expected key event, got {other:?}"),
+    };
+    assert_eq!(KeyCode::Up, read_key());
+    assert_eq!(KeyCode::Char('x'), read_key());
+    // A lone escape is reported once no sequence follows.
+    assert_eq!(KeyCode::Esc, read_key());
+    assert_eq!(
+        None,
+        raw_mode
+            .read_event(Some(Duration::from_millis(10)))
+            .unwrap()
+    );
+}
+
+#[cfg(feature = "events")]
+#[test]
+fn read_event_waits_for_rest_of_sequence_within_escape_timeout() {
+    use crate::events::{Event, KeyCode, ReadEventOptions};
+
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    let mut lock = terminal.lock();
+    let mut raw_mode = lock.enable_raw_mode().unwrap();
+    controlling.write_all(b"\x1b").unwrap();
+    wait_for_input(pty.user.as_fd());
+
+    let options = ReadEventOptions::new().escape_timeout(Duration::from_secs(5));
+    let event = std::thread::scope(|s| {
+        s.spawn(|| {
+            std::thread::sleep(Duration::from_millis(50));
+            controlling.write_all(b"[A").unwrap();
+        });
+        raw_mode.read_event_with(options).unwrap()
+    });
+    let Some(Event::Key(key)) = event else {
+        panic!("expected key event");
+    };
+    assert_eq!(KeyCode::Up, key.code());
+}
+
+#[cfg(feature = "events")]
+#[test]
+fn read_key_skips_other_events() {
+    use crate::events::KeyCode;
+
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    let mut lock = terminal.lock();
+    let mut raw_mode = lock.enable_raw_mode().unwrap();
+    // A focus event and a key release (kitty keyboard protocol) followed by Ctrl+Right.
+    controlling
+        .write_all(b"\x1b[I\x1b[97;1:3u\x1b[1;5C")
+        .unwrap();
+    wait_for_input(pty.user.as_fd());
+
+    let key = raw_mode.read_key().unwrap();
+    assert_eq!(KeyCode::Right, key.code());
+    assert!(key.ctrl());
+}
+
+#[test]
+fn readiness_reports_available_input() {
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    // Input is only readable after a newline in canonical mode.
+    let _raw_mode = terminal.0.enable_raw_mode(&RawModeOptions::new()).unwrap();
+    let readiness = terminal.readiness();
+    assert_eq!(terminal.as_raw_fd(), readiness.as_raw_fd());
+
+    assert!(!readiness.wait(Some(Duration::from_millis(10))).unwrap());
+    controlling.write_all(b"x").unwrap();
+    assert!(readiness.wait(Some(Duration::from_secs(1))).unwrap());
+}
+
+#[test]
+fn reads_are_retried_when_interrupted_unless_disabled() {
+    extern "C" fn ignore(_signal: c_int) {}
+    // SAFETY: The handler does nothing. `SA_RESTART` is not set, so blocking reads fail with `EINTR`.
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = ignore as *const () as libc::sighandler_t;
+        libc::sigaction(libc::SIGUSR2, &action, std::ptr::null_mut());
+    }
+
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let controlling = File::from(pty._controlling.try_clone().unwrap());
+    let _guard = terminal.enable_raw_mode(&RawModeOptions::new()).unwrap();
+    let read_while_signaled = |input: Option<&[u8]>| {
+        let done = AtomicBool::new(false);
+        // SAFETY: Always safe to call.
+        let reader = unsafe { libc::pthread_self() };
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                for _ in 0..5 {
+                    std::thread::sleep(Duration::from_millis(20));
+                    // SAFETY: The reading thread outlives this thread.
+                    unsafe { libc::pthread_kill(reader, libc::SIGUSR2) };
+                }
+                if let Some(input) = input {
+                    (&controlling).write_all(input).unwrap();
+                }
+                while !done.load(Ordering::SeqCst) {
+                    std::thread::sleep(Duration::from_millis(20));
+                    // SAFETY: The reading thread outlives this thread.
+                    unsafe { libc::pthread_kill(reader, libc::SIGUSR2) };
+                }
+            });
+            let result = (&terminal).read(&mut [0; 8]);
+            done.store(true, Ordering::SeqCst);
+            result
+        })
+    };
+
+    assert_eq!(1, read_while_signaled(Some(b"x")).unwrap());
+    terminal.set_retry_interrupted(false);
+    let error = read_while_signaled(None).unwrap_err();
+    assert_eq!(io::ErrorKind::Interrupted, error.kind());
+}
+
+#[test]
+fn write_timeout_gives_up_when_output_is_not_read() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let timeout = Duration::from_millis(50);
+    // Nobody reads from the controlling side, so the output queue eventually fills up.
+    let buf = vec![b'x'; 1 << 20];
+    let written = terminal.write_timeout(&buf, timeout).unwrap();
+    assert!(written > 0 && written < buf.len());
+    let start = Instant::now();
+    let error = terminal.write_timeout(&buf, timeout).unwrap_err();
+    assert_eq!(io::ErrorKind::TimedOut, error.kind());
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn write_timeout_leaves_terminal_blocking() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    terminal
+        .write_timeout(b"hello", Duration::from_millis(50))
+        .unwrap();
+    // SAFETY: The file descriptor is valid.
+    let flags = unsafe { fcntl(terminal.file.as_raw_fd(), F_GETFL) };
+    assert_eq!(0, flags & libc::O_NONBLOCK);
+}
+
+#[test]
+fn write_timeout_opens_device_once() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let timeout = Duration::from_millis(50);
+    terminal.write_timeout(b"foo", timeout).unwrap();
+    let file = terminal.nonblocking_file.get().unwrap().as_ref().unwrap();
+    let fd = file.as_raw_fd();
+    terminal.write_timeout(b"bar", timeout).unwrap();
+    let file = terminal.nonblocking_file.get().unwrap().as_ref().unwrap();
+    assert_eq!(fd, file.as_raw_fd());
+}
+
+#[test]
+fn write_timeout_falls_back_to_blocking_writes() {
+    let pty = pty_pair().unwrap();
+    // As if the device could not be opened again.
+    let terminal = Terminal {
+        nonblocking_file: OnceLock::from(None),
+        ..pty_terminal(&pty)
+    };
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    assert_eq!(
+        5,
+        terminal
+            .write_timeout(b"hello", Duration::from_millis(50))
+            .unwrap()
+    );
+    let mut buf = [0; 5];
+    controlling.read_exact(&mut buf).unwrap();
+    assert_eq!(b"hello", &buf);
+}
+
+#[test]
+fn resume_output_restarts_stopped_output() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    // SAFETY: File descriptor is valid.
+    unsafe { libc::tcflow(terminal.file.as_raw_fd(), libc::TCOOFF) };
+
+    std::thread::scope(|s| {
+        // Writes block while output is stopped.
+        s.spawn(|| (&terminal).write_all(b"x").unwrap());
+        let deadline = Instant::now() + Duration::from_millis(50);
+        assert!(!poll(controlling.as_fd(), libc::POLLIN, Some(deadline)).unwrap());
+        terminal.resume_output().unwrap();
+    });
+    let mut buf = [0; 1];
+    controlling.read_exact(&mut buf).unwrap();
+    assert_eq!(b"x", &buf);
+}
+
+#[test]
+fn set_size_resizes_terminal() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    terminal.set_size(24, 80).unwrap();
+
+    // SAFETY: An all-zero winsize is valid.
+    let mut size: libc::winsize = unsafe { mem::zeroed() };
+    // SAFETY: File descriptor is valid and we pass a pointer to a valid winsize.
+    unsafe { libc::ioctl(pty.user.as_raw_fd(), libc::TIOCGWINSZ, &mut size) };
+    assert_eq!((24, 80), (size.ws_row, size.ws_col));
+}
+
+#[test]
+fn terminal_state_restores_attributes() {
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let mut lock = terminal.lock();
+    let state = crate::TerminalState::capture(&mut lock).unwrap();
+    let state = crate::TerminalState::from_bytes(&state.to_bytes()).unwrap();
+
+    let mut attributes = lock.attributes().unwrap();
+    attributes.set_echo(false);
+    lock.set_attributes(&attributes).unwrap();
+
+    lock.restore(&state).unwrap();
+    assert!(lock.attributes().unwrap().echo());
+}
+
+#[test]
+fn terminal_state_diff_lists_changed_flags() {
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let mut lock = terminal.lock();
+    let before = crate::TerminalState::capture(&mut lock).unwrap();
+    assert!(before.diff(&before).is_empty());
+
+    let mut attributes = lock.attributes().unwrap();
+    attributes.set_echo(false);
+    attributes.set_canonical(false);
+    lock.set_attributes(&attributes).unwrap();
+
+    let after = crate::TerminalState::capture(&mut lock).unwrap();
+    assert_eq!("-ICANON -ECHO", before.diff(&after).to_string());
+}
+
+#[test]
+fn external_changes_are_detected() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let raw_mode = terminal.enable_raw_mode(&RawModeOptions::new()).unwrap();
+    assert!(!raw_mode.has_external_changes().unwrap());
+
+    let mut attributes = terminal.attributes().unwrap();
+    attributes.set_signals(false);
+    terminal.set_attributes(&attributes).unwrap();
+    assert!(raw_mode.has_external_changes().unwrap());
+}
+
+#[test]
+fn external_changes_are_kept() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let options = RawModeOptions::new().external_changes(ExternalModeChanges::Keep);
+    let raw_mode = terminal.enable_raw_mode(&options).unwrap();
+
+    let mut attributes = terminal.attributes().unwrap();
+    attributes.set_signals(false);
+    terminal.set_attributes(&attributes).unwrap();
+    drop(raw_mode);
+
+    let attributes = terminal.attributes().unwrap();
+    assert!(!attributes.echo());
+    assert!(!attributes.signals());
+}
+
+#[test]
+fn external_changes_are_merged() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let options = RawModeOptions::new().external_changes(ExternalModeChanges::Merge);
+    let raw_mode = terminal.enable_raw_mode(&options).unwrap();
+
+    let mut attributes = terminal.attributes().unwrap();
+    attributes.set_signals(false);
+    terminal.set_attributes(&attributes).unwrap();
+    drop(raw_mode);
+
+    let attributes = terminal.attributes().unwrap();
+    assert!(attributes.echo());
+    assert!(attributes.canonical());
+    assert!(!attributes.signals());
+}
+
+#[test]
+fn soft_reset_restores_sane_attributes() {
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let mut lock = terminal.lock();
+    let mut attributes = lock.attributes().unwrap();
+    attributes.set_echo(false);
+    attributes.set_canonical(false);
+    lock.set_attributes(&attributes).unwrap();
+
+    lock.soft_reset().unwrap();
+
+    let attributes = lock.attributes().unwrap();
+    assert!(attributes.echo());
+    assert!(attributes.canonical());
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    let mut buf = [0; 4];
+    controlling.read_exact(&mut buf).unwrap();
+    assert_eq!(b"\x1b[!p", &buf);
+}
+
+#[test]
+fn mode_stack_reverts_modes_in_reverse_order() {
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let mut controlling = File::from(pty._controlling.try_clone().unwrap());
+    let mut lock = terminal.lock();
+    let options = crate::ModeStackOptions::new()
+        .raw_mode(RawModeOptions::new())
+        .alternate_screen(true)
+        .hide_cursor(true);
+
+    let mut modes = lock.enable_modes(options).unwrap();
+    assert!(modes.raw_mode().is_some());
+    assert!(!terminal.0.attributes().unwrap().echo());
+    drop(modes);
+    assert!(terminal.0.attributes().unwrap().echo());
+
+    let expected = b"\x1b[?1049h\x1b[?25l\x1b[?25h\x1b[?1049l";
+    let mut buf = [0; 28];
+    controlling.read_exact(&mut buf).unwrap();
+    assert_eq!(expected, &buf);
+}
+
+#[test]
+fn owned_raw_mode_guard_restores_mode() {
+    let pty = pty_pair().unwrap();
+    let terminal = std::sync::Arc::new(crate::Terminal(pty_terminal(&pty)));
+
+    let guard = crate::OwnedRawModeGuard::new(terminal.clone(), RawModeOptions::new()).unwrap();
+    assert!(!terminal.0.attributes().unwrap().echo());
+    drop(guard);
+    assert!(terminal.0.attributes().unwrap().echo());
+
+    let guard = crate::OwnedRawModeGuard::new(terminal, RawModeOptions::new()).unwrap();
+    let terminal = guard.disable().unwrap();
+    assert!(terminal.0.attributes().unwrap().echo());
+}
+
+#[test]
+fn owned_raw_mode_guard_can_be_dropped_on_another_thread() {
+    let pty = pty_pair().unwrap();
+    let terminal = std::sync::Arc::new(crate::Terminal(pty_terminal(&pty)));
+
+    let guard = crate::OwnedRawModeGuard::new(terminal.clone(), RawModeOptions::new()).unwrap();
+    std::thread::spawn(move || drop(guard)).join().unwrap();
+    assert!(terminal.0.attributes().unwrap().echo());
+}
+
+#[test]
+fn run_child_restores_attributes_changed_by_child() {
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let mut lock = terminal.lock();
+    let stdin = File::from(pty.user.try_clone().unwrap());
+
+    let status = lock
+        .run_child(std::process::Command::new("stty").arg("-echo").stdin(stdin))
+        .unwrap();
+    assert!(status.success());
+    assert!(lock.attributes().unwrap().echo());
+}
+
+#[test]
+fn run_child_runs_child_in_cooked_mode() {
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let mut lock = terminal.lock();
+    _ = lock.enable_raw_mode().unwrap().persist();
+    let stdin = File::from(pty.user.try_clone().unwrap());
+
+    let status = lock
+        .run_child(
+            std::process::Command::new("sh")
+                .args([
+                    "-c",
+                    "stty -a | grep -q -- ' icanon' && stty -a | grep -q -- ' echo '",
+                ])
+                .stdin(stdin),
+        )
+        .unwrap();
+    assert!(status.success());
+    assert!(!lock.attributes().unwrap().canonical());
+}
+
+#[test]
+fn raw_mode_run_child_restores_original_mode_while_running() {
+    let pty = pty_pair().unwrap();
+    let terminal = crate::Terminal(pty_terminal(&pty));
+    let mut lock = terminal.lock();
+    let mut raw_mode = lock.enable_raw_mode().unwrap();
+    let stdin = File::from(pty.user.try_clone().unwrap());
+
+    // The child sees canonical mode (`icanon` rather than `-icanon`) as the original mode is restored.
+    let status = raw_mode
+        .run_child(
+            std::process::Command::new("sh")
+                .args(["-c", "stty -a | grep -q -- ' icanon'"])
+                .stdin(stdin),
+        )
+        .unwrap();
+    assert!(status.success());
+    assert!(!terminal.0.attributes().unwrap().canonical());
+}
+
+#[test]
+fn terminal_is_not_inherited_by_default() {
+    let pty = pty_pair().unwrap();
+    let terminal = pty_terminal(&pty);
+    let fd_flags = || {
+        // SAFETY: File descriptor is valid.
+        unsafe { fcntl(terminal.file.as_raw_fd(), F_GETFD) }
+    };
+    assert_ne!(0, fd_flags() & FD_CLOEXEC);
+
+    terminal.set_inheritable(true).unwrap();
+    assert_eq!(0, fd_flags() & FD_CLOEXEC);
+    terminal.set_inheritable(false).unwrap();
+    assert_ne!(0, fd_flags() & FD_CLOEXEC);
+}
+
+fn pty_terminal(pty: &pty_utils::PtyPair) -> Terminal {
+    let file = reopen_tty(pty.user.as_fd()).unwrap();
+    Terminal::from_controlling(TerminalFile::Owned(file)).unwrap()
+}
+
+/// Like [`pty_terminal`] but exempt from the query policy,
+/// which would otherwise look at the environment of the terminal running the tests.
+fn query_pty_terminal(pty: &pty_utils::PtyPair) -> Terminal {
+    Terminal {
+        provenance: Provenance::Pty,
+        ..pty_terminal(pty)
+    }
 }
@@ -1,48 +1,240 @@
-use crate::StdioLocks;
-use core::fmt;
-use std::error;
+use crate::lock::DeviceState;
+use crate::{
+    ControlChar, ModeChangeTiming, Provenance, Queue, RawModeOptions, StdioLocks, TerminalKind,
+};
+use std::ops::Deref;
+use std::path::PathBuf;
+use std::time::Duration;
 use std::{io, marker::PhantomData};
 
-pub(crate) fn terminal() -> io::Result<Terminal> {
-    Err(io::Error::new(io::ErrorKind::Unsupported, UnsupportedError))
+pub(crate) fn terminal() -> Result<Terminal, crate::Error> {
+    Err(crate::Error::Unsupported {
+        reason: "this platform is not supported",
+    })
 }
 
-#[derive(Debug)]
-struct UnsupportedError;
+pub(crate) fn invalidate_discovery() {}
 
-impl fmt::Display for UnsupportedError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "this platform is not supported")
-    }
+pub(crate) fn install_restore_on_signals(
+    _terminal: Terminal,
+    _attributes: TerminalAttributes,
+) -> io::Result<()> {
+    unreachable!()
 }
 
-impl error::Error for UnsupportedError {}
-
 #[derive(Debug)]
 pub(crate) struct Terminal {}
 
-impl io::Write for Terminal {
+impl io::Write for &Terminal {
     fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
         unreachable!()
     }
 
+    fn write_vectored(&mut self, _bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        unreachable!()
+    }
+
     fn flush(&mut self) -> io::Result<()> {
         unreachable!()
     }
 }
 
-impl io::Read for Terminal {
+impl io::Read for &Terminal {
     fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
         unreachable!()
     }
+
+    fn read_vectored(&mut self, _bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        unreachable!()
+    }
 }
 
 impl Terminal {
-    pub(crate) fn lock_stdio(&mut self) -> StdioLocks {
+    pub(crate) fn provenance(&self) -> Provenance {
+        unreachable!()
+    }
+
+    pub(crate) fn is_foreground(&self) -> io::Result<bool> {
+        unreachable!()
+    }
+
+    pub(crate) fn path(&self) -> io::Result<PathBuf> {
+        unreachable!()
+    }
+
+    pub(crate) fn set_inheritable(&self, _inheritable: bool) -> io::Result<()> {
+        unreachable!()
+    }
+
+    pub(crate) fn device_state(&self) -> &DeviceState {
         unreachable!()
     }
 
-    pub(crate) fn enable_raw_mode(&mut self) -> io::Result<RawModeGuard<'_>> {
+    pub(crate) fn is_stdin(&self) -> bool {
+        unreachable!()
+    }
+
+    pub(crate) fn is_stdout(&self) -> bool {
+        unreachable!()
+    }
+
+    pub(crate) fn is_stderr(&self) -> bool {
+        unreachable!()
+    }
+
+    pub(crate) fn lock_stdin(&self) -> StdioLocks {
+        unreachable!()
+    }
+
+    pub(crate) fn lock_stdout(&self) -> StdioLocks {
+        unreachable!()
+    }
+
+    pub(crate) fn kind(&self) -> TerminalKind {
+        unreachable!()
+    }
+
+    pub(crate) fn wait_for_input(&self, _timeout: Option<Duration>) -> io::Result<bool> {
+        unreachable!()
+    }
+
+    pub(crate) fn resume_output(&self) -> io::Result<()> {
+        unreachable!()
+    }
+
+    pub(crate) fn set_size(&self, _rows: u16, _columns: u16) -> io::Result<()> {
+        unreachable!()
+    }
+
+    pub(crate) fn write_timeout(&self, _buf: &[u8], _timeout: Duration) -> io::Result<usize> {
+        unreachable!()
+    }
+
+    pub(crate) fn set_retry_interrupted(&self, _retry: bool) {
+        unreachable!()
+    }
+
+    pub(crate) fn interruptible(&self) -> impl io::Read + '_ {
+        self
+    }
+
+    pub(crate) fn enable_raw_mode(
+        &self,
+        _options: &RawModeOptions,
+    ) -> io::Result<RawModeGuard<'_>> {
+        unreachable!()
+    }
+
+    pub(crate) fn discard(&self, _queue: Queue) -> io::Result<()> {
+        unreachable!()
+    }
+
+    pub(crate) fn drain(&self) -> io::Result<()> {
+        unreachable!()
+    }
+
+    pub(crate) fn attributes(&self) -> io::Result<TerminalAttributes> {
+        unreachable!()
+    }
+
+    pub(crate) fn set_attributes(&self, _attributes: &TerminalAttributes) -> io::Result<()> {
+        unreachable!()
+    }
+
+    pub(crate) fn set_attributes_with(
+        &self,
+        _attributes: &TerminalAttributes,
+        _timing: ModeChangeTiming,
+    ) -> io::Result<()> {
+        unreachable!()
+    }
+
+    pub(crate) fn set_title(&self, _title: &str) -> io::Result<()> {
+        unreachable!()
+    }
+
+    pub(crate) fn save_title(&self) -> io::Result<SavedTitle> {
+        unreachable!()
+    }
+
+    pub(crate) fn restore_title(&self, _saved: SavedTitle) -> io::Result<()> {
+        unreachable!()
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct SavedTitle;
+
+#[derive(Debug, Clone)]
+pub(crate) struct TerminalAttributes {}
+
+impl TerminalAttributes {
+    #[cfg(feature = "test-util")]
+    pub(crate) fn enable_raw_mode(&mut self, _options: &RawModeOptions) {
+        unreachable!()
+    }
+
+    pub(crate) fn echo(&self) -> bool {
+        unreachable!()
+    }
+
+    pub(crate) fn set_echo(&mut self, _enabled: bool) {
+        unreachable!()
+    }
+
+    pub(crate) fn canonical(&self) -> bool {
+        unreachable!()
+    }
+
+    pub(crate) fn set_canonical(&mut self, _enabled: bool) {
+        unreachable!()
+    }
+
+    pub(crate) fn signals(&self) -> bool {
+        unreachable!()
+    }
+
+    pub(crate) fn set_signals(&mut self, _enabled: bool) {
+        unreachable!()
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        unreachable!()
+    }
+
+    pub(crate) fn from_bytes(_bytes: &[u8]) -> Option<Self> {
+        None
+    }
+
+    pub(crate) fn control_char(&self, _control_char: ControlChar) -> Option<u8> {
+        unreachable!()
+    }
+
+    pub(crate) fn set_control_char(&mut self, _control_char: ControlChar, _value: Option<u8>) {
+        unreachable!()
+    }
+
+    pub(crate) fn make_sane(&mut self) {
+        unreachable!()
+    }
+
+    pub(crate) fn flags(&self) -> Vec<(&'static str, bool)> {
+        unreachable!()
+    }
+
+    pub(crate) fn flow_control(&self) -> bool {
+        unreachable!()
+    }
+
+    pub(crate) fn set_flow_control(&mut self, _enabled: bool) {
+        unreachable!()
+    }
+
+    pub(crate) fn virtual_terminal_processing(&self) -> bool {
+        unreachable!()
+    }
+
+    pub(crate) fn set_virtual_terminal_processing(&mut self, _enabled: bool) {
         unreachable!()
     }
 }
@@ -50,11 +242,49 @@ impl Terminal {
 #[derive(Debug)]
 pub(crate) struct RawModeGuard<'a>(PhantomData<&'a ()>);
 
+impl Deref for RawModeGuard<'_> {
+    type Target = Terminal;
+
+    fn deref(&self) -> &Self::Target {
+        unreachable!()
+    }
+}
+
+impl<'a> RawModeGuard<'a> {
+    pub(crate) fn terminal(&self) -> &'a Terminal {
+        unreachable!()
+    }
+
+    pub(crate) fn original_attributes(&self) -> TerminalAttributes {
+        unreachable!()
+    }
+
+    pub(crate) fn persist(self) -> TerminalAttributes {
+        unreachable!()
+    }
+
+    pub(crate) fn set_read_behavior(
+        &mut self,
+        _min: u8,
+        _timeout: Option<Duration>,
+    ) -> io::Result<()> {
+        unreachable!()
+    }
+
+    pub(crate) fn has_external_changes(&self) -> io::Result<bool> {
+        unreachable!()
+    }
+}
+
 impl io::Write for RawModeGuard<'_> {
     fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
         unreachable!()
     }
 
+    fn write_vectored(&mut self, _bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        unreachable!()
+    }
+
     fn flush(&mut self) -> io::Result<()> {
         unreachable!()
     }
@@ -64,4 +294,8 @@ impl io::Read for RawModeGuard<'_> {
     fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
         unreachable!()
     }
+
+    fn read_vectored(&mut self, _bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        unreachable!()
+    }
 }
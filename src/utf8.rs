@@ -0,0 +1,93 @@
+use std::char::REPLACEMENT_CHARACTER;
+use std::io;
+
+/// Reads a single UTF-8 encoded character, consuming no input after it.
+///
+/// Invalid input is decoded as [`REPLACEMENT_CHARACTER`]. Only the bytes up to
+/// the first byte that doesn't continue the sequence are consumed, so that
+/// a truncated sequence doesn't swallow the character following it.
+pub(crate) fn read_char(reader: &mut impl io::BufRead) -> io::Result<char> {
+    let first = peek_byte(reader)?.ok_or(io::ErrorKind::UnexpectedEof)?;
+    reader.consume(1);
+    let len = match first {
+        0x00..=0x7f => return Ok(char::from(first)),
+        0xc2..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf4 => 4,
+        _ => return Ok(REPLACEMENT_CHARACTER),
+    };
+
+    let mut bytes = [first, 0, 0, 0];
+    for byte in &mut bytes[1..len] {
+        match peek_byte(reader)? {
+            Some(b) if b & 0xc0 == 0x80 => {
+                *byte = b;
+                reader.consume(1);
+            }
+            _ => return Ok(REPLACEMENT_CHARACTER),
+        }
+    }
+    // Rejects overlong encodings and surrogates.
+    Ok(std::str::from_utf8(&bytes[..len])
+        .ok()
+        .and_then(|s| s.chars().next())
+        .unwrap_or(REPLACEMENT_CHARACTER))
+}
+
+fn peek_byte(reader: &mut impl io::BufRead) -> io::Result<Option<u8>> {
+    loop {
+        match reader.fill_buf() {
+            Ok(buf) => return Ok(buf.first().copied()),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+
+    fn read_all(mut input: &[u8]) -> String {
+        let mut text = String::new();
+        while !input.is_empty() {
+            text.push(read_char(&mut input).unwrap());
+        }
+        text
+    }
+
+    #[test]
+    fn reads_single_character() {
+        let mut input = "äb".as_bytes();
+        assert_eq!('ä', read_char(&mut input).unwrap());
+        assert_eq!(b"b", input);
+    }
+
+    #[test]
+    fn reads_multi_byte_characters() {
+        assert_eq!("a€🦀", read_all("a€🦀".as_bytes()));
+    }
+
+    #[test]
+    fn replaces_invalid_bytes() {
+        assert_eq!("\u{fffd}a\u{fffd}", read_all(b"\xffa\x80"));
+    }
+
+    #[test]
+    fn truncated_sequence_keeps_following_character() {
+        assert_eq!("\u{fffd}a", read_all(b"\xe2\x82a"));
+    }
+
+    #[test]
+    fn replaces_surrogates() {
+        assert_eq!("\u{fffd}", read_all(b"\xed\xa0\x80"));
+    }
+
+    #[test]
+    fn fails_on_empty_input() {
+        let error = read_char(&mut &b""[..]).unwrap_err();
+        assert_eq!(io::ErrorKind::UnexpectedEof, error.kind());
+    }
+}
@@ -1,59 +1,184 @@
+pub(crate) use self::console_mode::TerminalAttributes;
 use self::console_mode::{get_console_mode, set_console_mode};
-use crate::{ConsoleHandles, StdioLocks};
-use core::fmt;
+use crate::lock::DeviceState;
+use crate::raw_mode::{RawModeRefCounts, Restore};
+use crate::{
+    ConsoleFallback, ConsoleHandles, ExternalModeChanges, ModeChangeTiming, Provenance, Queue,
+    RawModeOptions, StdioLocks, TerminalKind,
+};
 use msys::msys_tty_on;
-use std::error;
 use std::fs::{File, OpenOptions};
 use std::io::{self, IsTerminal};
-use std::mem::ManuallyDrop;
-use std::os::windows::io::{AsHandle, AsRawHandle, BorrowedHandle, FromRawHandle, RawHandle};
-use windows_sys::Win32::Foundation::{CompareObjectHandles, BOOL};
-use windows_sys::Win32::System::Console::CONSOLE_MODE;
-
+use std::iter;
+use std::mem;
+use std::ops::Deref;
+use std::os::windows::io::{AsHandle, AsRawHandle, BorrowedHandle, RawHandle};
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+use windows_sys::Win32::Foundation::{
+    GetLastError, SetHandleInformation, SetLastError, BOOL, ERROR_FILE_NOT_FOUND,
+    ERROR_INVALID_HANDLE, ERROR_SUCCESS, HANDLE, HANDLE_FLAG_INHERIT, HWND, WAIT_OBJECT_0,
+    WAIT_TIMEOUT,
+};
+use windows_sys::Win32::Storage::FileSystem::FlushFileBuffers;
+use windows_sys::Win32::System::Console::{
+    AllocConsole, AttachConsole, FlushConsoleInputBuffer, GetConsoleTitleW, GetConsoleWindow,
+    SetConsoleTitleW, ATTACH_PARENT_PROCESS, CONSOLE_MODE,
+};
+use windows_sys::Win32::System::Threading::{WaitForSingleObject, INFINITE};
+
+mod code_page;
+pub use code_page::CodePageGuard;
+pub(crate) use code_page::{input_code_page, output_code_page};
 mod console_mode;
+mod console_read;
+use console_read::ConsoleReader;
+mod input_events;
+pub use input_events::{InputEvent, KeyEvent, MouseEvent};
 mod msys;
-
-pub(crate) fn terminal() -> io::Result<Terminal> {
-    let conin = conin()?;
-    let conout = conout()?;
-    let conin_same_as_stdin = compare_object_handles(conin.as_handle(), io::stdin());
-    let conout_same_as_stdout = compare_object_handles(conout.as_handle(), io::stdout());
-    let conout_same_as_stderr = compare_object_handles(conout.as_handle(), io::stderr());
+mod screen_buffer;
+pub use screen_buffer::ScreenBufferInfo;
+mod signals;
+pub(crate) use signals::install_restore_on_signals;
+mod vt_processing;
+pub use vt_processing::VirtualTerminalProcessingGuard;
+
+pub(crate) fn terminal() -> Result<Terminal, crate::Error> {
+    let mut cached = cached_discovery();
+    let (conin_source, conout_source) = match *cached {
+        Some(discovery) => (discovery.conin, discovery.conout),
+        None => (conin_source(), conout_source()),
+    };
+    let conin = conin(conin_source).map_err(console_error)?;
+    let conout = conout(conout_source).map_err(console_error)?;
+    let discovery = *cached.get_or_insert_with(|| Discovery {
+        conin: conin_source,
+        conout: conout_source,
+        conin_same_as_stdin: is_same_as_stdio(&conin, conin_source, StdioStream::Stdin),
+        conout_same_as_stdout: is_same_as_stdio(&conout, conout_source, StdioStream::Stdout),
+        conout_same_as_stderr: is_same_as_stdio(&conout, conout_source, StdioStream::Stderr),
+        // MSYS/Cygwin terminals are pipes, we can only read bytes from them.
+        conin_is_console: get_console_mode(conin.as_handle()).is_ok(),
+    });
     Ok(Terminal {
         conin,
+        conin_reader: discovery
+            .conin_is_console
+            .then(|| Mutex::new(ConsoleReader::default())),
         conout,
-        conin_same_as_stdin,
-        conout_same_as_stdout,
-        conout_same_as_stderr,
+        conin_same_as_stdin: discovery.conin_same_as_stdin,
+        conout_same_as_stdout: discovery.conout_same_as_stdout,
+        conout_same_as_stderr: discovery.conout_same_as_stderr,
     })
 }
 
-fn conin() -> io::Result<ConsoleBuffer> {
-    ConsoleBuffer::try_borrow(io::stdin())
-        .map(Ok)
-        .unwrap_or_else(|| {
-            OpenOptions::new()
-                .read(true)
-                .open("CONIN$")
-                .map(ConsoleBuffer::Owned)
-        })
+pub(crate) fn invalidate_discovery() {
+    cached_discovery().take();
 }
 
-fn conout() -> io::Result<ConsoleBuffer> {
-    ConsoleBuffer::try_borrow(io::stderr())
-        .or_else(|| ConsoleBuffer::try_borrow(io::stdout()))
-        .map(Ok)
-        .unwrap_or_else(|| {
-            OpenOptions::new()
-                .write(true)
-                .open("CONOUT$")
-                .map(ConsoleBuffer::Owned)
-        })
+static DISCOVERY: Mutex<Option<Discovery>> = Mutex::new(None);
+
+fn cached_discovery() -> MutexGuard<'static, Option<Discovery>> {
+    DISCOVERY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Where [`terminal`] found the console buffers, so that subsequent calls
+/// only need to duplicate or open the two handles.
+#[derive(Debug, Clone, Copy)]
+struct Discovery {
+    conin: Source,
+    conout: Source,
+    conin_same_as_stdin: bool,
+    conout_same_as_stdout: bool,
+    conout_same_as_stderr: bool,
+    conin_is_console: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Source {
+    Stdio(StdioStream),
+    /// `CONIN$` or `CONOUT$`.
+    ConsoleBuffer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StdioStream {
+    Stdin,
+    Stdout,
+    Stderr,
+}
+
+pub(crate) fn attach_console(fallback: ConsoleFallback) -> io::Result<()> {
+    // SAFETY: This function has no preconditions.
+    let attached = to_io_result(unsafe { AttachConsole(ATTACH_PARENT_PROCESS) });
+    match fallback {
+        ConsoleFallback::AttachParent => attached,
+        // SAFETY: This function has no preconditions.
+        ConsoleFallback::AttachParentOrAllocate => {
+            attached.or_else(|_| to_io_result(unsafe { AllocConsole() }))
+        }
+    }
+}
+
+pub(crate) fn console_window() -> Option<HWND> {
+    // SAFETY: This function has no preconditions.
+    let window = unsafe { GetConsoleWindow() };
+    (!window.is_null()).then_some(window)
+}
+
+fn conin_source() -> Source {
+    if io::stdin().is_terminal() {
+        Source::Stdio(StdioStream::Stdin)
+    } else {
+        Source::ConsoleBuffer
+    }
+}
+
+fn conout_source() -> Source {
+    if io::stderr().is_terminal() {
+        Source::Stdio(StdioStream::Stderr)
+    } else if io::stdout().is_terminal() {
+        Source::Stdio(StdioStream::Stdout)
+    } else {
+        Source::ConsoleBuffer
+    }
+}
+
+/// The console buffers can only be opened if the process has a console,
+/// opening them fails with `ERROR_INVALID_HANDLE` otherwise.
+fn console_error(error: io::Error) -> crate::Error {
+    match error.raw_os_error().map(|code| code as u32) {
+        Some(ERROR_INVALID_HANDLE | ERROR_FILE_NOT_FOUND) => crate::Error::NotATty,
+        _ => crate::Error::Io(error),
+    }
+}
+
+fn conin(source: Source) -> io::Result<ConsoleBuffer> {
+    match source {
+        Source::ConsoleBuffer => OpenOptions::new()
+            .read(true)
+            .open("CONIN$")
+            .map(ConsoleBuffer::Owned),
+        Source::Stdio(stream) => ConsoleBuffer::duplicate(stream),
+    }
+}
+
+fn conout(source: Source) -> io::Result<ConsoleBuffer> {
+    match source {
+        Source::ConsoleBuffer => OpenOptions::new()
+            .write(true)
+            .open("CONOUT$")
+            .map(ConsoleBuffer::Owned),
+        Source::Stdio(stream) => ConsoleBuffer::duplicate(stream),
+    }
 }
 
 #[derive(Debug)]
 pub(crate) struct Terminal {
     conin: ConsoleBuffer,
+    // Only present if the input buffer is a console.
+    conin_reader: Option<Mutex<ConsoleReader>>,
     conout: ConsoleBuffer,
     conin_same_as_stdin: bool,
     conout_same_as_stdout: bool,
@@ -63,30 +188,41 @@ pub(crate) struct Terminal {
 #[derive(Debug)]
 pub(crate) enum ConsoleBuffer {
     Owned(File),
-    Borrowed(ManuallyDrop<File>),
+    /// A duplicate of a standard I/O handle.
+    Stdio(File),
 }
 
 impl ConsoleBuffer {
-    // SAFETY: Only pass handles to global standard I/O that lives for the entire duration of the program.
-    fn try_borrow(handle: impl AsHandle) -> Option<ConsoleBuffer> {
-        let handle = handle.as_handle();
-        handle.is_terminal().then(|| {
-            // SAFETY: We pass a valid handle and we ensure that the
-            // standard I/O handle is not closed by wrapping the file in `ManuallyDrop`.
-            ConsoleBuffer::Borrowed(ManuallyDrop::new(unsafe {
-                File::from_raw_handle(handle.as_raw_handle())
-            }))
-        })
+    fn file(&self) -> &File {
+        match self {
+            ConsoleBuffer::Owned(f) => f,
+            ConsoleBuffer::Stdio(f) => f,
+        }
+    }
+
+    fn duplicate(stream: StdioStream) -> io::Result<ConsoleBuffer> {
+        // The handle is duplicated (without inheritance) so that we own it
+        // and can change its flags without affecting standard I/O.
+        match stream {
+            StdioStream::Stdin => io::stdin().as_handle().try_clone_to_owned(),
+            StdioStream::Stdout => io::stdout().as_handle().try_clone_to_owned(),
+            StdioStream::Stderr => io::stderr().as_handle().try_clone_to_owned(),
+        }
+        .map(|handle| ConsoleBuffer::Stdio(File::from(handle)))
     }
 }
 
-impl io::Write for Terminal {
+impl io::Write for &Terminal {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        self.conout.write(buf)
+        io::Write::write(&mut self.conout.file(), buf)
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        io::Write::write_vectored(&mut self.conout.file(), bufs)
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        self.conout.flush()
+        io::Write::flush(&mut self.conout.file())
     }
 }
 
@@ -94,7 +230,7 @@ impl AsHandle for ConsoleBuffer {
     fn as_handle(&self) -> BorrowedHandle<'_> {
         match self {
             ConsoleBuffer::Owned(f) => f.as_handle(),
-            ConsoleBuffer::Borrowed(f) => f.as_handle(),
+            ConsoleBuffer::Stdio(f) => f.as_handle(),
         }
     }
 }
@@ -103,122 +239,476 @@ impl AsRawHandle for ConsoleBuffer {
     fn as_raw_handle(&self) -> RawHandle {
         match self {
             ConsoleBuffer::Owned(f) => f.as_raw_handle(),
-            ConsoleBuffer::Borrowed(f) => f.as_raw_handle(),
+            ConsoleBuffer::Stdio(f) => f.as_raw_handle(),
         }
     }
 }
 
-impl io::Write for ConsoleBuffer {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        match self {
-            ConsoleBuffer::Owned(f) => f.write(buf),
-            ConsoleBuffer::Borrowed(f) => f.write(buf),
+impl io::Read for &Terminal {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match &self.conin_reader {
+            Some(reader) => lock_reader(reader).read(self.conin.as_handle(), buf),
+            None => io::Read::read(&mut self.conin.file(), buf),
         }
     }
 
-    fn flush(&mut self) -> io::Result<()> {
-        match self {
-            ConsoleBuffer::Owned(f) => f.flush(),
-            ConsoleBuffer::Borrowed(f) => f.flush(),
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        match &self.conin_reader {
+            Some(_) => {
+                let buf = bufs
+                    .iter_mut()
+                    .find(|b| !b.is_empty())
+                    .map_or(&mut [][..], |b| &mut **b);
+                io::Read::read(self, buf)
+            }
+            None => io::Read::read_vectored(&mut self.conin.file(), bufs),
         }
     }
 }
 
-impl io::Read for Terminal {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.conin.read(buf)
-    }
+fn lock_reader(reader: &Mutex<ConsoleReader>) -> MutexGuard<'_, ConsoleReader> {
+    reader.lock().unwrap_or_else(|e| e.into_inner())
 }
 
-impl io::Read for ConsoleBuffer {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        match self {
-            ConsoleBuffer::Owned(f) => f.read(buf),
-            ConsoleBuffer::Borrowed(f) => f.read(buf),
+impl Terminal {
+    /// Whether input can be read without waiting for the console.
+    fn has_pending_input(&self) -> bool {
+        self.conin_reader
+            .as_ref()
+            .is_some_and(|reader| lock_reader(reader).has_pending())
+    }
+
+    pub(crate) fn wait_for_input(&self, timeout: Option<Duration>) -> io::Result<bool> {
+        if self.has_pending_input() {
+            return Ok(true);
+        }
+        if self.kind() == TerminalKind::Msys {
+            return msys::wait_for_input(self.conin.as_handle(), timeout);
         }
+        wait_for_input(self.conin.as_handle(), timeout)
     }
-}
 
-impl Terminal {
-    pub(crate) fn lock_stdio(&mut self) -> StdioLocks {
-        let stdin_lock = self.conin_same_as_stdin.then(|| io::stdin().lock());
+    pub(crate) fn resume_output(&self) -> io::Result<()> {
+        // Output can't be stopped using flow control.
+        Ok(())
+    }
+
+    pub(crate) fn write_timeout(&self, buf: &[u8], _timeout: Duration) -> io::Result<usize> {
+        // There's no way to wait for the console to become writable.
+        io::Write::write(&mut self.conout.file(), buf)
+    }
+
+    pub(crate) fn set_retry_interrupted(&self, _retry: bool) {
+        // Reads and writes are not interrupted by signals on Windows.
+    }
+
+    pub(crate) fn interruptible(&self) -> impl io::Read + '_ {
+        self
+    }
+
+    pub(crate) fn kind(&self) -> TerminalKind {
+        // SAFETY: We pass a valid handle.
+        if unsafe { msys_tty_on(self.conin.as_raw_handle()) } {
+            TerminalKind::Msys
+        } else {
+            TerminalKind::Console
+        }
+    }
+
+    pub(crate) fn provenance(&self) -> Provenance {
+        match (&self.conin, &self.conout) {
+            (ConsoleBuffer::Stdio(_), ConsoleBuffer::Stdio(_)) => Provenance::Stdio,
+            _ => Provenance::Console,
+        }
+    }
+
+    pub(crate) fn is_foreground(&self) -> io::Result<bool> {
+        // There's no job control on Windows.
+        Ok(true)
+    }
+
+    pub(crate) fn path(&self) -> io::Result<PathBuf> {
+        Ok(PathBuf::from(r"\\.\CONIN$"))
+    }
+
+    pub(crate) fn set_inheritable(&self, inheritable: bool) -> io::Result<()> {
+        let flags = if inheritable { HANDLE_FLAG_INHERIT } else { 0 };
+        for handle in [self.conin.as_raw_handle(), self.conout.as_raw_handle()] {
+            // SAFETY: The handle is valid.
+            to_io_result(unsafe { SetHandleInformation(handle, HANDLE_FLAG_INHERIT, flags) })?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn device_state(&self) -> &DeviceState {
+        // A process has at most one console, so all handles share the same state.
+        static CONSOLE_STATE: DeviceState = DeviceState::new();
+        &CONSOLE_STATE
+    }
+
+    pub(crate) fn is_stdin(&self) -> bool {
+        self.conin_same_as_stdin
+    }
+
+    pub(crate) fn is_stdout(&self) -> bool {
+        self.conout_same_as_stdout
+    }
+
+    pub(crate) fn is_stderr(&self) -> bool {
+        self.conout_same_as_stderr
+    }
+
+    pub(crate) fn lock_stdin(&self) -> StdioLocks {
+        StdioLocks {
+            stdin_lock: self.conin_same_as_stdin.then(|| io::stdin().lock()),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn lock_stdout(&self) -> StdioLocks {
         let stdout_lock = self.conout_same_as_stdout.then(|| io::stdout().lock());
         let stderr_lock = self.conout_same_as_stderr.then(|| io::stderr().lock());
         StdioLocks {
-            stdin_lock,
             stdout_lock,
             stderr_lock,
+            ..Default::default()
         }
     }
 
-    pub(crate) fn enable_raw_mode(&mut self) -> io::Result<RawModeGuard<'_>> {
+    pub(crate) fn enable_raw_mode(&self, options: &RawModeOptions) -> io::Result<RawModeGuard<'_>> {
         let conin = self.conin.as_handle();
         let conout = self.conout.as_handle();
 
         // `is_terminal` recognizes MSYS/Cygwin pipes as terminal,
-        // but they are not a console, so we bail out.
+        // but they are not a console, so we fall back to `stty`.
         // SAFETY: We pass a valid handle.
         if unsafe { msys_tty_on(conin.as_raw_handle()) } {
-            return Err(io::Error::new(
-                io::ErrorKind::Unsupported,
-                MsysUnsupportedError,
-            ));
+            let settings = msys::enable_raw_mode(self.conin.file(), options)?;
+            let depth =
+                raw_mode_ref_counts().acquire((), TerminalAttributes::msys(settings.clone()));
+            return Ok(RawModeGuard {
+                inner: self,
+                // MSYS/Cygwin ptys don't have a console mode.
+                old_modes: (0, 0),
+                applied_modes: (0, 0),
+                modified: true,
+                timing: options.timing,
+                external_changes: ExternalModeChanges::Overwrite,
+                depth,
+                read_timeout: None,
+                msys_settings: Some(settings),
+            });
+        }
+
+        let input_mode = get_console_mode(conin)?;
+        let output_mode = get_console_mode(conout)?;
+        let raw_input_mode = console_mode::input::enable_raw_mode(input_mode, options);
+        let raw_output_mode = console_mode::output::enable_raw_mode(output_mode, options);
+
+        let modified = input_mode != raw_input_mode || output_mode != raw_output_mode;
+
+        if modified {
+            self.prepare_mode_change(options.timing)?;
+            set_console_mode(self.conin.as_handle(), raw_input_mode)?;
+            set_console_mode(self.conout.as_handle(), raw_output_mode)?;
         }
 
-        let old_input_mode =
-            set_raw_mode_if_necessary(conin, console_mode::input::enable_raw_mode)?;
-        let old_output_mode =
-            set_raw_mode_if_necessary(conout, console_mode::output::enable_raw_mode)?;
         Ok(RawModeGuard {
             inner: self,
-            old_input_mode,
-            old_output_mode,
+            old_modes: (input_mode, output_mode),
+            applied_modes: (raw_input_mode, raw_output_mode),
+            modified,
+            timing: options.timing,
+            external_changes: options.external_changes,
+            depth: raw_mode_ref_counts()
+                .acquire((), TerminalAttributes::console(input_mode, output_mode)),
+            read_timeout: options
+                .read_behavior
+                .and_then(|(min, timeout)| read_timeout(min, timeout)),
+            msys_settings: None,
         })
     }
-}
 
-fn set_raw_mode_if_necessary(
-    handle: BorrowedHandle,
-    enable: fn(CONSOLE_MODE) -> CONSOLE_MODE,
-) -> io::Result<Option<CONSOLE_MODE>> {
-    let mode = get_console_mode(handle)?;
-    let new_mode = enable(mode);
-    if mode == new_mode {
-        Ok(None)
-    } else {
-        set_console_mode(handle, new_mode)?;
-        Ok(Some(mode))
+    fn prepare_mode_change(&self, timing: ModeChangeTiming) -> io::Result<()> {
+        match timing {
+            ModeChangeTiming::Now => Ok(()),
+            ModeChangeTiming::Drain => self.drain(),
+            ModeChangeTiming::Flush => {
+                self.drain()?;
+                self.discard(Queue::Input)
+            }
+        }
     }
 }
 
-#[derive(Debug)]
-struct MsysUnsupportedError;
+impl Terminal {
+    pub(crate) fn discard(&self, queue: Queue) -> io::Result<()> {
+        match queue {
+            Queue::Input | Queue::Both => {
+                if let Some(reader) = &self.conin_reader {
+                    lock_reader(reader).clear();
+                }
+                // SAFETY: We pass a valid handle.
+                to_io_result(unsafe { FlushConsoleInputBuffer(self.conin.as_raw_handle()) })
+            }
+            // Writes to the console are synchronous, there's nothing to discard.
+            Queue::Output => Ok(()),
+        }
+    }
+
+    pub(crate) fn drain(&self) -> io::Result<()> {
+        let conout = self.conout.as_handle();
+        // Writes to the console are synchronous, so there's only something
+        // to wait for if we're not talking to a console (e.g. MSYS/Cygwin).
+        if get_console_mode(conout).is_ok() {
+            Ok(())
+        } else {
+            // SAFETY: We pass a valid handle.
+            to_io_result(unsafe { FlushFileBuffers(conout.as_raw_handle()) })
+        }
+    }
+
+    pub(crate) fn attributes(&self) -> io::Result<TerminalAttributes> {
+        if self.kind() == TerminalKind::Msys {
+            return msys::save(self.conin.file()).map(TerminalAttributes::msys);
+        }
+        Ok(TerminalAttributes::console(
+            get_console_mode(self.conin.as_handle())?,
+            get_console_mode(self.conout.as_handle())?,
+        ))
+    }
+
+    pub(crate) fn set_attributes(&self, attributes: &TerminalAttributes) -> io::Result<()> {
+        self.set_attributes_with(attributes, ModeChangeTiming::Drain)
+    }
+
+    pub(crate) fn set_attributes_with(
+        &self,
+        attributes: &TerminalAttributes,
+        timing: ModeChangeTiming,
+    ) -> io::Result<()> {
+        self.prepare_mode_change(timing)?;
+        if let Some(settings) = &attributes.msys_settings {
+            return msys::restore(self.conin.file(), settings);
+        }
+        set_console_mode(self.conin.as_handle(), attributes.input_mode)?;
+        set_console_mode(self.conout.as_handle(), attributes.output_mode)
+    }
+
+    pub(crate) fn screen_buffer_info(&self) -> io::Result<ScreenBufferInfo> {
+        screen_buffer::screen_buffer_info(self.conout.as_handle())
+    }
+
+    pub(crate) fn set_size(&self, rows: u16, columns: u16) -> io::Result<()> {
+        if self.kind() == TerminalKind::Msys {
+            return Err(crate::Error::Unsupported {
+                reason: "resizing MSYS/Cygwin terminals",
+            }
+            .into());
+        }
+        screen_buffer::set_size(self.conout.as_handle(), rows, columns)
+    }
+
+    pub(crate) fn read_input_events(&self) -> io::Result<Vec<InputEvent>> {
+        input_events::read_input_events(self.conin.as_handle())
+    }
+
+    pub(crate) fn set_title(&self, title: &str) -> io::Result<()> {
+        let title: Vec<u16> = title.encode_utf16().chain(iter::once(0)).collect();
+        // SAFETY: We pass a valid null-terminated string.
+        to_io_result(unsafe { SetConsoleTitleW(title.as_ptr()) })
+    }
+
+    pub(crate) fn save_title(&self) -> io::Result<SavedTitle> {
+        // The title is limited to 64K bytes.
+        let mut title = vec![0; 32 * 1024];
+        // An empty title and a failure both return zero, only the last error tells them apart.
+        // SAFETY: Resetting the last error has no preconditions.
+        unsafe { SetLastError(ERROR_SUCCESS) };
+        // SAFETY: We pass the length of our buffer.
+        let len = unsafe { GetConsoleTitleW(title.as_mut_ptr(), title.len() as u32) };
+        if len == 0 {
+            // SAFETY: Reading the last error has no preconditions.
+            let error = unsafe { GetLastError() };
+            if error != ERROR_SUCCESS {
+                return Err(io::Error::from_raw_os_error(error as i32));
+            }
+        }
+        title.truncate(len as usize);
+        title.push(0);
+        Ok(SavedTitle(title))
+    }
 
-impl fmt::Display for MsysUnsupportedError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "enabling raw mode on a MSYS/Cygwin terminal is not supported"
-        )
+    pub(crate) fn restore_title(&self, saved: SavedTitle) -> io::Result<()> {
+        // SAFETY: The saved title is null-terminated.
+        to_io_result(unsafe { SetConsoleTitleW(saved.0.as_ptr()) })
     }
 }
 
-impl error::Error for MsysUnsupportedError {}
+/// The null-terminated title.
+#[derive(Debug)]
+pub(crate) struct SavedTitle(Vec<u16>);
 
 #[derive(Debug)]
 pub(crate) struct RawModeGuard<'a> {
-    inner: &'a mut Terminal,
-    old_input_mode: Option<CONSOLE_MODE>,
-    old_output_mode: Option<CONSOLE_MODE>,
+    inner: &'a Terminal,
+    old_modes: ConsoleModes,
+    // The modes as set by this guard, used to detect changes made by someone else.
+    applied_modes: ConsoleModes,
+    modified: bool,
+    timing: ModeChangeTiming,
+    external_changes: ExternalModeChanges,
+    depth: usize,
+    // Emulates VMIN / VTIME by waiting for input before reading.
+    read_timeout: Option<Duration>,
+    // Only set for MSYS/Cygwin ptys, whose settings are restored using `stty`.
+    msys_settings: Option<msys::SttySettings>,
+}
+
+impl<'a> RawModeGuard<'a> {
+    pub(crate) fn terminal(&self) -> &'a Terminal {
+        self.inner
+    }
+
+    /// The mode before the first guard was created.
+    pub(crate) fn original_attributes(&self) -> TerminalAttributes {
+        let original = raw_mode_ref_counts().original(&()).cloned();
+        original.unwrap_or_else(|| self.previous_attributes())
+    }
+
+    pub(crate) fn persist(mut self) -> TerminalAttributes {
+        _ = raw_mode_ref_counts().release(&(), self.depth, false);
+        let previous = self.previous_attributes();
+        // Dropped here as `mem::forget` would leak them.
+        self.msys_settings = None;
+        mem::forget(self);
+        previous
+    }
+
+    /// The mode this guard replaced.
+    fn previous_attributes(&self) -> TerminalAttributes {
+        match &self.msys_settings {
+            Some(settings) => TerminalAttributes::msys(settings.clone()),
+            None => TerminalAttributes::console(self.old_modes.0, self.old_modes.1),
+        }
+    }
+
+    pub(crate) fn set_read_behavior(
+        &mut self,
+        min: u8,
+        timeout: Option<Duration>,
+    ) -> io::Result<()> {
+        if self.msys_settings.is_some() {
+            return msys::set_read_behavior(self.inner.conin.file(), min, timeout);
+        }
+        self.read_timeout = read_timeout(min, timeout);
+        Ok(())
+    }
+
+    pub(crate) fn has_external_changes(&self) -> io::Result<bool> {
+        if self.msys_settings.is_some() {
+            return Err(crate::Error::Unsupported {
+                reason: "detecting mode changes on MSYS/Cygwin terminals",
+            }
+            .into());
+        }
+        Ok(self.current_modes()? != self.applied_modes)
+    }
+
+    fn current_modes(&self) -> io::Result<ConsoleModes> {
+        Ok((
+            get_console_mode(self.inner.conin.as_handle())?,
+            get_console_mode(self.inner.conout.as_handle())?,
+        ))
+    }
+
+    /// Decides what to restore given the `previous` modes, see [`ExternalModeChanges`].
+    fn resolve_external_changes(&self, previous: ConsoleModes) -> Option<ConsoleModes> {
+        if self.external_changes == ExternalModeChanges::Overwrite {
+            return Some(previous);
+        }
+        let Ok(current) = self.current_modes() else {
+            return Some(previous);
+        };
+        let merge = |current: CONSOLE_MODE, applied: CONSOLE_MODE, previous: CONSOLE_MODE| {
+            let changed = applied ^ previous;
+            (current & !changed) | (previous & changed)
+        };
+        match self.external_changes {
+            ExternalModeChanges::Keep if current != self.applied_modes => None,
+            ExternalModeChanges::Merge => Some((
+                merge(current.0, self.applied_modes.0, previous.0),
+                merge(current.1, self.applied_modes.1, previous.1),
+            )),
+            _ => Some(previous),
+        }
+    }
+}
+
+impl Deref for RawModeGuard<'_> {
+    type Target = Terminal;
+
+    fn deref(&self) -> &Self::Target {
+        self.inner
+    }
+}
+
+/// The input and output mode.
+type ConsoleModes = (CONSOLE_MODE, CONSOLE_MODE);
+
+// There's only one console per process, so there's no need to distinguish between devices.
+// The same goes for the MSYS/Cygwin pty which takes the place of the console.
+static RAW_MODE_REF_COUNTS: Mutex<RawModeRefCounts<(), TerminalAttributes>> =
+    Mutex::new(RawModeRefCounts::new());
+
+fn raw_mode_ref_counts() -> MutexGuard<'static, RawModeRefCounts<(), TerminalAttributes>> {
+    RAW_MODE_REF_COUNTS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// Emulates `VMIN` and `VTIME` by waiting for the console input buffer before reading.
+fn read_timeout(min: u8, timeout: Option<Duration>) -> Option<Duration> {
+    match (min, timeout) {
+        (_, Some(timeout)) => Some(timeout),
+        (0, None) => Some(Duration::ZERO),
+        (_, None) => None,
+    }
 }
 
 impl Drop for RawModeGuard<'_> {
     fn drop(&mut self) {
-        if let Some(old_mode) = self.old_input_mode {
-            _ = set_console_mode(self.inner.conin.as_handle(), old_mode);
+        let restore = raw_mode_ref_counts().release(&(), self.depth, self.modified);
+        if let Some(settings) = &self.msys_settings {
+            let settings = match restore {
+                Restore::Original(original) => original.msys_settings,
+                Restore::Previous => Some(settings.clone()),
+                Restore::Nothing => None,
+            };
+            if let Some(settings) = settings {
+                _ = self.inner.prepare_mode_change(self.timing);
+                _ = msys::restore(self.inner.conin.file(), &settings);
+            }
+            return;
         }
-        if let Some(old_mode) = self.old_output_mode {
-            _ = set_console_mode(self.inner.conout.as_handle(), old_mode);
+        let modes = match restore {
+            Restore::Original(original) => Some((original.input_mode, original.output_mode)),
+            Restore::Previous => self.modified.then_some(self.old_modes),
+            Restore::Nothing => None,
+        };
+        if let Some((input_mode, output_mode)) =
+            modes.and_then(|m| self.resolve_external_changes(m))
+        {
+            // Nested guards that are dropped together often leave nothing to restore.
+            // A flush still has to discard the pending input though.
+            if self.timing != ModeChangeTiming::Flush
+                && self.current_modes().ok() == Some((input_mode, output_mode))
+            {
+                return;
+            }
+            _ = self.inner.prepare_mode_change(self.timing);
+            _ = set_console_mode(self.inner.conin.as_handle(), input_mode);
+            _ = set_console_mode(self.inner.conout.as_handle(), output_mode);
         }
     }
 }
@@ -228,6 +718,10 @@ impl io::Write for RawModeGuard<'_> {
         self.inner.write(buf)
     }
 
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        self.inner.write_vectored(bufs)
+    }
+
     fn flush(&mut self) -> io::Result<()> {
         self.inner.flush()
     }
@@ -235,7 +729,39 @@ impl io::Write for RawModeGuard<'_> {
 
 impl io::Read for RawModeGuard<'_> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.inner.read(buf)
+        match self.read_timeout {
+            Some(timeout)
+                if !self.inner.has_pending_input()
+                    && !wait_for_input(self.inner.conin.as_handle(), Some(timeout))? =>
+            {
+                Ok(0)
+            }
+            _ => self.inner.read(buf),
+        }
+    }
+
+    fn read_vectored(&mut self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        match self.read_timeout {
+            Some(timeout)
+                if !self.inner.has_pending_input()
+                    && !wait_for_input(self.inner.conin.as_handle(), Some(timeout))? =>
+            {
+                Ok(0)
+            }
+            _ => self.inner.read_vectored(bufs),
+        }
+    }
+}
+
+fn wait_for_input(handle: BorrowedHandle, timeout: Option<Duration>) -> io::Result<bool> {
+    let millis = timeout.map_or(INFINITE, |t| {
+        u32::try_from(t.as_millis()).unwrap_or(INFINITE - 1)
+    });
+    // SAFETY: We pass a valid handle.
+    match unsafe { WaitForSingleObject(handle.as_raw_handle(), millis) } {
+        WAIT_OBJECT_0 => Ok(true),
+        WAIT_TIMEOUT => Ok(false),
+        _ => Err(io::Error::last_os_error()),
     }
 }
 
@@ -247,6 +773,18 @@ fn to_io_result(result: BOOL) -> io::Result<()> {
     }
 }
 
+impl AsHandle for crate::Readiness<'_> {
+    fn as_handle(&self) -> BorrowedHandle<'_> {
+        self.0.conin.as_handle()
+    }
+}
+
+impl AsRawHandle for crate::Readiness<'_> {
+    fn as_raw_handle(&self) -> RawHandle {
+        self.0.conin.as_raw_handle()
+    }
+}
+
 impl ConsoleHandles for super::Terminal {
     fn input_buffer_handle(&self) -> std::os::windows::io::BorrowedHandle<'_> {
         self.0.conin.as_handle()
@@ -277,10 +815,77 @@ impl ConsoleHandles for super::RawModeGuard<'_> {
     }
 }
 
+impl<T: ConsoleHandles + ?Sized> ConsoleHandles for &mut T {
+    fn input_buffer_handle(&self) -> std::os::windows::io::BorrowedHandle<'_> {
+        (**self).input_buffer_handle()
+    }
+
+    fn screen_buffer_handle(&self) -> std::os::windows::io::BorrowedHandle<'_> {
+        (**self).screen_buffer_handle()
+    }
+}
+
+impl ConsoleHandles for Box<dyn super::Transceive + '_> {
+    fn input_buffer_handle(&self) -> std::os::windows::io::BorrowedHandle<'_> {
+        (**self).input_buffer_handle()
+    }
+
+    fn screen_buffer_handle(&self) -> std::os::windows::io::BorrowedHandle<'_> {
+        (**self).screen_buffer_handle()
+    }
+}
+
+/// A buffer duplicated from a standard I/O stream is known to be the same as that stream,
+/// which doesn't depend on `CompareObjectHandles` being available.
+fn is_same_as_stdio(buffer: &ConsoleBuffer, source: Source, stream: StdioStream) -> bool {
+    match source {
+        Source::Stdio(source) if source == stream => true,
+        _ => match stream {
+            StdioStream::Stdin => compare_object_handles(buffer.as_handle(), io::stdin()),
+            StdioStream::Stdout => compare_object_handles(buffer.as_handle(), io::stdout()),
+            StdioStream::Stderr => compare_object_handles(buffer.as_handle(), io::stderr()),
+        },
+    }
+}
+
+/// Returns `true` if both handles refer to the same kernel object.
+///
+/// `CompareObjectHandles` was added in Windows 10, on earlier versions
+/// distinct handles are assumed to refer to different objects.
 fn compare_object_handles(first: impl AsRawHandle, second: impl AsRawHandle) -> bool {
-    use windows_sys::Win32::Foundation::HANDLE;
     let first = first.as_raw_handle() as HANDLE;
     let second = second.as_raw_handle() as HANDLE;
-    // SAFETY: We pass two valid handles
-    unsafe { CompareObjectHandles(first, second) == 1 }
+    match load_compare_object_handles() {
+        // SAFETY: We pass two valid handles
+        Some(compare_object_handles) => unsafe { compare_object_handles(first, second) == 1 },
+        None => false,
+    }
+}
+
+type CompareObjectHandlesFn = unsafe extern "system" fn(HANDLE, HANDLE) -> BOOL;
+
+/// Looks up `CompareObjectHandles` at runtime as linking it statically
+/// would keep the process from starting on Windows versions before Windows 10.
+fn load_compare_object_handles() -> Option<CompareObjectHandlesFn> {
+    use std::sync::OnceLock;
+    use windows_sys::Win32::System::LibraryLoader::{GetModuleHandleW, GetProcAddress};
+
+    static FUNCTION: OnceLock<Option<CompareObjectHandlesFn>> = OnceLock::new();
+    *FUNCTION.get_or_init(|| {
+        let module_name: Vec<u16> = "kernelbase.dll"
+            .encode_utf16()
+            .chain(iter::once(0))
+            .collect();
+        // SAFETY: We pass a valid null-terminated string. kernelbase.dll is never unloaded.
+        let module = unsafe { GetModuleHandleW(module_name.as_ptr()) };
+        if module.is_null() {
+            return None;
+        }
+        // SAFETY: We pass a valid module handle and a null-terminated name.
+        let function = unsafe { GetProcAddress(module, b"CompareObjectHandles\0".as_ptr()) }?;
+        // SAFETY: `CompareObjectHandles` has this signature.
+        Some(unsafe {
+            mem::transmute::<unsafe extern "system" fn() -> isize, CompareObjectHandlesFn>(function)
+        })
+    })
 }
@@ -0,0 +1,122 @@
+use super::to_io_result;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use windows_sys::Win32::System::Console::{
+    GetConsoleCP, GetConsoleOutputCP, SetConsoleCP, SetConsoleOutputCP,
+};
+
+// Avoids depending on the `Win32_Globalization` feature just for this constant.
+const CP_UTF8: u32 = 65001;
+
+pub(crate) fn input_code_page() -> io::Result<u32> {
+    // SAFETY: This function has no preconditions.
+    to_code_page(unsafe { GetConsoleCP() })
+}
+
+pub(crate) fn output_code_page() -> io::Result<u32> {
+    // SAFETY: This function has no preconditions.
+    to_code_page(unsafe { GetConsoleOutputCP() })
+}
+
+fn to_code_page(code_page: u32) -> io::Result<u32> {
+    if code_page == 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(code_page)
+    }
+}
+
+fn set_code_pages(input: u32, output: u32) -> io::Result<()> {
+    // SAFETY: These functions have no preconditions.
+    to_io_result(unsafe { SetConsoleCP(input) })?;
+    // SAFETY: See above.
+    to_io_result(unsafe { SetConsoleOutputCP(output) })
+}
+
+/// Switches the console's input and output code pages to UTF-8 (65001) until the guard is dropped,
+/// see [`TerminalLock::enable_utf8_code_page`](`crate::TerminalLock::enable_utf8_code_page`).
+///
+/// The guard holds on to the [`TerminalLock`](`crate::TerminalLock`) or [`RawModeGuard`](`crate::RawModeGuard`)
+/// it was created from and can be used in its place (e.g. for writing).
+#[derive(Debug)]
+pub struct CodePageGuard<'a, T> {
+    inner: &'a mut T,
+    // Only set if the code pages were modified.
+    old_code_pages: Option<(u32, u32)>,
+}
+
+impl<'a, T> CodePageGuard<'a, T> {
+    pub(crate) fn new(inner: &'a mut T) -> io::Result<Self> {
+        let code_pages = (input_code_page()?, output_code_page()?);
+        let old_code_pages = if code_pages == (CP_UTF8, CP_UTF8) {
+            None
+        } else {
+            set_code_pages(CP_UTF8, CP_UTF8)?;
+            Some(code_pages)
+        };
+        Ok(CodePageGuard {
+            inner,
+            old_code_pages,
+        })
+    }
+}
+
+impl<T> Deref for CodePageGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.inner
+    }
+}
+
+impl<T> DerefMut for CodePageGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.inner
+    }
+}
+
+impl<T> Drop for CodePageGuard<'_, T> {
+    fn drop(&mut self) {
+        if let Some((input, output)) = self.old_code_pages {
+            _ = set_code_pages(input, output);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+
+    const CP_OEM_US: u32 = 437;
+
+    fn code_pages() -> io::Result<(u32, u32)> {
+        Ok((input_code_page()?, output_code_page()?))
+    }
+
+    // A single test as the code pages are shared by the whole console.
+    #[test]
+    fn code_pages_are_switched_and_restored() {
+        // The tests may run without a console.
+        let Ok((input, output)) = code_pages() else {
+            return;
+        };
+
+        let mut inner = Vec::<u8>::new();
+        set_code_pages(CP_OEM_US, CP_OEM_US).unwrap();
+        let guard = CodePageGuard::new(&mut inner).unwrap();
+        assert_eq!((CP_UTF8, CP_UTF8), code_pages().unwrap());
+        drop(guard);
+        assert_eq!((CP_OEM_US, CP_OEM_US), code_pages().unwrap());
+
+        // Code pages that are already UTF-8 are left alone.
+        set_code_pages(CP_UTF8, CP_UTF8).unwrap();
+        let guard = CodePageGuard::new(&mut inner).unwrap();
+        assert_eq!(None, guard.old_code_pages);
+        drop(guard);
+        assert_eq!((CP_UTF8, CP_UTF8), code_pages().unwrap());
+
+        set_code_pages(input, output).unwrap();
+    }
+}
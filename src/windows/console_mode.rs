@@ -1,9 +1,14 @@
+use super::msys::SttySettings;
 use super::to_io_result;
+use crate::{ControlChar, RawModeOptions};
 use std::io;
 use std::os::windows::io::{AsRawHandle as _, BorrowedHandle};
 use windows_sys::Win32::System::Console::{
-    GetConsoleMode, SetConsoleMode, CONSOLE_MODE, ENABLE_ECHO_INPUT, ENABLE_LINE_INPUT,
-    ENABLE_PROCESSED_OUTPUT, ENABLE_VIRTUAL_TERMINAL_INPUT, ENABLE_VIRTUAL_TERMINAL_PROCESSING,
+    GetConsoleMode, SetConsoleMode, CONSOLE_MODE, DISABLE_NEWLINE_AUTO_RETURN, ENABLE_ECHO_INPUT,
+    ENABLE_EXTENDED_FLAGS, ENABLE_INSERT_MODE, ENABLE_LINE_INPUT, ENABLE_LVB_GRID_WORLDWIDE,
+    ENABLE_MOUSE_INPUT, ENABLE_PROCESSED_INPUT, ENABLE_PROCESSED_OUTPUT, ENABLE_QUICK_EDIT_MODE,
+    ENABLE_VIRTUAL_TERMINAL_INPUT, ENABLE_VIRTUAL_TERMINAL_PROCESSING, ENABLE_WINDOW_INPUT,
+    ENABLE_WRAP_AT_EOL_OUTPUT,
 };
 
 pub(crate) fn get_console_mode(handle: BorrowedHandle) -> io::Result<CONSOLE_MODE> {
@@ -21,20 +26,34 @@ pub(crate) fn set_console_mode(handle: BorrowedHandle, mode: CONSOLE_MODE) -> io
 pub(crate) mod input {
     use super::*;
 
-    // We disable two flags:
+    // By default we disable two flags:
     // `ENABLE_ECHO_INPUT`
     //     To disable input characters from being echoed.
     // `ENABLE_LINE_INPUT`
-    //     We want input to be available immediately and not wait for a line terminator
-    const FLAGS_DISABLED_IN_RAW_MODE: CONSOLE_MODE = ENABLE_ECHO_INPUT | ENABLE_LINE_INPUT;
+    //     We want input to be available immediately and not wait for a line terminator.
+    //     Echo is only supported together with line input, so disabling this also disables echo.
+    // `ENABLE_PROCESSED_INPUT` is only disabled when requested
+    //     To read Ctrl+C as input instead of it being handled by the system.
+    const ECHO_FLAGS: CONSOLE_MODE = ENABLE_ECHO_INPUT;
+    const CANONICAL_FLAGS: CONSOLE_MODE = ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT;
+    const SIGNAL_FLAGS: CONSOLE_MODE = ENABLE_PROCESSED_INPUT;
 
     // We enable one flag:
     // `ENABLE_VIRTUAL_TERMINAL_INPUT`
     //     To ensure that we get back a response. See: https://github.com/microsoft/terminal/pull/17729#issuecomment-2295339876
-    const FLAGS_ENABLED_IN_RAW_MODE: CONSOLE_MODE = ENABLE_VIRTUAL_TERMINAL_INPUT;
+    const VIRTUAL_TERMINAL_FLAGS: CONSOLE_MODE = ENABLE_VIRTUAL_TERMINAL_INPUT;
 
-    pub(crate) fn enable_raw_mode(mode: CONSOLE_MODE) -> CONSOLE_MODE {
-        mode & !(FLAGS_DISABLED_IN_RAW_MODE) | FLAGS_ENABLED_IN_RAW_MODE
+    pub(crate) fn enable_raw_mode(mode: CONSOLE_MODE, options: &RawModeOptions) -> CONSOLE_MODE {
+        let disabled_if = |enabled: bool, flags: CONSOLE_MODE| if enabled { 0 } else { flags };
+        let disabled = disabled_if(options.echo, ECHO_FLAGS)
+            | disabled_if(options.canonical, CANONICAL_FLAGS)
+            | disabled_if(options.signals, SIGNAL_FLAGS);
+        let enabled = if options.virtual_terminal_input {
+            VIRTUAL_TERMINAL_FLAGS
+        } else {
+            0
+        };
+        mode & !disabled | enabled
     }
 
     #[cfg(test)]
@@ -43,7 +62,26 @@ pub(crate) mod input {
 
         #[test]
         fn enabled_and_disabled_flags_do_not_overlap() {
-            assert_eq!(0, FLAGS_DISABLED_IN_RAW_MODE & FLAGS_ENABLED_IN_RAW_MODE);
+            let disabled = ECHO_FLAGS | CANONICAL_FLAGS | SIGNAL_FLAGS;
+            assert_eq!(0, disabled & VIRTUAL_TERMINAL_FLAGS);
+        }
+
+        #[test]
+        fn default_options_disable_echo_and_line_input() {
+            let mode = ENABLE_ECHO_INPUT | ENABLE_LINE_INPUT | ENABLE_PROCESSED_INPUT;
+            assert_eq!(
+                ENABLE_PROCESSED_INPUT | ENABLE_VIRTUAL_TERMINAL_INPUT,
+                enable_raw_mode(mode, &RawModeOptions::default())
+            );
+        }
+
+        #[test]
+        fn virtual_terminal_input_can_be_disabled() {
+            let options = RawModeOptions::default().virtual_terminal_input(false);
+            assert_eq!(
+                0,
+                enable_raw_mode(0, &options) & ENABLE_VIRTUAL_TERMINAL_INPUT
+            );
         }
     }
 }
@@ -55,7 +93,224 @@ pub(crate) mod output {
     const FLAGS_ENABLED_IN_RAW_MODE: CONSOLE_MODE =
         ENABLE_PROCESSED_OUTPUT | ENABLE_VIRTUAL_TERMINAL_PROCESSING;
 
-    pub(crate) fn enable_raw_mode(mode: CONSOLE_MODE) -> CONSOLE_MODE {
-        mode | FLAGS_ENABLED_IN_RAW_MODE
+    // Processed output is required for VT sequences,
+    // so we only turn off the automatic carriage return after a newline.
+    const FLAGS_ENABLED_WITHOUT_OUTPUT_PROCESSING: CONSOLE_MODE = DISABLE_NEWLINE_AUTO_RETURN;
+
+    pub(crate) fn enable_raw_mode(mode: CONSOLE_MODE, options: &RawModeOptions) -> CONSOLE_MODE {
+        if options.output_processing {
+            mode | FLAGS_ENABLED_IN_RAW_MODE
+        } else {
+            mode | FLAGS_ENABLED_IN_RAW_MODE | FLAGS_ENABLED_WITHOUT_OUTPUT_PROCESSING
+        }
+    }
+
+    pub(crate) fn enable_virtual_terminal_processing(
+        mode: CONSOLE_MODE,
+        disable_newline_auto_return: bool,
+    ) -> CONSOLE_MODE {
+        if disable_newline_auto_return {
+            mode | FLAGS_ENABLED_IN_RAW_MODE | DISABLE_NEWLINE_AUTO_RETURN
+        } else {
+            mode | FLAGS_ENABLED_IN_RAW_MODE
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[test]
+        fn virtual_terminal_processing_requires_processed_output() {
+            assert_eq!(
+                ENABLE_PROCESSED_OUTPUT | ENABLE_VIRTUAL_TERMINAL_PROCESSING,
+                enable_virtual_terminal_processing(0, false)
+            );
+        }
+    }
+}
+
+/// MSYS/Cygwin ptys don't have a console mode, their settings are saved using `stty` instead.
+/// The console modes of such attributes are zero.
+#[derive(Debug, Clone)]
+pub(crate) struct TerminalAttributes {
+    pub(crate) input_mode: CONSOLE_MODE,
+    pub(crate) output_mode: CONSOLE_MODE,
+    pub(crate) msys_settings: Option<SttySettings>,
+}
+
+impl TerminalAttributes {
+    pub(crate) fn console(input_mode: CONSOLE_MODE, output_mode: CONSOLE_MODE) -> Self {
+        TerminalAttributes {
+            input_mode,
+            output_mode,
+            msys_settings: None,
+        }
+    }
+
+    pub(crate) fn msys(settings: SttySettings) -> Self {
+        TerminalAttributes {
+            input_mode: 0,
+            output_mode: 0,
+            msys_settings: Some(settings),
+        }
+    }
+
+    /// The modes of a freshly opened console.
+    #[cfg(feature = "test-util")]
+    pub(crate) fn cooked() -> Self {
+        TerminalAttributes::console(
+            ENABLE_PROCESSED_INPUT | ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT,
+            ENABLE_PROCESSED_OUTPUT | ENABLE_WRAP_AT_EOL_OUTPUT,
+        )
+    }
+
+    #[cfg(feature = "test-util")]
+    pub(crate) fn enable_raw_mode(&mut self, options: &RawModeOptions) {
+        self.input_mode = input::enable_raw_mode(self.input_mode, options);
+        self.output_mode = output::enable_raw_mode(self.output_mode, options);
+    }
+
+    pub(crate) fn echo(&self) -> bool {
+        self.input_mode & ENABLE_ECHO_INPUT != 0
+    }
+
+    pub(crate) fn set_echo(&mut self, enabled: bool) {
+        set_flags(&mut self.input_mode, ENABLE_ECHO_INPUT, enabled)
+    }
+
+    pub(crate) fn canonical(&self) -> bool {
+        self.input_mode & ENABLE_LINE_INPUT != 0
+    }
+
+    pub(crate) fn set_canonical(&mut self, enabled: bool) {
+        set_flags(&mut self.input_mode, ENABLE_LINE_INPUT, enabled)
+    }
+
+    pub(crate) fn signals(&self) -> bool {
+        self.input_mode & ENABLE_PROCESSED_INPUT != 0
+    }
+
+    pub(crate) fn set_signals(&mut self, enabled: bool) {
+        set_flags(&mut self.input_mode, ENABLE_PROCESSED_INPUT, enabled)
+    }
+
+    // The `stty` settings of MSYS/Cygwin ptys follow the console modes.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.input_mode.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&self.output_mode.to_le_bytes());
+        if let Some(settings) = &self.msys_settings {
+            bytes.extend_from_slice(settings.as_bytes());
+        }
+        bytes
+    }
+
+    pub(crate) fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        if bytes.len() < 8 {
+            return None;
+        }
+        let (modes, msys_settings) = bytes.split_at(8);
+        let (input_mode, output_mode) = modes.split_at(4);
+        Some(TerminalAttributes {
+            input_mode: CONSOLE_MODE::from_le_bytes(input_mode.try_into().ok()?),
+            output_mode: CONSOLE_MODE::from_le_bytes(output_mode.try_into().ok()?),
+            msys_settings: if msys_settings.is_empty() {
+                None
+            } else {
+                Some(SttySettings::from_bytes(msys_settings)?)
+            },
+        })
+    }
+
+    pub(crate) fn control_char(&self, control_char: ControlChar) -> Option<u8> {
+        match control_char {
+            ControlChar::Interrupt => Some(0x03),
+            ControlChar::EndOfFile => Some(0x1a),
+            ControlChar::Suspend => None,
+            ControlChar::Erase => Some(0x08),
+        }
+    }
+
+    pub(crate) fn set_control_char(&mut self, _control_char: ControlChar, _value: Option<u8>) {}
+
+    /// Enables line input, echo and processing of input and output, like `stty sane`.
+    pub(crate) fn make_sane(&mut self) {
+        set_flags(
+            &mut self.input_mode,
+            ENABLE_PROCESSED_INPUT | ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT,
+            true,
+        );
+        set_flags(&mut self.input_mode, ENABLE_VIRTUAL_TERMINAL_INPUT, false);
+        set_flags(
+            &mut self.output_mode,
+            ENABLE_PROCESSED_OUTPUT | ENABLE_WRAP_AT_EOL_OUTPUT,
+            true,
+        );
+        set_flags(&mut self.output_mode, DISABLE_NEWLINE_AUTO_RETURN, false);
+    }
+
+    /// The names of the input and output mode flags together with whether they're set.
+    pub(crate) fn flags(&self) -> Vec<(&'static str, bool)> {
+        let input_flags = [
+            ("ENABLE_PROCESSED_INPUT", ENABLE_PROCESSED_INPUT),
+            ("ENABLE_LINE_INPUT", ENABLE_LINE_INPUT),
+            ("ENABLE_ECHO_INPUT", ENABLE_ECHO_INPUT),
+            ("ENABLE_WINDOW_INPUT", ENABLE_WINDOW_INPUT),
+            ("ENABLE_MOUSE_INPUT", ENABLE_MOUSE_INPUT),
+            ("ENABLE_INSERT_MODE", ENABLE_INSERT_MODE),
+            ("ENABLE_QUICK_EDIT_MODE", ENABLE_QUICK_EDIT_MODE),
+            ("ENABLE_EXTENDED_FLAGS", ENABLE_EXTENDED_FLAGS),
+            (
+                "ENABLE_VIRTUAL_TERMINAL_INPUT",
+                ENABLE_VIRTUAL_TERMINAL_INPUT,
+            ),
+        ];
+        let output_flags = [
+            ("ENABLE_PROCESSED_OUTPUT", ENABLE_PROCESSED_OUTPUT),
+            ("ENABLE_WRAP_AT_EOL_OUTPUT", ENABLE_WRAP_AT_EOL_OUTPUT),
+            (
+                "ENABLE_VIRTUAL_TERMINAL_PROCESSING",
+                ENABLE_VIRTUAL_TERMINAL_PROCESSING,
+            ),
+            ("DISABLE_NEWLINE_AUTO_RETURN", DISABLE_NEWLINE_AUTO_RETURN),
+            ("ENABLE_LVB_GRID_WORLDWIDE", ENABLE_LVB_GRID_WORLDWIDE),
+        ];
+        let named = |mode: CONSOLE_MODE, flags: &[(&'static str, CONSOLE_MODE)]| {
+            flags
+                .iter()
+                .map(move |&(name, flag)| (name, mode & flag != 0))
+                .collect::<Vec<_>>()
+        };
+        [
+            named(self.input_mode, &input_flags),
+            named(self.output_mode, &output_flags),
+        ]
+        .concat()
+    }
+
+    pub(crate) fn flow_control(&self) -> bool {
+        false
+    }
+
+    pub(crate) fn set_flow_control(&mut self, _enabled: bool) {}
+
+    pub(crate) fn virtual_terminal_processing(&self) -> bool {
+        self.output_mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+    }
+
+    pub(crate) fn set_virtual_terminal_processing(&mut self, enabled: bool) {
+        set_flags(
+            &mut self.output_mode,
+            ENABLE_VIRTUAL_TERMINAL_PROCESSING,
+            enabled,
+        )
+    }
+}
+
+fn set_flags(target: &mut CONSOLE_MODE, flags: CONSOLE_MODE, enabled: bool) {
+    if enabled {
+        *target |= flags;
+    } else {
+        *target &= !flags;
     }
 }
@@ -0,0 +1,132 @@
+use super::to_io_result;
+use std::io;
+use std::os::windows::io::{AsRawHandle as _, BorrowedHandle};
+use windows_sys::Win32::System::Console::ReadConsoleW;
+
+/// Reads from the console using `ReadConsoleW` and converts the input to UTF-8.
+///
+/// Reading bytes from the console goes through the console's input code page
+/// which mangles everything that can't be represented in it.
+/// Leftover bytes (when the caller's buffer is too small for a character) and
+/// lone high surrogates (when a surrogate pair is split across reads) are kept for the next read.
+#[derive(Debug, Default)]
+pub(crate) struct ConsoleReader {
+    pending: Vec<u8>,
+    decoder: Utf16Decoder,
+}
+
+// Large enough to read a full line of input at once.
+const UTF16_BUFFER_SIZE: usize = 1024;
+
+impl ConsoleReader {
+    pub(crate) fn read(&mut self, handle: BorrowedHandle<'_>, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        while self.pending.is_empty() {
+            let mut utf16 = [0_u16; UTF16_BUFFER_SIZE];
+            // Every UTF-16 code unit is at least one byte in UTF-8,
+            // so there's no point in reading more than fits into the caller's buffer.
+            let len = buf.len().min(utf16.len());
+            let mut read = 0;
+            // SAFETY: We pass a valid handle and the length of our buffer (in UTF-16 code units).
+            to_io_result(unsafe {
+                ReadConsoleW(
+                    handle.as_raw_handle(),
+                    utf16.as_mut_ptr().cast(),
+                    len as u32,
+                    &mut read,
+                    std::ptr::null(),
+                )
+            })?;
+            if read == 0 {
+                return Ok(0);
+            }
+            self.decoder
+                .decode(&utf16[..read as usize], &mut self.pending);
+        }
+        let len = buf.len().min(self.pending.len());
+        buf[..len].copy_from_slice(&self.pending[..len]);
+        self.pending.drain(..len);
+        Ok(len)
+    }
+
+    /// Whether there's decoded input left over from a previous read.
+    pub(crate) fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.pending.clear();
+        self.decoder = Utf16Decoder::default();
+    }
+}
+
+#[derive(Debug, Default)]
+struct Utf16Decoder {
+    high_surrogate: Option<u16>,
+}
+
+impl Utf16Decoder {
+    /// Decodes the UTF-16 input and appends it to `out`. Unpaired surrogates are replaced
+    /// with U+FFFD except for a trailing high surrogate, which is paired with the next input.
+    fn decode(&mut self, mut input: &[u16], out: &mut Vec<u8>) {
+        let high_surrogate = self.high_surrogate.take();
+        if let Some((&last, rest)) = input.split_last() {
+            if is_high_surrogate(last) {
+                self.high_surrogate = Some(last);
+                input = rest;
+            }
+        }
+        let units = high_surrogate.into_iter().chain(input.iter().copied());
+        let mut char_buf = [0; 4];
+        for c in char::decode_utf16(units) {
+            let c = c.unwrap_or(char::REPLACEMENT_CHARACTER);
+            out.extend_from_slice(c.encode_utf8(&mut char_buf).as_bytes());
+        }
+    }
+}
+
+fn is_high_surrogate(unit: u16) -> bool {
+    (0xD800..=0xDBFF).contains(&unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(decoder: &mut Utf16Decoder, input: &str) -> Vec<u8> {
+        let input: Vec<u16> = input.encode_utf16().collect();
+        let mut out = Vec::new();
+        decoder.decode(&input, &mut out);
+        out
+    }
+
+    #[test]
+    fn decodes_non_ascii_input() {
+        let mut decoder = Utf16Decoder::default();
+        assert_eq!(
+            "äöü 漢字 🦀".as_bytes(),
+            decode(&mut decoder, "äöü 漢字 🦀")
+        );
+    }
+
+    #[test]
+    fn surrogate_pairs_split_across_reads_are_joined() {
+        let mut decoder = Utf16Decoder::default();
+        let crab: Vec<u16> = "🦀".encode_utf16().collect();
+        let mut out = Vec::new();
+        decoder.decode(&crab[..1], &mut out);
+        assert!(out.is_empty());
+        decoder.decode(&crab[1..], &mut out);
+        assert_eq!("🦀".as_bytes(), out);
+    }
+
+    #[test]
+    fn unpaired_surrogates_are_replaced() {
+        let mut decoder = Utf16Decoder::default();
+        let mut out = Vec::new();
+        decoder.decode(&[0xDC00, u16::from(b'a')], &mut out);
+        assert_eq!("\u{FFFD}a".as_bytes(), out);
+    }
+}
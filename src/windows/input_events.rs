@@ -0,0 +1,252 @@
+use super::to_io_result;
+use std::io;
+use std::os::windows::io::{AsRawHandle as _, BorrowedHandle};
+use windows_sys::Win32::System::Console::{
+    ReadConsoleInputW, FOCUS_EVENT, INPUT_RECORD, KEY_EVENT, LEFT_ALT_PRESSED, LEFT_CTRL_PRESSED,
+    MOUSE_EVENT, RIGHT_ALT_PRESSED, RIGHT_CTRL_PRESSED, SHIFT_PRESSED, WINDOW_BUFFER_SIZE_EVENT,
+};
+
+/// An event read from the console's input buffer,
+/// see [`TerminalLock::read_input_events`](`crate::TerminalLock::read_input_events`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InputEvent {
+    /// A key was pressed or released.
+    Key(KeyEvent),
+    /// The mouse was moved or a mouse button was pressed or released.
+    /// Only reported if `ENABLE_MOUSE_INPUT` is set and `ENABLE_QUICK_EDIT_MODE` is not.
+    Mouse(MouseEvent),
+    /// The screen buffer was resized.
+    /// Only reported if `ENABLE_WINDOW_INPUT` is set.
+    Resize {
+        /// The new number of columns.
+        columns: u16,
+        /// The new number of rows.
+        rows: u16,
+    },
+    /// The console window gained (`true`) or lost (`false`) focus.
+    Focus(bool),
+}
+
+/// A key event, see [`InputEvent::Key`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyEvent {
+    key_down: bool,
+    repeat_count: u16,
+    virtual_key_code: u16,
+    virtual_scan_code: u16,
+    unicode_char: u16,
+    control_key_state: u32,
+}
+
+impl KeyEvent {
+    /// Returns `true` if the key was pressed and `false` if it was released.
+    pub fn is_key_down(&self) -> bool {
+        self.key_down
+    }
+
+    /// The number of times the key was pressed (when it's being held down).
+    pub fn repeat_count(&self) -> u16 {
+        self.repeat_count
+    }
+
+    /// The device-independent virtual key code (e.g. `VK_LEFT`).
+    pub fn virtual_key_code(&self) -> u16 {
+        self.virtual_key_code
+    }
+
+    /// The device-dependent scan code.
+    pub fn virtual_scan_code(&self) -> u16 {
+        self.virtual_scan_code
+    }
+
+    /// The UTF-16 code unit produced by the key (or `0` if the key doesn't produce text).
+    /// Characters outside the basic multilingual plane are reported as two events, one per surrogate.
+    pub fn unicode_char(&self) -> u16 {
+        self.unicode_char
+    }
+
+    /// The character produced by the key, if any.
+    /// Returns `None` for surrogates, see [`KeyEvent::unicode_char`].
+    pub fn char(&self) -> Option<char> {
+        match self.unicode_char {
+            0 => None,
+            c => char::from_u32(u32::from(c)),
+        }
+    }
+
+    /// The raw state of the control keys (`dwControlKeyState`), e.g. `CAPSLOCK_ON`.
+    pub fn control_key_state(&self) -> u32 {
+        self.control_key_state
+    }
+
+    /// Returns `true` if either shift key is held down.
+    pub fn shift(&self) -> bool {
+        self.control_key_state & SHIFT_PRESSED != 0
+    }
+
+    /// Returns `true` if either control key is held down.
+    pub fn ctrl(&self) -> bool {
+        self.control_key_state & (LEFT_CTRL_PRESSED | RIGHT_CTRL_PRESSED) != 0
+    }
+
+    /// Returns `true` if either alt key is held down.
+    pub fn alt(&self) -> bool {
+        self.control_key_state & (LEFT_ALT_PRESSED | RIGHT_ALT_PRESSED) != 0
+    }
+}
+
+/// A mouse event, see [`InputEvent::Mouse`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MouseEvent {
+    column: i16,
+    row: i16,
+    button_state: u32,
+    control_key_state: u32,
+    event_flags: u32,
+}
+
+impl MouseEvent {
+    /// The (zero-based) column of the mouse cursor in the screen buffer.
+    pub fn column(&self) -> i16 {
+        self.column
+    }
+
+    /// The (zero-based) row of the mouse cursor in the screen buffer.
+    pub fn row(&self) -> i16 {
+        self.row
+    }
+
+    /// The raw state of the mouse buttons (`dwButtonState`), e.g. `FROM_LEFT_1ST_BUTTON_PRESSED`.
+    pub fn button_state(&self) -> u32 {
+        self.button_state
+    }
+
+    /// The raw state of the control keys (`dwControlKeyState`).
+    pub fn control_key_state(&self) -> u32 {
+        self.control_key_state
+    }
+
+    /// The kind of mouse event (`dwEventFlags`), e.g. `MOUSE_MOVED` or `MOUSE_WHEELED`.
+    /// Zero indicates that a button was pressed or released.
+    pub fn event_flags(&self) -> u32 {
+        self.event_flags
+    }
+}
+
+// Large enough to not need another call for typical bursts of input (e.g. a paste).
+const RECORD_BUFFER_SIZE: usize = 128;
+
+/// Blocks until at least one event is available and returns all available events (up to a limit).
+/// Events that have no public representation (e.g. menu events) are skipped.
+pub(crate) fn read_input_events(handle: BorrowedHandle<'_>) -> io::Result<Vec<InputEvent>> {
+    // SAFETY: `INPUT_RECORD` is a plain C struct for which all zeroes is a valid value.
+    let mut records: [INPUT_RECORD; RECORD_BUFFER_SIZE] = unsafe { std::mem::zeroed() };
+    let mut read = 0;
+    // SAFETY: We pass a valid handle and the length of our buffer.
+    to_io_result(unsafe {
+        ReadConsoleInputW(
+            handle.as_raw_handle(),
+            records.as_mut_ptr(),
+            records.len() as u32,
+            &mut read,
+        )
+    })?;
+    Ok(records[..read as usize]
+        .iter()
+        .filter_map(from_record)
+        .collect())
+}
+
+fn from_record(record: &INPUT_RECORD) -> Option<InputEvent> {
+    match u32::from(record.EventType) {
+        KEY_EVENT => {
+            // SAFETY: The event type tells us which variant is active.
+            let event = unsafe { record.Event.KeyEvent };
+            Some(InputEvent::Key(KeyEvent {
+                key_down: event.bKeyDown != 0,
+                repeat_count: event.wRepeatCount,
+                virtual_key_code: event.wVirtualKeyCode,
+                virtual_scan_code: event.wVirtualScanCode,
+                // SAFETY: We always read the UTF-16 variant.
+                unicode_char: unsafe { event.uChar.UnicodeChar },
+                control_key_state: event.dwControlKeyState,
+            }))
+        }
+        MOUSE_EVENT => {
+            // SAFETY: The event type tells us which variant is active.
+            let event = unsafe { record.Event.MouseEvent };
+            Some(InputEvent::Mouse(MouseEvent {
+                column: event.dwMousePosition.X,
+                row: event.dwMousePosition.Y,
+                button_state: event.dwButtonState,
+                control_key_state: event.dwControlKeyState,
+                event_flags: event.dwEventFlags,
+            }))
+        }
+        WINDOW_BUFFER_SIZE_EVENT => {
+            // SAFETY: The event type tells us which variant is active.
+            let size = unsafe { record.Event.WindowBufferSizeEvent }.dwSize;
+            Some(InputEvent::Resize {
+                columns: u16::try_from(size.X).unwrap_or(0),
+                rows: u16::try_from(size.Y).unwrap_or(0),
+            })
+        }
+        FOCUS_EVENT => {
+            // SAFETY: The event type tells us which variant is active.
+            let event = unsafe { record.Event.FocusEvent };
+            Some(InputEvent::Focus(event.bSetFocus != 0))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use windows_sys::Win32::System::Console::{
+        COORD, FOCUS_EVENT_RECORD, INPUT_RECORD_0, MENU_EVENT, MENU_EVENT_RECORD,
+        WINDOW_BUFFER_SIZE_RECORD,
+    };
+
+    #[test]
+    fn converts_resize_events() {
+        let record = INPUT_RECORD {
+            EventType: WINDOW_BUFFER_SIZE_EVENT as u16,
+            Event: INPUT_RECORD_0 {
+                WindowBufferSizeEvent: WINDOW_BUFFER_SIZE_RECORD {
+                    dwSize: COORD { X: 80, Y: 24 },
+                },
+            },
+        };
+        assert_eq!(
+            Some(InputEvent::Resize {
+                columns: 80,
+                rows: 24
+            }),
+            from_record(&record)
+        );
+    }
+
+    #[test]
+    fn converts_focus_events() {
+        let record = INPUT_RECORD {
+            EventType: FOCUS_EVENT as u16,
+            Event: INPUT_RECORD_0 {
+                FocusEvent: FOCUS_EVENT_RECORD { bSetFocus: 1 },
+            },
+        };
+        assert_eq!(Some(InputEvent::Focus(true)), from_record(&record));
+    }
+
+    #[test]
+    fn skips_menu_events() {
+        let record = INPUT_RECORD {
+            EventType: MENU_EVENT as u16,
+            Event: INPUT_RECORD_0 {
+                MenuEvent: MENU_EVENT_RECORD { dwCommandId: 0 },
+            },
+        };
+        assert_eq!(None, from_record(&record));
+    }
+}
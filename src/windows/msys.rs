@@ -1,10 +1,18 @@
-use std::ffi::c_void;
+use crate::RawModeOptions;
+use std::ffi::{c_void, OsStr};
+use std::fs::File;
+use std::io;
 use std::mem::size_of;
+use std::os::windows::io::{AsRawHandle as _, BorrowedHandle};
 use std::os::windows::raw::HANDLE;
-use windows_sys::Win32::Foundation::MAX_PATH;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use std::{ptr, thread};
+use windows_sys::Win32::Foundation::{ERROR_BROKEN_PIPE, MAX_PATH};
 use windows_sys::Win32::Storage::FileSystem::{
     FileNameInfo, GetFileInformationByHandleEx, GetFileType, FILE_TYPE_PIPE,
 };
+use windows_sys::Win32::System::Pipes::PeekNamedPipe;
 
 // Adopted from Rust's standard library with minimal changes to use windows_sys.
 // Source: https://github.com/rust-lang/rust/blob/32ec40c68533f325a3c8fe787b77ef5c9e209b23/library/std/src/sys/pal/windows/io.rs#L82
@@ -58,3 +66,190 @@ pub(super) unsafe fn msys_tty_on(handle: HANDLE) -> bool {
     let is_pty = name.contains("-pty");
     is_msys && is_pty
 }
+
+/// The settings of a MSYS/Cygwin pty as reported by `stty -g`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct SttySettings(String);
+
+impl SttySettings {
+    pub(super) fn as_bytes(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+
+    pub(super) fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        std::str::from_utf8(bytes)
+            .ok()
+            .map(|s| SttySettings(s.to_owned()))
+    }
+}
+
+// MSYS/Cygwin ptys are pipes on the Windows side, their settings
+// are managed by the Cygwin runtime. We can't talk to the runtime directly,
+// so we run `stty` (which ships with MSYS/Cygwin) with the pty as its standard input.
+fn stty(conin: &File, args: &[impl AsRef<OsStr>]) -> io::Result<String> {
+    let output = Command::new("stty")
+        .args(args)
+        .stdin(Stdio::from(conin.try_clone()?))
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|error| match error.kind() {
+            io::ErrorKind::NotFound => io::Error::from(crate::Error::Unsupported {
+                reason: "stty is required for raw mode on MSYS/Cygwin terminals",
+            }),
+            _ => error,
+        })?;
+    if output.status.success() {
+        String::from_utf8(output.stdout)
+            .map(|s| s.trim().to_owned())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("stty exited with {}", output.status),
+        ))
+    }
+}
+
+pub(super) fn save(conin: &File) -> io::Result<SttySettings> {
+    stty(conin, &["-g"]).map(SttySettings)
+}
+
+pub(super) fn enable_raw_mode(conin: &File, options: &RawModeOptions) -> io::Result<SttySettings> {
+    let saved = save(conin)?;
+    stty(conin, &raw_mode_arguments(options))?;
+    Ok(saved)
+}
+
+pub(super) fn restore(conin: &File, settings: &SttySettings) -> io::Result<()> {
+    stty(conin, &[&settings.0]).map(drop)
+}
+
+/// Waits until input is available on a MSYS/Cygwin pty.
+/// Pipes are never signaled, so we check for available bytes at a short interval instead.
+pub(super) fn wait_for_input(conin: BorrowedHandle, timeout: Option<Duration>) -> io::Result<bool> {
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    let deadline = timeout.map(|t| Instant::now() + t);
+    loop {
+        let mut available = 0;
+        // SAFETY: We pass a valid handle and no buffer to copy into.
+        let result = unsafe {
+            PeekNamedPipe(
+                conin.as_raw_handle(),
+                ptr::null_mut(),
+                0,
+                ptr::null_mut(),
+                &mut available,
+                ptr::null_mut(),
+            )
+        };
+        if result == 0 {
+            let error = io::Error::last_os_error();
+            // The other end was closed, so the next read won't block.
+            return if error.raw_os_error() == Some(ERROR_BROKEN_PIPE as i32) {
+                Ok(true)
+            } else {
+                Err(error)
+            };
+        }
+        if available > 0 {
+            return Ok(true);
+        }
+        let remaining = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+        if remaining == Some(Duration::ZERO) {
+            return Ok(false);
+        }
+        thread::sleep(remaining.map_or(POLL_INTERVAL, |r| r.min(POLL_INTERVAL)));
+    }
+}
+
+pub(super) fn set_read_behavior(
+    conin: &File,
+    min: u8,
+    timeout: Option<Duration>,
+) -> io::Result<()> {
+    stty(conin, &read_behavior_arguments(min, timeout)).map(drop)
+}
+
+fn read_behavior_arguments(min: u8, timeout: Option<Duration>) -> [String; 4] {
+    // Same rounding as on Unix: short timeouts must not turn into "no timeout".
+    let deciseconds = timeout.map_or(0, |t| ((t.as_millis() + 99) / 100).clamp(1, 255));
+    [
+        "min".to_owned(),
+        min.to_string(),
+        "time".to_owned(),
+        deciseconds.to_string(),
+    ]
+}
+
+/// Mirrors the flags that are changed on Unix.
+fn raw_mode_arguments(options: &RawModeOptions) -> Vec<String> {
+    let flag = |enabled: bool, on: &str, off: &str| if enabled { on } else { off }.to_owned();
+    let mut args = vec![
+        flag(options.echo, "echo", "-echo"),
+        flag(options.canonical, "icanon", "-icanon"),
+    ];
+    if !options.signals {
+        args.push("-isig".to_owned());
+    }
+    if !options.flow_control {
+        args.extend(["-ixon".to_owned(), "-ixoff".to_owned()]);
+    }
+    if !options.output_processing {
+        args.push("-opost".to_owned());
+    }
+    if options.echo_newline {
+        args.push("echonl".to_owned());
+    }
+    if let Some((min, timeout)) = options.read_behavior {
+        args.extend(read_behavior_arguments(min, timeout));
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_options_only_disable_echo_and_canonical_mode() {
+        assert_eq!(
+            vec!["-echo", "-icanon"],
+            raw_mode_arguments(&RawModeOptions::default())
+        );
+    }
+
+    #[test]
+    fn disabled_features_are_turned_off() {
+        let options = RawModeOptions::default()
+            .signals(false)
+            .flow_control(false)
+            .output_processing(false);
+        assert_eq!(
+            vec!["-echo", "-icanon", "-isig", "-ixon", "-ixoff", "-opost"],
+            raw_mode_arguments(&options)
+        );
+    }
+
+    #[test]
+    fn read_behavior_is_set_together_with_raw_mode() {
+        let options = RawModeOptions::default().read_behavior(0, Some(Duration::from_millis(150)));
+        assert_eq!(
+            vec!["-echo", "-icanon", "min", "0", "time", "2"],
+            raw_mode_arguments(&options)
+        );
+    }
+
+    #[test]
+    fn settings_survive_serialization() {
+        #![allow(clippy::unwrap_used)]
+
+        use crate::imp::TerminalAttributes;
+
+        let settings = SttySettings("500:5:bf:8a3b:3:1c:7f:15:4:0:1:0".to_owned());
+        let attributes = TerminalAttributes::msys(settings.clone());
+        let restored = TerminalAttributes::from_bytes(&attributes.to_bytes()).unwrap();
+        assert_eq!(Some(settings), restored.msys_settings);
+        assert_eq!(None, TerminalAttributes::console(7, 3).msys_settings);
+    }
+}
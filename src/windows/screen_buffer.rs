@@ -0,0 +1,107 @@
+use super::to_io_result;
+use std::io;
+use std::os::windows::io::{AsRawHandle as _, BorrowedHandle};
+use windows_sys::Win32::System::Console::{
+    GetConsoleScreenBufferInfo, SetConsoleScreenBufferSize, SetConsoleWindowInfo,
+    CONSOLE_SCREEN_BUFFER_INFO, COORD, SMALL_RECT,
+};
+
+/// Information about the console's screen buffer,
+/// see [`TerminalLock::screen_buffer_info`](`crate::TerminalLock::screen_buffer_info`).
+///
+/// All coordinates are zero-based and given as `(column, row)` in character cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScreenBufferInfo {
+    buffer_size: (i16, i16),
+    cursor_position: (i16, i16),
+    attributes: u16,
+    window: (i16, i16, i16, i16),
+    maximum_window_size: (i16, i16),
+}
+
+impl ScreenBufferInfo {
+    /// The size of the screen buffer, including the scrollback, as `(columns, rows)`.
+    pub fn buffer_size(&self) -> (i16, i16) {
+        self.buffer_size
+    }
+
+    /// The position of the cursor in the screen buffer as `(column, row)`.
+    pub fn cursor_position(&self) -> (i16, i16) {
+        self.cursor_position
+    }
+
+    /// The attributes (e.g. `FOREGROUND_RED`) used for text written to the console.
+    pub fn attributes(&self) -> u16 {
+        self.attributes
+    }
+
+    /// The visible part of the screen buffer as `(left, top, right, bottom)` (inclusive).
+    pub fn window(&self) -> (i16, i16, i16, i16) {
+        self.window
+    }
+
+    /// The size of the visible part of the screen buffer as `(columns, rows)`.
+    pub fn window_size(&self) -> (i16, i16) {
+        let (left, top, right, bottom) = self.window;
+        (right - left + 1, bottom - top + 1)
+    }
+
+    /// The largest possible window size given the current font and display size as `(columns, rows)`.
+    pub fn maximum_window_size(&self) -> (i16, i16) {
+        self.maximum_window_size
+    }
+}
+
+pub(crate) fn screen_buffer_info(handle: BorrowedHandle<'_>) -> io::Result<ScreenBufferInfo> {
+    // SAFETY: `CONSOLE_SCREEN_BUFFER_INFO` is a plain C struct for which all zeroes is a valid value.
+    let mut info: CONSOLE_SCREEN_BUFFER_INFO = unsafe { std::mem::zeroed() };
+    // SAFETY: We pass a valid handle and a pointer to a properly sized struct.
+    to_io_result(unsafe { GetConsoleScreenBufferInfo(handle.as_raw_handle(), &mut info) })?;
+    Ok(ScreenBufferInfo {
+        buffer_size: (info.dwSize.X, info.dwSize.Y),
+        cursor_position: (info.dwCursorPosition.X, info.dwCursorPosition.Y),
+        attributes: info.wAttributes,
+        window: (
+            info.srWindow.Left,
+            info.srWindow.Top,
+            info.srWindow.Right,
+            info.srWindow.Bottom,
+        ),
+        maximum_window_size: (info.dwMaximumWindowSize.X, info.dwMaximumWindowSize.Y),
+    })
+}
+
+/// Resizes both the window and the screen buffer (discarding the scrollback).
+pub(crate) fn set_size(handle: BorrowedHandle<'_>, rows: u16, columns: u16) -> io::Result<()> {
+    let invalid_size = || io::Error::new(io::ErrorKind::InvalidInput, "invalid console size");
+    let rows = i16::try_from(rows)
+        .ok()
+        .filter(|&r| r > 0)
+        .ok_or_else(invalid_size)?;
+    let columns = i16::try_from(columns)
+        .ok()
+        .filter(|&c| c > 0)
+        .ok_or_else(invalid_size)?;
+    let set_window = |columns: i16, rows: i16| {
+        let window = SMALL_RECT {
+            Left: 0,
+            Top: 0,
+            Right: columns - 1,
+            Bottom: rows - 1,
+        };
+        // SAFETY: We pass a valid handle and a pointer to a valid rectangle.
+        to_io_result(unsafe { SetConsoleWindowInfo(handle.as_raw_handle(), 1, &window) })
+    };
+
+    // The window must fit into the screen buffer at all times,
+    // so it is shrunk before and grown after resizing the buffer.
+    let (current_columns, current_rows) = screen_buffer_info(handle)?.window_size();
+    set_window(columns.min(current_columns), rows.min(current_rows))?;
+    let size = COORD {
+        X: columns,
+        Y: rows,
+    };
+    // SAFETY: We pass a valid handle.
+    to_io_result(unsafe { SetConsoleScreenBufferSize(handle.as_raw_handle(), size) })?;
+    set_window(columns, rows)
+}
@@ -0,0 +1,53 @@
+use super::console_mode::set_console_mode;
+use super::{msys, to_io_result, Terminal, TerminalAttributes};
+use std::io;
+use std::os::windows::io::AsHandle as _;
+use std::sync::OnceLock;
+use windows_sys::Win32::Foundation::{BOOL, FALSE, TRUE};
+use windows_sys::Win32::System::Console::SetConsoleCtrlHandler;
+
+struct SavedState {
+    terminal: Terminal,
+    attributes: TerminalAttributes,
+}
+
+static SAVED_STATE: OnceLock<SavedState> = OnceLock::new();
+
+pub(crate) fn install_restore_on_signals(
+    terminal: Terminal,
+    attributes: TerminalAttributes,
+) -> io::Result<()> {
+    if SAVED_STATE
+        .set(SavedState {
+            terminal,
+            attributes,
+        })
+        .is_err()
+    {
+        return Ok(());
+    }
+
+    // SAFETY: We pass a valid handler routine.
+    to_io_result(unsafe { SetConsoleCtrlHandler(Some(handle_ctrl_event), TRUE) })
+}
+
+// Control handlers run on a separate thread, so unlike Unix signal handlers
+// there are no restrictions on what we can do here.
+unsafe extern "system" fn handle_ctrl_event(_ctrl_type: u32) -> BOOL {
+    if let Some(state) = SAVED_STATE.get() {
+        if let Some(settings) = &state.attributes.msys_settings {
+            _ = msys::restore(state.terminal.conin.file(), settings);
+            return FALSE;
+        }
+        _ = set_console_mode(
+            state.terminal.conin.as_handle(),
+            state.attributes.input_mode,
+        );
+        _ = set_console_mode(
+            state.terminal.conout.as_handle(),
+            state.attributes.output_mode,
+        );
+    }
+    // Let the next handler (usually the default handler which terminates the process) run.
+    FALSE
+}
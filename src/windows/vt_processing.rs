@@ -0,0 +1,67 @@
+use super::console_mode::{self, get_console_mode, set_console_mode};
+use super::Terminal;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::os::windows::io::AsHandle as _;
+use windows_sys::Win32::System::Console::CONSOLE_MODE;
+
+/// Enables processing of VT sequences written to the console until the guard is dropped,
+/// see [`TerminalLock::enable_virtual_terminal_processing`](`crate::TerminalLock::enable_virtual_terminal_processing`).
+///
+/// The guard holds on to the [`TerminalLock`](`crate::TerminalLock`) or [`RawModeGuard`](`crate::RawModeGuard`)
+/// it was created from and can be used in its place (e.g. for writing).
+#[derive(Debug)]
+pub struct VirtualTerminalProcessingGuard<'a, T> {
+    inner: &'a mut T,
+    terminal: &'a Terminal,
+    // Only set if the mode was modified.
+    old_mode: Option<CONSOLE_MODE>,
+}
+
+impl<'a, T> VirtualTerminalProcessingGuard<'a, T> {
+    pub(crate) fn new(
+        inner: &'a mut T,
+        terminal: &'a Terminal,
+        disable_newline_auto_return: bool,
+    ) -> io::Result<Self> {
+        let conout = terminal.conout.as_handle();
+        let mode = get_console_mode(conout)?;
+        let new_mode = console_mode::output::enable_virtual_terminal_processing(
+            mode,
+            disable_newline_auto_return,
+        );
+        let old_mode = if new_mode == mode {
+            None
+        } else {
+            set_console_mode(conout, new_mode)?;
+            Some(mode)
+        };
+        Ok(VirtualTerminalProcessingGuard {
+            inner,
+            terminal,
+            old_mode,
+        })
+    }
+}
+
+impl<T> Deref for VirtualTerminalProcessingGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.inner
+    }
+}
+
+impl<T> DerefMut for VirtualTerminalProcessingGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.inner
+    }
+}
+
+impl<T> Drop for VirtualTerminalProcessingGuard<'_, T> {
+    fn drop(&mut self) {
+        if let Some(old_mode) = self.old_mode {
+            _ = set_console_mode(self.terminal.conout.as_handle(), old_mode);
+        }
+    }
+}
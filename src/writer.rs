@@ -0,0 +1,117 @@
+use std::io;
+
+/// Accumulates output and writes it to the terminal in one go, e.g. once per frame.
+/// Created using [`TerminalLock::buffered`](`crate::TerminalLock::buffered`)
+/// or [`RawModeGuard::buffered`](`crate::RawModeGuard::buffered`).
+///
+/// Unlike [`io::BufWriter`], output is never written implicitly when the buffer fills up,
+/// only by [`TerminalWriter::present`] (or [`io::Write::flush`]) and when the writer is dropped.
+/// Errors that occur when presenting the remaining output on drop are ignored,
+/// so call [`TerminalWriter::present`] explicitly to handle them.
+///
+/// ## Example
+/// ```no_run
+/// use std::io::Write as _;
+/// use terminal_trx::terminal;
+///
+/// let mut tty = terminal().unwrap();
+/// let mut lock = tty.lock();
+/// let mut raw_mode = lock.enable_raw_mode().unwrap();
+/// let mut writer = raw_mode.buffered();
+///
+/// write!(writer, "\x1b[2J").unwrap();
+/// write!(writer, "\x1b[Hhello world").unwrap();
+/// writer.present().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct TerminalWriter<'a, T: io::Write> {
+    inner: &'a mut T,
+    buf: Vec<u8>,
+}
+
+impl<'a, T: io::Write> TerminalWriter<'a, T> {
+    pub(crate) fn new(inner: &'a mut T) -> Self {
+        TerminalWriter {
+            inner,
+            buf: Vec::new(),
+        }
+    }
+
+    /// Writes all accumulated output with a single write call and flushes the terminal.
+    ///
+    /// The buffer is emptied even if writing fails, so that a partially written frame is not repeated.
+    pub fn present(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let result = self.inner.write_all(&self.buf);
+        self.buf.clear();
+        result?;
+        self.inner.flush()
+    }
+
+    /// Discards all accumulated output without writing it.
+    pub fn discard(&mut self) {
+        self.buf.clear();
+    }
+
+    /// Returns the output that has been accumulated since it was last presented.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl<T: io::Write> io::Write for TerminalWriter<'_, T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.present()
+    }
+}
+
+impl<T: io::Write> Drop for TerminalWriter<'_, T> {
+    fn drop(&mut self) {
+        _ = self.present();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(clippy::unwrap_used)]
+
+    use super::*;
+    use std::io::Write as _;
+
+    #[test]
+    fn output_is_only_written_when_presented() {
+        let mut output = Vec::new();
+        let mut writer = TerminalWriter::new(&mut output);
+        write!(writer, "foo").unwrap();
+        write!(writer, "bar").unwrap();
+        assert_eq!(b"foobar", writer.buffer());
+        writer.present().unwrap();
+        assert!(writer.buffer().is_empty());
+        drop(writer);
+        assert_eq!(b"foobar", output.as_slice());
+    }
+
+    #[test]
+    fn remaining_output_is_written_on_drop() {
+        let mut output = Vec::new();
+        write!(TerminalWriter::new(&mut output), "foo").unwrap();
+        assert_eq!(b"foo", output.as_slice());
+    }
+
+    #[test]
+    fn discarded_output_is_not_written() {
+        let mut output = Vec::new();
+        let mut writer = TerminalWriter::new(&mut output);
+        write!(writer, "foo").unwrap();
+        writer.discard();
+        drop(writer);
+        assert!(output.is_empty());
+    }
+}